@@ -0,0 +1,329 @@
+#![doc = include_str!("../README.md")]
+
+use stackdump_core::{
+    device_memory::MemoryReadError, memory_region::MemoryRegion, register_data::VecRegisterData,
+};
+use std::{
+    cell::RefCell,
+    io::{BufRead, BufReader, ErrorKind, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+};
+
+/// The maximum amount of bytes that is read from memory in one packet.
+/// The reply has two hex characters per byte, which keeps it well within the packet size of common stubs.
+const MAX_MEMORY_READ_SIZE: usize = 1024;
+
+/// The amount of core registers that are captured: r0 - r15
+const CORE_REGISTER_COUNT: usize = 16;
+
+/// A connection to a gdb stub (like the one of QEMU) that speaks the gdb remote serial protocol
+struct GdbConnection<S: Read + Write> {
+    stream: BufReader<S>,
+}
+
+impl<S: Read + Write> GdbConnection<S> {
+    /// Sends the command and returns the reply of the stub
+    fn command(&mut self, command: &str) -> std::io::Result<Vec<u8>> {
+        self.send_packet(command)?;
+        self.receive_packet()
+    }
+
+    fn send_packet(&mut self, data: &str) -> std::io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum(data.as_bytes()));
+
+        // A '-' means the stub didn't receive the packet correctly and wants it again
+        loop {
+            self.stream.get_mut().write_all(packet.as_bytes())?;
+            self.stream.get_mut().flush()?;
+
+            match self.read_byte()? {
+                b'+' => return Ok(()),
+                b'-' => continue,
+                byte => {
+                    return Err(protocol_error(format!(
+                        "Expected an acknowledgement, but got {:?}",
+                        byte as char
+                    )))
+                }
+            }
+        }
+    }
+
+    fn receive_packet(&mut self) -> std::io::Result<Vec<u8>> {
+        loop {
+            // Skip everything until the start of a packet, like stray acknowledgements
+            while self.read_byte()? != b'$' {}
+
+            let mut data = Vec::new();
+            self.stream.read_until(b'#', &mut data)?;
+            if data.pop() != Some(b'#') {
+                return Err(ErrorKind::UnexpectedEof.into());
+            }
+
+            let mut checksum_chars = [0; 2];
+            self.stream.read_exact(&mut checksum_chars)?;
+
+            if parse_hex_byte(&checksum_chars) == Some(checksum(&data)) {
+                self.stream.get_mut().write_all(b"+")?;
+                self.stream.get_mut().flush()?;
+                return decode_run_length(&data);
+            }
+
+            self.stream.get_mut().write_all(b"-")?;
+            self.stream.get_mut().flush()?;
+        }
+    }
+
+    fn read_byte(&mut self) -> std::io::Result<u8> {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+}
+
+/// Captures the registers and memory of a target over a gdb remote connection.
+///
+/// Only 32-bit little endian targets are supported, like Cortex-M running in QEMU.
+pub struct StackdumpCapturer<S: Read + Write = TcpStream>(RefCell<GdbConnection<S>>);
+
+impl StackdumpCapturer<TcpStream> {
+    /// Connects to the gdb stub at the given address, e.g. `localhost:1234` for QEMU started with `-s`.
+    ///
+    /// QEMU halts the target while a debugger is connected.
+    pub fn connect(address: impl ToSocketAddrs) -> std::io::Result<Self> {
+        Self::new(TcpStream::connect(address)?)
+    }
+}
+
+impl<S: Read + Write> StackdumpCapturer<S> {
+    /// Creates a capturer that uses an existing connection to a gdb stub
+    pub fn new(stream: S) -> std::io::Result<Self> {
+        let mut connection = GdbConnection {
+            stream: BufReader::new(stream),
+        };
+
+        // Ask why the target halted. This is what gdb does first as well and it checks that the stub is responding.
+        connection.command("?")?;
+
+        Ok(Self(RefCell::new(connection)))
+    }
+
+    /// Reads the core registers r0 - r15
+    pub fn capture_core_registers(&mut self) -> std::io::Result<VecRegisterData<u32>> {
+        let reply = self.0.get_mut().command("g")?;
+        check_error_reply(&reply)?;
+
+        if reply.len() < CORE_REGISTER_COUNT * 8 {
+            return Err(protocol_error(format!(
+                "Expected at least {} registers, but got {} bytes of register data",
+                CORE_REGISTER_COUNT,
+                reply.len() / 2
+            )));
+        }
+
+        let register_data = reply[..CORE_REGISTER_COUNT * 8]
+            .chunks(8)
+            .map(|register| {
+                decode_hex(register)
+                    .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+                    .ok_or_else(|| protocol_error("A core register is not available"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(VecRegisterData::new(
+            stackdump_core::gimli::Arm::R0,
+            register_data,
+        ))
+    }
+
+    /// Detaches from the stub, which lets the target continue running
+    pub fn detach(&self) -> std::io::Result<()> {
+        let reply = self.0.borrow_mut().command("D")?;
+        check_error_reply(&reply)
+    }
+
+    fn read_memory(&self, address_range: std::ops::Range<u64>) -> std::io::Result<Option<Vec<u8>>> {
+        // The range can come from corrupted data, so the memory isn't allocated up front, but grows as the replies come in
+        let total_length = address_range.end.saturating_sub(address_range.start);
+        let mut memory = Vec::new();
+        let mut connection = self.0.borrow_mut();
+
+        while (memory.len() as u64) < total_length {
+            let address = address_range.start + memory.len() as u64;
+            let length = (address_range.end - address).min(MAX_MEMORY_READ_SIZE as u64) as usize;
+
+            let reply = connection.command(&format!("m{:x},{:x}", address, length))?;
+
+            // The stub can't read the memory
+            if reply.len() != length * 2 && reply.starts_with(b"E") {
+                return Ok(None);
+            }
+
+            match decode_hex(&reply) {
+                Some(bytes) if !bytes.is_empty() => memory.extend(bytes),
+                _ => return Ok(None),
+            }
+        }
+
+        memory.truncate(total_length as usize);
+        Ok(Some(memory))
+    }
+}
+
+impl<S: Read + Write> MemoryRegion for StackdumpCapturer<S> {
+    fn read(
+        &self,
+        address_range: std::ops::Range<u64>,
+    ) -> Result<Option<Vec<u8>>, MemoryReadError> {
+        self.read_memory(address_range)
+            .map_err(MemoryReadError::from_std_error)
+    }
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn parse_hex_byte(chars: &[u8]) -> Option<u8> {
+    u8::from_str_radix(std::str::from_utf8(chars).ok()?, 16).ok()
+}
+
+/// Decodes hex characters to bytes. Returns None if not all characters are hex, like the `xx` of unavailable data.
+fn decode_hex(hex: &[u8]) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    hex.chunks(2).map(parse_hex_byte).collect()
+}
+
+/// Replies can be run length encoded: `X*N` means that `X` is repeated `N - 29` more times
+fn decode_run_length(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(data.len());
+    let mut bytes = data.iter();
+
+    while let Some(byte) = bytes.next() {
+        if *byte != b'*' {
+            decoded.push(*byte);
+            continue;
+        }
+
+        match (decoded.last().copied(), bytes.next()) {
+            (Some(previous), Some(count)) if *count >= 29 => {
+                decoded.extend(std::iter::repeat_n(previous, (*count - 29) as usize))
+            }
+            _ => return Err(protocol_error("Invalid run length encoding")),
+        }
+    }
+
+    Ok(decoded)
+}
+
+/// Returns an error if the reply is an error (`Exx`) or empty, which means the command is not supported
+fn check_error_reply(reply: &[u8]) -> std::io::Result<()> {
+    match reply {
+        [] => Err(protocol_error(
+            "The command is not supported by the gdb stub",
+        )),
+        [b'E', code @ ..] if code.len() == 2 => Err(protocol_error(format!(
+            "The gdb stub replied with error {}",
+            String::from_utf8_lossy(code)
+        ))),
+        _ => Ok(()),
+    }
+}
+
+fn protocol_error(message: impl Into<String>) -> std::io::Error {
+    std::io::Error::new(ErrorKind::InvalidData, message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// A stream that plays back the replies of a stub and records what is sent to it
+    struct StubStream {
+        replies: Cursor<Vec<u8>>,
+        sent: Vec<u8>,
+    }
+
+    impl StubStream {
+        fn new(replies: &[&str]) -> Self {
+            let replies = replies
+                .iter()
+                .map(|reply| format!("+${}#{:02x}", reply, checksum(reply.as_bytes())))
+                .collect::<String>();
+
+            Self {
+                replies: Cursor::new(replies.into_bytes()),
+                sent: Vec::new(),
+            }
+        }
+    }
+
+    impl Read for StubStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.replies.read(buf)
+        }
+    }
+
+    impl Write for StubStream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.sent.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn capture_core_registers() {
+        // r0 = 1, r15 = 0x800 and the zeroes in between are run length encoded. The xpsr at the end is ignored.
+        let mut capturer =
+            StackdumpCapturer::new(StubStream::new(&["S05", "010*~0*208000001000000"])).unwrap();
+
+        let registers = capturer.capture_core_registers().unwrap();
+
+        let mut expected = vec![0; 16];
+        expected[0] = 1;
+        expected[15] = 0x800;
+        assert_eq!(
+            registers,
+            VecRegisterData::new(stackdump_core::gimli::Arm::R0, expected)
+        );
+
+        let sent = String::from_utf8(capturer.0.into_inner().stream.into_inner().sent).unwrap();
+        assert_eq!(sent, "$?#3f+$g#67+");
+    }
+
+    #[test]
+    fn read_memory() {
+        let capturer =
+            StackdumpCapturer::new(StubStream::new(&["S05", "01020304", "E14"])).unwrap();
+
+        assert_eq!(
+            capturer.read(0x2000_0000..0x2000_0004).unwrap(),
+            Some(vec![1, 2, 3, 4])
+        );
+        // The stub can't read this memory
+        assert_eq!(capturer.read(0x4000_0000..0x4000_0004).unwrap(), None);
+
+        let sent = String::from_utf8(capturer.0.into_inner().stream.into_inner().sent).unwrap();
+        assert!(sent.ends_with("$m20000000,4#4f+$m40000000,4#51+"));
+    }
+
+    #[test]
+    fn read_huge_memory_range() {
+        let capturer =
+            StackdumpCapturer::new(StubStream::new(&["S05", "01020304", "E14"])).unwrap();
+
+        // The memory is read in chunks until the stub can't read it anymore.
+        // Nothing is allocated for the rest of the range
+        assert_eq!(capturer.read(0x2000_0000..u64::MAX).unwrap(), None);
+
+        let sent = String::from_utf8(capturer.0.into_inner().stream.into_inner().sent).unwrap();
+        assert!(sent.ends_with("$m20000000,400#af+$m20000004,400#b3+"));
+    }
+}
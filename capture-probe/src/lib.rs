@@ -1,8 +1,18 @@
-use probe_rs::MemoryInterface;
+use probe_rs::{MemoryInterface, RegisterId};
 use stackdump_core::{
-    device_memory::MemoryReadError, memory_region::MemoryRegion, register_data::VecRegisterData,
+    device_memory::MemoryReadError,
+    memory_region::MemoryRegion,
+    register_data::{cortex_m, VecRegisterData},
 };
-use std::{cell::RefCell, rc::Rc};
+use std::cell::RefCell;
+
+/// The address of the ID_PFR1 register that tells which extensions a Cortex-M implements
+const ID_PFR1_ADDRESS: u64 = 0xE000_ED44;
+/// The field of ID_PFR1 that is non-zero when the security extension of ARMv8-M is implemented
+const ID_PFR1_SECURITY_MASK: u32 = 0xF << 4;
+
+/// The DCRSR selectors of MSP, PSP, MSP_NS, PSP_NS, MSP_S and PSP_S
+const STACK_POINTER_SELECTORS: [u16; 6] = [0b10001, 0b10010, 0b11000, 0b11001, 0b11010, 0b11011];
 
 pub struct StackdumpCapturer<'a, 'probe>(RefCell<&'a mut probe_rs::Core<'probe>>);
 
@@ -27,9 +37,42 @@ impl<'a, 'probe> StackdumpCapturer<'a, 'probe> {
         Ok(VecRegisterData::new(starting_register, register_data))
     }
 
+    /// Captures the banked stack pointers of a Cortex-M: MSP and PSP.
+    /// On ARMv8-M with the security extension, MSP_NS, PSP_NS, MSP_S and PSP_S are captured as well.
+    ///
+    /// The trace needs them for exception frames that were stacked on another stack than the current one.
+    pub fn capture_stack_pointers(
+        &mut self,
+    ) -> Result<Option<VecRegisterData<u32>>, probe_rs::Error> {
+        let core = self.0.get_mut();
+
+        if !core.core_type().is_cortex_m() {
+            return Ok(None);
+        }
+
+        let security_extension = matches!(core.core_type(), probe_rs::CoreType::Armv8m)
+            && core.read_word_32(ID_PFR1_ADDRESS)? & ID_PFR1_SECURITY_MASK != 0;
+        let stack_pointer_count = if security_extension { 6 } else { 2 };
+
+        let mut register_data = Vec::new();
+        for selector in &STACK_POINTER_SELECTORS[..stack_pointer_count] {
+            register_data.push(core.read_core_reg(RegisterId(*selector))?);
+        }
+
+        Ok(Some(VecRegisterData::new(cortex_m::MSP, register_data)))
+    }
+
+    /// Captures the floating point registers, starting at S0 on ARM and F0 on RISC-V.
+    ///
+    /// Returns None if the core doesn't have an FPU.
+    /// Without these, the trace can't recover the floating point registers that were stacked in an exception frame.
     pub fn capture_fpu_registers(
         &mut self,
     ) -> Result<Option<VecRegisterData<u32>>, probe_rs::Error> {
+        if !self.0.get_mut().fpu_support()? {
+            return Ok(None);
+        }
+
         let registers = self.0.get_mut().registers();
 
         match registers.fpu_registers() {
@@ -50,6 +93,19 @@ impl<'a, 'probe> StackdumpCapturer<'a, 'probe> {
             None => Ok(None),
         }
     }
+
+    /// Captures the core registers and, if the core has an FPU, the floating point registers.
+    ///
+    /// Both should be added to the device memory as register data.
+    /// The banked stack pointers are not included, see [Self::capture_stack_pointers] for those.
+    pub fn capture_all(
+        &mut self,
+    ) -> Result<(VecRegisterData<u32>, Option<VecRegisterData<u32>>), probe_rs::Error> {
+        Ok((
+            self.capture_core_registers()?,
+            self.capture_fpu_registers()?,
+        ))
+    }
 }
 
 impl<'a, 'probe> MemoryRegion for StackdumpCapturer<'a, 'probe> {
@@ -66,7 +122,7 @@ impl<'a, 'probe> MemoryRegion for StackdumpCapturer<'a, 'probe> {
             .read(address_range.start as _, &mut buffer)
         {
             Ok(_) => Ok(Some(buffer)),
-            Err(e) => Err(MemoryReadError(Rc::new(e))),
+            Err(e) => Err(MemoryReadError::from_std_error(e)),
         }
     }
 }
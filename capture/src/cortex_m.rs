@@ -1,38 +1,144 @@
 //! Capture functions for the cortex-m platform
 
-use stackdump_core::register_data::RegisterData;
+use crate::target::Target;
+use core::ops::Range;
+use stackdump_core::dump_meta::DumpMeta;
 use stackdump_core::{memory_region::ArrayMemoryRegion, register_data::ArrayRegisterData};
 
+/// The cortex-m [Target]. It captures the core registers.
+///
+/// The fpu registers are not part of it, they're captured separately by [capture] when the target has an fpu.
+pub struct CortexM;
+
+impl Target for CortexM {
+    type Word = u32;
+    type Registers = ArrayRegisterData<16, u32>;
+
+    const STACK_POINTER: stackdump_core::gimli::Register = stackdump_core::gimli::Arm::SP;
+
+    fn capture_registers(registers: &mut Self::Registers) {
+        capture_core_registers(registers);
+    }
+
+    #[allow(unused_unsafe)] // Taking the address of a static mut was unsafe on older compilers
+    fn stack_start() -> u64 {
+        extern "C" {
+            static mut _stack_start: core::ffi::c_void;
+        }
+
+        unsafe { core::ptr::addr_of!(_stack_start) as u32 as u64 }
+    }
+}
+
 /// Capture the core registers and the stack
+///
+/// Returns false if the stack could not be captured, see [crate::target::capture_in_stack_range].
 #[cfg(not(has_fpu))]
 pub fn capture<const SIZE: usize>(
     stack: &mut ArrayMemoryRegion<SIZE>,
     core_registers: &mut ArrayRegisterData<16, u32>,
-) {
-    capture_core_registers(core_registers);
-    capture_stack(
-        core_registers
-            .register(stackdump_core::gimli::Arm::SP)
-            .unwrap(),
-        stack,
-    );
+) -> bool {
+    crate::target::capture::<CortexM, SIZE>(stack, core_registers)
 }
 
 /// Capture the core & fpu registers and the stack
+///
+/// Returns false if the stack could not be captured, see [crate::target::capture_in_stack_range].
 #[cfg(has_fpu)]
 pub fn capture<const SIZE: usize>(
     stack: &mut ArrayMemoryRegion<SIZE>,
     core_registers: &mut ArrayRegisterData<16, u32>,
     fpu_registers: &mut ArrayRegisterData<32, u32>,
-) {
-    capture_core_registers(core_registers);
+) -> bool {
+    capture_fpu_registers(fpu_registers);
+    crate::target::capture::<CortexM, SIZE>(stack, core_registers)
+}
+
+/// Capture the core registers and the stack, which lies in the given address range (e.g. `_stack_end.._stack_start`).
+///
+/// If the stack pointer is outside of the range, the stack is not captured and false is returned.
+#[cfg(not(has_fpu))]
+pub fn capture_in_stack_range<const SIZE: usize>(
+    stack: &mut ArrayMemoryRegion<SIZE>,
+    core_registers: &mut ArrayRegisterData<16, u32>,
+    stack_range: Range<u64>,
+) -> bool {
+    crate::target::capture_in_stack_range::<CortexM, SIZE>(stack, core_registers, stack_range)
+}
+
+/// Capture the core & fpu registers and the stack, which lies in the given address range (e.g. `_stack_end.._stack_start`).
+///
+/// If the stack pointer is outside of the range, the stack is not captured and false is returned.
+#[cfg(has_fpu)]
+pub fn capture_in_stack_range<const SIZE: usize>(
+    stack: &mut ArrayMemoryRegion<SIZE>,
+    core_registers: &mut ArrayRegisterData<16, u32>,
+    fpu_registers: &mut ArrayRegisterData<32, u32>,
+    stack_range: Range<u64>,
+) -> bool {
     capture_fpu_registers(fpu_registers);
-    capture_stack(
-        core_registers
-            .register(stackdump_core::gimli::Arm::SP)
-            .unwrap(),
-        stack,
-    );
+    crate::target::capture_in_stack_range::<CortexM, SIZE>(stack, core_registers, stack_range)
+}
+
+/// Capture the core registers and the stack, and store the meta data of the dump.
+///
+/// The meta data is given by the caller, because only it knows the reason and how to get a timestamp (e.g. from a cycle counter or RTC).
+#[cfg(not(has_fpu))]
+pub fn capture_with_meta<const SIZE: usize>(
+    stack: &mut ArrayMemoryRegion<SIZE>,
+    core_registers: &mut ArrayRegisterData<16, u32>,
+    meta_capture: &mut DumpMeta,
+    meta: DumpMeta,
+) -> bool {
+    *meta_capture = meta;
+    capture(stack, core_registers)
+}
+
+/// Capture the core & fpu registers and the stack, and store the meta data of the dump.
+///
+/// The meta data is given by the caller, because only it knows the reason and how to get a timestamp (e.g. from a cycle counter or RTC).
+#[cfg(has_fpu)]
+pub fn capture_with_meta<const SIZE: usize>(
+    stack: &mut ArrayMemoryRegion<SIZE>,
+    core_registers: &mut ArrayRegisterData<16, u32>,
+    fpu_registers: &mut ArrayRegisterData<32, u32>,
+    meta_capture: &mut DumpMeta,
+    meta: DumpMeta,
+) -> bool {
+    *meta_capture = meta;
+    capture(stack, core_registers, fpu_registers)
+}
+
+/// Capture the main and process stack pointers (MSP and PSP)
+///
+/// The core registers only have the stack pointer that is in use. When the dump is made in an exception handler,
+/// the exception frame can be on the other stack, e.g. the process stack of the thread that was interrupted.
+/// With these registers the trace can find that frame. The memory of that stack has to be captured as well.
+///
+/// On ARMv8-M with the security extension, these are the stack pointers of the current security state.
+pub fn capture_stack_pointers(buffer: &mut ArrayRegisterData<2, u32>) {
+    #[cfg(cortex_m)]
+    use core::arch::asm;
+
+    // This array is going to hold the register data
+    let mut register_array = arrayvec::ArrayVec::new();
+
+    unsafe {
+        // We've got 2 registers, so make space for that
+        register_array.set_len(2);
+
+        #[cfg(cortex_m)]
+        asm!(
+            "mrs {tmp}, msp", // The stack pointers can only be read into a general purpose register first
+            "str {tmp}, [{0}, #0]",
+            "mrs {tmp}, psp",
+            "str {tmp}, [{0}, #4]",
+            in(reg) register_array.as_mut_ptr(), // Every register is going to be written to an offset of this pointer
+            tmp = out(reg) _, // We need a temporary register
+        );
+    }
+
+    *buffer = ArrayRegisterData::new(stackdump_core::register_data::cortex_m::MSP, register_array);
 }
 
 fn capture_core_registers(buffer: &mut ArrayRegisterData<16, u32>) {
@@ -125,25 +231,3 @@ fn capture_fpu_registers(buffer: &mut ArrayRegisterData<32, u32>) {
 
     *buffer = ArrayRegisterData::new(stackdump_core::gimli::Arm::S0, register_array)
 }
-
-/// Capture the stack from the current given stack pointer until the start of the stack into the given stack memory region.
-/// The captured stack will be the smallest of the sizes of the current stack size or the memory region size.
-///
-/// If the memory region is too small, it will contain the top stack space and miss the bottom stack space.
-/// This is done because the top of the stack is often more interesting than the bottom.
-fn capture_stack<const SIZE: usize>(stack_pointer: u32, stack: &mut ArrayMemoryRegion<SIZE>) {
-    extern "C" {
-        static mut _stack_start: core::ffi::c_void;
-    }
-
-    /// Get the start address of the stack. The stack grows to lower addresses,
-    /// so this should be the highest stack address you can get.
-    fn stack_start() -> u32 {
-        unsafe { &_stack_start as *const _ as u32 }
-    }
-
-    let stack_size = stack_start().saturating_sub(stack_pointer).min(SIZE as u32);
-    unsafe {
-        stack.copy_from_memory(stack_pointer as *const u8, stack_size as usize);
-    }
-}
@@ -4,4 +4,9 @@
 
 #[cfg(any(cortex_m, doc, test))]
 pub mod cortex_m;
+pub mod memory;
+pub mod peripheral;
+#[cfg(feature = "semihosting")]
+pub mod semihosting;
+pub mod target;
 pub use stackdump_core as core;
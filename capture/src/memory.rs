@@ -0,0 +1,67 @@
+//! Capture functions for other memory than the stack, like a heap
+
+use arrayvec::CapacityError;
+use core::ops::Range;
+use stackdump_core::memory_region::ArrayMemoryRegion;
+
+/// Capture a block of memory, like a heap arena or a buffer.
+///
+/// The stack often only has pointers to the interesting data, e.g. the elements of a `Vec` or the text of a `String`.
+/// The trace can only show that data when the memory it's in has been captured too.
+/// Send the bytes of the region along with the stack and the registers. The tracer adds every region it finds to the device memory.
+///
+/// If the range is longer than the capacity of the region, then an error is returned and the region is left untouched.
+/// Use multiple regions to capture a block that is too big for one, or capture only the start of it.
+///
+/// ```rust,ignore
+/// use stackdump_capture::core::memory_region::ArrayMemoryRegion;
+///
+/// extern "C" {
+///     static __sheap: u8;
+/// }
+///
+/// // Capture the first 1 KiB of the heap
+/// let mut heap_capture = ArrayMemoryRegion::<1024>::default();
+/// let heap_start = unsafe { core::ptr::addr_of!(__sheap) as u64 };
+/// unsafe { stackdump_capture::memory::capture_memory(&mut heap_capture, heap_start..heap_start + 1024).unwrap() };
+/// ```
+///
+/// ## Safety
+///
+/// The entire block of memory in the range must be readable.
+/// (A memcpy must be possible with the start of the range as source)
+pub unsafe fn capture_memory<const SIZE: usize>(
+    region: &mut ArrayMemoryRegion<SIZE>,
+    range: Range<u64>,
+) -> Result<(), CapacityError> {
+    let length = range.end.saturating_sub(range.start);
+    if length > SIZE as u64 {
+        return Err(CapacityError::new(()));
+    }
+
+    region.try_copy_from_memory(range.start as usize as *const u8, length as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static HEAP: [u8; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+
+    #[test]
+    fn capture_memory() {
+        let heap_start = HEAP.as_ptr() as u64;
+
+        let mut region = ArrayMemoryRegion::<4>::default();
+        unsafe { super::capture_memory(&mut region, heap_start + 2..heap_start + 6).unwrap() };
+        assert_eq!(
+            region,
+            ArrayMemoryRegion::new(heap_start + 2, [3, 4, 5, 6].into_iter().collect())
+        );
+
+        // Too big for the region, so it is left as it was
+        let previous_region = region.clone();
+        assert!(unsafe { super::capture_memory(&mut region, heap_start..heap_start + 8) }.is_err());
+        assert_eq!(region, previous_region);
+    }
+}
@@ -0,0 +1,74 @@
+//! Capture functions for memory mapped peripherals
+
+use arrayvec::{ArrayVec, CapacityError};
+use stackdump_core::memory_region::{ArrayMemoryRegion, MemoryRegionKind};
+
+/// Capture a block of memory mapped peripheral registers.
+///
+/// The registers are read with volatile 32-bit reads, because peripherals often don't support other accesses.
+/// The captured region is marked as [MemoryRegionKind::Peripheral] so that the tracer doesn't decode it as program data.
+///
+/// If the length is greater than the capacity of the region, then an error is returned and the region is left untouched.
+///
+/// ```rust,ignore
+/// use stackdump_capture::core::memory_region::ArrayMemoryRegion;
+///
+/// // Capture the first 64 bytes of the UARTE0 peripheral of the nRF52840
+/// let mut uarte0 = ArrayMemoryRegion::<64>::default();
+/// unsafe { stackdump_capture::peripheral::capture_peripheral(&mut uarte0, 0x4000_2000, 64).unwrap() };
+/// ```
+///
+/// ## Safety
+///
+/// The entire block of memory from `base .. base + len` must be readable with 32-bit volatile reads
+/// and reading it must not have side effects that the program doesn't expect (like clearing a status register).
+/// The base address and the length must be a multiple of 4.
+pub unsafe fn capture_peripheral<const SIZE: usize>(
+    region: &mut ArrayMemoryRegion<SIZE>,
+    base: usize,
+    len: usize,
+) -> Result<(), CapacityError> {
+    if len > SIZE {
+        return Err(CapacityError::new(()));
+    }
+
+    let mut data = ArrayVec::new();
+
+    for word_address in (base..base + len).step_by(4) {
+        let word = core::ptr::read_volatile(word_address as *const u32);
+        // The region contains the bytes as they are in memory, so we use the native endianness
+        data.try_extend_from_slice(&word.to_ne_bytes())?;
+    }
+
+    *region = ArrayMemoryRegion::new(base as u64, data).with_kind(MemoryRegionKind::Peripheral);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static REGISTERS: [u32; 2] = [0x0403_0201, 0x0807_0605];
+
+    #[test]
+    fn capture_peripheral() {
+        let base = REGISTERS.as_ptr() as usize;
+
+        let mut region = ArrayMemoryRegion::<8>::default();
+        unsafe { super::capture_peripheral(&mut region, base, 8).unwrap() };
+        assert_eq!(
+            region,
+            ArrayMemoryRegion::new(
+                base as u64,
+                REGISTERS.iter().flat_map(|r| r.to_ne_bytes()).collect()
+            )
+            .with_kind(MemoryRegionKind::Peripheral)
+        );
+
+        // The length fits, but the last word doesn't, so the region is left as it was
+        let mut region = ArrayMemoryRegion::<6>::default();
+        assert!(unsafe { super::capture_peripheral(&mut region, base, 6) }.is_err());
+        assert_eq!(region, ArrayMemoryRegion::default());
+    }
+}
@@ -0,0 +1,198 @@
+//! Writing the captured data to a file on the host with ARM semihosting
+//!
+//! This is an alternative to sending the data over RTT or a serial port, for boards that don't have those.
+//! Semihosting only works while a debugger that supports it is attached, like OpenOCD or pyOCD.
+//! Without one, the semihosting breakpoint causes a hard fault.
+
+use arrayvec::ArrayVec;
+
+/// The semihosting operation that opens a file
+const SYS_OPEN: usize = 0x01;
+/// The semihosting operation that closes a file
+const SYS_CLOSE: usize = 0x02;
+/// The semihosting operation that writes to a file
+const SYS_WRITE: usize = 0x05;
+/// The mode of [SYS_OPEN] that opens a file for writing in binary, like `fopen` with "wb"
+const MODE_WRITE_BINARY: usize = 5;
+/// The size of the buffer on the stack in which the bytes are gathered before they are written
+const BUFFER_SIZE: usize = 64;
+
+/// An error that can occur when writing to a file on the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SemihostingError {
+    /// The path doesn't end with a nul byte
+    InvalidPath,
+    /// The host could not open the file
+    OpenFailed,
+    /// The host did not write all bytes to the file
+    WriteFailed {
+        /// The amount of bytes that have been written
+        written: usize,
+    },
+    /// The host could not close the file, so the bytes might not all be in it
+    CloseFailed,
+}
+
+impl core::fmt::Display for SemihostingError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::InvalidPath => write!(f, "The path must end with a nul byte"),
+            Self::OpenFailed => write!(f, "The host could not open the file"),
+            Self::WriteFailed { written } => write!(
+                f,
+                "The host could not write all bytes to the file. {} bytes have been written",
+                written
+            ),
+            Self::CloseFailed => write!(f, "The host could not close the file"),
+        }
+    }
+}
+
+/// Writes the bytes to a file on the host with ARM semihosting.
+///
+/// The path must end with a nul byte, like `b"stackdump.bin\0"`. A relative path is relative to the working directory of the debugger.
+/// The file is created, or emptied when it already exists.
+/// Chain the bytes of everything that has been captured, so it all lands in the same file. The tracer reads all of it from the file.
+///
+/// Nothing is allocated. The bytes are written in chunks from a small buffer on the stack, so this can be used in a `#[panic_handler]`.
+///
+/// ```rust,ignore
+/// let bytes = core_registers.bytes().chain(stack_capture.bytes());
+/// stackdump_capture::semihosting::write_semihosting(b"stackdump.bin\0", bytes).ok();
+/// ```
+pub fn write_semihosting(
+    path: &[u8],
+    bytes: impl IntoIterator<Item = u8>,
+) -> Result<(), SemihostingError> {
+    if path.last() != Some(&0) {
+        return Err(SemihostingError::InvalidPath);
+    }
+
+    // The length of the path is without the nul byte
+    let handle = unsafe {
+        semihosting_call(
+            SYS_OPEN,
+            &[path.as_ptr() as usize, MODE_WRITE_BINARY, path.len() - 1],
+        )
+    };
+    if handle == -1 {
+        return Err(SemihostingError::OpenFailed);
+    }
+
+    let write_result = write_chunks(bytes, |chunk| {
+        // The result is the amount of bytes that have not been written
+        let not_written = unsafe {
+            semihosting_call(
+                SYS_WRITE,
+                &[handle as usize, chunk.as_ptr() as usize, chunk.len()],
+            )
+        };
+        chunk.len().saturating_sub(not_written as usize)
+    });
+
+    // The file is closed even when writing failed, so the host doesn't keep it open
+    let close_result = unsafe { semihosting_call(SYS_CLOSE, &[handle as usize]) };
+
+    write_result?;
+    if close_result != 0 {
+        return Err(SemihostingError::CloseFailed);
+    }
+
+    Ok(())
+}
+
+/// Gathers the bytes in chunks of at most [BUFFER_SIZE] and writes them.
+/// The write function returns how many bytes of the chunk it has written.
+fn write_chunks(
+    bytes: impl IntoIterator<Item = u8>,
+    mut write: impl FnMut(&[u8]) -> usize,
+) -> Result<(), SemihostingError> {
+    let mut buffer = ArrayVec::<u8, BUFFER_SIZE>::new();
+    let mut written = 0;
+
+    let mut write_buffer = |buffer: &mut ArrayVec<u8, BUFFER_SIZE>| {
+        let chunk_written = write(buffer);
+        written += chunk_written;
+        if chunk_written != buffer.len() {
+            return Err(SemihostingError::WriteFailed { written });
+        }
+        buffer.clear();
+        Ok(())
+    };
+
+    for byte in bytes {
+        if buffer.is_full() {
+            write_buffer(&mut buffer)?;
+        }
+        buffer.push(byte);
+    }
+
+    if !buffer.is_empty() {
+        write_buffer(&mut buffer)?;
+    }
+
+    Ok(())
+}
+
+/// Does the semihosting operation with the block of parameters and returns its result
+#[allow(unused_variables)]
+unsafe fn semihosting_call(operation: usize, parameters: &[usize]) -> isize {
+    #[cfg(cortex_m)]
+    {
+        let mut result = operation;
+        core::arch::asm!(
+            "bkpt #0xab",
+            inout("r0") result,
+            in("r1") parameters.as_ptr(),
+            options(nostack, preserves_flags),
+        );
+        result as isize
+    }
+
+    // Semihosting is only implemented for cortex-m
+    #[cfg(not(cortex_m))]
+    {
+        -1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_in_chunks() {
+        let mut chunk_lengths = ArrayVec::<usize, 4>::new();
+        let mut written_bytes = ArrayVec::<u8, 150>::new();
+        let result = write_chunks(0..150u8, |chunk| {
+            chunk_lengths.push(chunk.len());
+            written_bytes.try_extend_from_slice(chunk).unwrap();
+            chunk.len()
+        });
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(chunk_lengths.as_slice(), [64, 64, 22]);
+        assert!(written_bytes.iter().copied().eq(0..150u8));
+    }
+
+    #[test]
+    fn write_failed() {
+        // The host stops writing in the middle of the second chunk
+        let mut capacity = 100;
+        let result = write_chunks(0..150u8, |chunk| {
+            let written = chunk.len().min(capacity);
+            capacity -= written;
+            written
+        });
+
+        assert_eq!(result, Err(SemihostingError::WriteFailed { written: 100 }));
+    }
+
+    #[test]
+    fn invalid_path() {
+        assert_eq!(
+            write_semihosting(b"stackdump.bin", [1, 2, 3]),
+            Err(SemihostingError::InvalidPath)
+        );
+    }
+}
@@ -0,0 +1,174 @@
+//! A generic capture for targets that don't have a capture module in this crate
+//!
+//! To add a target, implement [Target] for it. That is only the architecture specific part:
+//! - [Target::capture_registers]: Read the current registers into a register container
+//! - [Target::STACK_POINTER]: The DWARF register number of the stack pointer
+//! - [Target::stack_start]: The address the stack starts at
+//!
+//! The [capture] function then captures the registers and the stack.
+//! If the lowest address of the stack is known too, use [capture_in_stack_range].
+//! The captured data uses the formats of `stackdump-core`, so it is serialized the same way as
+//! the data of the built-in targets. To trace it, the `stackdump-trace` crate needs a `Platform` implementation
+//! for the same architecture.
+
+use core::ops::Range;
+use funty::Fundamental;
+use stackdump_core::{gimli, memory_region::ArrayMemoryRegion, register_data::RegisterData};
+
+/// An architecture of which the registers and the stack can be captured
+///
+/// It is assumed that the stack grows down, to lower addresses.
+pub trait Target {
+    /// The type of a single register
+    type Word: funty::Integral;
+    /// The container the registers are captured into, e.g. an `ArrayRegisterData`
+    type Registers: RegisterData<Self::Word>;
+
+    /// The DWARF register number of the stack pointer. It must be part of the captured [Self::Registers].
+    const STACK_POINTER: gimli::Register;
+
+    /// Capture the current values of the registers
+    fn capture_registers(registers: &mut Self::Registers);
+
+    /// Get the start address of the stack. The stack grows to lower addresses,
+    /// so this should be the highest stack address you can get.
+    fn stack_start() -> u64;
+}
+
+/// Capture the registers and the stack of the target
+///
+/// The stack is assumed to be able to grow down to address 0.
+/// Returns false if the stack could not be captured, see [capture_in_stack_range].
+pub fn capture<T: Target, const SIZE: usize>(
+    stack: &mut ArrayMemoryRegion<SIZE>,
+    registers: &mut T::Registers,
+) -> bool {
+    capture_in_stack_range::<T, SIZE>(stack, registers, 0..T::stack_start())
+}
+
+/// Capture the registers and the stack of the target, where the stack lies in the given address range.
+/// For a stack that grows down, this is from the lowest address it can grow to until [Target::stack_start],
+/// like `_stack_end.._stack_start` of the linker script.
+///
+/// Returns false if the stack could not be captured because the stack pointer is not in the range.
+/// The stack capture is empty then and starts at the stack pointer, so the tracer can report where it was.
+pub fn capture_in_stack_range<T: Target, const SIZE: usize>(
+    stack: &mut ArrayMemoryRegion<SIZE>,
+    registers: &mut T::Registers,
+    stack_range: Range<u64>,
+) -> bool {
+    T::capture_registers(registers);
+
+    // Without a stack pointer we don't know where the stack is, so it stays empty
+    match registers.register(T::STACK_POINTER) {
+        Some(stack_pointer) => capture_stack::<SIZE>(stack_pointer.as_u64(), stack_range, stack),
+        None => false,
+    }
+}
+
+/// Capture the stack from the current given stack pointer until the start of the stack into the given stack memory region.
+/// The captured stack will be the smallest of the sizes of the current stack size or the memory region size.
+///
+/// If the memory region is too small, it will contain the top stack space and miss the bottom stack space.
+/// This is done because the top of the stack is often more interesting than the bottom.
+///
+/// A stack pointer outside of the stack range is garbage, e.g. during startup.
+/// Nothing is read then, because it could be any amount of memory anywhere.
+pub(crate) fn capture_stack<const SIZE: usize>(
+    stack_pointer: u64,
+    stack_range: Range<u64>,
+    stack: &mut ArrayMemoryRegion<SIZE>,
+) -> bool {
+    // The stack pointer is at the end of the range when the stack is empty
+    if stack_pointer < stack_range.start || stack_pointer > stack_range.end {
+        *stack = ArrayMemoryRegion::new(stack_pointer, Default::default());
+        return false;
+    }
+
+    let stack_size = (stack_range.end - stack_pointer).min(SIZE as u64);
+    // The size is clamped to the capacity, so this should never fail.
+    // But if it does, we'd rather have an empty stack capture than a panic in the fault handler.
+    unsafe {
+        stack
+            .try_copy_from_memory(stack_pointer as usize as *const u8, stack_size as usize)
+            .is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stackdump_core::register_data::ArrayRegisterData;
+
+    static STACK: [u8; 16] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16];
+
+    /// A target with two registers of which the second one is the stack pointer.
+    /// It pretends to be halfway the stack.
+    struct StubTarget;
+
+    impl Target for StubTarget {
+        type Word = u64;
+        type Registers = ArrayRegisterData<2, u64>;
+
+        const STACK_POINTER: gimli::Register = gimli::Register(1);
+
+        fn capture_registers(registers: &mut Self::Registers) {
+            *registers = ArrayRegisterData::new(
+                gimli::Register(0),
+                [0x1234, STACK.as_ptr() as u64 + 8].into(),
+            );
+        }
+
+        fn stack_start() -> u64 {
+            STACK.as_ptr() as u64 + STACK.len() as u64
+        }
+    }
+
+    #[test]
+    fn stub_target() {
+        let mut stack = ArrayMemoryRegion::<32>::default();
+        let mut registers = ArrayRegisterData::default();
+
+        assert!(capture::<StubTarget, 32>(&mut stack, &mut registers));
+
+        assert_eq!(registers.register(gimli::Register(0)), Some(0x1234));
+        assert_eq!(
+            stack,
+            ArrayMemoryRegion::new(
+                STACK.as_ptr() as u64 + 8,
+                [9, 10, 11, 12, 13, 14, 15, 16].into_iter().collect()
+            )
+        );
+    }
+
+    #[test]
+    fn stack_pointer_outside_of_stack_range() {
+        let mut stack = ArrayMemoryRegion::<32>::default();
+        let mut registers = ArrayRegisterData::default();
+
+        // The stack pointer is halfway the stack, so a range that ends below that doesn't contain it
+        let stack_start = StubTarget::stack_start();
+        assert!(!capture_in_stack_range::<StubTarget, 32>(
+            &mut stack,
+            &mut registers,
+            stack_start - 16..stack_start - 12,
+        ));
+        assert_eq!(
+            stack,
+            ArrayMemoryRegion::new(stack_start - 8, Default::default())
+        );
+
+        assert!(capture_in_stack_range::<StubTarget, 32>(
+            &mut stack,
+            &mut registers,
+            stack_start - 16..stack_start,
+        ));
+        assert_eq!(
+            stack,
+            ArrayMemoryRegion::new(
+                stack_start - 8,
+                [9, 10, 11, 12, 13, 14, 15, 16].into_iter().collect()
+            )
+        );
+    }
+}
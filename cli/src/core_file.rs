@@ -0,0 +1,255 @@
+//! Writing the captured memory and registers to an ELF core file, so the same snapshot can be opened with gdb
+
+use crate::Arguments;
+use object::{
+    elf,
+    write::elf::{FileHeader, ProgramHeader, Writer},
+    Endianness,
+};
+use stackdump_trace::stackdump_core::{device_memory::DeviceMemory, gimli};
+use std::{error::Error, path::Path};
+
+/// The size of the `elf_prstatus` struct of 32-bit ARM linux, which is what gdb expects in the `NT_PRSTATUS` note
+const PRSTATUS_SIZE: usize = 148;
+/// The offset of `pr_reg` in the `elf_prstatus` struct.
+/// It holds r0 - r15, the cpsr and the orig_r0.
+const PRSTATUS_REGISTERS_OFFSET: usize = 72;
+/// The size of the `NT_ARM_VFP` note: d0 - d31 and the fpscr
+const VFP_SIZE: usize = 32 * 8 + 4;
+/// The thumb bit of the xPSR. It's not captured, but a Cortex-M always runs thumb code.
+const XPSR_THUMB: u32 = 1 << 24;
+
+/// Writes the core file if `--emit-core` is given.
+///
+/// This has to be done before tracing, because the unwinding changes the registers.
+pub(crate) fn emit_core_file(
+    device_memory: &DeviceMemory<u32>,
+    args: &Arguments,
+) -> Result<(), Box<dyn Error>> {
+    match &args.emit_core {
+        Some(path) => write_core_file(path, device_memory),
+        None => Ok(()),
+    }
+}
+
+/// Writes an `ET_CORE` elf file with a `PT_LOAD` segment for every captured memory range
+/// and a note with the core registers (and one with the fpu registers if they were captured).
+///
+/// Memory that is read lazily, like from a probe, is not captured and so not part of the core file.
+fn write_core_file(path: &Path, device_memory: &DeviceMemory<u32>) -> Result<(), Box<dyn Error>> {
+    std::fs::write(path, core_file(device_memory)?)?;
+
+    Ok(())
+}
+
+/// Creates the contents of the core file, see [write_core_file]
+fn core_file(device_memory: &DeviceMemory<u32>) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut segments = Vec::new();
+    for range in device_memory.captured_ranges() {
+        segments.push((range.start, read_captured_range(device_memory, range)?));
+    }
+
+    let mut notes = Vec::new();
+    write_note(
+        &mut notes,
+        b"CORE",
+        elf::NT_PRSTATUS,
+        &prstatus(device_memory),
+    );
+    if let Some(vfp) = vfp(device_memory) {
+        write_note(&mut notes, b"LINUX", elf::NT_ARM_VFP, &vfp);
+    }
+
+    let mut buffer = Vec::new();
+    let mut writer = Writer::new(Endianness::Little, false, &mut buffer);
+
+    writer.reserve_file_header();
+    writer.reserve_program_headers(1 + segments.len() as u32);
+    let notes_offset = writer.reserve(notes.len(), 4);
+    let segment_offsets = segments
+        .iter()
+        .map(|(_, data)| writer.reserve(data.len(), 4))
+        .collect::<Vec<_>>();
+
+    writer.write_file_header(&FileHeader {
+        os_abi: elf::ELFOSABI_NONE,
+        abi_version: 0,
+        e_type: elf::ET_CORE,
+        e_machine: elf::EM_ARM,
+        e_entry: 0,
+        e_flags: 0,
+    })?;
+
+    writer.write_align_program_headers();
+    writer.write_program_header(&ProgramHeader {
+        p_type: elf::PT_NOTE,
+        p_flags: 0,
+        p_offset: notes_offset as u64,
+        p_vaddr: 0,
+        p_paddr: 0,
+        p_filesz: notes.len() as u64,
+        p_memsz: 0,
+        p_align: 4,
+    });
+    for ((address, data), offset) in segments.iter().zip(&segment_offsets) {
+        writer.write_program_header(&ProgramHeader {
+            p_type: elf::PT_LOAD,
+            p_flags: elf::PF_R | elf::PF_W | elf::PF_X,
+            p_offset: *offset as u64,
+            p_vaddr: *address,
+            p_paddr: *address,
+            p_filesz: data.len() as u64,
+            p_memsz: data.len() as u64,
+            p_align: 4,
+        });
+    }
+
+    writer.write_align(4);
+    writer.write(&notes);
+    for (_, data) in &segments {
+        writer.write_align(4);
+        writer.write(data);
+    }
+
+    Ok(buffer)
+}
+
+/// Reads a captured range. The range can be made up of multiple touching regions, which can't be read in one go.
+fn read_captured_range(
+    device_memory: &DeviceMemory<u32>,
+    range: std::ops::Range<u64>,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    if let Some(data) = device_memory.read_slice(range.clone())? {
+        return Ok(data);
+    }
+
+    range
+        .map(|address| {
+            device_memory.read_u8(address)?.ok_or_else(|| {
+                format!("The captured memory at {address:#010X} can't be read").into()
+            })
+        })
+        .collect()
+}
+
+/// Creates the `elf_prstatus` struct. Only the registers are filled in, because the rest is about linux processes.
+fn prstatus(device_memory: &DeviceMemory<u32>) -> Vec<u8> {
+    let mut prstatus = vec![0; PRSTATUS_SIZE];
+
+    let registers = (gimli::Arm::R0.0..=gimli::Arm::R15.0)
+        .map(|register| {
+            device_memory
+                .register(gimli::Register(register))
+                .unwrap_or(0)
+        })
+        .chain([XPSR_THUMB]);
+
+    for (index, value) in registers.enumerate() {
+        let offset = PRSTATUS_REGISTERS_OFFSET + index * 4;
+        prstatus[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    prstatus
+}
+
+/// Creates the contents of the `NT_ARM_VFP` note if the fpu registers were captured.
+/// The s registers are the lower halves of the d registers, so s0 - s31 fill d0 - d15.
+fn vfp(device_memory: &DeviceMemory<u32>) -> Option<Vec<u8>> {
+    device_memory.register(gimli::Arm::S0).ok()?;
+
+    let mut vfp = vec![0; VFP_SIZE];
+    for index in 0..32 {
+        let value = device_memory
+            .register(gimli::Register(gimli::Arm::S0.0 + index))
+            .unwrap_or(0);
+        let offset = index as usize * 4;
+        vfp[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    Some(vfp)
+}
+
+/// Appends an elf note. The name and the descriptor are both padded to 4 bytes.
+fn write_note(notes: &mut Vec<u8>, name: &[u8], note_type: u32, descriptor: &[u8]) {
+    notes.extend_from_slice(&(name.len() as u32 + 1).to_le_bytes());
+    notes.extend_from_slice(&(descriptor.len() as u32).to_le_bytes());
+    notes.extend_from_slice(&note_type.to_le_bytes());
+
+    notes.extend_from_slice(name);
+    notes.push(0);
+    notes.resize((notes.len() + 3) & !3, 0);
+
+    notes.extend_from_slice(descriptor);
+    notes.resize((notes.len() + 3) & !3, 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object::read::elf::{ElfFile32, FileHeader as _, ProgramHeader as _};
+    use stackdump_trace::stackdump_core::{
+        memory_region::VecMemoryRegion, register_data::VecRegisterData,
+    };
+
+    #[test]
+    fn core_file_round_trip() {
+        let mut device_memory = DeviceMemory::new();
+        device_memory.add_memory_region(VecMemoryRegion::new(0x2000_0000, vec![1, 2, 3, 4]));
+        // Touching the region above, so it's written as one segment
+        device_memory.add_memory_region(VecMemoryRegion::new(0x2000_0004, vec![5, 6, 7, 8]));
+        device_memory.add_memory_region(VecMemoryRegion::new(0x2000_1000, vec![9; 16]));
+        device_memory.add_register_data(VecRegisterData::new(
+            gimli::Arm::R0,
+            (0..16).map(|register| 0x100 + register).collect(),
+        ));
+
+        let data = core_file(&device_memory).unwrap();
+        let elf = ElfFile32::<Endianness>::parse(&*data).unwrap();
+        let endian = elf.endian();
+        assert_eq!(elf.raw_header().e_type(endian), elf::ET_CORE);
+        assert_eq!(elf.raw_header().e_machine(endian), elf::EM_ARM);
+
+        let program_headers = elf.raw_segments();
+        assert_eq!(program_headers.len(), 3);
+
+        let load_segments = program_headers
+            .iter()
+            .filter(|header| header.p_type(endian) == elf::PT_LOAD)
+            .map(|header| {
+                (
+                    header.p_vaddr(endian),
+                    header.p_memsz(endian),
+                    header.data(endian, &*data).unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            load_segments,
+            [
+                (0x2000_0000, 8, &[1, 2, 3, 4, 5, 6, 7, 8][..]),
+                (0x2000_1000, 16, &[9; 16][..]),
+            ]
+        );
+
+        let mut notes = program_headers[0].notes(endian, &*data).unwrap().unwrap();
+        let note = notes.next().unwrap().unwrap();
+        assert_eq!(note.name(), b"CORE");
+        assert_eq!(note.n_type(endian), elf::NT_PRSTATUS);
+
+        let descriptor = note.desc();
+        assert_eq!(descriptor.len(), PRSTATUS_SIZE);
+        let registers = descriptor[PRSTATUS_REGISTERS_OFFSET..]
+            .chunks_exact(4)
+            .take(17)
+            .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            registers[..16],
+            (0..16).map(|register| 0x100 + register).collect::<Vec<_>>()
+        );
+        assert_eq!(registers[16], XPSR_THUMB);
+
+        // The fpu registers weren't captured, so there's no note for them
+        assert!(notes.next().unwrap().is_none());
+    }
+}
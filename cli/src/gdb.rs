@@ -0,0 +1,46 @@
+use crate::Arguments;
+use stackdump_capture_gdb::StackdumpCapturer;
+use stackdump_trace::stackdump_core::{device_memory::DeviceMemory, memory_region::MemoryRegion};
+use std::{error::Error, path::Path};
+
+pub(crate) fn trace_gdb(
+    elf_file: &Path,
+    host: &str,
+    port: u16,
+    args: &Arguments,
+) -> Result<(), Box<dyn Error>> {
+    let elf_data = std::fs::read(elf_file)?;
+
+    let mut stackcapturer = StackdumpCapturer::connect((host, port))?;
+
+    // The target stays halted until we detach, so that's done even when the trace fails
+    let trace_result = trace_target(&mut stackcapturer, &elf_data, args);
+    let detach_result = stackcapturer.detach();
+
+    trace_result?;
+    detach_result?;
+
+    Ok(())
+}
+
+/// Captures the registers of the halted target and traces it
+fn trace_target(
+    stackcapturer: &mut StackdumpCapturer,
+    elf_data: &[u8],
+    args: &Arguments,
+) -> Result<(), Box<dyn Error>> {
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_register_data(stackcapturer.capture_core_registers()?);
+
+    // Read the memory lazily from the target for anything that isn't available in the elf file
+    let memory_capturer = &*stackcapturer;
+    device_memory.set_memory_fallback(move |address_range| memory_capturer.read(address_range));
+
+    let registers = crate::captured_registers(&device_memory, args);
+    crate::core_file::emit_core_file(&device_memory, args)?;
+    let elf = object::File::parse(elf_data)?;
+    let frames = crate::trace(device_memory, &elf, args)?;
+    crate::output_frames(frames, &[], &registers, &elf, args)?;
+
+    Ok(())
+}
@@ -4,10 +4,17 @@ use std::{
 };
 
 use env_logger::fmt::{Color, Style, StyledValue};
-use log::Level;
+use log::{Level, LevelFilter};
 
-pub fn init_logger() {
-    env_logger::builder()
+/// Initializes the logger. When verbose, the debug messages of the trace are shown as well.
+pub fn init_logger(verbose: bool) {
+    let mut builder = env_logger::builder();
+
+    if verbose {
+        builder.filter_module("stackdump_trace", LevelFilter::Debug);
+    }
+
+    builder
         .format(|f, record| {
             use std::io::Write;
 
@@ -1,23 +1,32 @@
 #![doc = include_str!("../README.md")]
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use colored::Colorize;
+use gdb::trace_gdb;
 use probe::trace_probe;
 use probe_rs::DebugProbeSelector;
+use regex::Regex;
 use stackdump_trace::{
-    platform::cortex_m::CortexMPlatform,
+    platform::{cortex_m::CortexMPlatform, Platform as _},
     render_colors::Theme,
     stackdump_core::{
-        device_memory::DeviceMemory,
-        memory_region::{VecMemoryRegion, MEMORY_REGION_IDENTIFIER},
-        register_data::{VecRegisterData, REGISTER_DATA_IDENTIFIER},
+        device_memory::{DeviceMemory, EvaluationStep},
+        dump::{add_dump_file_to_device_memory, Dump},
+        dump_meta::DumpMeta,
+        gimli,
     },
+    type_value_tree::rendering::{IntRadix, RenderOptions},
+    DecodeOptions, DemangleMode, FrameDisplayOptions, TraceTree,
 };
 use std::{
     error::Error,
+    fs::File,
+    io::{BufWriter, Read, Write},
     path::{Path, PathBuf},
 };
 
+mod core_file;
+mod gdb;
 mod logging;
 mod probe;
 
@@ -47,17 +56,150 @@ struct Arguments {
         default_value_t = Theme::Dark,
     )]
     theme: Theme,
+    #[clap(
+        long,
+        visible_alias = "radix",
+        help = "The radix integers are printed in: dec, hex or bin. With dec, integers of types with 'flag' or 'mask' in the name are printed in hex",
+        default_value_t = IntRadix::Dec,
+    )]
+    int_radix: IntRadix,
+    #[clap(
+        long,
+        help = "How the function names are demangled: auto, raw, rust or cpp. With auto, the language of the function decides. Use raw to get the symbols as they are in e.g. a linker map",
+        default_value_t = DemangleMode::Auto,
+    )]
+    demangle: DemangleMode,
+    #[clap(
+        long,
+        help = "The name of the section with the vector table. By default .vector_table, .isr_vector and .vectors are tried"
+    )]
+    vector_table_section: Option<String>,
+    #[clap(
+        long,
+        value_parser = parse_address,
+        help = "The base address of the thread local storage of the traced thread, like 0x20001000. Without it, thread local variables can't be read"
+    )]
+    tls_base: Option<u32>,
+    #[clap(
+        long,
+        help = "How deep the fields of structs and the elements of arrays are printed. Deeper ones are printed as { … } and […]"
+    )]
+    max_depth: Option<usize>,
+    #[clap(
+        long,
+        help = "How many elements of an array are printed. The rest is summarized like [1, 2, 3, … (+4093 more)]"
+    )]
+    max_array_elements: Option<usize>,
+    #[clap(
+        long,
+        help = "Print the bytes that values were decoded from after them. The bytes of values that could not be decoded are always printed"
+    )]
+    raw: bool,
+    #[clap(long, help = "Only print the frame with the given index")]
+    frame: Option<usize>,
+    #[clap(long, help = "Print the captured registers before the frames")]
+    show_registers: bool,
+    #[clap(
+        long,
+        help = "Print the registers of every frame. Registers of which the value is unknown in a frame are left out"
+    )]
+    show_frame_registers: bool,
+    #[clap(
+        long,
+        help = "Print how many bytes of stack every function in the trace uses and the total"
+    )]
+    stack_usage: bool,
+    #[clap(
+        long,
+        help = "Only print the frames of which the function name contains the given text"
+    )]
+    function: Option<String>,
+    #[clap(
+        long,
+        help = "Only keep the frames of which the function name matches the given regex. The static frame is kept as well. Frames are numbered after filtering"
+    )]
+    filter: Option<Regex>,
+    #[clap(
+        long,
+        requires = "filter",
+        help = "Also keep the caller of every frame that matches the --filter"
+    )]
+    filter_with_caller: bool,
+    #[clap(long, help = "Don't print the static frame with the global variables")]
+    no_static: bool,
+    #[clap(
+        long,
+        value_enum,
+        help = "The format of the output. Flamegraph is JSON that can be opened with flamegraph viewers like d3-flame-graph. Json is a list of the frames with their variables as trees of values",
+        default_value_t = OutputFormat::Text,
+    )]
+    format: OutputFormat,
+    #[clap(
+        short = 'o',
+        long,
+        help = "Write the traced frames to the given file instead of to stdout"
+    )]
+    output: Option<PathBuf>,
+    #[clap(
+        long,
+        help = "Write the captured memory and registers to an ELF core file, so they can be opened with gdb together with the elf file. Memory that is read from a probe or gdb stub while tracing is not included"
+    )]
+    emit_core: Option<PathBuf>,
+    #[clap(
+        short = 'q',
+        long,
+        help = "Don't print any log messages and timing information"
+    )]
+    quiet: bool,
+    #[clap(
+        short = 'v',
+        long,
+        help = "Print the debug log messages of the trace and show the variables that could not be decoded with the reason"
+    )]
+    verbose: bool,
+    #[clap(
+        short = 'r',
+        long,
+        help = "Print a run of equal frames, like those of a deep recursion, only once with the amount of times it is repeated"
+    )]
+    collapse_recursion: bool,
+    #[clap(
+        long,
+        requires = "path_prefix_to",
+        help = "A path prefix in the debug info that should be replaced, like the directory the program was built in"
+    )]
+    path_prefix_from: Option<String>,
+    #[clap(
+        long,
+        requires = "path_prefix_from",
+        help = "The path that replaces the prefix given with --path-prefix-from, like the local directory of the source code"
+    )]
+    path_prefix_to: Option<PathBuf>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    Text,
+    Flamegraph,
+    Json,
 }
 
 #[derive(Subcommand, Debug)]
 enum Platform {
     #[clap(about = "Trace from files using Cortex-M as the target")]
     CortexM {
-        #[clap(help = "Path to the elf file with debug info")]
+        #[clap(
+            help = "Path to the elf file with debug info, or to a directory of elf files when --elf-from-dump is used"
+        )]
         elf_file: PathBuf,
+        #[clap(
+            long,
+            help = "Find the elf file in the directory given as elf file that has the same build id as the dump"
+        )]
+        elf_from_dump: bool,
         #[clap(
             num_args = 1..,
-            help = "The memory dumps. Must be in the format of the byte iterator in the core crate. Multiple dumps can be put into the file."
+            help = "The memory dumps. Must be in the format of the byte iterator in the core crate. Multiple dumps can be put into the file. Use - to read a dump from stdin."
         )]
         dumps: Vec<PathBuf>,
     },
@@ -76,32 +218,62 @@ enum Platform {
         #[clap(long = "core", help = "The core to trace (default is core 0)")]
         core: Option<usize>,
     },
+    #[clap(
+        about = "Trace a Cortex-M target by capturing the data over a gdb remote connection, like the gdbstub of QEMU"
+    )]
+    Gdb {
+        #[clap(help = "Path to the elf file with debug info")]
+        elf_file: PathBuf,
+        #[clap(long, help = "The host of the gdb stub", default_value = "localhost")]
+        host: String,
+        #[clap(
+            long,
+            help = "The port of the gdb stub (QEMU uses 1234 with -s)",
+            default_value_t = 1234
+        )]
+        port: u16,
+    },
 }
 
 fn main() {
     let start = std::time::Instant::now();
 
-    match result_main() {
-        Ok(_) => {}
-        Err(e) => {
-            println!("Error: {e}");
-        }
+    let args = Arguments::parse();
+
+    if !args.quiet {
+        logging::init_logger(args.verbose);
     }
 
-    println!("\nDone in {:.03} seconds", start.elapsed().as_secs_f32());
-}
+    // Only the trace itself goes to the output. Everything else is diagnostic and goes to stderr
+    let result = result_main(&args);
 
-fn result_main() -> Result<(), Box<dyn Error>> {
-    logging::init_logger();
+    if let Err(e) = &result {
+        eprintln!("Error: {e}");
+    }
 
-    let args = Arguments::parse();
+    if !args.quiet {
+        eprintln!("\nDone in {:.03} seconds", start.elapsed().as_secs_f32());
+    }
 
+    if result.is_err() {
+        std::process::exit(1);
+    }
+}
+
+fn result_main(args: &Arguments) -> Result<(), Box<dyn Error>> {
     match &args.platform {
-        Platform::CortexM { elf_file, dumps } => {
-            let (elf_data, device_memory) = read_files_into_device_memory(elf_file, dumps)?;
-            let frames =
-                stackdump_trace::platform::trace::<CortexMPlatform>(device_memory, &elf_data)?;
-            print_frames(frames, &args);
+        Platform::CortexM {
+            elf_file,
+            elf_from_dump,
+            dumps,
+        } => {
+            let (elf_data, device_memory, metas) =
+                read_files_into_device_memory(elf_file, *elf_from_dump, dumps)?;
+            let registers = captured_registers(&device_memory, args);
+            core_file::emit_core_file(&device_memory, args)?;
+            let elf = object::File::parse(&*elf_data)?;
+            let frames = trace(device_memory, &elf, args)?;
+            output_frames(frames, &metas, &registers, &elf, args)?;
         }
         Platform::Probe {
             elf_file,
@@ -109,23 +281,261 @@ fn result_main() -> Result<(), Box<dyn Error>> {
             chip,
             core,
         } => {
-            trace_probe(elf_file, probe.clone(), chip.into(), *core, &args)?;
+            trace_probe(elf_file, probe.clone(), chip.into(), *core, args)?;
+        }
+        Platform::Gdb {
+            elf_file,
+            host,
+            port,
+        } => {
+            trace_gdb(elf_file, host, *port, args)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the core registers from the device memory if they are shown.
+///
+/// This has to be done before tracing, because the unwinding changes the registers.
+pub(crate) fn captured_registers(
+    device_memory: &DeviceMemory<u32>,
+    args: &Arguments,
+) -> Vec<(gimli::Register, u32)> {
+    if !args.show_registers {
+        return Vec::new();
+    }
+
+    (gimli::Arm::R0.0..=gimli::Arm::R15.0)
+        .map(gimli::Register)
+        .filter_map(|register| {
+            device_memory
+                .register(register)
+                .ok()
+                .map(|value| (register, value))
+        })
+        .collect()
+}
+
+/// Traces the device memory with the options that were selected in the arguments
+pub(crate) fn trace<'data>(
+    mut device_memory: DeviceMemory<u32>,
+    elf: &object::File<'data, &'data [u8]>,
+    args: &Arguments,
+) -> Result<Vec<stackdump_trace::Frame<u32>>, Box<dyn Error>> {
+    let options = DecodeOptions {
+        demangle_mode: args.demangle,
+        vector_table_section: args.vector_table_section.clone(),
+        ..Default::default()
+    };
+
+    if let Some(tls_base) = args.tls_base {
+        device_memory.set_evaluation_step_resolver(move |step, _| match step {
+            EvaluationStep::TlsBase => Some(tls_base as u64),
+            _ => None,
+        });
+    }
+
+    let (frames, _) = stackdump_trace::platform::trace_and_keep_memory::<CortexMPlatform>(
+        device_memory,
+        elf,
+        options,
+    )?;
+
+    Ok(frames)
+}
+
+/// Parses an address in hexadecimal with a `0x` prefix or in decimal
+fn parse_address(address: &str) -> Result<u32, std::num::ParseIntError> {
+    match address
+        .strip_prefix("0x")
+        .or_else(|| address.strip_prefix("0X"))
+    {
+        Some(hex_address) => u32::from_str_radix(&hex_address.replace('_', ""), 16),
+        None => address.replace('_', "").parse(),
+    }
+}
+
+/// Prints the meta data of the dumps, the registers, the frames and a summary to the output that was selected in the arguments
+pub(crate) fn output_frames(
+    mut frames: Vec<stackdump_trace::Frame<u32>>,
+    metas: &[DumpMeta],
+    registers: &[(gimli::Register, u32)],
+    elf: &object::File,
+    args: &Arguments,
+) -> Result<(), Box<dyn Error>> {
+    if let (Some(from), Some(to)) = (&args.path_prefix_from, &args.path_prefix_to) {
+        for frame in &mut frames {
+            frame.remap_path_prefix(from, to);
         }
     }
 
+    let stack_usage = stackdump_trace::platform::cortex_m::initial_stack_pointer(elf)
+        .ok()
+        .and_then(|initial_sp| stackdump_trace::stack_usage(&frames, initial_sp));
+
+    let frames = filter_frames(frames, args);
+
+    match &args.output {
+        Some(output) => {
+            let mut file = BufWriter::new(File::create(output)?);
+            print_frames(frames, metas, registers, stack_usage, args, &mut file)?;
+            file.flush()?;
+        }
+        None => print_frames(
+            frames,
+            metas,
+            registers,
+            stack_usage,
+            args,
+            &mut std::io::stdout().lock(),
+        )?,
+    }
+
     Ok(())
 }
 
-pub(crate) fn print_frames(frames: Vec<stackdump_trace::Frame<u32>>, args: &Arguments) {
-    for (i, frame) in frames.iter().enumerate() {
-        print!("{}: ", i);
+/// Removes the frames that don't match the `--filter` and the static frame if `--no-static` is given.
+///
+/// The `inlined_into` indices of the frames are updated to the indices in the returned list.
+/// When the frame an inlined frame was inlined into is removed, its `inlined_into` is cleared.
+fn filter_frames(
+    frames: Vec<stackdump_trace::Frame<u32>>,
+    args: &Arguments,
+) -> Vec<stackdump_trace::Frame<u32>> {
+    let matches = frames
+        .iter()
+        .map(|frame| match &args.filter {
+            Some(filter) => filter.is_match(&frame.function),
+            None => true,
+        })
+        .collect::<Vec<_>>();
+
+    let keep = frames
+        .iter()
+        .enumerate()
+        .map(|(index, frame)| match frame.frame_type {
+            stackdump_trace::FrameType::Static => !args.no_static,
+            // The caller of a frame is the next frame
+            _ => matches[index] || (args.filter_with_caller && index > 0 && matches[index - 1]),
+        })
+        .collect::<Vec<_>>();
+
+    // The index in the filtered frames for every original frame that is kept
+    let new_indices = keep
+        .iter()
+        .scan(0, |new_index, keep| {
+            if !*keep {
+                return Some(None);
+            }
+
+            *new_index += 1;
+            Some(Some(*new_index - 1))
+        })
+        .collect::<Vec<_>>();
+
+    frames
+        .into_iter()
+        .zip(&keep)
+        .filter(|(_, keep)| **keep)
+        .map(|(mut frame, _)| {
+            frame.inlined_into = frame
+                .inlined_into
+                .and_then(|index| new_indices.get(index).copied().flatten());
+            frame
+        })
+        .collect()
+}
+
+fn print_frames(
+    frames: Vec<stackdump_trace::Frame<u32>>,
+    metas: &[DumpMeta],
+    registers: &[(gimli::Register, u32)],
+    stack_usage: Option<u32>,
+    args: &Arguments,
+    output: &mut impl Write,
+) -> std::io::Result<()> {
+    let render_options = RenderOptions {
+        int_radix: args.int_radix,
+        max_depth: args.max_depth,
+        max_array_elements: args.max_array_elements,
+        show_raw: args.raw,
+        ..Default::default()
+    };
+
+    if args.format == OutputFormat::Json {
+        return writeln!(output, "{}", stackdump_trace::frames_to_json(&frames));
+    }
+
+    // The flamegraph is JSON, so the header goes to stderr to keep the output readable for the viewers
+    if args.format == OutputFormat::Flamegraph {
+        print_header(metas, registers, &mut std::io::stderr().lock())?;
+    } else {
+        print_header(metas, registers, output)?;
+    }
+
+    let frame_sizes = if args.stack_usage {
+        stackdump_trace::frame_sizes(&frames)
+            .into_iter()
+            .map(|(function, size)| (function.to_string(), size))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let frames = if args.collapse_recursion {
+        stackdump_trace::collapse_recursion(frames)
+    } else {
+        frames
+            .into_iter()
+            .map(|frame| stackdump_trace::CollapsedFrame { frame, count: 1 })
+            .collect()
+    };
+
+    // The frames that are selected with --frame and --function, with their indices in the full trace
+    let frames = frames
+        .into_iter()
+        .scan(0, |i, frame| {
+            let frame_indices = *i..*i + frame.count;
+            *i += frame.count;
+            Some((frame_indices, frame))
+        })
+        .filter(|(frame_indices, frame)| {
+            args.frame
+                .map_or(true, |index| frame_indices.contains(&index))
+                && args.function.as_ref().map_or(true, |function| {
+                    frame.frame.function.contains(function.as_str())
+                })
+        })
+        .collect::<Vec<_>>();
 
-        let frame_text = frame.display(
-            true,
-            args.show_inlined_variables,
-            args.show_zero_sized_variables,
-            args.theme,
+    if args.format == OutputFormat::Flamegraph {
+        let frames = frames
+            .into_iter()
+            .map(|(_, frame)| frame.frame)
+            .collect::<Vec<_>>();
+        return writeln!(
+            output,
+            "{}",
+            TraceTree::new(&frames, &render_options).to_json()
         );
+    }
+
+    let display_options = FrameDisplayOptions {
+        show_parameters: true,
+        show_inlined_vars: args.show_inlined_variables,
+        show_zero_sized_vars: args.show_zero_sized_variables,
+        show_omitted_vars: args.verbose,
+        show_registers: args.show_frame_registers,
+        register_name: CortexMPlatform::register_name,
+        theme: args.theme,
+        render_options,
+    };
+
+    for (frame_indices, frame) in frames.iter() {
+        write!(output, "{}: ", frame_indices.start)?;
+
+        let frame_text = frame.display(&display_options);
 
         let line_wrapping_options = textwrap::Options::with_termwidth()
             .wrap_algorithm(textwrap::WrapAlgorithm::new_optimal_fit())
@@ -144,42 +554,192 @@ pub(crate) fn print_frames(frames: Vec<stackdump_trace::Frame<u32>>, args: &Argu
             let wrapping_lines_count = wrapping_lines.len();
 
             for (wrapping_line_index, wrapping_line) in wrapping_lines.iter().enumerate() {
-                println!("{wrapping_line}");
+                writeln!(output, "{wrapping_line}")?;
 
                 if wrapping_line_index == max_lines - 1 {
-                    println!(
+                    writeln!(
+                        output,
                         "      {}",
                         format!("... ({} more)", wrapping_lines_count - max_lines).dimmed()
-                    );
+                    )?;
                     break;
                 }
             }
         }
     }
+
+    if !frame_sizes.is_empty() {
+        writeln!(output, "\nStack usage per function:")?;
+        for (function, size) in &frame_sizes {
+            writeln!(output, "  {size:>6} bytes  {function}")?;
+        }
+        writeln!(
+            output,
+            "  {:>6} bytes  total",
+            frame_sizes.iter().map(|(_, size)| size).sum::<u64>()
+        )?;
+    }
+
+    if let Some(stack_usage) = stack_usage {
+        writeln!(output, "\nMax stack usage: {stack_usage} bytes")?;
+    }
+
+    Ok(())
+}
+
+/// Prints the meta data of the dumps and the registers that were captured, if there are any
+fn print_header(
+    metas: &[DumpMeta],
+    registers: &[(gimli::Register, u32)],
+    output: &mut impl Write,
+) -> std::io::Result<()> {
+    for meta in metas {
+        writeln!(output, "{meta}")?;
+    }
+    if !metas.is_empty() {
+        writeln!(output)?;
+    }
+
+    if !registers.is_empty() {
+        writeln!(output, "Registers:")?;
+        for (register, value) in registers {
+            let name = CortexMPlatform::register_name(*register)
+                .map(str::to_lowercase)
+                .unwrap_or_else(|| register.0.to_string());
+            match CortexMPlatform::register_role(*register) {
+                Some(role) => writeln!(output, "  {name} ({role}) = {value:#010X}")?,
+                None => writeln!(output, "  {name} = {value:#010X}")?,
+            }
+        }
+        writeln!(output)?;
+    }
+
+    Ok(())
 }
 
+/// Reads the elf file and the dumps. The meta data of the dumps that have it is returned as well.
+///
+/// If `elf_from_dump` is true, `elf_file` is a directory and the elf file in it with the build id of the dumps is used.
+#[allow(clippy::type_complexity)]
 fn read_files_into_device_memory(
     elf_file: &Path,
+    elf_from_dump: bool,
     dumps: &[PathBuf],
-) -> Result<(Vec<u8>, DeviceMemory<'static, u32>), Box<dyn Error>> {
-    let elf_data = std::fs::read(elf_file)?;
+) -> Result<(Vec<u8>, DeviceMemory<'static, u32>, Vec<DumpMeta>), Box<dyn Error>> {
     let mut device_memory = DeviceMemory::new();
+    let mut metas = Vec::new();
     for dump_path in dumps {
-        let dump_data = std::fs::read(dump_path)?;
+        // Stdin can't be read from lazily, so that dump is read completely
+        if dump_path.as_os_str() == "-" {
+            let mut dump_bytes = Vec::new();
+            std::io::stdin().read_to_end(&mut dump_bytes)?;
 
-        let mut dump_iter = dump_data.into_iter().peekable();
+            let dump = Dump::<u32>::from_dump_bytes(&dump_bytes)?;
+            metas.extend(dump.meta.clone());
+            dump.add_to_device_memory(&mut device_memory);
+            continue;
+        }
 
-        while let Some(id) = dump_iter.peek().cloned() {
-            match id {
-                MEMORY_REGION_IDENTIFIER => {
-                    device_memory.add_memory_region(VecMemoryRegion::from_iter(&mut dump_iter))
-                }
-                REGISTER_DATA_IDENTIFIER => {
-                    device_memory.add_register_data(VecRegisterData::from_iter(&mut dump_iter))
-                }
-                _ => return Err("Dump data error. Got to an unexpected identifier".into()),
+        // The memory of the dump is read from the file when it's needed, so big dumps aren't loaded completely
+        metas.extend(add_dump_file_to_device_memory(
+            File::open(dump_path)?,
+            &mut device_memory,
+        )?);
+    }
+
+    let elf_data = if elf_from_dump {
+        let build_id = metas
+            .iter()
+            .rev()
+            .find(|meta| !meta.build_id.is_empty())
+            .map(|meta| meta.build_id.as_slice())
+            .ok_or("The dumps don't have a build id to find the elf file with")?;
+        find_elf_with_build_id(elf_file, build_id)?
+    } else {
+        std::fs::read(elf_file)?
+    };
+
+    Ok((elf_data, device_memory, metas))
+}
+
+/// Searches the directory for the elf file with the given build id and returns its data
+fn find_elf_with_build_id(directory: &Path, build_id: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+
+        let data = std::fs::read(&path)?;
+        // Files that are not elf files are skipped
+        if let Ok(Some(elf_build_id)) = stackdump_trace::platform::elf_build_id(&data) {
+            // The build id in the dump meta is truncated if it's very long
+            if elf_build_id.starts_with(build_id) {
+                log::info!("Using elf file {}", path.display());
+                return Ok(data);
             }
         }
     }
-    Ok((elf_data, device_memory))
+
+    Err(format!(
+        "None of the elf files in {} have the build id of the dump",
+        directory.display()
+    )
+    .into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stackdump_trace::{Frame, FrameType, Location};
+
+    fn frame(function: &str, frame_type: FrameType, inlined_into: Option<usize>) -> Frame<u32> {
+        Frame {
+            function: function.into(),
+            location: Location::default(),
+            frame_type,
+            variables: Vec::new(),
+            omitted_variables: Vec::new(),
+            inlined_into,
+            stack_pointer: None,
+            registers: Vec::new(),
+            frame_size: None,
+        }
+    }
+
+    #[test]
+    fn filter_frames_remaps_inlined_into() {
+        let frames = vec![
+            frame("inlined_a", FrameType::InlineFunction, Some(2)),
+            frame("inlined_b", FrameType::InlineFunction, Some(2)),
+            frame("function", FrameType::Function, None),
+            frame("inlined_c", FrameType::InlineFunction, Some(4)),
+            frame("main", FrameType::Function, None),
+            frame("Static", FrameType::Static, None),
+        ];
+
+        let args = Arguments::parse_from([
+            "stackdump",
+            "--filter",
+            "^(inlined_a|inlined_c|main)$",
+            "cortex-m",
+            "elf",
+            "dump",
+        ]);
+        let filtered_frames = filter_frames(frames, &args);
+
+        // The frame that inlined_a was inlined into is gone and main has moved
+        assert_eq!(
+            filtered_frames
+                .iter()
+                .map(|frame| (frame.function.as_str(), frame.inlined_into))
+                .collect::<Vec<_>>(),
+            [
+                ("inlined_a", None),
+                ("inlined_c", Some(2)),
+                ("main", None),
+                ("Static", None),
+            ]
+        );
+    }
 }
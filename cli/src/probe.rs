@@ -1,9 +1,7 @@
 use crate::Arguments;
 use probe_rs::{config::TargetSelector, DebugProbeSelector, Permissions, Probe, Session};
 use stackdump_capture_probe::StackdumpCapturer;
-use stackdump_trace::{
-    platform::cortex_m::CortexMPlatform, stackdump_core::device_memory::DeviceMemory,
-};
+use stackdump_trace::stackdump_core::{device_memory::DeviceMemory, memory_region::MemoryRegion};
 use std::{error::Error, path::Path, time::Duration};
 
 pub(crate) fn trace_probe(
@@ -22,25 +20,31 @@ pub(crate) fn trace_probe(
     let mut core = session.core(core.unwrap_or(0))?;
 
     let core_type = core.core_type();
-    let fpu_supported = core.fpu_support()?;
     core.halt(Duration::from_secs(2))?;
 
     let mut stackcapturer = StackdumpCapturer::new(&mut core);
 
     let mut device_memory = DeviceMemory::new();
-    device_memory.add_register_data(stackcapturer.capture_core_registers()?);
+    let (core_registers, fpu_registers) = stackcapturer.capture_all()?;
+    device_memory.add_register_data(core_registers);
 
-    if fpu_supported {
-        if let Some(fpu_registers) = stackcapturer.capture_fpu_registers()? {
-            device_memory.add_register_data(fpu_registers);
-        }
+    if let Some(stack_pointers) = stackcapturer.capture_stack_pointers()? {
+        device_memory.add_register_data(stack_pointers);
     }
 
-    device_memory.add_memory_region(stackcapturer);
+    if let Some(fpu_registers) = fpu_registers {
+        device_memory.add_register_data(fpu_registers);
+    }
+
+    // Read the memory lazily from the device for anything that isn't available in the elf file
+    device_memory.set_memory_fallback(move |address_range| stackcapturer.read(address_range));
 
     if core_type.is_cortex_m() {
-        let frames = stackdump_trace::platform::trace::<CortexMPlatform>(device_memory, &elf_data)?;
-        crate::print_frames(frames, args);
+        let registers = crate::captured_registers(&device_memory, args);
+        crate::core_file::emit_core_file(&device_memory, args)?;
+        let elf = object::File::parse(&*elf_data)?;
+        let frames = crate::trace(device_memory, &elf, args)?;
+        crate::output_frames(frames, &[], &registers, &elf, args)?;
     } else {
         unimplemented!("Other tracing than on cortex-m is not yet implemented");
     }
@@ -0,0 +1,80 @@
+//! The checksum at the end of the serialized memory regions and register data
+
+/// The CRC-32 checksum (the one of zip and ethernet).
+///
+/// It's calculated one byte at a time without a lookup table,
+/// so it can be used while streaming the bytes out of a device that doesn't have memory to spare.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Crc32(u32);
+
+impl Crc32 {
+    pub(crate) const fn new() -> Self {
+        Self(!0)
+    }
+
+    pub(crate) fn update(&mut self, byte: u8) {
+        self.0 ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (self.0 & 1).wrapping_neg();
+            self.0 = (self.0 >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+
+    pub(crate) fn value(&self) -> u32 {
+        !self.0
+    }
+}
+
+/// An iterator that calculates the checksum of the bytes that are read through it
+pub(crate) struct ChecksumIter<I> {
+    pub(crate) inner: I,
+    checksum: Crc32,
+}
+
+impl<I: Iterator<Item = u8>> ChecksumIter<I> {
+    pub(crate) fn new(inner: I) -> Self {
+        Self {
+            inner,
+            checksum: Crc32::new(),
+        }
+    }
+
+    /// Reads the checksum that follows the bytes that have been read so far.
+    ///
+    /// Returns the checksum that was read and the one that was calculated,
+    /// or `None` if the bytes ended before the checksum was complete.
+    pub(crate) fn read_checksum(&mut self) -> Option<(u32, u32)> {
+        let mut bytes = [0; 4];
+        for byte in bytes.iter_mut() {
+            *byte = self.inner.next()?;
+        }
+
+        Some((u32::from_le_bytes(bytes), self.checksum.value()))
+    }
+}
+
+impl<I: Iterator<Item = u8>> Iterator for ChecksumIter<I> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let byte = self.inner.next()?;
+        self.checksum.update(byte);
+        Some(byte)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32() {
+        let mut crc = Crc32::new();
+        for byte in b"123456789" {
+            crc.update(*byte);
+        }
+
+        // The check value of the CRC-32
+        assert_eq!(crc.value(), 0xCBF4_3926);
+    }
+}
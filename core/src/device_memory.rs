@@ -1,13 +1,42 @@
 //! Module containing the definitions for device memory, a summation of all available memory that was captured
 
-use crate::{memory_region::MemoryRegion, register_data::RegisterData};
-use std::{error::Error, fmt::Display, ops::Range, rc::Rc};
+use crate::{
+    memory_region::{MemoryRegion, MemoryRegionKind, VecMemoryRegion},
+    register_data::RegisterData,
+};
+use alloc::{borrow::Cow, boxed::Box, format, rc::Rc, string::ToString, vec::Vec};
+use core::{any::Any, fmt::Display, ops::Range};
+
+/// The error type that is wrapped by a [MemoryReadError].
+///
+/// This is implemented for anything that can be displayed, regardless of the `std` feature.
+/// Use [MemoryReadError::from_std_error] to keep a [std::error::Error] as the source of the memory read error.
+pub trait MemoryReadErrorSource: core::fmt::Debug + Display {
+    /// Gets the error as [Any], so it can be downcast to its concrete type
+    fn as_any(&self) -> &dyn Any;
+}
+impl<T: core::fmt::Debug + Display + 'static> MemoryReadErrorSource for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// A [std::error::Error] that is wrapped by a [MemoryReadError], so it can be returned as its source
+#[cfg(feature = "std")]
+#[derive(Debug)]
+struct StdErrorSource(Box<dyn std::error::Error>);
+#[cfg(feature = "std")]
+impl Display for StdErrorSource {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.0.fmt(f)
+    }
+}
 
 /// An error to signal that a register is not present
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct MissingRegisterError(gimli::Register);
 impl Display for MissingRegisterError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(
             f,
             "Missing register: {}",
@@ -17,28 +46,122 @@ impl Display for MissingRegisterError {
         )
     }
 }
-impl Error for MissingRegisterError {}
+#[cfg(feature = "std")]
+impl std::error::Error for MissingRegisterError {}
 
 /// An error to signal that memory could not be read
 #[derive(Debug, Clone)]
-pub struct MemoryReadError(pub Rc<dyn Error>);
+pub struct MemoryReadError(pub Rc<dyn MemoryReadErrorSource>);
 impl Display for MemoryReadError {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "Memory read error: {}", self.0)
     }
 }
-impl Error for MemoryReadError {}
+#[cfg(feature = "std")]
+impl MemoryReadError {
+    /// Creates a memory read error of which the given error is the [source](std::error::Error::source)
+    pub fn from_std_error<E: std::error::Error + 'static>(error: E) -> Self {
+        Self(Rc::new(StdErrorSource(Box::new(error))))
+    }
+}
+#[cfg(feature = "std")]
+impl std::error::Error for MemoryReadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        // The Rc implements the trait too, so the call must go to what it points to
+        (*self.0)
+            .as_any()
+            .downcast_ref::<StdErrorSource>()
+            .map(|source| &*source.0)
+    }
+}
+
+/// An error to signal that the memory fallback returned a different amount of bytes than were asked for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FallbackLengthError {
+    /// The address range that was asked for
+    pub address_range: Range<u64>,
+    /// The amount of bytes the fallback returned
+    pub length: usize,
+}
+
+impl Display for FallbackLengthError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "The memory fallback returned {} bytes for the {} bytes at {:#X}..{:#X}",
+            self.length,
+            self.address_range.end - self.address_range.start,
+            self.address_range.start,
+            self.address_range.end
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FallbackLengthError {}
+
 impl PartialEq for MemoryReadError {
     fn eq(&self, other: &Self) -> bool {
         self.0.to_string() == other.0.to_string()
     }
 }
 
+/// A function that can read memory on demand, e.g. from a live device.
+/// It has the same semantics as [MemoryRegion::read].
+pub type MemoryFallback<'memory> =
+    Box<dyn Fn(Range<u64>) -> Result<Option<Vec<u8>>, MemoryReadError> + 'memory>;
+
+/// A step of a DWARF location evaluation that needs a value that can't be found in the captured data.
+///
+/// The value can be given by an [EvaluationStepResolver], see [DeviceMemory::set_evaluation_step_resolver].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EvaluationStep {
+    /// The frame base of the function (`DW_AT_frame_base`) is needed, but it isn't known
+    FrameBase,
+    /// The canonical frame address of the frame is needed (`DW_OP_call_frame_cfa`)
+    CallFrameCfa,
+    /// The value of a parameter of the function in the caller is needed (`DW_OP_GNU_parameter_ref`)
+    ParameterRef {
+        /// The offset of the entry of the parameter in the `.debug_info` section
+        offset: gimli::DebugInfoOffset,
+    },
+    /// An address from the `.debug_addr` section is needed (`DW_OP_addrx` or `DW_OP_constx`)
+    IndexedAddress {
+        /// The index of the address
+        index: u64,
+    },
+    /// The base address of the thread local storage (TLS) of the traced thread is needed to find a thread local variable.
+    ///
+    /// Where the TLS base can be found differs per OS or RTOS.
+    TlsBase,
+}
+
+/// The frame of a trace in which a location evaluation needs an [EvaluationStep]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EvaluationFrame {
+    /// The index of the frame in the trace
+    pub index: usize,
+    /// The value of the program counter in the frame
+    pub pc: u64,
+}
+
+/// A function that gives the value for an [EvaluationStep], or None if it doesn't know it.
+///
+/// It also gets the frame of which a variable is being decoded. This is None for the static variables.
+pub type EvaluationStepResolver<'memory> =
+    Box<dyn Fn(EvaluationStep, Option<EvaluationFrame>) -> Option<u64> + 'memory>;
+
 /// Object containing all memory regions (we have available) of the device
 pub struct DeviceMemory<'memory, RB: funty::Integral> {
     // Register data must be mutable for stack unwinding
     register_data: Vec<Box<dyn RegisterData<RB> + 'memory>>,
     memory_regions: Vec<Box<dyn MemoryRegion + 'memory>>,
+    /// The address ranges of the memory regions with their index, sorted on their start address.
+    /// None when the regions can't be searched like this, because they overlap or don't all know their address range.
+    sorted_regions: Option<Vec<(Range<u64>, usize)>>,
+    memory_fallback: Option<MemoryFallback<'memory>>,
+    evaluation_step_resolver: Option<EvaluationStepResolver<'memory>>,
+    unknown_registers: Vec<gimli::Register>,
 }
 
 impl<'memory, RB: funty::Integral> DeviceMemory<'memory, RB> {
@@ -47,12 +170,339 @@ impl<'memory, RB: funty::Integral> DeviceMemory<'memory, RB> {
         Self {
             register_data: Vec::new(),
             memory_regions: Vec::new(),
+            sorted_regions: Some(Vec::new()),
+            memory_fallback: None,
+            evaluation_step_resolver: None,
+            unknown_registers: Vec::new(),
         }
     }
 
-    /// Adds a memory region to the device memory
-    pub fn add_memory_region<M: MemoryRegion + 'memory>(&mut self, region: M) {
+    /// Adds a memory region to the device memory.
+    ///
+    /// Where regions overlap, the one that was added last is read from.
+    /// So a region added later overrides older data, like a RAM dump that is added after the static image of the elf file.
+    ///
+    /// Returns the address ranges at which the new region overlaps the regions that were already added.
+    /// This is empty when there is no overlap or when the new region doesn't know its address range.
+    ///
+    /// ```
+    /// use stackdump_core::{device_memory::DeviceMemory, memory_region::VecMemoryRegion};
+    ///
+    /// let mut device_memory = DeviceMemory::<u32>::new();
+    /// device_memory.add_memory_region(VecMemoryRegion::new(0x100, vec![1; 0x100]));
+    ///
+    /// let overlaps = device_memory.add_memory_region(VecMemoryRegion::new(0x180, vec![2; 0x100]));
+    /// assert_eq!(overlaps, vec![0x180..0x200]);
+    /// assert_eq!(device_memory.read_u8(0x1C0), Ok(Some(2)));
+    /// ```
+    pub fn add_memory_region<M: MemoryRegion + 'memory>(&mut self, region: M) -> Vec<Range<u64>> {
+        let overlaps = match region.address_range() {
+            Some(new_range) => self
+                .captured_ranges()
+                .into_iter()
+                .map(|range| range.start.max(new_range.start)..range.end.min(new_range.end))
+                .filter(|overlap| !overlap.is_empty())
+                .collect(),
+            None => Vec::new(),
+        };
+
+        self.index_region(region.address_range(), self.memory_regions.len());
         self.memory_regions.push(Box::new(region));
+        overlaps
+    }
+
+    /// Adds a memory region of a static image, like a section of the elf file.
+    ///
+    /// The region gets the lowest precedence, so all other regions override it where they overlap,
+    /// regardless of whether they are added before or after it.
+    pub fn add_image_memory_region<M: MemoryRegion + 'memory>(&mut self, region: M) {
+        if let Some(sorted_regions) = self.sorted_regions.as_mut() {
+            for (_, index) in sorted_regions.iter_mut() {
+                *index += 1;
+            }
+        }
+        self.index_region(region.address_range(), 0);
+        self.memory_regions.insert(0, Box::new(region));
+    }
+
+    /// Adds the region with the given address range and index to the sorted regions,
+    /// or stops using them if the region can't be found with a binary search
+    fn index_region(&mut self, address_range: Option<Range<u64>>, index: usize) {
+        let sorted_regions = match self.sorted_regions.as_mut() {
+            Some(sorted_regions) => sorted_regions,
+            None => return,
+        };
+
+        let address_range = match address_range {
+            Some(address_range) if !address_range.is_empty() => address_range,
+            _ => {
+                self.sorted_regions = None;
+                return;
+            }
+        };
+
+        let position =
+            sorted_regions.partition_point(|(range, _)| range.start < address_range.start);
+        let overlaps_previous = position
+            .checked_sub(1)
+            .map(|previous| sorted_regions[previous].0.end > address_range.start)
+            .unwrap_or_default();
+        let overlaps_next = sorted_regions
+            .get(position)
+            .map(|(next_range, _)| next_range.start < address_range.end)
+            .unwrap_or_default();
+
+        if overlaps_previous || overlaps_next {
+            self.sorted_regions = None;
+        } else {
+            sorted_regions.insert(position, (address_range, index));
+        }
+    }
+
+    /// Gets the regions that may contain the given address, in the order of their precedence from low to high.
+    ///
+    /// When the regions are sorted, this is at most the one region that contains the address, found with a binary search.
+    /// Otherwise, all regions have to be tried.
+    fn candidate_regions(&self, address: u64) -> &[Box<dyn MemoryRegion + 'memory>] {
+        let sorted_regions = match self.sorted_regions.as_ref() {
+            Some(sorted_regions) => sorted_regions,
+            None => return &self.memory_regions,
+        };
+
+        let position = sorted_regions.partition_point(|(range, _)| range.start <= address);
+        match position
+            .checked_sub(1)
+            .map(|previous| &sorted_regions[previous])
+        {
+            Some((range, index)) if range.contains(&address) => {
+                core::slice::from_ref(&self.memory_regions[*index])
+            }
+            _ => &[],
+        }
+    }
+
+    /// Merges the memory regions of which the address ranges touch or overlap into one region.
+    ///
+    /// This makes reading faster when many small regions have been added, like the chunks of a stack or the data of every RTT write.
+    /// The region to read from is found with a binary search, but only as long as no regions overlap.
+    /// Otherwise the regions are gone through one by one.
+    /// What is read doesn't change. Where the regions overlap, the merged region has the data of the region that was read from before,
+    /// so captured RAM still overrides the static image of the elf file.
+    ///
+    /// Regions are only merged when they are of the same kind. Regions that don't know their address range are kept as they are.
+    /// If reading one of the regions fails, the device memory is left unchanged.
+    ///
+    /// ```
+    /// use stackdump_core::{device_memory::DeviceMemory, memory_region::VecMemoryRegion};
+    ///
+    /// let mut device_memory = DeviceMemory::<u32>::new();
+    /// device_memory.add_image_memory_region(VecMemoryRegion::new(0x100, vec![1; 0x100]));
+    /// device_memory.add_memory_region(VecMemoryRegion::new(0x180, vec![2; 0x100]));
+    /// device_memory.add_memory_region(VecMemoryRegion::new(0x280, vec![3; 0x80]));
+    ///
+    /// device_memory.compact().unwrap();
+    ///
+    /// assert_eq!(device_memory.captured_ranges(), vec![0x100..0x300]);
+    /// assert_eq!(device_memory.read_slice(0x17F..0x181), Ok(Some(vec![1, 2])));
+    /// assert_eq!(device_memory.read_slice(0x27F..0x281), Ok(Some(vec![2, 3])));
+    /// ```
+    pub fn compact(&mut self) -> Result<(), MemoryReadError> {
+        // First the merged regions are made, so nothing changes when a read fails
+        let mut merged_regions = Vec::new();
+        let mut run_start = 0;
+        for (index, region) in self.memory_regions.iter().enumerate() {
+            // A region without an address range may override any other region,
+            // so only the regions between those can be merged without changing the precedence
+            if region.address_range().is_none() {
+                merged_regions.extend(self.merge_regions(run_start..index)?);
+                run_start = index + 1;
+            }
+        }
+        merged_regions.extend(self.merge_regions(run_start..self.memory_regions.len())?);
+
+        // The merged region takes the place of the region with the highest precedence it is made of
+        let mut removed_regions = alloc::vec![false; self.memory_regions.len()];
+        let mut placed_regions = Vec::from_iter((0..self.memory_regions.len()).map(|_| None));
+        for (members, merged_region) in merged_regions {
+            for index in members.iter() {
+                removed_regions[*index] = true;
+            }
+            placed_regions[*members.last().unwrap()] = Some(merged_region);
+        }
+
+        let mut memory_regions = Vec::with_capacity(self.memory_regions.len());
+        for (index, region) in core::mem::take(&mut self.memory_regions)
+            .into_iter()
+            .enumerate()
+        {
+            match placed_regions[index].take() {
+                Some(merged_region) => {
+                    memory_regions.push(Box::new(merged_region) as Box<dyn MemoryRegion>)
+                }
+                None if removed_regions[index] => {}
+                None => memory_regions.push(region),
+            }
+        }
+        self.memory_regions = memory_regions;
+
+        self.sorted_regions = Some(Vec::new());
+        for index in 0..self.memory_regions.len() {
+            self.index_region(self.memory_regions[index].address_range(), index);
+        }
+
+        Ok(())
+    }
+
+    /// Merges the regions with the given indices of which the address ranges touch or overlap.
+    ///
+    /// Returns the merged regions with the indices of the regions they are made of, in ascending order.
+    fn merge_regions(
+        &self,
+        indices: Range<usize>,
+    ) -> Result<Vec<(Vec<usize>, VecMemoryRegion)>, MemoryReadError> {
+        let mut ranges = indices
+            .filter_map(|index| {
+                let region = &self.memory_regions[index];
+                Some((index, region.address_range()?, region.kind()))
+            })
+            .filter(|(_, range, _)| !range.is_empty())
+            .collect::<Vec<_>>();
+        ranges.sort_by_key(|(_, range, _)| range.start);
+
+        let mut groups: Vec<(Range<u64>, MemoryRegionKind, Vec<usize>)> = Vec::new();
+        for (index, range, kind) in ranges.iter().cloned() {
+            match groups
+                .iter_mut()
+                .rev()
+                .find(|(_, group_kind, _)| *group_kind == kind)
+            {
+                Some((group_range, _, members)) if range.start <= group_range.end => {
+                    group_range.end = group_range.end.max(range.end);
+                    members.push(index);
+                }
+                _ => groups.push((range, kind, Vec::from([index]))),
+            }
+        }
+
+        let mut merged_regions = Vec::new();
+        'groups: for (group_range, kind, mut members) in groups {
+            // A region of another kind in the middle of the group decides the precedence by itself, so the group can't be merged
+            let overlaps_other_kind = ranges.iter().any(|(_, range, other_kind)| {
+                *other_kind != kind
+                    && range.start < group_range.end
+                    && range.end > group_range.start
+            });
+            if members.len() < 2 || overlaps_other_kind {
+                continue;
+            }
+
+            // The data of the regions with a higher precedence is written over that of the ones with a lower one
+            members.sort_unstable();
+            let mut data = alloc::vec![0; (group_range.end - group_range.start) as usize];
+            for index in members.iter() {
+                let region = &self.memory_regions[*index];
+                let range = region.address_range().unwrap();
+                let region_data = match region.read_ref(range.clone())? {
+                    Some(region_data) => region_data,
+                    // The region doesn't have the data it says it has, so we leave the group alone
+                    None => continue 'groups,
+                };
+                let start = (range.start - group_range.start) as usize;
+                data[start..start + region_data.len()].copy_from_slice(&region_data);
+            }
+
+            merged_regions.push((
+                members,
+                VecMemoryRegion::new(group_range.start, data).with_kind(kind),
+            ));
+        }
+
+        Ok(merged_regions)
+    }
+
+    /// Sets a fallback that is used to read memory that is not present in any of the memory regions.
+    ///
+    /// This can be used to fetch memory lazily, for example from a live device,
+    /// instead of having to capture all memory up front.
+    /// The fallback must return all bytes of the address range or None. Returning fewer or more bytes is a [FallbackLengthError].
+    /// Anything implementing [MemoryRegion] can be used as the backing store like this:
+    ///
+    /// ```
+    /// use stackdump_core::{device_memory::DeviceMemory, memory_region::{MemoryRegion, VecMemoryRegion}};
+    ///
+    /// let live_memory = VecMemoryRegion::new(0x2000_0000, vec![1, 2, 3, 4]);
+    ///
+    /// let mut device_memory = DeviceMemory::<u32>::new();
+    /// device_memory.set_memory_fallback(move |address_range| live_memory.read(address_range));
+    ///
+    /// assert_eq!(device_memory.read_u8(0x2000_0002), Ok(Some(3)));
+    /// ```
+    pub fn set_memory_fallback<F>(&mut self, fallback: F)
+    where
+        F: Fn(Range<u64>) -> Result<Option<Vec<u8>>, MemoryReadError> + 'memory,
+    {
+        self.memory_fallback = Some(Box::new(fallback));
+    }
+
+    /// Reads the memory using the fallback, if there is one.
+    ///
+    /// The fallback is user code, so it's checked that it returned exactly the bytes that were asked for.
+    fn read_fallback(&self, address_range: Range<u64>) -> Result<Option<Vec<u8>>, MemoryReadError> {
+        let fallback = match &self.memory_fallback {
+            Some(fallback) => fallback,
+            None => return Ok(None),
+        };
+
+        match fallback(address_range.clone())? {
+            Some(bytes) if bytes.len() as u64 != address_range.end - address_range.start => {
+                Err(MemoryReadError(Rc::new(FallbackLengthError {
+                    address_range,
+                    length: bytes.len(),
+                })))
+            }
+            bytes => Ok(bytes),
+        }
+    }
+
+    /// Sets a function that gives the values that a location evaluation needs, but that can't be found in the captured data.
+    ///
+    /// Without it, the variables of which the location needs such a value can't be read.
+    /// With it, you can provide e.g. the canonical frame address, a parameter value that you know from somewhere else
+    /// or the TLS base of the traced thread.
+    ///
+    /// The resolver gets the frame in which the value is needed, so it can give e.g. the TLS base of the task
+    /// that a frame belongs to.
+    ///
+    /// ```
+    /// use stackdump_core::device_memory::{DeviceMemory, EvaluationFrame, EvaluationStep};
+    ///
+    /// let mut device_memory = DeviceMemory::<u32>::new();
+    /// device_memory.set_evaluation_step_resolver(|step, frame| match (step, frame) {
+    ///     (EvaluationStep::CallFrameCfa, Some(frame)) if frame.index == 0 => Some(0x2000_0100),
+    ///     _ => None,
+    /// });
+    ///
+    /// let first_frame = EvaluationFrame { index: 0, pc: 0x0800_0100 };
+    /// assert_eq!(device_memory.resolve_evaluation_step(EvaluationStep::CallFrameCfa, Some(first_frame)), Some(0x2000_0100));
+    /// assert_eq!(device_memory.resolve_evaluation_step(EvaluationStep::CallFrameCfa, None), None);
+    /// assert_eq!(device_memory.resolve_evaluation_step(EvaluationStep::FrameBase, Some(first_frame)), None);
+    /// ```
+    pub fn set_evaluation_step_resolver<F>(&mut self, resolver: F)
+    where
+        F: Fn(EvaluationStep, Option<EvaluationFrame>) -> Option<u64> + 'memory,
+    {
+        self.evaluation_step_resolver = Some(Box::new(resolver));
+    }
+
+    /// Gets the value for the evaluation step in the frame from the resolver, if there is one and it knows the value
+    pub fn resolve_evaluation_step(
+        &self,
+        step: EvaluationStep,
+        frame: Option<EvaluationFrame>,
+    ) -> Option<u64> {
+        self.evaluation_step_resolver
+            .as_ref()
+            .and_then(|resolver| resolver(step, frame))
     }
 
     /// Adds register data to the device memory
@@ -61,29 +511,179 @@ impl<'memory, RB: funty::Integral> DeviceMemory<'memory, RB> {
     }
 
     /// Returns the slice of memory that can be found at the given address_range.
-    /// If the given address range is not fully within one of the captured regions present in the device memory, then None is returned.
+    /// If the range is in multiple regions, it is read from the region that was added last (see [Self::add_memory_region]).
+    /// If the given address range is not fully within one of the captured regions present in the device memory,
+    /// then the memory fallback is consulted. If that isn't there or doesn't have the memory either, then None is returned.
     pub fn read_slice(
         &self,
         address_range: Range<u64>,
     ) -> Result<Option<Vec<u8>>, MemoryReadError> {
-        for mr in self.memory_regions.iter() {
-            if let Some(v) = mr.read(address_range.clone())? {
+        Ok(self.read_slice_ref(address_range)?.map(Cow::into_owned))
+    }
+
+    /// Same as [Self::read_slice], but borrows the memory from the region it's in instead of copying it when that's possible.
+    ///
+    /// ```
+    /// use std::borrow::Cow;
+    /// use stackdump_core::{device_memory::DeviceMemory, memory_region::VecMemoryRegion};
+    ///
+    /// let mut device_memory = DeviceMemory::<u32>::new();
+    /// device_memory.add_memory_region(VecMemoryRegion::new(0x2000_0000, vec![1, 2, 3, 4]));
+    ///
+    /// assert_eq!(
+    ///     device_memory.read_slice_ref(0x2000_0001..0x2000_0003),
+    ///     Ok(Some(Cow::Borrowed(&[2, 3][..])))
+    /// );
+    /// ```
+    pub fn read_slice_ref(
+        &self,
+        address_range: Range<u64>,
+    ) -> Result<Option<Cow<'_, [u8]>>, MemoryReadError> {
+        for mr in self.candidate_regions(address_range.start).iter().rev() {
+            if let Some(v) = mr.read_ref(address_range.clone())? {
                 return Ok(Some(v));
             }
         }
 
-        Ok(None)
+        Ok(self.read_fallback(address_range)?.map(Cow::Owned))
+    }
+
+    /// Returns the kind of memory that is captured at the given address.
+    ///
+    /// Returns None if the address is not in any of the regions that know their address range.
+    ///
+    /// ```
+    /// use stackdump_core::{device_memory::DeviceMemory, memory_region::{MemoryRegionKind, VecMemoryRegion}};
+    ///
+    /// let mut device_memory = DeviceMemory::<u32>::new();
+    /// device_memory.add_memory_region(VecMemoryRegion::new(0x2000_0000, vec![0; 4]));
+    /// device_memory.add_memory_region(
+    ///     VecMemoryRegion::new(0x4000_0000, vec![0; 4]).with_kind(MemoryRegionKind::Peripheral),
+    /// );
+    ///
+    /// assert_eq!(device_memory.memory_kind(0x2000_0000), Some(MemoryRegionKind::Ram));
+    /// assert_eq!(device_memory.memory_kind(0x4000_0002), Some(MemoryRegionKind::Peripheral));
+    /// assert_eq!(device_memory.memory_kind(0x4000_0004), None);
+    /// ```
+    pub fn memory_kind(&self, address: u64) -> Option<MemoryRegionKind> {
+        self.candidate_regions(address)
+            .iter()
+            .rev()
+            .find(|mr| {
+                mr.address_range()
+                    .map(|range| range.contains(&address))
+                    .unwrap_or_default()
+            })
+            .map(|mr| mr.kind())
+    }
+
+    /// Returns the address ranges of all captured memory regions, sorted and with touching or overlapping ranges merged.
+    ///
+    /// Regions that don't know their address range up front and the memory fallback are not included.
+    pub fn captured_ranges(&self) -> Vec<Range<u64>> {
+        let mut ranges = self
+            .memory_regions
+            .iter()
+            .filter_map(|mr| mr.address_range())
+            .filter(|range| !range.is_empty())
+            .collect::<Vec<_>>();
+        ranges.sort_by_key(|range| range.start);
+
+        let mut merged_ranges: Vec<Range<u64>> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            match merged_ranges.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged_ranges.push(range),
+            }
+        }
+
+        merged_ranges
+    }
+
+    /// Returns the address ranges that are covered by more than one of the captured memory regions, sorted and merged.
+    ///
+    /// Regions that don't know their address range up front are not included.
+    ///
+    /// ```
+    /// use stackdump_core::{device_memory::DeviceMemory, memory_region::VecMemoryRegion};
+    ///
+    /// let mut device_memory = DeviceMemory::<u32>::new();
+    /// device_memory.add_memory_region(VecMemoryRegion::new(0x100, vec![0; 0x100]));
+    /// device_memory.add_memory_region(VecMemoryRegion::new(0x1F0, vec![0; 0x20]));
+    /// device_memory.add_memory_region(VecMemoryRegion::new(0x300, vec![0; 0x10]));
+    ///
+    /// assert_eq!(device_memory.overlapping_ranges(), vec![0x1F0..0x200]);
+    /// ```
+    pub fn overlapping_ranges(&self) -> Vec<Range<u64>> {
+        let mut ranges = self
+            .memory_regions
+            .iter()
+            .filter_map(|mr| mr.address_range())
+            .filter(|range| !range.is_empty())
+            .collect::<Vec<_>>();
+        ranges.sort_by_key(|range| range.start);
+
+        let mut overlaps: Vec<Range<u64>> = Vec::new();
+        let mut covered_end = 0;
+        for range in ranges {
+            if range.start < covered_end {
+                let overlap = range.start..range.end.min(covered_end);
+                match overlaps.last_mut() {
+                    Some(last) if overlap.start <= last.end => last.end = last.end.max(overlap.end),
+                    _ => overlaps.push(overlap),
+                }
+            }
+            covered_end = covered_end.max(range.end);
+        }
+
+        overlaps
+    }
+
+    /// Returns the parts of the given address range that are not covered by any of the captured memory regions.
+    ///
+    /// ```
+    /// use stackdump_core::{device_memory::DeviceMemory, memory_region::VecMemoryRegion};
+    ///
+    /// let mut device_memory = DeviceMemory::<u32>::new();
+    /// device_memory.add_memory_region(VecMemoryRegion::new(0x100, vec![0; 0x100]));
+    ///
+    /// assert_eq!(device_memory.gaps(0x80..0x280), vec![0x80..0x100, 0x200..0x280]);
+    /// ```
+    pub fn gaps(&self, address_range: Range<u64>) -> Vec<Range<u64>> {
+        let mut gaps = Vec::new();
+        let mut gap_start = address_range.start;
+
+        for captured_range in self.captured_ranges() {
+            if captured_range.start >= address_range.end {
+                break;
+            }
+            if captured_range.end <= gap_start {
+                continue;
+            }
+            if captured_range.start > gap_start {
+                gaps.push(gap_start..captured_range.start);
+            }
+            gap_start = captured_range.end;
+        }
+
+        if gap_start < address_range.end {
+            gaps.push(gap_start..address_range.end);
+        }
+
+        gaps
     }
 
     /// Reads a byte from the given address if it is present in one of the captured regions present in the device memory
     pub fn read_u8(&self, address: u64) -> Result<Option<u8>, MemoryReadError> {
-        for mr in self.memory_regions.iter() {
+        for mr in self.candidate_regions(address).iter().rev() {
             if let Some(v) = mr.read_u8(address)? {
                 return Ok(Some(v));
             }
         }
 
-        Ok(None)
+        Ok(self
+            .read_fallback(address..address + 1)?
+            .and_then(|b| b.first().copied()))
     }
 
     /// Reads a u32 from the given address if it is present in one of the captured regions present in the device memory
@@ -92,25 +692,150 @@ impl<'memory, RB: funty::Integral> DeviceMemory<'memory, RB> {
         address: u64,
         endianness: gimli::RunTimeEndian,
     ) -> Result<Option<u32>, MemoryReadError> {
-        for mr in self.memory_regions.iter() {
+        for mr in self.candidate_regions(address).iter().rev() {
             if let Some(v) = mr.read_u32(address, endianness)? {
                 return Ok(Some(v));
             }
         }
 
-        Ok(None)
+        Ok(self
+            .read_fallback(address..address + 4)?
+            .and_then(|slice| slice[..].try_into().ok())
+            .map(|slice| {
+                if gimli::Endianity::is_little_endian(endianness) {
+                    u32::from_le_bytes(slice)
+                } else {
+                    u32::from_be_bytes(slice)
+                }
+            }))
+    }
+
+    /// Reads a u64 from the given address if it is present in one of the captured regions present in the device memory
+    pub fn read_u64(
+        &self,
+        address: u64,
+        endianness: gimli::RunTimeEndian,
+    ) -> Result<Option<u64>, MemoryReadError> {
+        for mr in self.candidate_regions(address).iter().rev() {
+            if let Some(v) = mr.read_u64(address, endianness)? {
+                return Ok(Some(v));
+            }
+        }
+
+        Ok(self
+            .read_fallback(address..address + 8)?
+            .and_then(|slice| slice[..].try_into().ok())
+            .map(|slice| {
+                if gimli::Endianity::is_little_endian(endianness) {
+                    u64::from_le_bytes(slice)
+                } else {
+                    u64::from_be_bytes(slice)
+                }
+            }))
+    }
+
+    /// Reads an integer of the size of `W` from the given address if it is present in the device memory.
+    ///
+    /// This is used to read words of the platform, like pointers, without knowing their size up front.
+    ///
+    /// ```
+    /// use stackdump_core::{device_memory::DeviceMemory, gimli, memory_region::VecMemoryRegion};
+    ///
+    /// let mut device_memory = DeviceMemory::<u32>::new();
+    /// device_memory.add_memory_region(VecMemoryRegion::new(0x2000_0000, vec![1, 2, 3, 4, 0xFF, 0xFF]));
+    ///
+    /// let endianness = gimli::RunTimeEndian::Little;
+    /// assert_eq!(device_memory.read_uint::<u32>(0x2000_0000, endianness), Ok(Some(0x04030201)));
+    /// assert_eq!(device_memory.read_uint::<u16>(0x2000_0002, endianness), Ok(Some(0x0403)));
+    /// assert_eq!(device_memory.read_uint::<i16>(0x2000_0004, endianness), Ok(Some(-1)));
+    /// assert_eq!(device_memory.read_uint::<u64>(0x2000_0000, endianness), Ok(None));
+    /// ```
+    pub fn read_uint<W: funty::Integral>(
+        &self,
+        address: u64,
+        endianness: gimli::RunTimeEndian,
+    ) -> Result<Option<W>, MemoryReadError> {
+        let size = core::mem::size_of::<W>() as u64;
+        Ok(self
+            .read_slice_ref(address..address + size)?
+            .map(|bytes| integer_from_bytes(&bytes, endianness)))
+    }
+
+    /// Marks the register as unknown. It is then treated as missing until it gets a new value through [Self::register_mut].
+    ///
+    /// This is used during unwinding for registers that are not preserved across calls.
+    /// Their value in the caller is lost, so reading them would give the value of the callee.
+    pub fn invalidate_register(&mut self, register: gimli::Register) {
+        if !self.unknown_registers.contains(&register) {
+            self.unknown_registers.push(register);
+        }
+    }
+
+    fn check_register_known(&self, register: gimli::Register) -> Result<(), MissingRegisterError> {
+        if self.unknown_registers.contains(&register) {
+            Err(MissingRegisterError(register))
+        } else {
+            Ok(())
+        }
     }
 
     /// Try to get the value of the given register. Returns an error if the register is not present in any of the register collections.
     pub fn register(&self, register: gimli::Register) -> Result<RB, MissingRegisterError> {
+        self.check_register_known(register)?;
         self.register_data
             .iter()
             .find_map(|registers| registers.register(register))
             .ok_or(MissingRegisterError(register))
     }
 
+    /// Try to get the byte order of the register collection that contains the given register.
+    /// Returns an error if the register is not present in any of the register collections.
+    pub fn register_endianness(
+        &self,
+        register: gimli::Register,
+    ) -> Result<gimli::RunTimeEndian, MissingRegisterError> {
+        self.check_register_known(register)?;
+        self.register_data
+            .iter()
+            .find(|registers| registers.register_ref(register).is_some())
+            .map(|registers| registers.endianness())
+            .ok_or(MissingRegisterError(register))
+    }
+
+    /// Try to get the bytes of the given register in the byte order of the target it's from.
+    /// Returns an error if the register is not present in any of the register collections.
+    ///
+    /// This is how the register would look in memory on the target, e.g. when a variable is stored in it.
+    ///
+    /// ```
+    /// use stackdump_core::{device_memory::DeviceMemory, gimli, register_data::VecRegisterData};
+    ///
+    /// let mut device_memory = DeviceMemory::new();
+    /// device_memory.add_register_data(
+    ///     VecRegisterData::new(gimli::Arm::R0, vec![0x0102_0304u32])
+    ///         .with_endianness(gimli::RunTimeEndian::Big),
+    /// );
+    /// device_memory.add_register_data(VecRegisterData::new(gimli::Arm::R1, vec![0x0102_0304u32]));
+    ///
+    /// assert_eq!(device_memory.register_bytes(gimli::Arm::R0).unwrap(), [1, 2, 3, 4]);
+    /// assert_eq!(device_memory.register_bytes(gimli::Arm::R1).unwrap(), [4, 3, 2, 1]);
+    /// ```
+    pub fn register_bytes(
+        &self,
+        register: gimli::Register,
+    ) -> Result<RB::Bytes, MissingRegisterError> {
+        let value = self.register(register)?;
+
+        if gimli::Endianity::is_little_endian(self.register_endianness(register)?) {
+            Ok(value.to_le_bytes())
+        } else {
+            Ok(value.to_be_bytes())
+        }
+    }
+
     /// Try to get a reference to the given register. Returns an error if the register is not present in any of the register collections.
     pub fn register_ref(&self, register: gimli::Register) -> Result<&RB, MissingRegisterError> {
+        self.check_register_known(register)?;
         self.register_data
             .iter()
             .find_map(|registers| registers.register_ref(register))
@@ -118,10 +843,13 @@ impl<'memory, RB: funty::Integral> DeviceMemory<'memory, RB> {
     }
 
     /// Try to get a mutable reference to the given register. Returns an error if the register is not present in any of the register collections.
+    ///
+    /// The register is expected to get a new value, so it is no longer unknown if it was invalidated.
     pub fn register_mut(
         &mut self,
         register: gimli::Register,
     ) -> Result<&mut RB, MissingRegisterError> {
+        self.unknown_registers.retain(|r| *r != register);
         self.register_data
             .iter_mut()
             .find_map(|registers| registers.register_mut(register))
@@ -129,8 +857,204 @@ impl<'memory, RB: funty::Integral> DeviceMemory<'memory, RB> {
     }
 }
 
+/// Creates an integer from its bytes. The amount of bytes must be the size of `W`.
+///
+/// The bytes are gathered in a u128 first, because funty can't create a `W` from a slice.
+fn integer_from_bytes<W: funty::Integral>(bytes: &[u8], endianness: gimli::RunTimeEndian) -> W {
+    let fold = |value: u128, byte: &u8| value << 8 | *byte as u128;
+    let value = if gimli::Endianity::is_little_endian(endianness) {
+        bytes.iter().rev().fold(0, fold)
+    } else {
+        bytes.iter().fold(0, fold)
+    };
+
+    // Signed values have to be sign extended to be converted
+    let unused_bits = 128 - bytes.len() as u32 * 8;
+    let converted = if W::MIN == W::ZERO {
+        W::try_from(value).ok()
+    } else {
+        W::try_from(((value << unused_bits) as i128) >> unused_bits).ok()
+    };
+
+    converted.expect("The value has the size of the integer")
+}
+
 impl<'memory, RB: funty::Integral> Default for DeviceMemory<'memory, RB> {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A region that reads every address as the same byte, without knowing its address range
+    struct FilledMemoryRegion(u8);
+
+    impl MemoryRegion for FilledMemoryRegion {
+        fn read(&self, address_range: Range<u64>) -> Result<Option<Vec<u8>>, MemoryReadError> {
+            Ok(Some(alloc::vec![
+                self.0;
+                (address_range.end - address_range.start) as usize
+            ]))
+        }
+    }
+
+    #[test]
+    fn memory_read_error_source() {
+        // An error that can only be displayed can be wrapped, even with the std feature
+        #[derive(Debug)]
+        struct DisplayOnlyError;
+        impl Display for DisplayOnlyError {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                write!(f, "display only")
+            }
+        }
+
+        let error = MemoryReadError(Rc::new(DisplayOnlyError));
+        assert_eq!(error.to_string(), "Memory read error: display only");
+        assert!(std::error::Error::source(&error).is_none());
+
+        // A std error is kept as the source
+        let error = MemoryReadError::from_std_error(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            "timed out",
+        ));
+        assert_eq!(error.to_string(), "Memory read error: timed out");
+        assert_eq!(
+            std::error::Error::source(&error)
+                .unwrap()
+                .downcast_ref::<std::io::Error>()
+                .unwrap()
+                .kind(),
+            std::io::ErrorKind::TimedOut
+        );
+    }
+
+    #[test]
+    fn short_fallback() {
+        let mut device_memory = DeviceMemory::<u32>::new();
+        // A misbehaving fallback that returns only a part of the bytes
+        device_memory.set_memory_fallback(|address_range| {
+            Ok(Some(vec![
+                0xAA;
+                (address_range.end - address_range.start) as usize
+                    / 2
+            ]))
+        });
+
+        let expected_error = |address_range: Range<u64>, length| {
+            MemoryReadError(Rc::new(FallbackLengthError {
+                address_range,
+                length,
+            }))
+        };
+
+        assert_eq!(
+            device_memory.read_u8(0x2000_0000),
+            Err(expected_error(0x2000_0000..0x2000_0001, 0))
+        );
+        assert_eq!(
+            device_memory.read_u32(0x2000_0000, gimli::RunTimeEndian::Little),
+            Err(expected_error(0x2000_0000..0x2000_0004, 2))
+        );
+        assert_eq!(
+            device_memory.read_u64(0x2000_0000, gimli::RunTimeEndian::Little),
+            Err(expected_error(0x2000_0000..0x2000_0008, 4))
+        );
+        assert_eq!(
+            device_memory.read_slice(0x2000_0000..0x2000_0010),
+            Err(expected_error(0x2000_0000..0x2000_0010, 8))
+        );
+    }
+
+    #[test]
+    fn compact_adjacent_regions() {
+        let mut device_memory = DeviceMemory::<u32>::new();
+        device_memory.add_memory_region(VecMemoryRegion::new(0x100, alloc::vec![1; 0x100]));
+        device_memory.add_memory_region(VecMemoryRegion::new(0x200, alloc::vec![2; 0x100]));
+        device_memory.add_memory_region(VecMemoryRegion::new(0x400, alloc::vec![3; 0x100]));
+
+        device_memory.compact().unwrap();
+
+        assert_eq!(device_memory.memory_regions.len(), 2);
+        assert_eq!(
+            device_memory.memory_regions[0].address_range(),
+            Some(0x100..0x300)
+        );
+        assert_eq!(device_memory.read_u8(0x1FF), Ok(Some(1)));
+        assert_eq!(device_memory.read_u8(0x200), Ok(Some(2)));
+        assert_eq!(device_memory.read_u8(0x400), Ok(Some(3)));
+    }
+
+    #[test]
+    fn sorted_regions() {
+        let mut device_memory = DeviceMemory::<u32>::new();
+        for chunk in (0..16u8).rev() {
+            device_memory.add_memory_region(VecMemoryRegion::new(
+                0x1000 + chunk as u64 * 0x20,
+                alloc::vec![chunk; 0x10],
+            ));
+        }
+        device_memory.add_image_memory_region(VecMemoryRegion::new(0x0, alloc::vec![0xEE; 0x10]));
+
+        assert!(device_memory.sorted_regions.is_some());
+        assert_eq!(device_memory.read_u8(0x8), Ok(Some(0xEE)));
+        assert_eq!(device_memory.read_u8(0x1000), Ok(Some(0)));
+        assert_eq!(device_memory.read_u8(0x11EF), Ok(Some(15)));
+        assert_eq!(device_memory.read_u8(0x1010), Ok(None));
+        assert_eq!(
+            device_memory.read_slice(0x1020..0x1024),
+            Ok(Some(alloc::vec![1; 4]))
+        );
+        assert_eq!(device_memory.read_slice(0x100C..0x1014), Ok(None));
+
+        // An overlapping region has to be read from with precedence
+        device_memory.add_memory_region(VecMemoryRegion::new(0x1008, alloc::vec![0xFF; 0x20]));
+        assert!(device_memory.sorted_regions.is_none());
+        assert_eq!(device_memory.read_u8(0x1000), Ok(Some(0)));
+        assert_eq!(device_memory.read_u8(0x1008), Ok(Some(0xFF)));
+        assert_eq!(device_memory.read_u8(0x1028), Ok(Some(1)));
+
+        // Compacting removes the overlap
+        device_memory.compact().unwrap();
+        assert!(device_memory.sorted_regions.is_some());
+        assert_eq!(device_memory.read_u8(0x1000), Ok(Some(0)));
+        assert_eq!(device_memory.read_u8(0x1008), Ok(Some(0xFF)));
+        assert_eq!(device_memory.read_u8(0x1028), Ok(Some(1)));
+        assert_eq!(device_memory.read_u8(0x1030), Ok(None));
+    }
+
+    #[test]
+    fn compact_keeps_precedence() {
+        let mut device_memory = DeviceMemory::<u32>::new();
+        device_memory.add_memory_region(VecMemoryRegion::new(0x100, alloc::vec![1; 0x100]));
+        device_memory.add_memory_region(FilledMemoryRegion(0xFF));
+        device_memory.add_memory_region(VecMemoryRegion::new(0x180, alloc::vec![2; 0x100]));
+        device_memory.add_memory_region(VecMemoryRegion::new(0x200, alloc::vec![3; 0x10]));
+        device_memory.add_memory_region(
+            VecMemoryRegion::new(0x280, alloc::vec![4; 0x10])
+                .with_kind(MemoryRegionKind::Peripheral),
+        );
+        device_memory.add_image_memory_region(VecMemoryRegion::new(0x300, alloc::vec![5; 0x10]));
+
+        device_memory.compact().unwrap();
+
+        // The regions before and after the one without an address range are not merged
+        // and the peripheral region is not merged with the RAM it touches
+        assert_eq!(device_memory.memory_regions.len(), 5);
+        // The region without an address range was added after the first region, so it overrides it
+        assert_eq!(device_memory.read_u8(0x100), Ok(Some(0xFF)));
+        assert_eq!(device_memory.read_u8(0x180), Ok(Some(2)));
+        assert_eq!(device_memory.read_u8(0x200), Ok(Some(3)));
+        assert_eq!(device_memory.read_u8(0x210), Ok(Some(2)));
+        assert_eq!(device_memory.read_u8(0x280), Ok(Some(4)));
+        // The image has the lowest precedence
+        assert_eq!(device_memory.read_u8(0x300), Ok(Some(0xFF)));
+        assert_eq!(
+            device_memory.memory_kind(0x280),
+            Some(MemoryRegionKind::Peripheral)
+        );
+    }
+}
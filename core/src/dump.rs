@@ -0,0 +1,559 @@
+//! Module containing the definition of a complete dump: all captured memory regions and register data together
+
+use crate::{
+    checksum::ChecksumIter,
+    device_memory::DeviceMemory,
+    dump_meta::{DumpMeta, DumpMetaFromIterError, DUMP_META_IDENTIFIER},
+    memory_region::{
+        FileMemoryRegion, MemoryRegionFromIterError, VecMemoryRegion,
+        CHECKSUMMED_MEMORY_REGION_IDENTIFIER, MEMORY_REGION_IDENTIFIER,
+        MEMORY_REGION_WITH_KIND_IDENTIFIER,
+    },
+    register_data::{
+        RegisterDataFromIterError, VecRegisterData, BIG_ENDIAN_REGISTER_DATA_IDENTIFIER,
+        CHECKSUMMED_REGISTER_DATA_IDENTIFIER, REGISTER_DATA_IDENTIFIER,
+    },
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    fmt::Display,
+    fs::File,
+    io::{BufRead, BufReader, Read},
+};
+
+/// The version of the serde representation of a [Dump].
+///
+/// This is increased every time the representation changes in a way that is not backwards compatible.
+pub const DUMP_FORMAT_VERSION: u32 = 1;
+
+/// An error that can occur when reading a dump from bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpBytesError {
+    /// An identifier was found that is not of a memory region or register data
+    UnexpectedIdentifier {
+        /// The identifier that was found
+        identifier: u8,
+        /// The offset in the bytes at which the identifier was found
+        offset: usize,
+    },
+    /// A memory region could not be read
+    MemoryRegion {
+        /// The error of the memory region
+        error: MemoryRegionFromIterError,
+        /// The offset in the bytes at which the memory region starts
+        offset: usize,
+    },
+    /// Register data could not be read
+    RegisterData {
+        /// The error of the register data
+        error: RegisterDataFromIterError,
+        /// The offset in the bytes at which the register data starts
+        offset: usize,
+    },
+    /// The dump meta could not be read
+    DumpMeta {
+        /// The error of the dump meta
+        error: DumpMetaFromIterError,
+        /// The offset in the bytes at which the dump meta starts
+        offset: usize,
+    },
+}
+impl Display for DumpBytesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnexpectedIdentifier { identifier, offset } => write!(
+                f,
+                "Dump data error. Got an unexpected identifier {:#04X} at offset {}",
+                identifier, offset
+            ),
+            Self::MemoryRegion { error, offset } => write!(
+                f,
+                "Dump data error. Could not read the memory region at offset {}: {}",
+                offset, error
+            ),
+            Self::RegisterData { error, offset } => write!(
+                f,
+                "Dump data error. Could not read the register data at offset {}: {}",
+                offset, error
+            ),
+            Self::DumpMeta { error, offset } => write!(
+                f,
+                "Dump data error. Could not read the dump meta at offset {}: {}",
+                offset, error
+            ),
+        }
+    }
+}
+impl Error for DumpBytesError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::UnexpectedIdentifier { .. } => None,
+            Self::MemoryRegion { error, .. } => Some(error),
+            Self::RegisterData { error, .. } => Some(error),
+            Self::DumpMeta { error, .. } => Some(error),
+        }
+    }
+}
+
+/// An error that can occur when reading a dump file with [add_dump_file_to_device_memory]
+#[derive(Debug)]
+pub enum DumpFileError {
+    /// The file could not be read
+    Io(std::io::Error),
+    /// The file does not contain a valid dump
+    Bytes(DumpBytesError),
+}
+impl Display for DumpFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "The dump file could not be read: {}", error),
+            Self::Bytes(error) => write!(f, "{}", error),
+        }
+    }
+}
+impl Error for DumpFileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            Self::Bytes(error) => Some(error),
+        }
+    }
+}
+impl From<std::io::Error> for DumpFileError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+impl From<DumpBytesError> for DumpFileError {
+    fn from(error: DumpBytesError) -> Self {
+        Self::Bytes(error)
+    }
+}
+
+/// All the memory regions and register data of a dump.
+///
+/// There are two ways to store a dump:
+/// - As bytes in the same format as the byte iterators of the memory regions and register data.
+///   This is compact and is what the capture crate produces. See [Dump::to_dump_bytes] and [Dump::from_dump_bytes].
+/// - Using serde in any format you like (e.g. JSON or CBOR). This is easier to inspect with other tools.
+///   The representation is a struct with a `version` (see [DUMP_FORMAT_VERSION]), `regions` and `registers` field.
+///   Every region has a `start_address`, the `data` bytes and a `kind` (see [MemoryRegionKind](crate::memory_region::MemoryRegionKind)).
+///   The kind is left out for RAM regions.
+///   Every register data has a `starting_register_number` (the DWARF register number) and the `registers` values.
+///   The optional `meta` field has the [DumpMeta] and is left out when there is none.
+///
+/// ```
+/// use stackdump_core::{dump::Dump, memory_region::VecMemoryRegion, register_data::VecRegisterData};
+///
+/// let dump = Dump::new(
+///     vec![VecMemoryRegion::new(0x2000_0000, vec![1, 2, 3, 4])],
+///     vec![VecRegisterData::new(stackdump_core::gimli::Arm::R0, vec![5u32, 6, 7, 8])],
+/// );
+///
+/// let bytes = dump.to_dump_bytes();
+/// assert_eq!(Dump::from_dump_bytes(&bytes), Ok(dump));
+/// ```
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Dump<RB> {
+    /// The version of the format. Is [DUMP_FORMAT_VERSION] for dumps created by this version of the crate.
+    pub version: u32,
+    /// All captured memory regions
+    pub regions: Vec<VecMemoryRegion>,
+    /// All captured register data
+    pub registers: Vec<VecRegisterData<RB>>,
+    /// When and why the dump was made, if that was captured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<DumpMeta>,
+}
+
+impl<RB: funty::Integral> Dump<RB> {
+    /// Creates a new dump with the current format version
+    pub fn new(regions: Vec<VecMemoryRegion>, registers: Vec<VecRegisterData<RB>>) -> Self {
+        Self {
+            version: DUMP_FORMAT_VERSION,
+            regions,
+            registers,
+            meta: None,
+        }
+    }
+
+    /// Sets the meta data of the dump
+    pub fn with_meta(mut self, meta: DumpMeta) -> Self {
+        self.meta = Some(meta);
+        self
+    }
+
+    /// Gets the bytes of the dump in the format of the byte iterators of the memory regions and register data.
+    ///
+    /// First the meta data is put in (if any), then all memory regions and then all register data.
+    pub fn to_dump_bytes(&self) -> Vec<u8> {
+        self.meta
+            .iter()
+            .flat_map(|meta| meta.bytes())
+            .chain(self.regions.iter().flat_map(|region| region.bytes()))
+            .chain(
+                self.registers
+                    .iter()
+                    .flat_map(|registers| registers.bytes()),
+            )
+            .collect()
+    }
+
+    /// Reads a dump from bytes in the format of the byte iterators of the memory regions and register data.
+    /// The meta data, regions and register data may be in any order.
+    /// If there are multiple meta data records, the last one is used.
+    ///
+    /// Returns an error when an unknown identifier is found or when a region or register data is invalid or truncated.
+    /// This never panics, so it can be used on untrusted bytes.
+    pub fn from_dump_bytes(bytes: &[u8]) -> Result<Self, DumpBytesError>
+    where
+        RB::Bytes: for<'a> TryFrom<&'a [u8]>,
+    {
+        let mut regions = Vec::new();
+        let mut registers = Vec::new();
+        let mut meta = None;
+
+        let mut iter = bytes.iter().copied().peekable();
+
+        while let Some(identifier) = iter.peek().copied() {
+            let offset = bytes.len() - iter.len();
+
+            match identifier {
+                MEMORY_REGION_IDENTIFIER
+                | MEMORY_REGION_WITH_KIND_IDENTIFIER
+                | CHECKSUMMED_MEMORY_REGION_IDENTIFIER => regions.push(
+                    VecMemoryRegion::try_from_iter(&mut iter)
+                        .map_err(|error| DumpBytesError::MemoryRegion { error, offset })?,
+                ),
+                REGISTER_DATA_IDENTIFIER
+                | BIG_ENDIAN_REGISTER_DATA_IDENTIFIER
+                | CHECKSUMMED_REGISTER_DATA_IDENTIFIER => registers.push(
+                    VecRegisterData::try_from_iter(&mut iter)
+                        .map_err(|error| DumpBytesError::RegisterData { error, offset })?,
+                ),
+                DUMP_META_IDENTIFIER => {
+                    meta = Some(
+                        DumpMeta::try_from_iter(&mut iter)
+                            .map_err(|error| DumpBytesError::DumpMeta { error, offset })?,
+                    )
+                }
+                identifier => {
+                    return Err(DumpBytesError::UnexpectedIdentifier { identifier, offset })
+                }
+            }
+        }
+
+        Ok(Self {
+            meta,
+            ..Self::new(regions, registers)
+        })
+    }
+
+    /// Adds all regions and register data of this dump to the given device memory
+    pub fn add_to_device_memory(self, device_memory: &mut DeviceMemory<RB>) {
+        for region in self.regions {
+            device_memory.add_memory_region(region);
+        }
+        for registers in self.registers {
+            device_memory.add_register_data(registers);
+        }
+    }
+}
+
+/// Iterates over the bytes of a reader and counts them. It stops at the first IO error, which is kept.
+struct ReaderBytes<R> {
+    reader: R,
+    count: usize,
+    error: Option<std::io::Error>,
+}
+
+impl<R> ReaderBytes<R> {
+    /// A read error ends the bytes early, so that is the real error instead of the given error about the format
+    fn take_error(&mut self, format_error: DumpBytesError) -> DumpFileError {
+        match self.error.take() {
+            Some(error) => DumpFileError::Io(error),
+            None => DumpFileError::Bytes(format_error),
+        }
+    }
+}
+
+impl<R: Read> Iterator for ReaderBytes<R> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut byte = [0];
+        match self.reader.read_exact(&mut byte) {
+            Ok(()) => {
+                self.count += 1;
+                Some(byte[0])
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => None,
+            Err(e) => {
+                self.error = Some(e);
+                None
+            }
+        }
+    }
+}
+
+/// Reads a dump file in the format of [Dump::from_dump_bytes] into the device memory.
+/// The meta data of the dump is returned, if it has any.
+///
+/// Unlike [Dump::from_dump_bytes], the data of the memory regions is not loaded.
+/// The regions are [FileMemoryRegion]s that read the data from the file when it's needed,
+/// so big dumps don't have to be loaded into memory.
+pub fn add_dump_file_to_device_memory<RB: funty::Integral>(
+    file: File,
+    device_memory: &mut DeviceMemory<RB>,
+) -> Result<Option<DumpMeta>, DumpFileError>
+where
+    RB::Bytes: for<'a> TryFrom<&'a [u8]>,
+{
+    let file_length = file.metadata()?.len();
+    let mut bytes = ReaderBytes {
+        reader: BufReader::new(file.try_clone()?),
+        count: 0,
+        error: None,
+    };
+    let mut meta = None;
+
+    while let Some(identifier) = bytes.reader.fill_buf()?.first().copied() {
+        let offset = bytes.count;
+
+        match identifier {
+            MEMORY_REGION_IDENTIFIER
+            | MEMORY_REGION_WITH_KIND_IDENTIFIER
+            | CHECKSUMMED_MEMORY_REGION_IDENTIFIER => {
+                let mut checked_bytes = ChecksumIter::new(&mut bytes);
+                let (kind, start_address, length, checksummed) =
+                    crate::memory_region::read_header(&mut checked_bytes).map_err(|error| {
+                        checked_bytes
+                            .inner
+                            .take_error(DumpBytesError::MemoryRegion { error, offset })
+                    })?;
+
+                let data_offset = checked_bytes.inner.count as u64;
+                if length > file_length.saturating_sub(data_offset) {
+                    return Err(DumpBytesError::MemoryRegion {
+                        error: MemoryRegionFromIterError::NotEnoughItems,
+                        offset,
+                    }
+                    .into());
+                }
+
+                if checksummed {
+                    // The data is only read to check the checksum. It's read from the file again when it's needed
+                    for _ in 0..length {
+                        checked_bytes.next();
+                    }
+                    crate::memory_region::read_checksum(&mut checked_bytes).map_err(|error| {
+                        checked_bytes
+                            .inner
+                            .take_error(DumpBytesError::MemoryRegion { error, offset })
+                    })?;
+                } else {
+                    // The data is skipped, it's read from the file when it's needed
+                    bytes.reader.seek_relative(length as i64)?;
+                    bytes.count += length as usize;
+                }
+
+                device_memory.add_memory_region(
+                    FileMemoryRegion::new(file.try_clone()?, data_offset, start_address, length)
+                        .with_kind(kind),
+                );
+            }
+            REGISTER_DATA_IDENTIFIER
+            | BIG_ENDIAN_REGISTER_DATA_IDENTIFIER
+            | CHECKSUMMED_REGISTER_DATA_IDENTIFIER => {
+                let registers = VecRegisterData::try_from_iter(&mut bytes).map_err(|error| {
+                    bytes.take_error(DumpBytesError::RegisterData { error, offset })
+                })?;
+                device_memory.add_register_data(registers);
+            }
+            DUMP_META_IDENTIFIER => {
+                meta = Some(DumpMeta::try_from_iter(&mut bytes).map_err(|error| {
+                    bytes.take_error(DumpBytesError::DumpMeta { error, offset })
+                })?);
+            }
+            identifier => {
+                return Err(DumpBytesError::UnexpectedIdentifier { identifier, offset }.into())
+            }
+        }
+    }
+
+    Ok(meta)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dump_meta::DumpReason;
+
+    #[test]
+    fn serde_roundtrip() {
+        let dump = Dump::new(
+            vec![
+                VecMemoryRegion::new(0x2000_0000, vec![1, 2, 3, 4]),
+                VecMemoryRegion::new(0x0800_0000, vec![5, 6]),
+            ],
+            vec![VecRegisterData::new(gimli::Arm::R0, vec![1u32, 2, 3])],
+        );
+
+        let json = serde_json::to_string(&dump).unwrap();
+        assert_eq!(
+            json,
+            r#"{"version":1,"regions":[{"start_address":536870912,"data":[1,2,3,4]},{"start_address":134217728,"data":[5,6]}],"registers":[{"starting_register_number":0,"registers":[1,2,3]}]}"#
+        );
+        assert_eq!(serde_json::from_str::<Dump<u32>>(&json).unwrap(), dump);
+    }
+
+    #[test]
+    fn dump_bytes_with_meta() {
+        let dump = Dump::new(
+            vec![VecMemoryRegion::new(0x2000_0000, vec![1, 2, 3, 4])],
+            vec![VecRegisterData::new(gimli::Arm::R0, vec![1u32, 2, 3])],
+        )
+        .with_meta(DumpMeta::new(42, DumpReason::HardFault).with_message("HFSR: 0x40000000"));
+
+        let bytes = dump.to_dump_bytes();
+        assert_eq!(bytes[0], DUMP_META_IDENTIFIER);
+        assert_eq!(Dump::from_dump_bytes(&bytes), Ok(dump.clone()));
+
+        let json = serde_json::to_string(&dump).unwrap();
+        assert_eq!(serde_json::from_str::<Dump<u32>>(&json).unwrap(), dump);
+    }
+
+    #[test]
+    fn from_dump_bytes_unexpected_identifier() {
+        let mut bytes = VecMemoryRegion::new(0x2000_0000, vec![1, 2, 3, 4])
+            .bytes()
+            .collect::<Vec<_>>();
+        let offset = bytes.len();
+        bytes.push(0xFF);
+
+        assert_eq!(
+            Dump::<u32>::from_dump_bytes(&bytes),
+            Err(DumpBytesError::UnexpectedIdentifier {
+                identifier: 0xFF,
+                offset
+            })
+        );
+    }
+
+    #[test]
+    fn from_dump_bytes_truncated() {
+        let mut bytes = VecMemoryRegion::new(0x2000_0000, vec![1, 2, 3, 4])
+            .bytes()
+            .collect::<Vec<_>>();
+        let offset = bytes.len();
+        bytes.extend(VecRegisterData::new(gimli::Arm::R0, vec![1u32, 2, 3]).bytes());
+
+        for length in 0..bytes.len() {
+            let result = Dump::<u32>::from_dump_bytes(&bytes[..length]);
+
+            match length {
+                0 => assert_eq!(result, Ok(Dump::new(Vec::new(), Vec::new()))),
+                length if length < offset => assert_eq!(
+                    result,
+                    Err(DumpBytesError::MemoryRegion {
+                        error: MemoryRegionFromIterError::NotEnoughItems,
+                        offset: 0
+                    })
+                ),
+                length if length == offset => assert_eq!(result.unwrap().regions.len(), 1),
+                _ => assert_eq!(
+                    result,
+                    Err(DumpBytesError::RegisterData {
+                        error: RegisterDataFromIterError::NotEnoughItems,
+                        offset
+                    })
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn high_address_region() {
+        let dump = Dump::new(
+            vec![VecMemoryRegion::new(u64::MAX - 1, vec![1, 2, 3, 4])],
+            vec![VecRegisterData::new(gimli::Arm::R0, vec![1u32, 2, 3])],
+        );
+
+        assert_eq!(
+            Dump::<u32>::from_dump_bytes(&dump.to_dump_bytes()),
+            Err(DumpBytesError::MemoryRegion {
+                error: MemoryRegionFromIterError::AddressOverflow {
+                    start_address: u64::MAX - 1,
+                    length: 4
+                },
+                offset: 0
+            })
+        );
+
+        // A region made in code instead of read from bytes must not make the device memory panic either
+        let mut device_memory = DeviceMemory::<u32>::new();
+        dump.add_to_device_memory(&mut device_memory);
+        assert_eq!(device_memory.read_u8(u64::MAX - 1), Ok(Some(1)));
+    }
+
+    #[test]
+    fn dump_file() {
+        let dump = Dump::new(
+            vec![VecMemoryRegion::new(0x2000_0000, vec![1, 2, 3, 4])],
+            vec![VecRegisterData::new(gimli::Arm::R0, vec![5u32, 6])],
+        )
+        .with_meta(DumpMeta::new(42, DumpReason::Panic));
+
+        let path = std::env::temp_dir().join(format!("stackdump-{}.dump", std::process::id()));
+        let mut bytes = dump.to_dump_bytes();
+        std::fs::write(&path, &bytes).unwrap();
+
+        let mut device_memory = DeviceMemory::<u32>::new();
+        let meta = add_dump_file_to_device_memory(File::open(&path).unwrap(), &mut device_memory);
+        assert_eq!(meta.unwrap(), dump.meta);
+        assert_eq!(
+            device_memory.read_slice(0x2000_0001..0x2000_0004),
+            Ok(Some(vec![2, 3, 4]))
+        );
+        assert_eq!(device_memory.register(gimli::Arm::R1), Ok(6));
+
+        // A dump that ends in the middle of the data of a region
+        let offset = bytes.len();
+        bytes.extend(VecMemoryRegion::new(0x0800_0000, vec![1, 2, 3, 4]).bytes());
+        bytes.pop();
+        std::fs::write(&path, &bytes).unwrap();
+
+        let result = add_dump_file_to_device_memory(
+            File::open(&path).unwrap(),
+            &mut DeviceMemory::<u32>::new(),
+        );
+        assert!(matches!(
+            result,
+            Err(DumpFileError::Bytes(DumpBytesError::MemoryRegion {
+                error: MemoryRegionFromIterError::NotEnoughItems,
+                offset: error_offset
+            })) if error_offset == offset
+        ));
+
+        // A dump with a corrupted byte in the data of a region
+        bytes.truncate(offset);
+        bytes.extend(VecMemoryRegion::new(0x0800_0000, vec![1, 2, 3, 4]).bytes());
+        let last_data_index = bytes.len() - 5;
+        bytes[last_data_index] = 0xFF;
+        std::fs::write(&path, &bytes).unwrap();
+
+        let result = add_dump_file_to_device_memory(
+            File::open(&path).unwrap(),
+            &mut DeviceMemory::<u32>::new(),
+        );
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            result,
+            Err(DumpFileError::Bytes(DumpBytesError::MemoryRegion {
+                error: MemoryRegionFromIterError::ChecksumMismatch { .. },
+                offset: error_offset
+            })) if error_offset == offset
+        ));
+    }
+}
@@ -0,0 +1,438 @@
+//! Module containing the definitions for the meta data of a dump
+
+use arrayvec::{ArrayString, ArrayVec};
+use gimli::{Endianity, RunTimeEndian};
+use serde::{Deserialize, Serialize};
+
+/// The identifier that is being used in the byte iterator to be able to differentiate the dump meta from memory regions and register data
+pub const DUMP_META_IDENTIFIER: u8 = 0x04;
+
+/// The maximum length in bytes of the message of a [DumpMeta]
+pub const DUMP_META_MESSAGE_CAPACITY: usize = 32;
+
+/// The maximum length in bytes of the build id of a [DumpMeta].
+///
+/// The GNU build id is 20 bytes when made with the default `sha1` style.
+pub const DUMP_META_BUILD_ID_CAPACITY: usize = 32;
+
+/// The reason a dump was made
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Default, PartialEq, Eq, Hash)]
+pub enum DumpReason {
+    /// The reason is not known
+    #[default]
+    Unknown,
+    /// The program panicked
+    Panic,
+    /// A hardfault (or another fault exception) occurred
+    HardFault,
+    /// The watchdog is about to reset the device
+    Watchdog,
+    /// A user defined reason.
+    ///
+    /// The value should not be one of the values of the other reasons, so use e.g. `0x80` and higher.
+    Other(u8),
+}
+
+impl DumpReason {
+    /// Gets the byte that is used for this reason in the byte iterator
+    pub fn as_u8(&self) -> u8 {
+        match self {
+            Self::Unknown => 0,
+            Self::Panic => 1,
+            Self::HardFault => 2,
+            Self::Watchdog => 3,
+            Self::Other(value) => *value,
+        }
+    }
+
+    /// Gets the reason from the byte that is used for it in the byte iterator
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            0 => Self::Unknown,
+            1 => Self::Panic,
+            2 => Self::HardFault,
+            3 => Self::Watchdog,
+            value => Self::Other(value),
+        }
+    }
+}
+
+impl core::fmt::Display for DumpReason {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Unknown => write!(f, "unknown"),
+            Self::Panic => write!(f, "panic"),
+            Self::HardFault => write!(f, "hardfault"),
+            Self::Watchdog => write!(f, "watchdog"),
+            Self::Other(value) => write!(f, "other ({:#04X})", value),
+        }
+    }
+}
+
+/// Information about when and why a dump was made.
+///
+/// This helps to order and triage dumps when many of them are collected.
+/// In the byte iterator it is a header record that is put in front of the memory regions and register data.
+#[derive(Clone, Debug, Deserialize, Serialize, Default, PartialEq, Eq)]
+pub struct DumpMeta {
+    /// A monotonic timestamp of when the dump was made.
+    /// The unit is up to the user, e.g. the value of a cycle counter or an RTC.
+    pub timestamp: u64,
+    /// The reason the dump was made
+    pub reason: DumpReason,
+    /// A short message with more details, e.g. the location of a panic
+    #[serde(default, skip_serializing_if = "ArrayString::is_empty")]
+    pub message: ArrayString<DUMP_META_MESSAGE_CAPACITY>,
+    /// The build id of the program that made the dump, e.g. the GNU build id of the elf file.
+    /// It can be used to find the elf file that belongs to the dump.
+    #[serde(default, skip_serializing_if = "ArrayVec::is_empty")]
+    pub build_id: ArrayVec<u8, DUMP_META_BUILD_ID_CAPACITY>,
+}
+
+impl DumpMeta {
+    /// Create new dump meta data without a message
+    pub fn new(timestamp: u64, reason: DumpReason) -> Self {
+        Self {
+            timestamp,
+            reason,
+            message: ArrayString::new(),
+            build_id: ArrayVec::new(),
+        }
+    }
+
+    /// Sets the build id of the meta data.
+    ///
+    /// A build id that is longer than [DUMP_META_BUILD_ID_CAPACITY] is truncated.
+    pub fn with_build_id(mut self, build_id: &[u8]) -> Self {
+        self.build_id.clear();
+        let length = build_id.len().min(DUMP_META_BUILD_ID_CAPACITY);
+        self.build_id
+            .try_extend_from_slice(&build_id[..length])
+            .expect("The build id is truncated to the capacity");
+        self
+    }
+
+    /// Sets the message of the meta data.
+    ///
+    /// A message that is longer than [DUMP_META_MESSAGE_CAPACITY] is truncated on a character boundary.
+    pub fn with_message(mut self, message: &str) -> Self {
+        self.message.clear();
+        for c in message.chars() {
+            if self.message.try_push(c).is_err() {
+                break;
+            }
+        }
+        self
+    }
+
+    /// Get a byte iterator for this meta data.
+    ///
+    /// This iterator can be used to store the meta data as bytes or to stream over a network.
+    /// The iterated bytes include the length of the message so that if you use the FromIterator implementation,
+    /// it consumes only the bytes that are part of the meta data.
+    /// This means you can chain the memory regions and register data after it.
+    ///
+    /// ```
+    /// use stackdump_core::dump_meta::{DumpMeta, DumpReason};
+    ///
+    /// let meta = DumpMeta::new(1234, DumpReason::Panic).with_message("main.rs:12");
+    ///
+    /// assert_eq!(meta, DumpMeta::from_iter(meta.bytes()));
+    /// ```
+    pub fn bytes(&self) -> DumpMetaBytesIterator<'_> {
+        DumpMetaBytesIterator {
+            meta: self,
+            index: 0,
+        }
+    }
+
+    /// Writes the bytes of [Self::bytes] to the writer in chunks instead of one by one, see [crate::io::write_bytes]
+    #[cfg(feature = "embedded-io")]
+    pub fn write_to<W: embedded_io::Write>(&self, writer: &mut W) -> Result<(), W::Error> {
+        crate::io::write_bytes(writer, self.bytes())
+    }
+
+    /// Reads the meta data from bytes in the format of [Self::bytes].
+    ///
+    /// Only the bytes of the meta data are consumed.
+    /// Returns an error instead of panicking when the bytes are not valid meta data.
+    pub fn try_from_iter<T: IntoIterator<Item = u8>>(
+        iter: T,
+    ) -> Result<Self, DumpMetaFromIterError> {
+        let mut iter = iter.into_iter();
+        let mut next = || iter.next().ok_or(DumpMetaFromIterError::NotEnoughItems);
+
+        match next()? {
+            DUMP_META_IDENTIFIER => {}
+            identifier => return Err(DumpMetaFromIterError::InvalidIdentifier(identifier)),
+        }
+
+        let mut timestamp_bytes = [0; 8];
+        for byte in timestamp_bytes.iter_mut() {
+            *byte = next()?;
+        }
+        let timestamp = u64::from_le_bytes(timestamp_bytes);
+
+        let reason = DumpReason::from_u8(next()?);
+
+        let message_length = next()? as usize;
+        if message_length > DUMP_META_MESSAGE_CAPACITY {
+            return Err(DumpMetaFromIterError::InvalidMessage);
+        }
+
+        let mut message_bytes = [0; DUMP_META_MESSAGE_CAPACITY];
+        for byte in message_bytes[..message_length].iter_mut() {
+            *byte = next()?;
+        }
+        let message = core::str::from_utf8(&message_bytes[..message_length])
+            .ok()
+            .and_then(|message| ArrayString::from(message).ok())
+            .ok_or(DumpMetaFromIterError::InvalidMessage)?;
+
+        let build_id_length = next()? as usize;
+        if build_id_length > DUMP_META_BUILD_ID_CAPACITY {
+            return Err(DumpMetaFromIterError::InvalidBuildId);
+        }
+
+        let mut build_id = ArrayVec::new();
+        for _ in 0..build_id_length {
+            build_id.push(next()?);
+        }
+
+        Ok(Self {
+            timestamp,
+            reason,
+            message,
+            build_id,
+        })
+    }
+}
+
+impl FromIterator<u8> for DumpMeta {
+    /// Reads the meta data from the bytes.
+    ///
+    /// Panics if the bytes are not valid meta data. Use [DumpMeta::try_from_iter] to get an error instead.
+    fn from_iter<T: IntoIterator<Item = u8>>(iter: T) -> Self {
+        Self::try_from_iter(iter).unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+impl core::fmt::Display for DumpMeta {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Dump at {} because of {}", self.timestamp, self.reason)?;
+        if !self.message.is_empty() {
+            write!(f, ": {}", self.message)?;
+        }
+        if !self.build_id.is_empty() {
+            write!(f, " (build id ")?;
+            for byte in self.build_id.iter() {
+                write!(f, "{:02x}", byte)?;
+            }
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+/// The type of the GNU build id note
+const NT_GNU_BUILD_ID: u32 = 3;
+
+/// Gets the build id out of the `.note.gnu.build-id` section of an elf file.
+///
+/// The note is in the byte order of the elf file, which is given with the endianness.
+/// On the device this is meant for a program that reads its own note (which can be made available with a symbol in the linker script)
+/// to put it in the [DumpMeta]. The elf file then has the byte order of the device, e.g. [RunTimeEndian::Little] on Cortex-M.
+///
+/// Returns None if the bytes are not a GNU build id note.
+pub fn gnu_build_id_from_note(note: &[u8], endianness: RunTimeEndian) -> Option<&[u8]> {
+    let read_u32 = |index: usize| -> Option<usize> {
+        let bytes = note.get(index * 4..index * 4 + 4)?;
+        usize::try_from(endianness.read_u32(bytes)).ok()
+    };
+
+    let name_size = read_u32(0)?;
+    let description_size = read_u32(1)?;
+    if read_u32(2)? != NT_GNU_BUILD_ID as usize {
+        return None;
+    }
+
+    // The name is padded to a multiple of 4 bytes.
+    // The sizes come from the note, so they can be anything and must not overflow.
+    let name_start: usize = 12;
+    let name_end = name_start.checked_add(name_size)?;
+    let description_start = name_size
+        .checked_add(3)
+        .map(|padded_size| padded_size / 4 * 4)?
+        .checked_add(name_start)?;
+    let description_end = description_start.checked_add(description_size)?;
+
+    if note.get(name_start..name_end)? != b"GNU\0" {
+        return None;
+    }
+
+    note.get(description_start..description_end)
+}
+
+/// An error that can occur when reading dump meta data from bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpMetaFromIterError {
+    /// The bytes ended before the meta data was complete
+    NotEnoughItems,
+    /// The first byte is not the [DUMP_META_IDENTIFIER]
+    InvalidIdentifier(u8),
+    /// The message is too long or is not valid UTF-8
+    InvalidMessage,
+    /// The build id is too long
+    InvalidBuildId,
+}
+
+impl core::fmt::Display for DumpMetaFromIterError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::NotEnoughItems => write!(f, "The bytes ended before the dump meta was complete"),
+            Self::InvalidIdentifier(identifier) => write!(
+                f,
+                "The bytes are not for dump meta. Got identifier {:#04X}",
+                identifier
+            ),
+            Self::InvalidMessage => {
+                write!(f, "The dump meta message is too long or not valid UTF-8")
+            }
+            Self::InvalidBuildId => write!(f, "The dump meta build id is too long"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DumpMetaFromIterError {}
+
+/// An iterator that iterates over the serialized bytes of dump meta data
+pub struct DumpMetaBytesIterator<'a> {
+    meta: &'a DumpMeta,
+    index: usize,
+}
+
+impl<'a> Iterator for DumpMetaBytesIterator<'a> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let message_length = self.meta.message.len();
+        let byte = match self.index {
+            0 => DUMP_META_IDENTIFIER,
+            index @ 1..=8 => self.meta.timestamp.to_le_bytes()[index - 1],
+            9 => self.meta.reason.as_u8(),
+            10 => message_length as u8,
+            index if index < 11 + message_length => self.meta.message.as_bytes()[index - 11],
+            index if index == 11 + message_length => self.meta.build_id.len() as u8,
+            index => *self.meta.build_id.get(index - 12 - message_length)?,
+        };
+
+        self.index += 1;
+        Some(byte)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining_length = 12 + self.meta.message.len() + self.meta.build_id.len() - self.index;
+        (remaining_length, Some(remaining_length))
+    }
+}
+
+impl<'a> ExactSizeIterator for DumpMetaBytesIterator<'a> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iterator() {
+        let meta = DumpMeta::new(0x0123_4567_89AB_CDEF, DumpReason::Watchdog)
+            .with_message("wdt")
+            .with_build_id(&[0xAB, 0xCD]);
+        let bytes = meta.bytes().collect::<Vec<_>>();
+
+        assert_eq!(bytes.len(), meta.bytes().len());
+        assert_eq!(bytes.len(), 12 + 3 + 2);
+        assert_eq!(meta, DumpMeta::from_iter(bytes.iter().copied()));
+
+        for length in 0..bytes.len() {
+            assert_eq!(
+                DumpMeta::try_from_iter(bytes[..length].iter().copied()),
+                Err(DumpMetaFromIterError::NotEnoughItems)
+            );
+        }
+    }
+
+    #[test]
+    fn message_is_truncated() {
+        let meta = DumpMeta::new(0, DumpReason::Panic).with_message(&"ä".repeat(20));
+
+        assert_eq!(meta.message.as_str(), "ä".repeat(16));
+    }
+
+    #[test]
+    fn build_id() {
+        let meta = DumpMeta::new(0, DumpReason::Panic).with_build_id(&[0x12; 40]);
+        assert_eq!(
+            meta.build_id.as_slice(),
+            [0x12; DUMP_META_BUILD_ID_CAPACITY]
+        );
+
+        let meta = DumpMeta::new(0, DumpReason::Panic).with_build_id(&[0x01, 0xAB]);
+        assert_eq!(
+            meta.to_string(),
+            "Dump at 0 because of panic (build id 01ab)"
+        );
+    }
+
+    #[test]
+    fn gnu_build_id_note() {
+        let note = |endianness: RunTimeEndian, name_size: u32| {
+            let to_bytes = |value: u32| match endianness {
+                RunTimeEndian::Little => value.to_le_bytes(),
+                RunTimeEndian::Big => value.to_be_bytes(),
+            };
+            [
+                &to_bytes(name_size)[..],
+                &to_bytes(3),
+                &to_bytes(NT_GNU_BUILD_ID),
+                b"GNU\0",
+                &[0xAB, 0xCD, 0xEF],
+            ]
+            .concat()
+        };
+
+        for endianness in [RunTimeEndian::Little, RunTimeEndian::Big] {
+            let mut note = note(endianness, 4);
+            assert_eq!(
+                gnu_build_id_from_note(&note, endianness),
+                Some(&[0xAB, 0xCD, 0xEF][..])
+            );
+            assert_eq!(
+                gnu_build_id_from_note(&note[..note.len() - 1], endianness),
+                None
+            );
+
+            note[if endianness == RunTimeEndian::Little {
+                8
+            } else {
+                11
+            }] = 1;
+            assert_eq!(gnu_build_id_from_note(&note, endianness), None);
+        }
+
+        // In the wrong byte order the sizes are wrong
+        assert_eq!(
+            gnu_build_id_from_note(&note(RunTimeEndian::Big, 4), RunTimeEndian::Little),
+            None
+        );
+
+        // A size that makes the offsets overflow is not a valid note
+        assert_eq!(
+            gnu_build_id_from_note(
+                &note(RunTimeEndian::Little, u32::MAX),
+                RunTimeEndian::Little
+            ),
+            None
+        );
+    }
+}
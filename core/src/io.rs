@@ -0,0 +1,175 @@
+//! Writing and reading the bytes of captured data in bulk with [embedded_io]
+//!
+//! The byte iterators of the memory regions, the register data and the meta data give one byte at a time.
+//! Writing those one by one to e.g. an RTT channel or a UART is slow, because every write has its own overhead.
+//! [write_bytes] gathers the bytes in a small buffer and writes them in chunks instead.
+//! [ReadBytes] does the opposite: it reads chunks and gives the bytes one by one to the `try_from_iter` functions.
+//!
+//! The bytes are the same as those of the iterators, so data written like this can be read by any of the parsers.
+//!
+//! ```
+//! use stackdump_core::{
+//!     io::{write_bytes, ReadBytes},
+//!     memory_region::VecMemoryRegion,
+//!     register_data::VecRegisterData,
+//! };
+//!
+//! let registers = VecRegisterData::new(stackdump_core::gimli::Arm::R0, vec![1u32, 2, 3, 4]);
+//! let stack = VecMemoryRegion::new(0x2000_0000, vec![5; 100]);
+//!
+//! let mut channel = [0; 256];
+//! let mut writer = &mut channel[..];
+//! write_bytes(&mut writer, registers.bytes().chain(stack.bytes())).unwrap();
+//! let written = 256 - writer.len();
+//!
+//! let mut bytes = ReadBytes::new(&channel[..written]);
+//! assert_eq!(VecRegisterData::try_from_iter(&mut bytes), Ok(registers));
+//! assert_eq!(VecMemoryRegion::try_from_iter(&mut bytes), Ok(stack));
+//! assert!(bytes.error().is_none());
+//! ```
+
+use embedded_io::{Read, Write};
+
+/// The size of the buffer on the stack that is used for the chunks
+const BUFFER_SIZE: usize = 64;
+
+/// Writes the bytes to the writer in chunks.
+///
+/// Nothing is allocated, the chunks are gathered in a small buffer on the stack.
+/// Chain the byte iterators of everything that has been captured to write it all at once.
+pub fn write_bytes<W: Write>(
+    writer: &mut W,
+    bytes: impl IntoIterator<Item = u8>,
+) -> Result<(), W::Error> {
+    let mut buffer = [0; BUFFER_SIZE];
+    let mut length = 0;
+
+    for byte in bytes {
+        if length == BUFFER_SIZE {
+            writer.write_all(&buffer)?;
+            length = 0;
+        }
+        buffer[length] = byte;
+        length += 1;
+    }
+
+    writer.write_all(&buffer[..length])?;
+    writer.flush()
+}
+
+/// An iterator over the bytes of a reader, which reads them in chunks.
+///
+/// Use it by mutable reference in the `try_from_iter` functions, so it can be used for the next one as well.
+/// Bytes are read ahead, so bytes after the captured data may have been read from the reader already.
+///
+/// The iterator ends when the reader has no more bytes or gives an error. The error can be gotten with [Self::error].
+pub struct ReadBytes<R: Read> {
+    reader: R,
+    buffer: [u8; BUFFER_SIZE],
+    position: usize,
+    length: usize,
+    error: Option<R::Error>,
+}
+
+impl<R: Read> ReadBytes<R> {
+    /// Creates the iterator over the bytes of the reader
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buffer: [0; BUFFER_SIZE],
+            position: 0,
+            length: 0,
+            error: None,
+        }
+    }
+
+    /// The error of the reader, if it gave one
+    pub fn error(&self) -> Option<&R::Error> {
+        self.error.as_ref()
+    }
+
+    /// Gets the reader back
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Iterator for ReadBytes<R> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.position == self.length {
+            if self.error.is_some() {
+                return None;
+            }
+
+            self.position = 0;
+            self.length = match self.reader.read(&mut self.buffer) {
+                Ok(length) => length,
+                Err(e) => {
+                    self.error = Some(e);
+                    0
+                }
+            };
+
+            // A read of 0 bytes means the reader has reached its end
+            if self.length == 0 {
+                return None;
+            }
+        }
+
+        let byte = self.buffer[self.position];
+        self.position += 1;
+        Some(byte)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_region::ArrayMemoryRegion;
+
+    /// A writer that counts the writes it gets
+    struct CountingWriter {
+        bytes: Vec<u8>,
+        writes: usize,
+    }
+
+    impl embedded_io::ErrorType for CountingWriter {
+        type Error = core::convert::Infallible;
+    }
+
+    impl Write for CountingWriter {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.bytes.extend_from_slice(buf);
+            self.writes += 1;
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn write_in_chunks() {
+        let region = ArrayMemoryRegion::<256>::new(0x2000_0000, [7; 256].into());
+
+        let mut writer = CountingWriter {
+            bytes: Vec::new(),
+            writes: 0,
+        };
+        write_bytes(&mut writer, region.bytes()).unwrap();
+
+        // The framing is the same as that of the byte iterator
+        assert_eq!(writer.bytes, region.bytes().collect::<Vec<_>>());
+        assert_eq!(writer.writes, region.bytes().len().div_ceil(BUFFER_SIZE));
+
+        let mut bytes = ReadBytes::new(writer.bytes.as_slice());
+        assert_eq!(
+            ArrayMemoryRegion::<256>::try_from_iter(&mut bytes),
+            Ok(region)
+        );
+        assert_eq!(bytes.next(), None);
+    }
+}
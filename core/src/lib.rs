@@ -2,8 +2,17 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 #![warn(missing_docs)]
 
-#[cfg(any(feature = "std", doc))]
+#[cfg(any(feature = "alloc", doc))]
+extern crate alloc;
+
+mod checksum;
+#[cfg(any(feature = "alloc", doc))]
 pub mod device_memory;
+#[cfg(feature = "std")]
+pub mod dump;
+pub mod dump_meta;
+#[cfg(feature = "embedded-io")]
+pub mod io;
 pub mod memory_region;
 pub mod register_data;
 
@@ -1,13 +1,206 @@
 //! Module containing the definitions for memory regions
 
-use arrayvec::ArrayVec;
+use crate::checksum::{ChecksumIter, Crc32};
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use arrayvec::{ArrayVec, CapacityError};
 use serde::{Deserialize, Serialize};
 
-/// The identifier that is being used in the byte iterator to be able to differentiate between memory regions and register data
+/// The identifier that was used in the byte iterator for memory regions before they had a checksum.
+/// Regions with this identifier can still be read and are of the [MemoryRegionKind::Ram] kind.
 pub const MEMORY_REGION_IDENTIFIER: u8 = 0x01;
+/// The identifier that was used in the byte iterator for memory regions with a kind before they had a checksum.
+/// It is followed by a byte with the kind. Regions with this identifier can still be read.
+pub const MEMORY_REGION_WITH_KIND_IDENTIFIER: u8 = 0x03;
+/// The identifier that is being used in the byte iterator to be able to differentiate between memory regions and register data.
+/// It is followed by a byte with the [MEMORY_REGION_FORMAT_VERSION] and a byte with the kind.
+/// The bytes of the region end with a CRC-32 of all bytes before it, so a truncated or corrupted region is detected when it's read.
+pub const CHECKSUMMED_MEMORY_REGION_IDENTIFIER: u8 = 0x06;
+/// The version of the format of the bytes after the [CHECKSUMMED_MEMORY_REGION_IDENTIFIER].
+///
+/// It is increased when the format changes, so bytes of another version are rejected instead of being read wrongly.
+pub const MEMORY_REGION_FORMAT_VERSION: u8 = 1;
+
+/// The kind of memory a memory region has captured
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Default, PartialEq, Eq, Hash)]
+pub enum MemoryRegionKind {
+    /// Normal memory that contains program data, like the stack, the heap or static variables
+    #[default]
+    Ram,
+    /// Non-volatile memory that contains the program and read-only data
+    Flash,
+    /// Memory mapped peripheral registers (MMIO).
+    /// The data is the state of the peripheral and not program data, so it must not be decoded as such.
+    Peripheral,
+}
+
+impl MemoryRegionKind {
+    /// Returns true if this is the [MemoryRegionKind::Ram] kind
+    pub fn is_ram(&self) -> bool {
+        *self == Self::Ram
+    }
+
+    /// Gets the byte that is used for this kind in the byte iterator
+    pub fn as_u8(&self) -> u8 {
+        match self {
+            Self::Ram => 0,
+            Self::Flash => 1,
+            Self::Peripheral => 2,
+        }
+    }
+
+    /// Gets the kind from the byte that is used for it in the byte iterator
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Ram),
+            1 => Some(Self::Flash),
+            2 => Some(Self::Peripheral),
+            _ => None,
+        }
+    }
+}
+
+/// An error that can occur when reading a memory region from bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryRegionFromIterError {
+    /// The bytes ended before the memory region was complete
+    NotEnoughItems,
+    /// The first byte is not a memory region identifier
+    InvalidIdentifier(u8),
+    /// The format version byte is not the [MEMORY_REGION_FORMAT_VERSION]
+    UnsupportedVersion(u8),
+    /// The kind byte is not a known [MemoryRegionKind]
+    InvalidKind(u8),
+    /// The data of the memory region does not fit in the memory region
+    CapacityExceeded {
+        /// The length of the data in the bytes
+        needed: u64,
+        /// The capacity of the memory region
+        capacity: usize,
+    },
+    /// The checksum at the end of the memory region does not match its bytes, so they have been corrupted
+    ChecksumMismatch {
+        /// The checksum that is at the end of the bytes
+        expected: u32,
+        /// The checksum of the bytes that were read
+        actual: u32,
+    },
+    /// The memory region goes past the end of the address space, so the bytes have been corrupted
+    AddressOverflow {
+        /// The start address of the memory region
+        start_address: u64,
+        /// The length of the data of the memory region
+        length: u64,
+    },
+}
+
+impl core::fmt::Display for MemoryRegionFromIterError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::NotEnoughItems => {
+                write!(f, "The bytes ended before the memory region was complete")
+            }
+            Self::InvalidIdentifier(identifier) => write!(
+                f,
+                "The bytes are not for a memory region. Got identifier {:#04X}",
+                identifier
+            ),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "The memory region has format version {}, but only version {} is supported",
+                version, MEMORY_REGION_FORMAT_VERSION
+            ),
+            Self::InvalidKind(kind) => write!(f, "The memory region has an unknown kind: {}", kind),
+            Self::CapacityExceeded { needed, capacity } => write!(
+                f,
+                "The memory region has {} bytes of data, but only {} fit",
+                needed, capacity
+            ),
+            Self::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "The bytes of the memory region are corrupted. The checksum should be {:#010X}, but is {:#010X}",
+                expected, actual
+            ),
+            Self::AddressOverflow {
+                start_address,
+                length,
+            } => write!(
+                f,
+                "The memory region at {:#X} with {} bytes of data goes past the end of the address space",
+                start_address, length
+            ),
+        }
+    }
+}
 
-/// A collection of bytes that capture a memory region
 #[cfg(feature = "std")]
+impl std::error::Error for MemoryRegionFromIterError {}
+
+/// Reads the kind, start address and data length of a memory region from the bytes.
+/// The last value is true if the region ends with a checksum, see [read_checksum].
+pub(crate) fn read_header(
+    iter: &mut ChecksumIter<impl Iterator<Item = u8>>,
+) -> Result<(MemoryRegionKind, u64, u64, bool), MemoryRegionFromIterError> {
+    let mut next = || iter.next().ok_or(MemoryRegionFromIterError::NotEnoughItems);
+
+    let identifier = next()?;
+    let kind = match identifier {
+        MEMORY_REGION_IDENTIFIER => MemoryRegionKind::Ram,
+        MEMORY_REGION_WITH_KIND_IDENTIFIER | CHECKSUMMED_MEMORY_REGION_IDENTIFIER => {
+            if identifier == CHECKSUMMED_MEMORY_REGION_IDENTIFIER {
+                match next()? {
+                    MEMORY_REGION_FORMAT_VERSION => {}
+                    version => return Err(MemoryRegionFromIterError::UnsupportedVersion(version)),
+                }
+            }
+
+            let kind = next()?;
+            MemoryRegionKind::from_u8(kind).ok_or(MemoryRegionFromIterError::InvalidKind(kind))?
+        }
+        identifier => return Err(MemoryRegionFromIterError::InvalidIdentifier(identifier)),
+    };
+
+    let mut read_u64 = || -> Result<u64, MemoryRegionFromIterError> {
+        let mut bytes = [0; 8];
+        for byte in bytes.iter_mut() {
+            *byte = next()?;
+        }
+        Ok(u64::from_le_bytes(bytes))
+    };
+
+    let start_address = read_u64()?;
+    let length = read_u64()?;
+
+    if start_address.checked_add(length).is_none() {
+        return Err(MemoryRegionFromIterError::AddressOverflow {
+            start_address,
+            length,
+        });
+    }
+
+    Ok((
+        kind,
+        start_address,
+        length,
+        identifier == CHECKSUMMED_MEMORY_REGION_IDENTIFIER,
+    ))
+}
+
+/// Reads the checksum at the end of a memory region and checks it against the bytes that were read before it
+pub(crate) fn read_checksum(
+    iter: &mut ChecksumIter<impl Iterator<Item = u8>>,
+) -> Result<(), MemoryRegionFromIterError> {
+    match iter.read_checksum() {
+        Some((expected, actual)) if expected == actual => Ok(()),
+        Some((expected, actual)) => {
+            Err(MemoryRegionFromIterError::ChecksumMismatch { expected, actual })
+        }
+        None => Err(MemoryRegionFromIterError::NotEnoughItems),
+    }
+}
+
+/// A collection of bytes that capture a memory region
+#[cfg(feature = "alloc")]
 pub trait MemoryRegion {
     /// Returns the slice of memory that can be found at the given address_range.
     /// If the given address range is not fully within the captured region, then None is returned.
@@ -16,9 +209,31 @@ pub trait MemoryRegion {
         address_range: core::ops::Range<u64>,
     ) -> Result<Option<Vec<u8>>, crate::device_memory::MemoryReadError>;
 
+    /// Same as [Self::read], but borrows the memory instead of copying it when the region can do that.
+    ///
+    /// By default the memory is read with [Self::read], so regions that have their memory at hand should override this.
+    fn read_ref(
+        &self,
+        address_range: core::ops::Range<u64>,
+    ) -> Result<Option<alloc::borrow::Cow<'_, [u8]>>, crate::device_memory::MemoryReadError> {
+        Ok(self.read(address_range)?.map(alloc::borrow::Cow::Owned))
+    }
+
+    /// The kind of memory that this region contains
+    fn kind(&self) -> MemoryRegionKind {
+        MemoryRegionKind::Ram
+    }
+
+    /// The range of addresses that this region contains, if that is known up front.
+    ///
+    /// This is used to report what has been captured. Regions that read their memory lazily return None.
+    fn address_range(&self) -> Option<core::ops::Range<u64>> {
+        None
+    }
+
     /// Reads a byte from the given address if it is present in the region
     fn read_u8(&self, address: u64) -> Result<Option<u8>, crate::device_memory::MemoryReadError> {
-        Ok(self.read(address..address + 1)?.map(|b| b[0]))
+        Ok(self.read_ref(address..address + 1)?.map(|b| b[0]))
     }
 
     /// Reads a u32 from the given address if it is present in the region
@@ -28,7 +243,7 @@ pub trait MemoryRegion {
         endianness: gimli::RunTimeEndian,
     ) -> Result<Option<u32>, crate::device_memory::MemoryReadError> {
         if let Some(slice) = self
-            .read(address..address + 4)?
+            .read_ref(address..address + 4)?
             .map(|slice| slice[..].try_into().unwrap())
         {
             if gimli::Endianity::is_little_endian(endianness) {
@@ -40,6 +255,26 @@ pub trait MemoryRegion {
             Ok(None)
         }
     }
+
+    /// Reads a u64 from the given address if it is present in the region
+    fn read_u64(
+        &self,
+        address: u64,
+        endianness: gimli::RunTimeEndian,
+    ) -> Result<Option<u64>, crate::device_memory::MemoryReadError> {
+        if let Some(slice) = self
+            .read_ref(address..address + 8)?
+            .map(|slice| slice[..].try_into().unwrap())
+        {
+            if gimli::Endianity::is_little_endian(endianness) {
+                Ok(Some(u64::from_le_bytes(slice)))
+            } else {
+                Ok(Some(u64::from_be_bytes(slice)))
+            }
+        } else {
+            Ok(None)
+        }
+    }
 }
 
 /// A memory region that is backed by a stack allocated array
@@ -47,6 +282,8 @@ pub trait MemoryRegion {
 pub struct ArrayMemoryRegion<const SIZE: usize> {
     start_address: u64,
     data: ArrayVec<u8, SIZE>,
+    #[serde(default, skip_serializing_if = "MemoryRegionKind::is_ram")]
+    kind: MemoryRegionKind,
 }
 
 impl<const SIZE: usize> ArrayMemoryRegion<SIZE> {
@@ -55,9 +292,16 @@ impl<const SIZE: usize> ArrayMemoryRegion<SIZE> {
         Self {
             start_address,
             data,
+            kind: MemoryRegionKind::Ram,
         }
     }
 
+    /// Sets the kind of memory this region contains. By default this is [MemoryRegionKind::Ram].
+    pub fn with_kind(mut self, kind: MemoryRegionKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
     /// Get a byte iterator for this region.
     ///
     /// This iterator can be used to store the region as bytes or to stream over a network.
@@ -82,8 +326,14 @@ impl<const SIZE: usize> ArrayMemoryRegion<SIZE> {
     /// assert_eq!(region1, ArrayMemoryRegion::<4>::from_iter(&mut intermediate_iter));
     /// assert_eq!(region2, ArrayMemoryRegion::<4>::from_iter(&mut intermediate_iter));
     /// ```
-    pub fn bytes(&self) -> MemoryRegionIterator {
-        MemoryRegionIterator::new(self.start_address, &self.data)
+    pub fn bytes(&self) -> MemoryRegionIterator<'_> {
+        MemoryRegionIterator::new(self.start_address, &self.data, self.kind)
+    }
+
+    /// Writes the bytes of [Self::bytes] to the writer in chunks instead of one by one, see [crate::io::write_bytes]
+    #[cfg(feature = "embedded-io")]
+    pub fn write_to<W: embedded_io::Write>(&self, writer: &mut W) -> Result<(), W::Error> {
+        crate::io::write_bytes(writer, self.bytes())
     }
 
     /// Clears the existing memory data and copies the new data from the given pointer
@@ -95,22 +345,94 @@ impl<const SIZE: usize> ArrayMemoryRegion<SIZE> {
     /// The entire block of memory from `data_ptr .. data_ptr + data_len` must be readable.
     /// (A memcpy must be possible with the pointer as source)
     pub unsafe fn copy_from_memory(&mut self, data_ptr: *const u8, data_len: usize) {
+        self.try_copy_from_memory(data_ptr, data_len).unwrap();
+    }
+
+    /// Clears the existing memory data and copies the new data from the given pointer
+    ///
+    /// If the data_len is greater than the capacity of this memory region, then an error is returned and
+    /// the region is left untouched. This makes it usable in places where a panic is not acceptable,
+    /// like in a fault handler.
+    ///
+    /// ## Safety
+    ///
+    /// The entire block of memory from `data_ptr .. data_ptr + data_len` must be readable.
+    /// (A memcpy must be possible with the pointer as source)
+    pub unsafe fn try_copy_from_memory(
+        &mut self,
+        data_ptr: *const u8,
+        data_len: usize,
+    ) -> Result<(), CapacityError> {
+        if data_len > self.data.capacity() {
+            return Err(CapacityError::new(()));
+        }
+
         self.start_address = data_ptr as u64;
         self.data.clear();
 
-        assert!(data_len <= self.data.capacity());
-
         self.data.set_len(data_len);
         self.data.as_mut_ptr().copy_from(data_ptr, data_len);
+
+        Ok(())
+    }
+
+    /// Returns the amount of bytes that can still be added to this region before it is full
+    pub fn remaining_capacity(&self) -> usize {
+        self.data.remaining_capacity()
+    }
+
+    /// Reads a memory region from bytes in the format of [Self::bytes].
+    ///
+    /// Only the bytes of the memory region are consumed, so multiple regions can be read from the same iterator.
+    /// Returns an error instead of panicking when the bytes are not a valid memory region
+    /// or when the data does not fit in this region.
+    pub fn try_from_iter<T: IntoIterator<Item = u8>>(
+        iter: T,
+    ) -> Result<Self, MemoryRegionFromIterError> {
+        let mut iter = ChecksumIter::new(iter.into_iter());
+
+        let (kind, start_address, length, checksummed) = read_header(&mut iter)?;
+
+        if length > SIZE as u64 {
+            return Err(MemoryRegionFromIterError::CapacityExceeded {
+                needed: length,
+                capacity: SIZE,
+            });
+        }
+
+        let mut data = ArrayVec::new();
+        for _ in 0..length {
+            data.push(
+                iter.next()
+                    .ok_or(MemoryRegionFromIterError::NotEnoughItems)?,
+            );
+        }
+
+        if checksummed {
+            read_checksum(&mut iter)?;
+        }
+
+        Ok(Self {
+            start_address,
+            data,
+            kind,
+        })
     }
 }
 
-#[cfg(feature = "std")]
+#[cfg(feature = "alloc")]
 impl<const SIZE: usize> MemoryRegion for ArrayMemoryRegion<SIZE> {
     fn read(
         &self,
         index: core::ops::Range<u64>,
     ) -> Result<Option<Vec<u8>>, crate::device_memory::MemoryReadError> {
+        Ok(self.read_ref(index)?.map(alloc::borrow::Cow::into_owned))
+    }
+
+    fn read_ref(
+        &self,
+        index: core::ops::Range<u64>,
+    ) -> Result<Option<alloc::borrow::Cow<'_, [u8]>>, crate::device_memory::MemoryReadError> {
         let start = match index.start.checked_sub(self.start_address) {
             Some(start) => start,
             None => return Ok(None),
@@ -122,7 +444,16 @@ impl<const SIZE: usize> MemoryRegion for ArrayMemoryRegion<SIZE> {
         Ok(self
             .data
             .get(start as usize..end as usize)
-            .map(|slice| slice.to_vec()))
+            .map(alloc::borrow::Cow::Borrowed))
+    }
+
+    fn kind(&self) -> MemoryRegionKind {
+        self.kind
+    }
+
+    fn address_range(&self) -> Option<core::ops::Range<u64>> {
+        let end_address = self.start_address.checked_add(self.data.len() as u64)?;
+        Some(self.start_address..end_address)
     }
 }
 
@@ -133,64 +464,41 @@ impl<'a, const SIZE: usize> FromIterator<&'a u8> for ArrayMemoryRegion<SIZE> {
 }
 
 impl<const SIZE: usize> FromIterator<u8> for ArrayMemoryRegion<SIZE> {
+    /// Reads the memory region from the bytes.
+    ///
+    /// Panics if the bytes are not a valid memory region. Use [ArrayMemoryRegion::try_from_iter] to get an error instead.
     fn from_iter<T: IntoIterator<Item = u8>>(iter: T) -> Self {
-        let mut iter = iter.into_iter();
-
-        assert_eq!(
-            iter.next().unwrap(),
-            MEMORY_REGION_IDENTIFIER,
-            "The given iterator is not for a memory region"
-        );
-
-        let start_address = u64::from_le_bytes([
-            iter.next().unwrap(),
-            iter.next().unwrap(),
-            iter.next().unwrap(),
-            iter.next().unwrap(),
-            iter.next().unwrap(),
-            iter.next().unwrap(),
-            iter.next().unwrap(),
-            iter.next().unwrap(),
-        ]);
-
-        let length = u64::from_le_bytes([
-            iter.next().unwrap(),
-            iter.next().unwrap(),
-            iter.next().unwrap(),
-            iter.next().unwrap(),
-            iter.next().unwrap(),
-            iter.next().unwrap(),
-            iter.next().unwrap(),
-            iter.next().unwrap(),
-        ]);
-
-        let data = ArrayVec::from_iter(iter.take(length as usize));
-
-        Self {
-            start_address,
-            data,
-        }
+        Self::try_from_iter(iter).unwrap_or_else(|e| panic!("{}", e))
     }
 }
 
 /// A memory region that is backed by a stack allocated array
-#[cfg(feature = "std")]
+#[cfg(feature = "alloc")]
 #[derive(Clone, Debug, Deserialize, Serialize, Default, PartialEq, Eq)]
 pub struct VecMemoryRegion {
     start_address: u64,
     data: Vec<u8>,
+    #[serde(default, skip_serializing_if = "MemoryRegionKind::is_ram")]
+    kind: MemoryRegionKind,
 }
 
-#[cfg(feature = "std")]
+#[cfg(feature = "alloc")]
 impl VecMemoryRegion {
     /// Creates a new memory region starting at the given address with the given data
     pub fn new(start_address: u64, data: Vec<u8>) -> Self {
         Self {
             start_address,
             data,
+            kind: MemoryRegionKind::Ram,
         }
     }
 
+    /// Sets the kind of memory this region contains. By default this is [MemoryRegionKind::Ram].
+    pub fn with_kind(mut self, kind: MemoryRegionKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
     /// Get a byte iterator for this region.
     ///
     /// This iterator can be used to store the region as bytes or to stream over a network.
@@ -215,8 +523,14 @@ impl VecMemoryRegion {
     /// assert_eq!(region1, ArrayMemoryRegion::<4>::from_iter(&mut intermediate_iter));
     /// assert_eq!(region2, ArrayMemoryRegion::<4>::from_iter(&mut intermediate_iter));
     /// ```
-    pub fn bytes(&self) -> MemoryRegionIterator {
-        MemoryRegionIterator::new(self.start_address, &self.data)
+    pub fn bytes(&self) -> MemoryRegionIterator<'_> {
+        MemoryRegionIterator::new(self.start_address, &self.data, self.kind)
+    }
+
+    /// Writes the bytes of [Self::bytes] to the writer in chunks instead of one by one, see [crate::io::write_bytes]
+    #[cfg(feature = "embedded-io")]
+    pub fn write_to<W: embedded_io::Write>(&self, writer: &mut W) -> Result<(), W::Error> {
+        crate::io::write_bytes(writer, self.bytes())
     }
 
     /// Clears the existing memory data and copies the new data from the given pointer
@@ -234,14 +548,52 @@ impl VecMemoryRegion {
 
         self.data.as_mut_ptr().copy_from(data_ptr, data_len);
     }
+
+    /// Reads a memory region from bytes in the format of [Self::bytes].
+    ///
+    /// Only the bytes of the memory region are consumed, so multiple regions can be read from the same iterator.
+    /// Returns an error instead of panicking when the bytes are not a valid memory region.
+    pub fn try_from_iter<T: IntoIterator<Item = u8>>(
+        iter: T,
+    ) -> Result<Self, MemoryRegionFromIterError> {
+        let mut iter = ChecksumIter::new(iter.into_iter());
+
+        let (kind, start_address, length, checksummed) = read_header(&mut iter)?;
+
+        // The length comes from the bytes, so we don't trust it to preallocate
+        let mut data = Vec::new();
+        for _ in 0..length {
+            data.push(
+                iter.next()
+                    .ok_or(MemoryRegionFromIterError::NotEnoughItems)?,
+            );
+        }
+
+        if checksummed {
+            read_checksum(&mut iter)?;
+        }
+
+        Ok(Self {
+            start_address,
+            data,
+            kind,
+        })
+    }
 }
 
-#[cfg(feature = "std")]
+#[cfg(feature = "alloc")]
 impl MemoryRegion for VecMemoryRegion {
     fn read(
         &self,
         index: core::ops::Range<u64>,
     ) -> Result<Option<Vec<u8>>, crate::device_memory::MemoryReadError> {
+        Ok(self.read_ref(index)?.map(alloc::borrow::Cow::into_owned))
+    }
+
+    fn read_ref(
+        &self,
+        index: core::ops::Range<u64>,
+    ) -> Result<Option<alloc::borrow::Cow<'_, [u8]>>, crate::device_memory::MemoryReadError> {
         let start = match index.start.checked_sub(self.start_address) {
             Some(start) => start,
             None => return Ok(None),
@@ -253,56 +605,33 @@ impl MemoryRegion for VecMemoryRegion {
         Ok(self
             .data
             .get(start as usize..end as usize)
-            .map(|slice| slice.to_vec()))
+            .map(alloc::borrow::Cow::Borrowed))
+    }
+
+    fn kind(&self) -> MemoryRegionKind {
+        self.kind
+    }
+
+    fn address_range(&self) -> Option<core::ops::Range<u64>> {
+        let end_address = self.start_address.checked_add(self.data.len() as u64)?;
+        Some(self.start_address..end_address)
     }
 }
 
-#[cfg(feature = "std")]
+#[cfg(feature = "alloc")]
 impl<'a> FromIterator<&'a u8> for VecMemoryRegion {
     fn from_iter<T: IntoIterator<Item = &'a u8>>(iter: T) -> Self {
         Self::from_iter(iter.into_iter().copied())
     }
 }
 
-#[cfg(feature = "std")]
+#[cfg(feature = "alloc")]
 impl FromIterator<u8> for VecMemoryRegion {
+    /// Reads the memory region from the bytes.
+    ///
+    /// Panics if the bytes are not a valid memory region. Use [VecMemoryRegion::try_from_iter] to get an error instead.
     fn from_iter<T: IntoIterator<Item = u8>>(iter: T) -> Self {
-        let mut iter = iter.into_iter();
-
-        assert_eq!(
-            iter.next().unwrap(),
-            MEMORY_REGION_IDENTIFIER,
-            "The given iterator is not for a memory region"
-        );
-
-        let start_address = u64::from_le_bytes([
-            iter.next().unwrap(),
-            iter.next().unwrap(),
-            iter.next().unwrap(),
-            iter.next().unwrap(),
-            iter.next().unwrap(),
-            iter.next().unwrap(),
-            iter.next().unwrap(),
-            iter.next().unwrap(),
-        ]);
-
-        let length = u64::from_le_bytes([
-            iter.next().unwrap(),
-            iter.next().unwrap(),
-            iter.next().unwrap(),
-            iter.next().unwrap(),
-            iter.next().unwrap(),
-            iter.next().unwrap(),
-            iter.next().unwrap(),
-            iter.next().unwrap(),
-        ]);
-
-        let data = Vec::from_iter(iter.take(length as usize));
-
-        Self {
-            start_address,
-            data,
-        }
+        Self::try_from_iter(iter).unwrap_or_else(|e| panic!("{}", e))
     }
 }
 
@@ -310,12 +639,23 @@ impl FromIterator<u8> for VecMemoryRegion {
 #[derive(Clone, Debug, Deserialize, Serialize, Default, PartialEq, Eq)]
 pub struct SliceMemoryRegion<'a> {
     data: &'a [u8],
+    #[serde(default, skip_serializing_if = "MemoryRegionKind::is_ram")]
+    kind: MemoryRegionKind,
 }
 
 impl<'a> SliceMemoryRegion<'a> {
     /// Creates a new memory region starting at the given address with the given data
     pub fn new(data: &'a [u8]) -> Self {
-        Self { data }
+        Self {
+            data,
+            kind: MemoryRegionKind::Ram,
+        }
+    }
+
+    /// Sets the kind of memory this region contains. By default this is [MemoryRegionKind::Ram].
+    pub fn with_kind(mut self, kind: MemoryRegionKind) -> Self {
+        self.kind = kind;
+        self
     }
 
     /// Get a byte iterator for this region.
@@ -342,9 +682,15 @@ impl<'a> SliceMemoryRegion<'a> {
     /// assert_eq!(region1, ArrayMemoryRegion::<4>::from_iter(&mut intermediate_iter));
     /// assert_eq!(region2, ArrayMemoryRegion::<4>::from_iter(&mut intermediate_iter));
     /// ```
-    pub fn bytes(&self) -> MemoryRegionIterator {
+    pub fn bytes(&self) -> MemoryRegionIterator<'_> {
         let start_address = self.data.as_ptr() as u64;
-        MemoryRegionIterator::new(start_address, self.data)
+        MemoryRegionIterator::new(start_address, self.data, self.kind)
+    }
+
+    /// Writes the bytes of [Self::bytes] to the writer in chunks instead of one by one, see [crate::io::write_bytes]
+    #[cfg(feature = "embedded-io")]
+    pub fn write_to<W: embedded_io::Write>(&self, writer: &mut W) -> Result<(), W::Error> {
+        crate::io::write_bytes(writer, self.bytes())
     }
 
     /// This function is especially unsafe.
@@ -362,12 +708,19 @@ impl<'a> SliceMemoryRegion<'a> {
     }
 }
 
-#[cfg(feature = "std")]
+#[cfg(feature = "alloc")]
 impl<'a> MemoryRegion for SliceMemoryRegion<'a> {
     fn read(
         &self,
         index: core::ops::Range<u64>,
     ) -> Result<Option<Vec<u8>>, crate::device_memory::MemoryReadError> {
+        Ok(self.read_ref(index)?.map(alloc::borrow::Cow::into_owned))
+    }
+
+    fn read_ref(
+        &self,
+        index: core::ops::Range<u64>,
+    ) -> Result<Option<alloc::borrow::Cow<'_, [u8]>>, crate::device_memory::MemoryReadError> {
         let start_address = self.data.as_ptr() as u64;
         let start = match index.start.checked_sub(start_address) {
             Some(start) => start,
@@ -380,23 +733,113 @@ impl<'a> MemoryRegion for SliceMemoryRegion<'a> {
         Ok(self
             .data
             .get(start as usize..end as usize)
-            .map(|slice| slice.to_vec()))
+            .map(alloc::borrow::Cow::Borrowed))
+    }
+
+    fn kind(&self) -> MemoryRegionKind {
+        self.kind
+    }
+
+    fn address_range(&self) -> Option<core::ops::Range<u64>> {
+        let start_address = self.data.as_ptr() as u64;
+        let end_address = start_address.checked_add(self.data.len() as u64)?;
+        Some(start_address..end_address)
+    }
+}
+
+/// A memory region of which the data is stored in a file (or anything else that can seek), like a dump on disk.
+///
+/// The data is only read from the file when it's needed, so big dumps don't have to be loaded into memory.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct FileMemoryRegion<F = std::fs::File> {
+    start_address: u64,
+    length: u64,
+    file_offset: u64,
+    file: core::cell::RefCell<F>,
+    kind: MemoryRegionKind,
+}
+
+#[cfg(feature = "std")]
+impl<F: std::io::Read + std::io::Seek> FileMemoryRegion<F> {
+    /// Creates a new memory region starting at the given address with `length` bytes of data
+    /// that are stored in the file at `file_offset`
+    pub fn new(file: F, file_offset: u64, start_address: u64, length: u64) -> Self {
+        Self {
+            start_address,
+            length,
+            file_offset,
+            file: core::cell::RefCell::new(file),
+            kind: MemoryRegionKind::Ram,
+        }
+    }
+
+    /// Sets the kind of memory this region contains. By default this is [MemoryRegionKind::Ram].
+    pub fn with_kind(mut self, kind: MemoryRegionKind) -> Self {
+        self.kind = kind;
+        self
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F: std::io::Read + std::io::Seek> MemoryRegion for FileMemoryRegion<F> {
+    fn read(
+        &self,
+        address_range: core::ops::Range<u64>,
+    ) -> Result<Option<Vec<u8>>, crate::device_memory::MemoryReadError> {
+        let in_region = match self.address_range() {
+            Some(region_range) => {
+                region_range.start <= address_range.start
+                    && address_range.start <= address_range.end
+                    && address_range.end <= region_range.end
+            }
+            None => false,
+        };
+        if !in_region {
+            return Ok(None);
+        }
+
+        let mut data = vec![0; (address_range.end - address_range.start) as usize];
+        let mut file = self.file.borrow_mut();
+        file.seek(std::io::SeekFrom::Start(
+            self.file_offset + address_range.start - self.start_address,
+        ))
+        .and_then(|_| file.read_exact(&mut data))
+        .map_err(crate::device_memory::MemoryReadError::from_std_error)?;
+
+        Ok(Some(data))
+    }
+
+    fn kind(&self) -> MemoryRegionKind {
+        self.kind
+    }
+
+    fn address_range(&self) -> Option<core::ops::Range<u64>> {
+        let end_address = self.start_address.checked_add(self.length)?;
+        Some(self.start_address..end_address)
     }
 }
 
+/// The length of the identifier, version, kind, start address and data length of the serialized memory region
+const HEADER_LENGTH: usize = 19;
+
 /// An iterator that iterates over the serialized bytes of a memory region
 pub struct MemoryRegionIterator<'a> {
     start_address: u64,
     data: &'a [u8],
+    kind: MemoryRegionKind,
     index: usize,
+    checksum: Crc32,
 }
 
 impl<'a> MemoryRegionIterator<'a> {
-    fn new(start_address: u64, data: &'a [u8]) -> Self {
+    fn new(start_address: u64, data: &'a [u8], kind: MemoryRegionKind) -> Self {
         Self {
             start_address,
             data,
+            kind,
             index: 0,
+            checksum: Crc32::new(),
         }
     }
 }
@@ -405,28 +848,32 @@ impl<'a> Iterator for MemoryRegionIterator<'a> {
     type Item = u8;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.index {
-            0 => {
-                self.index += 1;
-                Some(MEMORY_REGION_IDENTIFIER)
-            }
-            index @ 1..=8 => {
-                self.index += 1;
-                Some(self.start_address.to_le_bytes()[index - 1])
-            }
-            index @ 9..=16 => {
-                self.index += 1;
-                Some((self.data.len() as u64).to_le_bytes()[index - 9])
-            }
-            index => {
-                self.index += 1;
-                self.data.get(index - 17).copied()
-            }
+        let checksum_start = HEADER_LENGTH + self.data.len();
+
+        let value = match self.index {
+            0 => CHECKSUMMED_MEMORY_REGION_IDENTIFIER,
+            1 => MEMORY_REGION_FORMAT_VERSION,
+            2 => self.kind.as_u8(),
+            index @ 3..=10 => self.start_address.to_le_bytes()[index - 3],
+            index @ 11..=18 => (self.data.len() as u64).to_le_bytes()[index - 11],
+            index if index < checksum_start => self.data[index - HEADER_LENGTH],
+            index => *self
+                .checksum
+                .value()
+                .to_le_bytes()
+                .get(index - checksum_start)?,
+        };
+
+        if self.index < checksum_start {
+            self.checksum.update(value);
         }
+
+        self.index += 1;
+        Some(value)
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        let remaining_length = 17 + self.data.len() - self.index;
+        let remaining_length = (HEADER_LENGTH + self.data.len() + 4).saturating_sub(self.index);
         (remaining_length, Some(remaining_length))
     }
 }
@@ -445,6 +892,69 @@ mod tests {
         assert_eq!(region, copied_region);
     }
 
+    #[test]
+    fn iterator_with_kind() {
+        let region = VecMemoryRegion::new(0x4000_0000, vec![1, 2, 3, 4])
+            .with_kind(MemoryRegionKind::Peripheral);
+        let bytes = region.bytes().collect::<Vec<_>>();
+
+        assert_eq!(
+            bytes[..3],
+            [
+                CHECKSUMMED_MEMORY_REGION_IDENTIFIER,
+                MEMORY_REGION_FORMAT_VERSION,
+                2
+            ]
+        );
+        assert_eq!(bytes.len(), region.bytes().len());
+        assert_eq!(region, VecMemoryRegion::from_iter(bytes));
+    }
+
+    #[test]
+    fn old_formats() {
+        let header = |identifier: &[u8]| {
+            [
+                identifier,
+                &0x2000_0000u64.to_le_bytes(),
+                &4u64.to_le_bytes(),
+                &[1, 2, 3, 4],
+            ]
+            .concat()
+        };
+
+        // Regions from before there was a checksum can still be read
+        assert_eq!(
+            VecMemoryRegion::try_from_iter(header(&[MEMORY_REGION_IDENTIFIER])),
+            Ok(VecMemoryRegion::new(0x2000_0000, vec![1, 2, 3, 4]))
+        );
+        assert_eq!(
+            VecMemoryRegion::try_from_iter(header(&[MEMORY_REGION_WITH_KIND_IDENTIFIER, 1])),
+            Ok(VecMemoryRegion::new(0x2000_0000, vec![1, 2, 3, 4])
+                .with_kind(MemoryRegionKind::Flash))
+        );
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let mut bytes = VecMemoryRegion::new(0x2000_0000, vec![1, 2, 3, 4])
+            .bytes()
+            .collect::<Vec<_>>();
+        let checksum = u32::from_le_bytes(bytes[bytes.len() - 4..].try_into().unwrap());
+
+        // A bit flip in the data
+        bytes[HEADER_LENGTH] ^= 0x10;
+
+        assert!(matches!(
+            VecMemoryRegion::try_from_iter(bytes.iter().copied()),
+            Err(MemoryRegionFromIterError::ChecksumMismatch { expected, actual })
+                if expected == checksum && actual != checksum
+        ));
+        assert!(matches!(
+            ArrayMemoryRegion::<4>::try_from_iter(bytes.iter().copied()),
+            Err(MemoryRegionFromIterError::ChecksumMismatch { .. })
+        ));
+    }
+
     #[test]
     fn iterator_len() {
         let region = VecMemoryRegion::new(0x2000_0000, vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 0]);
@@ -455,4 +965,181 @@ mod tests {
         iter.nth(10).unwrap();
         assert_eq!(iter.len(), iter.count());
     }
+
+    #[test]
+    fn high_address_region() {
+        // The region ends exactly at the end of the address space
+        let region = VecMemoryRegion::new(u64::MAX - 4, vec![1, 2, 3, 4]);
+        assert_eq!(region.address_range(), Some(u64::MAX - 4..u64::MAX));
+        assert_eq!(
+            VecMemoryRegion::try_from_iter(region.bytes()),
+            Ok(region.clone())
+        );
+
+        // The region goes past the end of the address space
+        let region = VecMemoryRegion::new(u64::MAX - 1, vec![1, 2, 3, 4]);
+        assert_eq!(region.address_range(), None);
+        assert_eq!(
+            VecMemoryRegion::try_from_iter(region.bytes()),
+            Err(MemoryRegionFromIterError::AddressOverflow {
+                start_address: u64::MAX - 1,
+                length: 4
+            })
+        );
+        assert_eq!(
+            ArrayMemoryRegion::<4>::try_from_iter(region.bytes()),
+            Err(MemoryRegionFromIterError::AddressOverflow {
+                start_address: u64::MAX - 1,
+                length: 4
+            })
+        );
+    }
+
+    #[test]
+    fn try_copy_from_memory() {
+        let data = [1, 2, 3, 4, 5, 6];
+        let mut region = ArrayMemoryRegion::<4>::default();
+        assert_eq!(region.remaining_capacity(), 4);
+
+        unsafe {
+            assert!(region
+                .try_copy_from_memory(data.as_ptr(), data.len())
+                .is_err());
+        }
+        assert_eq!(region, ArrayMemoryRegion::<4>::default());
+
+        unsafe {
+            region.try_copy_from_memory(data.as_ptr(), 3).unwrap();
+        }
+        assert_eq!(region.remaining_capacity(), 1);
+        assert_eq!(
+            region.read(data.as_ptr() as u64..data.as_ptr() as u64 + 3),
+            Ok(Some(vec![1, 2, 3]))
+        );
+    }
+
+    #[test]
+    fn try_from_iter_invalid() {
+        let bytes = VecMemoryRegion::new(0x2000_0000, vec![1, 2, 3, 4])
+            .bytes()
+            .collect::<Vec<_>>();
+
+        for length in 0..bytes.len() {
+            assert_eq!(
+                VecMemoryRegion::try_from_iter(bytes[..length].iter().copied()),
+                Err(MemoryRegionFromIterError::NotEnoughItems)
+            );
+        }
+
+        assert_eq!(
+            VecMemoryRegion::try_from_iter([0xFF]),
+            Err(MemoryRegionFromIterError::InvalidIdentifier(0xFF))
+        );
+        assert_eq!(
+            VecMemoryRegion::try_from_iter([MEMORY_REGION_WITH_KIND_IDENTIFIER, 0xFF]),
+            Err(MemoryRegionFromIterError::InvalidKind(0xFF))
+        );
+        assert_eq!(
+            VecMemoryRegion::try_from_iter([CHECKSUMMED_MEMORY_REGION_IDENTIFIER, 2]),
+            Err(MemoryRegionFromIterError::UnsupportedVersion(2))
+        );
+        assert_eq!(
+            ArrayMemoryRegion::<2>::try_from_iter(bytes.iter().copied()),
+            Err(MemoryRegionFromIterError::CapacityExceeded {
+                needed: 4,
+                capacity: 2
+            })
+        );
+        assert_eq!(
+            ArrayMemoryRegion::<4>::try_from_iter(bytes.iter().copied()),
+            Ok(ArrayMemoryRegion::new(
+                0x2000_0000,
+                ArrayVec::from([1, 2, 3, 4])
+            ))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "4 bytes of data, but only 2 fit")]
+    fn from_iter_doesnt_truncate() {
+        let region = VecMemoryRegion::new(0x2000_0000, vec![1, 2, 3, 4]);
+
+        // The region used to be cut off at the capacity, which was only noticed as missing memory while tracing
+        ArrayMemoryRegion::<2>::from_iter(region.bytes());
+    }
+
+    #[test]
+    fn read_ref() {
+        use alloc::borrow::Cow;
+
+        /// A region that can only give copies of its memory
+        struct CopyingRegion(VecMemoryRegion);
+
+        impl MemoryRegion for CopyingRegion {
+            fn read(
+                &self,
+                address_range: core::ops::Range<u64>,
+            ) -> Result<Option<Vec<u8>>, crate::device_memory::MemoryReadError> {
+                self.0.read(address_range)
+            }
+        }
+
+        let region = VecMemoryRegion::new(0x2000_0000, vec![1, 2, 3, 4]);
+        assert!(matches!(
+            region.read_ref(0x2000_0001..0x2000_0003),
+            Ok(Some(Cow::Borrowed(&[2, 3])))
+        ));
+        assert_eq!(region.read_ref(0x2000_0002..0x2000_0005), Ok(None));
+
+        let region = CopyingRegion(region);
+        assert!(matches!(
+            region.read_ref(0x2000_0001..0x2000_0003),
+            Ok(Some(Cow::Owned(data))) if data == [2, 3]
+        ));
+        assert_eq!(
+            region.read_u32(0x2000_0000, gimli::RunTimeEndian::Little),
+            Ok(Some(0x04030201))
+        );
+    }
+
+    #[test]
+    fn read_u64() {
+        let region = VecMemoryRegion::new(0x2000_0000, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+
+        assert_eq!(
+            region.read_u64(0x2000_0000, gimli::RunTimeEndian::Little),
+            Ok(Some(0x0807060504030201))
+        );
+        assert_eq!(
+            region.read_u64(0x2000_0000, gimli::RunTimeEndian::Big),
+            Ok(Some(0x0102030405060708))
+        );
+        assert_eq!(
+            region.read_u64(0x2000_0001, gimli::RunTimeEndian::Little),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn file_memory_region() {
+        // The data of the region is preceded by other data in the file
+        let file = std::io::Cursor::new(vec![0xFF, 0xFF, 1, 2, 3, 4]);
+        let region =
+            FileMemoryRegion::new(file, 2, 0x2000_0000, 4).with_kind(MemoryRegionKind::Flash);
+
+        assert_eq!(region.read(0x2000_0001..0x2000_0003), Ok(Some(vec![2, 3])));
+        assert_eq!(
+            region.read_u32(0x2000_0000, gimli::RunTimeEndian::Little),
+            Ok(Some(0x04030201))
+        );
+        assert_eq!(region.read(0x1FFF_FFFF..0x2000_0001), Ok(None));
+        assert_eq!(region.read(0x2000_0002..0x2000_0005), Ok(None));
+        assert_eq!(region.address_range(), Some(0x2000_0000..0x2000_0004));
+        assert_eq!(region.kind(), MemoryRegionKind::Flash);
+
+        // The file is shorter than the region says
+        let file = std::io::Cursor::new(vec![1, 2]);
+        let region = FileMemoryRegion::new(file, 0, 0x2000_0000, 4);
+        assert!(region.read(0x2000_0000..0x2000_0004).is_err());
+    }
 }
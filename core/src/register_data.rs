@@ -1,11 +1,44 @@
 //! Module containing the definitions for register data
 
+use crate::checksum::{ChecksumIter, Crc32};
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
 use arrayvec::ArrayVec;
 use core::fmt::Debug;
 use serde::{Deserialize, Serialize};
 
-/// The identifier that is being used in the byte iterator to be able to differentiate between register data and memory regions
+/// The identifier that was used in the byte iterator for little endian register data before it had a checksum.
+/// Register data with this identifier can still be read.
 pub const REGISTER_DATA_IDENTIFIER: u8 = 0x02;
+/// The identifier that was used in the byte iterator for big endian register data before it had a checksum.
+/// Register data with this identifier can still be read.
+pub const BIG_ENDIAN_REGISTER_DATA_IDENTIFIER: u8 = 0x05;
+/// The identifier that is being used in the byte iterator to be able to differentiate between register data and memory regions.
+/// It is followed by a byte with the [REGISTER_DATA_FORMAT_VERSION] and a byte with the endianness
+/// (0 for little and 1 for big endian), in which the registers are stored.
+/// The bytes of the register data end with a CRC-32 of all bytes before it,
+/// so truncated or corrupted register data is detected when it's read.
+pub const CHECKSUMMED_REGISTER_DATA_IDENTIFIER: u8 = 0x07;
+/// The version of the format of the bytes after the [CHECKSUMMED_REGISTER_DATA_IDENTIFIER].
+///
+/// It is increased when the format changes, so bytes of another version are rejected instead of being read wrongly.
+pub const REGISTER_DATA_FORMAT_VERSION: u8 = 1;
+
+/// Serde definition for [gimli::RunTimeEndian], which doesn't implement the serde traits itself
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "gimli::RunTimeEndian")]
+enum RunTimeEndianDef {
+    Little,
+    Big,
+}
+
+fn little_endian() -> gimli::RunTimeEndian {
+    gimli::RunTimeEndian::Little
+}
+
+fn is_little_endian(endianness: &gimli::RunTimeEndian) -> bool {
+    *endianness == gimli::RunTimeEndian::Little
+}
 
 /// A trait for reading registers from a register collection
 ///
@@ -20,12 +53,168 @@ pub trait RegisterData<RB: funty::Integral>: Debug {
     /// Try to get a mutable reference to the given register.
     /// Returns None if the register is not present in this collection.
     fn register_mut(&mut self, register: gimli::Register) -> Option<&mut RB>;
+    /// The byte order of the target the registers are from.
+    ///
+    /// This is needed when a register is turned into bytes, e.g. when a variable is stored in a register.
+    fn endianness(&self) -> gimli::RunTimeEndian {
+        gimli::RunTimeEndian::Little
+    }
+}
+
+/// An error that can occur when reading register data from bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterDataFromIterError {
+    /// The bytes ended before the register data was complete
+    NotEnoughItems,
+    /// The first byte is not one of the register data identifiers
+    InvalidIdentifier(u8),
+    /// The format version byte is not the [REGISTER_DATA_FORMAT_VERSION]
+    UnsupportedVersion(u8),
+    /// The endianness byte is not 0 (little endian) or 1 (big endian)
+    InvalidEndianness(u8),
+    /// There are more registers in the bytes than fit in the collection
+    CapacityExceeded {
+        /// The amount of registers in the bytes
+        needed: usize,
+        /// The capacity of the collection
+        capacity: usize,
+    },
+    /// The checksum at the end of the register data does not match its bytes, so they have been corrupted
+    ChecksumMismatch {
+        /// The checksum that is at the end of the bytes
+        expected: u32,
+        /// The checksum of the bytes that were read
+        actual: u32,
+    },
+}
+
+impl core::fmt::Display for RegisterDataFromIterError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::NotEnoughItems => {
+                write!(f, "The bytes ended before the register data was complete")
+            }
+            Self::InvalidIdentifier(identifier) => write!(
+                f,
+                "The bytes are not for register data. Got identifier {:#04X}",
+                identifier
+            ),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "The register data has format version {}, but only version {} is supported",
+                version, REGISTER_DATA_FORMAT_VERSION
+            ),
+            Self::InvalidEndianness(endianness) => write!(
+                f,
+                "The register data has an unknown endianness: {}",
+                endianness
+            ),
+            Self::CapacityExceeded { needed, capacity } => write!(
+                f,
+                "The register data has {} registers, but only {} fit",
+                needed, capacity
+            ),
+            Self::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "The bytes of the register data are corrupted. The checksum should be {:#010X}, but is {:#010X}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RegisterDataFromIterError {}
+
+/// Reads the endianness of the registers, the starting register number and the register count from the bytes.
+/// The last value is true if the register data ends with a checksum, see [read_checksum].
+fn read_header(
+    iter: &mut ChecksumIter<impl Iterator<Item = u8>>,
+) -> Result<(gimli::RunTimeEndian, u16, u16, bool), RegisterDataFromIterError> {
+    let mut next = || iter.next().ok_or(RegisterDataFromIterError::NotEnoughItems);
+
+    let identifier = next()?;
+    let endianness = match identifier {
+        REGISTER_DATA_IDENTIFIER => gimli::RunTimeEndian::Little,
+        BIG_ENDIAN_REGISTER_DATA_IDENTIFIER => gimli::RunTimeEndian::Big,
+        CHECKSUMMED_REGISTER_DATA_IDENTIFIER => {
+            match next()? {
+                REGISTER_DATA_FORMAT_VERSION => {}
+                version => return Err(RegisterDataFromIterError::UnsupportedVersion(version)),
+            }
+
+            match next()? {
+                0 => gimli::RunTimeEndian::Little,
+                1 => gimli::RunTimeEndian::Big,
+                endianness => return Err(RegisterDataFromIterError::InvalidEndianness(endianness)),
+            }
+        }
+        identifier => return Err(RegisterDataFromIterError::InvalidIdentifier(identifier)),
+    };
+
+    // First the starting number is encoded
+    let starting_register_number = u16::from_le_bytes([next()?, next()?]);
+    // Second is how many registers there are
+    let register_count = u16::from_le_bytes([next()?, next()?]);
+
+    Ok((
+        endianness,
+        starting_register_number,
+        register_count,
+        identifier == CHECKSUMMED_REGISTER_DATA_IDENTIFIER,
+    ))
+}
+
+/// Reads the checksum at the end of the register data and checks it against the bytes that were read before it
+fn read_checksum(
+    iter: &mut ChecksumIter<impl Iterator<Item = u8>>,
+) -> Result<(), RegisterDataFromIterError> {
+    match iter.read_checksum() {
+        Some((expected, actual)) if expected == actual => Ok(()),
+        Some((expected, actual)) => {
+            Err(RegisterDataFromIterError::ChecksumMismatch { expected, actual })
+        }
+        None => Err(RegisterDataFromIterError::NotEnoughItems),
+    }
+}
+
+/// Reads one register with the given endianness from the bytes
+fn read_register<RB>(
+    iter: &mut impl Iterator<Item = u8>,
+    endianness: gimli::RunTimeEndian,
+) -> Result<RB, RegisterDataFromIterError>
+where
+    RB: funty::Integral,
+    RB::Bytes: for<'a> TryFrom<&'a [u8]>,
+{
+    // We process everything byte-by-byte generically so every register has an unknown length
+    // So we need to store the bytes temporarily until we have enough to fully read the bytes as a register
+    let mut register_bytes_buffer = ArrayVec::<u8, 16>::new();
+
+    for _ in 0..core::mem::size_of::<RB>() {
+        register_bytes_buffer.push(
+            iter.next()
+                .ok_or(RegisterDataFromIterError::NotEnoughItems)?,
+        );
+    }
+
+    // The buffer has exactly the size of the register, so this can't fail
+    let register_bytes = register_bytes_buffer
+        .as_slice()
+        .try_into()
+        .unwrap_or_else(|_| panic!());
+
+    if is_little_endian(&endianness) {
+        Ok(RB::from_le_bytes(register_bytes))
+    } else {
+        Ok(RB::from_be_bytes(register_bytes))
+    }
 }
 
 /// A collection of registers, backed by a stack allocated array.
 ///
 /// SIZE is the maximum amount of registers this collection can hold.
-#[derive(Clone, Debug, Deserialize, Serialize, Default, PartialEq, Eq)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
 pub struct ArrayRegisterData<const SIZE: usize, RB> {
     /// The DWARF register number of the first register
     starting_register_number: u16,
@@ -33,6 +222,23 @@ pub struct ArrayRegisterData<const SIZE: usize, RB> {
     /// The first value is of the register that is correlated with `starting_register_number`.
     /// Must be contiguous.
     registers: ArrayVec<RB, SIZE>,
+    /// The byte order of the target the registers are from
+    #[serde(
+        with = "RunTimeEndianDef",
+        default = "little_endian",
+        skip_serializing_if = "is_little_endian"
+    )]
+    endianness: gimli::RunTimeEndian,
+}
+
+impl<const SIZE: usize, RB> Default for ArrayRegisterData<SIZE, RB> {
+    fn default() -> Self {
+        Self {
+            starting_register_number: 0,
+            registers: ArrayVec::new(),
+            endianness: gimli::RunTimeEndian::Little,
+        }
+    }
 }
 
 impl<const SIZE: usize, RB: funty::Integral> ArrayRegisterData<SIZE, RB> {
@@ -44,9 +250,18 @@ impl<const SIZE: usize, RB: funty::Integral> ArrayRegisterData<SIZE, RB> {
         Self {
             starting_register_number: starting_register.0,
             registers,
+            endianness: gimli::RunTimeEndian::Little,
         }
     }
 
+    /// Sets the byte order of the target the registers are from. By default this is little endian.
+    ///
+    /// The register values are numbers, so this doesn't change them. It determines how they are turned into bytes.
+    pub fn with_endianness(mut self, endianness: gimli::RunTimeEndian) -> Self {
+        self.endianness = endianness;
+        self
+    }
+
     /// Get a byte iterator for this collection.
     ///
     /// This iterator can be used to store the collection as bytes or to stream over a network.
@@ -71,12 +286,18 @@ impl<const SIZE: usize, RB: funty::Integral> ArrayRegisterData<SIZE, RB> {
     /// assert_eq!(regs1, ArrayRegisterData::<4, u32>::from_iter(&mut intermediate_iter));
     /// assert_eq!(regs2, ArrayRegisterData::<4, u32>::from_iter(&mut intermediate_iter));
     /// ```
-    pub fn bytes(&self) -> RegisterDataBytesIterator<RB> {
-        RegisterDataBytesIterator {
-            index: 0,
-            starting_register_number: self.starting_register_number,
-            registers: &self.registers,
-        }
+    pub fn bytes(&self) -> RegisterDataBytesIterator<'_, RB> {
+        RegisterDataBytesIterator::new(
+            self.starting_register_number,
+            &self.registers,
+            self.endianness,
+        )
+    }
+
+    /// Writes the bytes of [Self::bytes] to the writer in chunks instead of one by one, see [crate::io::write_bytes]
+    #[cfg(feature = "embedded-io")]
+    pub fn write_to<W: embedded_io::Write>(&self, writer: &mut W) -> Result<(), W::Error> {
+        crate::io::write_bytes(writer, self.bytes())
     }
 }
 
@@ -93,64 +314,69 @@ impl<const SIZE: usize, RB: funty::Integral> RegisterData<RB> for ArrayRegisterD
         let local_register_index = register.0.checked_sub(self.starting_register_number)?;
         self.registers.get_mut(local_register_index as usize)
     }
+    fn endianness(&self) -> gimli::RunTimeEndian {
+        self.endianness
+    }
 }
 
-impl<const SIZE: usize, RB> FromIterator<u8> for ArrayRegisterData<SIZE, RB>
+impl<const SIZE: usize, RB> ArrayRegisterData<SIZE, RB>
 where
     RB: funty::Integral,
     RB::Bytes: for<'a> TryFrom<&'a [u8]>,
 {
-    fn from_iter<T: IntoIterator<Item = u8>>(iter: T) -> Self {
-        // Get the iterator. We assume that it is in the same format as the bytes function outputs
-        let mut iter = iter.into_iter();
-
-        assert_eq!(
-            iter.next().unwrap(),
-            REGISTER_DATA_IDENTIFIER,
-            "The given iterator is not for register data"
-        );
-
-        // First the starting number is encoded
-        let starting_register_number =
-            u16::from_le_bytes([iter.next().unwrap(), iter.next().unwrap()]);
-
-        // Second is how many registers there are
-        let register_count = u16::from_le_bytes([iter.next().unwrap(), iter.next().unwrap()]);
+    /// Reads register data from bytes in the format of [Self::bytes].
+    ///
+    /// Only the bytes of the register data are consumed, so multiple collections can be read from the same iterator.
+    /// Returns an error instead of panicking when the bytes are not valid register data
+    /// or when there are more registers than fit in this collection.
+    pub fn try_from_iter<T: IntoIterator<Item = u8>>(
+        iter: T,
+    ) -> Result<Self, RegisterDataFromIterError> {
+        let mut iter = ChecksumIter::new(iter.into_iter());
+
+        let (endianness, starting_register_number, register_count, checksummed) =
+            read_header(&mut iter)?;
+
+        if register_count as usize > SIZE {
+            return Err(RegisterDataFromIterError::CapacityExceeded {
+                needed: register_count as usize,
+                capacity: SIZE,
+            });
+        }
 
-        // Create the buffer we're storing the registers in
         let mut registers = ArrayVec::new();
-
-        // We process everything byte-by-byte generically so every register has an unknown length
-        // So we need to store the bytes temporarily until we have enough to fully read the bytes as a register
-        let register_size = core::mem::size_of::<RB>();
-        let mut register_bytes_buffer = ArrayVec::<u8, 16>::new();
-
-        for byte in (0..register_count as usize * register_size).map(|_| iter.next().unwrap()) {
-            register_bytes_buffer.push(byte);
-
-            if register_bytes_buffer.len() == register_size {
-                registers.push(RB::from_le_bytes(
-                    register_bytes_buffer
-                        .as_slice()
-                        .try_into()
-                        .unwrap_or_else(|_| panic!()),
-                ));
-                register_bytes_buffer = ArrayVec::new();
-            }
+        for _ in 0..register_count {
+            registers.push(read_register(&mut iter, endianness)?);
         }
 
-        assert!(register_bytes_buffer.is_empty());
+        if checksummed {
+            read_checksum(&mut iter)?;
+        }
 
-        Self {
+        Ok(Self {
             starting_register_number,
             registers,
-        }
+            endianness,
+        })
+    }
+}
+
+impl<const SIZE: usize, RB> FromIterator<u8> for ArrayRegisterData<SIZE, RB>
+where
+    RB: funty::Integral,
+    RB::Bytes: for<'a> TryFrom<&'a [u8]>,
+{
+    /// Reads the register data from the bytes.
+    ///
+    /// Panics if the bytes are not valid register data. Use [ArrayRegisterData::try_from_iter] to get an error instead.
+    fn from_iter<T: IntoIterator<Item = u8>>(iter: T) -> Self {
+        Self::try_from_iter(iter).unwrap_or_else(|e| panic!("{}", e))
     }
 }
 
 /// A collection of registers, backed by a vec.
-#[cfg(feature = "std")]
-#[derive(Clone, Debug, Deserialize, Serialize, Default, PartialEq, Eq)]
+#[cfg(feature = "alloc")]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
 pub struct VecRegisterData<RB> {
     /// The DWARF register number of the first register
     starting_register_number: u16,
@@ -158,9 +384,27 @@ pub struct VecRegisterData<RB> {
     /// The first value is of the register that is correlated with `starting_register_number`.
     /// Must be contiguous.
     registers: Vec<RB>,
+    /// The byte order of the target the registers are from
+    #[serde(
+        with = "RunTimeEndianDef",
+        default = "little_endian",
+        skip_serializing_if = "is_little_endian"
+    )]
+    endianness: gimli::RunTimeEndian,
 }
 
-#[cfg(feature = "std")]
+#[cfg(feature = "alloc")]
+impl<RB> Default for VecRegisterData<RB> {
+    fn default() -> Self {
+        Self {
+            starting_register_number: 0,
+            registers: Vec::new(),
+            endianness: gimli::RunTimeEndian::Little,
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
 impl<RB: funty::Integral> VecRegisterData<RB> {
     /// Create a new register collection backed by a vec
     ///
@@ -170,9 +414,18 @@ impl<RB: funty::Integral> VecRegisterData<RB> {
         Self {
             starting_register_number: starting_register.0,
             registers,
+            endianness: gimli::RunTimeEndian::Little,
         }
     }
 
+    /// Sets the byte order of the target the registers are from. By default this is little endian.
+    ///
+    /// The register values are numbers, so this doesn't change them. It determines how they are turned into bytes.
+    pub fn with_endianness(mut self, endianness: gimli::RunTimeEndian) -> Self {
+        self.endianness = endianness;
+        self
+    }
+
     /// Get a byte iterator for this collection.
     ///
     /// This iterator can be used to store the collection as bytes or to stream over a network.
@@ -197,16 +450,22 @@ impl<RB: funty::Integral> VecRegisterData<RB> {
     /// assert_eq!(regs1, ArrayRegisterData::<4, u32>::from_iter(&mut intermediate_iter));
     /// assert_eq!(regs2, ArrayRegisterData::<4, u32>::from_iter(&mut intermediate_iter));
     /// ```
-    pub fn bytes(&self) -> RegisterDataBytesIterator<RB> {
-        RegisterDataBytesIterator {
-            index: 0,
-            starting_register_number: self.starting_register_number,
-            registers: &self.registers,
-        }
+    pub fn bytes(&self) -> RegisterDataBytesIterator<'_, RB> {
+        RegisterDataBytesIterator::new(
+            self.starting_register_number,
+            &self.registers,
+            self.endianness,
+        )
+    }
+
+    /// Writes the bytes of [Self::bytes] to the writer in chunks instead of one by one, see [crate::io::write_bytes]
+    #[cfg(feature = "embedded-io")]
+    pub fn write_to<W: embedded_io::Write>(&self, writer: &mut W) -> Result<(), W::Error> {
+        crate::io::write_bytes(writer, self.bytes())
     }
 }
 
-#[cfg(feature = "std")]
+#[cfg(feature = "alloc")]
 impl<RB: funty::Integral> RegisterData<RB> for VecRegisterData<RB> {
     fn register(&self, register: gimli::Register) -> Option<RB> {
         let local_register_index = register.0.checked_sub(self.starting_register_number)?;
@@ -220,110 +479,167 @@ impl<RB: funty::Integral> RegisterData<RB> for VecRegisterData<RB> {
         let local_register_index = register.0.checked_sub(self.starting_register_number)?;
         self.registers.get_mut(local_register_index as usize)
     }
+    fn endianness(&self) -> gimli::RunTimeEndian {
+        self.endianness
+    }
 }
 
-#[cfg(feature = "std")]
-impl<RB> FromIterator<u8> for VecRegisterData<RB>
+#[cfg(feature = "alloc")]
+impl<RB> VecRegisterData<RB>
 where
     RB: funty::Integral,
     RB::Bytes: for<'a> TryFrom<&'a [u8]>,
 {
-    fn from_iter<T: IntoIterator<Item = u8>>(iter: T) -> Self {
-        let mut iter = iter.into_iter();
-
-        assert_eq!(
-            iter.next().unwrap(),
-            REGISTER_DATA_IDENTIFIER,
-            "The given iterator is not for register data"
-        );
-
-        let starting_register_number =
-            u16::from_le_bytes([iter.next().unwrap(), iter.next().unwrap()]);
+    /// Reads register data from bytes in the format of [Self::bytes].
+    ///
+    /// Only the bytes of the register data are consumed, so multiple collections can be read from the same iterator.
+    /// Returns an error instead of panicking when the bytes are not valid register data.
+    pub fn try_from_iter<T: IntoIterator<Item = u8>>(
+        iter: T,
+    ) -> Result<Self, RegisterDataFromIterError> {
+        let mut iter = ChecksumIter::new(iter.into_iter());
 
-        let register_count = u16::from_le_bytes([iter.next().unwrap(), iter.next().unwrap()]);
+        let (endianness, starting_register_number, register_count, checksummed) =
+            read_header(&mut iter)?;
 
         let mut registers = Vec::new();
-        let register_size = core::mem::size_of::<RB>();
-
-        let mut register_bytes_buffer = ArrayVec::<u8, 16>::new();
-
-        for byte in (0..register_count as usize * register_size).map(|_| iter.next().unwrap()) {
-            register_bytes_buffer.push(byte);
-
-            if register_bytes_buffer.len() == register_size {
-                registers.push(RB::from_le_bytes(
-                    register_bytes_buffer
-                        .as_slice()
-                        .try_into()
-                        .unwrap_or_else(|_| panic!()),
-                ));
-                register_bytes_buffer.clear();
-            }
+        for _ in 0..register_count {
+            registers.push(read_register(&mut iter, endianness)?);
         }
 
-        assert!(register_bytes_buffer.is_empty());
+        if checksummed {
+            read_checksum(&mut iter)?;
+        }
 
-        Self {
+        Ok(Self {
             starting_register_number,
             registers,
-        }
+            endianness,
+        })
     }
 }
 
+#[cfg(feature = "alloc")]
+impl<RB> FromIterator<u8> for VecRegisterData<RB>
+where
+    RB: funty::Integral,
+    RB::Bytes: for<'a> TryFrom<&'a [u8]>,
+{
+    /// Reads the register data from the bytes.
+    ///
+    /// Panics if the bytes are not valid register data. Use [VecRegisterData::try_from_iter] to get an error instead.
+    fn from_iter<T: IntoIterator<Item = u8>>(iter: T) -> Self {
+        Self::try_from_iter(iter).unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+/// The length of the identifier, version, endianness, starting register number and register count of the serialized register data
+const HEADER_LENGTH: usize = 7;
+
 /// An iterator that iterates over the serialized bytes of register data
 pub struct RegisterDataBytesIterator<'a, RB: funty::Integral> {
     starting_register_number: u16,
     registers: &'a [RB],
+    endianness: gimli::RunTimeEndian,
     index: usize,
+    checksum: Crc32,
+}
+
+impl<'a, RB: funty::Integral> RegisterDataBytesIterator<'a, RB> {
+    fn new(
+        starting_register_number: u16,
+        registers: &'a [RB],
+        endianness: gimli::RunTimeEndian,
+    ) -> Self {
+        Self {
+            starting_register_number,
+            registers,
+            endianness,
+            index: 0,
+            checksum: Crc32::new(),
+        }
+    }
+
+    /// Gets a byte of the registers in the endianness of the target
+    fn register_byte(&self, index: usize) -> u8 {
+        let register_size = core::mem::size_of::<RB>();
+        let register_index = index / register_size;
+        let byte_index = index % register_size;
+
+        // We get the number in the forced endianness of the target
+        let register = self.registers[register_index];
+        let endian_register = if is_little_endian(&self.endianness) {
+            register.to_le()
+        } else {
+            register.to_be()
+        };
+        // We can take a slice to it because we checked the length and we know its endianness
+        let register_slice = unsafe {
+            core::slice::from_raw_parts(&endian_register as *const RB as *const u8, register_size)
+        };
+        register_slice[byte_index]
+    }
 }
 
 impl<'a, RB: funty::Integral> Iterator for RegisterDataBytesIterator<'a, RB> {
     type Item = u8;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.index {
-            0 => {
-                self.index += 1;
-                Some(REGISTER_DATA_IDENTIFIER)
-            }
-            index @ 1..=2 => {
-                self.index += 1;
-                Some(self.starting_register_number.to_le_bytes()[index - 1])
-            }
-            index @ 3..=4 => {
-                self.index += 1;
-                Some((self.registers.len() as u16).to_le_bytes()[index - 3])
-            }
-            index => {
-                self.index += 1;
-
-                let index = index - 5;
-                let register_size = core::mem::size_of::<RB>();
-                let register_index = index / register_size;
-                let byte_index = index % register_size;
-
-                // We get the number in forced little endian format
-                let le_register = self.registers.get(register_index)?.to_le();
-                // We can take a slice to it because we checked the length and we know it's in little endian
-                let register_slice = unsafe {
-                    core::slice::from_raw_parts(
-                        &le_register as *const RB as *const u8,
-                        register_size,
-                    )
-                };
-                Some(register_slice[byte_index])
-            }
+        let checksum_start = HEADER_LENGTH + core::mem::size_of_val(self.registers);
+
+        let value = match self.index {
+            0 => CHECKSUMMED_REGISTER_DATA_IDENTIFIER,
+            1 => REGISTER_DATA_FORMAT_VERSION,
+            2 if is_little_endian(&self.endianness) => 0,
+            2 => 1,
+            index @ 3..=4 => self.starting_register_number.to_le_bytes()[index - 3],
+            index @ 5..=6 => (self.registers.len() as u16).to_le_bytes()[index - 5],
+            index if index < checksum_start => self.register_byte(index - HEADER_LENGTH),
+            index => *self
+                .checksum
+                .value()
+                .to_le_bytes()
+                .get(index - checksum_start)?,
+        };
+
+        if self.index < checksum_start {
+            self.checksum.update(value);
         }
+
+        self.index += 1;
+        Some(value)
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        let remaining_length = 5 + self.registers.len() * core::mem::size_of::<RB>() - self.index;
+        let remaining_length =
+            (HEADER_LENGTH + core::mem::size_of_val(self.registers) + 4).saturating_sub(self.index);
         (remaining_length, Some(remaining_length))
     }
 }
 
 impl<'a, RB: funty::Integral> ExactSizeIterator for RegisterDataBytesIterator<'a, RB> {}
 
+/// DWARF register numbers of Cortex-M registers that don't have one in the DWARF for the Arm architecture.
+///
+/// These are the banked stack pointers. They're numbered in the range that is reserved for vendors.
+/// The trace uses them to find an exception frame that was stacked on another stack than the current one.
+pub mod cortex_m {
+    use gimli::Register;
+
+    /// The main stack pointer
+    pub const MSP: Register = Register(0x2000);
+    /// The process stack pointer
+    pub const PSP: Register = Register(0x2001);
+    /// The non-secure main stack pointer of ARMv8-M with the security extension
+    pub const MSP_NS: Register = Register(0x2002);
+    /// The non-secure process stack pointer of ARMv8-M with the security extension
+    pub const PSP_NS: Register = Register(0x2003);
+    /// The secure main stack pointer of ARMv8-M with the security extension
+    pub const MSP_S: Register = Register(0x2004);
+    /// The secure process stack pointer of ARMv8-M with the security extension
+    pub const PSP_S: Register = Register(0x2005);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -347,4 +663,115 @@ mod tests {
         iter.nth(10).unwrap();
         assert_eq!(iter.len(), iter.count());
     }
+
+    #[test]
+    fn big_endian_iterator() {
+        let data = VecRegisterData::new(gimli::Arm::R0, vec![0x0102_0304u32, 0x0506_0708])
+            .with_endianness(gimli::RunTimeEndian::Big);
+        let bytes = data.bytes().collect::<Vec<_>>();
+
+        // The checksum is at the end
+        assert_eq!(
+            bytes[..bytes.len() - 4],
+            [
+                CHECKSUMMED_REGISTER_DATA_IDENTIFIER,
+                REGISTER_DATA_FORMAT_VERSION,
+                1,
+                0,
+                0,
+                2,
+                0,
+                1,
+                2,
+                3,
+                4,
+                5,
+                6,
+                7,
+                8
+            ]
+        );
+        assert_eq!(data, VecRegisterData::from_iter(bytes.iter().copied()));
+        assert_eq!(
+            ArrayRegisterData::<2, u32>::from_iter(bytes.iter().copied()).endianness(),
+            gimli::RunTimeEndian::Big
+        );
+    }
+
+    #[test]
+    fn old_formats() {
+        // Register data from before there was a checksum can still be read
+        assert_eq!(
+            VecRegisterData::try_from_iter([REGISTER_DATA_IDENTIFIER, 0, 0, 1, 0, 4, 3, 2, 1]),
+            Ok(VecRegisterData::new(gimli::Arm::R0, vec![0x0102_0304u32]))
+        );
+        assert_eq!(
+            VecRegisterData::try_from_iter([
+                BIG_ENDIAN_REGISTER_DATA_IDENTIFIER,
+                0,
+                0,
+                1,
+                0,
+                1,
+                2,
+                3,
+                4
+            ]),
+            Ok(VecRegisterData::new(gimli::Arm::R0, vec![0x0102_0304u32])
+                .with_endianness(gimli::RunTimeEndian::Big))
+        );
+    }
+
+    #[test]
+    fn try_from_iter_invalid() {
+        let bytes = VecRegisterData::new(gimli::Arm::R0, vec![1u32, 2, 3])
+            .bytes()
+            .collect::<Vec<_>>();
+
+        for length in 0..bytes.len() {
+            assert_eq!(
+                VecRegisterData::<u32>::try_from_iter(bytes[..length].iter().copied()),
+                Err(RegisterDataFromIterError::NotEnoughItems)
+            );
+        }
+
+        assert_eq!(
+            VecRegisterData::<u32>::try_from_iter([0xFF]),
+            Err(RegisterDataFromIterError::InvalidIdentifier(0xFF))
+        );
+        assert_eq!(
+            VecRegisterData::<u32>::try_from_iter([CHECKSUMMED_REGISTER_DATA_IDENTIFIER, 2]),
+            Err(RegisterDataFromIterError::UnsupportedVersion(2))
+        );
+        assert_eq!(
+            VecRegisterData::<u32>::try_from_iter([
+                CHECKSUMMED_REGISTER_DATA_IDENTIFIER,
+                REGISTER_DATA_FORMAT_VERSION,
+                2
+            ]),
+            Err(RegisterDataFromIterError::InvalidEndianness(2))
+        );
+
+        // A bit flip in the last register
+        let mut corrupted_bytes = bytes.clone();
+        corrupted_bytes[bytes.len() - 5] ^= 0x01;
+        assert!(matches!(
+            VecRegisterData::<u32>::try_from_iter(corrupted_bytes),
+            Err(RegisterDataFromIterError::ChecksumMismatch { .. })
+        ));
+        assert_eq!(
+            ArrayRegisterData::<2, u32>::try_from_iter(bytes.iter().copied()),
+            Err(RegisterDataFromIterError::CapacityExceeded {
+                needed: 3,
+                capacity: 2
+            })
+        );
+        assert_eq!(
+            ArrayRegisterData::<3, u32>::try_from_iter(bytes.iter().copied()),
+            Ok(ArrayRegisterData::new(
+                gimli::Arm::R0,
+                ArrayVec::from([1, 2, 3])
+            ))
+        );
+    }
 }
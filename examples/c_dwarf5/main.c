@@ -0,0 +1,46 @@
+// A small C program that is compiled with DWARF 5 debug info to test the decoding of its variables.
+// It's never run, so it doesn't need a C library.
+
+struct Point {
+    int x;
+    short y;
+};
+
+enum Color { RED, GREEN, BLUE };
+
+struct Point POINT = { 1, -2 };
+enum Color COLOR = BLUE;
+unsigned char BYTES[4] = { 1, 2, 3, 4 };
+const struct Point *POINT_POINTER = &POINT;
+int ZEROED;
+
+typedef union {
+    unsigned int raw;
+    unsigned char bytes[4];
+} Register;
+
+struct Peripheral {
+    int id;
+    // An anonymous member through a typedef, which GCC allows with -fms-extensions
+    Register;
+    // A plain anonymous member
+    union {
+        short low;
+        unsigned short both[2];
+    };
+};
+
+struct Peripheral PERIPHERAL = { .id = 7, .raw = 0x04030201, .both = { 5, 6 } };
+
+// The optimizer moves `count` between registers, so it gets a location list
+__attribute__((noinline, regparm(1))) int sum(int count) {
+    int total = 0;
+    for (int i = 0; i < count; i++) {
+        total += BYTES[i & 3] * count;
+    }
+    return total;
+}
+
+int main(void) {
+    return sum(POINT.x + COLOR) + POINT_POINTER->y + ZEROED + PERIPHERAL.raw;
+}
@@ -0,0 +1,8 @@
+#!/bin/sh
+# Compiles the C example with DWARF 5 debug info.
+# It's a 32 bit program without a C library, so the addresses fit the u32 that the tests use.
+# The ms extensions allow anonymous struct and union members through a typedef.
+set -e
+cd "$(dirname "$0")"
+gcc -m32 -gdwarf-5 -O2 -fms-extensions -nostdlib -static -fno-pie -no-pie -fdebug-prefix-map="$PWD"=. \
+    -Wl,--entry=main -Wl,--build-id=none -o ./data/c_dwarf5 ./c_dwarf5/main.c
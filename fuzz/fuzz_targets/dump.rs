@@ -0,0 +1,15 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use stackdump_core::{device_memory::DeviceMemory, dump::Dump};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(dump) = Dump::<u32>::from_dump_bytes(data) {
+        let mut device_memory = DeviceMemory::new();
+        dump.add_to_device_memory(&mut device_memory);
+
+        // Reading from the memory must not panic either
+        let _ = device_memory.read_slice(0..16);
+        let _ = device_memory.register(stackdump_core::gimli::Arm::PC);
+    }
+});
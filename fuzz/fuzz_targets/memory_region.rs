@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use stackdump_core::memory_region::{ArrayMemoryRegion, VecMemoryRegion};
+
+fuzz_target!(|data: &[u8]| {
+    let mut iter = data.iter().copied();
+    // Keep reading regions until the bytes are invalid or run out, like a stream of regions would be read
+    while VecMemoryRegion::try_from_iter(&mut iter).is_ok() {}
+
+    let _ = ArrayMemoryRegion::<64>::try_from_iter(data.iter().copied());
+});
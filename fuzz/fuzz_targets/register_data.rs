@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use stackdump_core::register_data::{ArrayRegisterData, VecRegisterData};
+
+fuzz_target!(|data: &[u8]| {
+    let mut iter = data.iter().copied();
+    // Keep reading register data until the bytes are invalid or run out, like a stream would be read
+    while VecRegisterData::<u32>::try_from_iter(&mut iter).is_ok() {}
+
+    let _ = VecRegisterData::<u64>::try_from_iter(data.iter().copied());
+    let _ = ArrayRegisterData::<16, u32>::try_from_iter(data.iter().copied());
+});
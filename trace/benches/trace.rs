@@ -0,0 +1,48 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use stackdump_core::{device_memory::DeviceMemory, dump::Dump};
+use stackdump_trace::{platform::cortex_m::CortexMPlatform, DecodeOptions};
+
+const ELF: &[u8] = include_bytes!("../../examples/data/nrf52840");
+const DUMP: &[u8] = include_bytes!("../../examples/data/nrf52840.dump");
+
+fn device_memory() -> DeviceMemory<'static, u32> {
+    let mut device_memory = DeviceMemory::new();
+    Dump::<u32>::from_dump_bytes(DUMP)
+        .expect("The example dump is valid")
+        .add_to_device_memory(&mut device_memory);
+
+    device_memory
+}
+
+fn trace_example(c: &mut Criterion) {
+    c.bench_function("trace nrf52840 example", |b| {
+        b.iter_batched(
+            device_memory,
+            |device_memory| {
+                stackdump_trace::platform::trace::<CortexMPlatform>(device_memory, ELF).unwrap()
+            },
+            criterion::BatchSize::LargeInput,
+        )
+    });
+
+    c.bench_function("trace nrf52840 example with lazy arrays", |b| {
+        b.iter_batched(
+            device_memory,
+            |device_memory| {
+                stackdump_trace::platform::trace_with_options::<CortexMPlatform>(
+                    device_memory,
+                    ELF,
+                    DecodeOptions {
+                        lazy_array_threshold: Some(16),
+                        ..Default::default()
+                    },
+                )
+                .unwrap()
+            },
+            criterion::BatchSize::LargeInput,
+        )
+    });
+}
+
+criterion_group!(benches, trace_example);
+criterion_main!(benches);
@@ -15,6 +15,8 @@ use crate::{type_value_tree::VariableDataError, DefaultReader};
 pub enum TraceError {
     #[error("The elf file does not contain the required `{0}` section")]
     MissingElfSection(String),
+    #[error("None of the elf files match the captured data of the device")]
+    NoMatchingElf,
     #[error("The elf file could not be read: {0}")]
     ObjectReadError(#[from] addr2line::object::Error),
     #[error("An IO error occured: {0}")]
@@ -54,6 +56,8 @@ pub enum TraceError {
     ExpectedChildNotPresent { entry_tag: String },
     #[error("The frame base is not known yet")]
     UnknownFrameBase,
+    #[error("The TLS base is required, but it is not known. It can be set on the device memory")]
+    UnknownTlsBase,
     #[error("The dwarf unit for a `pc` of {pc:#X} could not be found")]
     DwarfUnitNotFound { pc: u64 },
     #[error("A number could not be converted to another type")]
@@ -88,3 +92,27 @@ impl From<std::io::Error> for TraceError {
         Self::IOError(Rc::new(e))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn memory_read_error_source() {
+        let io_error = std::io::Error::new(std::io::ErrorKind::TimedOut, "probe timed out");
+        let error = TraceError::from(VariableDataError::from(MemoryReadError::from_std_error(
+            io_error,
+        )));
+
+        let variable_data_error = error.source().unwrap();
+        assert!(variable_data_error.is::<VariableDataError>());
+        let memory_read_error = variable_data_error.source().unwrap();
+        assert!(memory_read_error.is::<MemoryReadError>());
+        let io_error = memory_read_error.source().unwrap();
+        assert_eq!(
+            io_error.downcast_ref::<std::io::Error>().unwrap().kind(),
+            std::io::ErrorKind::TimedOut
+        );
+    }
+}
@@ -2,15 +2,22 @@
 // #![warn(missing_docs)]
 
 use render_colors::{Theme, ThemeColors};
+use serde::{Serialize, Serializer};
 pub use stackdump_core;
 
 use crate::type_value_tree::variable_type::Archetype;
 use gimli::{EndianReader, EvaluationResult, Piece, RunTimeEndian};
 use std::{
+    collections::HashMap,
     fmt::{Debug, Display},
+    hash::{Hash, Hasher},
+    path::Path,
     rc::Rc,
 };
-use type_value_tree::{rendering::render_type_value_tree, TypeValueTree};
+use type_value_tree::{
+    rendering::{render_type_value_tree_with_options, RenderOptions},
+    TypeValueTree,
+};
 
 pub mod error;
 mod gimli_extensions;
@@ -22,7 +29,7 @@ mod variables;
 type DefaultReader = EndianReader<RunTimeEndian, Rc<[u8]>>;
 
 /// A source code location
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize)]
 pub struct Location {
     /// The file path of the piece of code
     pub file: Option<String>,
@@ -32,6 +39,51 @@ pub struct Location {
     pub column: Option<u64>,
 }
 
+impl Location {
+    /// Replaces the `from` prefix of the file path with `to`.
+    ///
+    /// The paths in the debug info are the paths on the machine that built the program.
+    /// This can be used to make them point to the source code on the machine that does the tracing.
+    /// Nothing happens if the file path doesn't start with the prefix.
+    ///
+    /// The build machine may use other path separators than the local machine,
+    /// so the part after the prefix is split on both `/` and `\` and joined onto `to`.
+    ///
+    /// ```
+    /// use stackdump_trace::Location;
+    ///
+    /// let mut location = Location {
+    ///     file: Some("/build/firmware/src/main.rs".into()),
+    ///     line: Some(12),
+    ///     column: None,
+    /// };
+    ///
+    /// location.remap_path_prefix("/build/firmware", "/home/me/firmware");
+    /// assert_eq!(location.file.as_deref(), Some("/home/me/firmware/src/main.rs"));
+    /// ```
+    pub fn remap_path_prefix(&mut self, from: &str, to: impl AsRef<Path>) {
+        let from = from.trim_end_matches(['/', '\\']);
+
+        let remapped_file = self
+            .file
+            .as_ref()
+            .and_then(|file| file.strip_prefix(from))
+            .filter(|remainder| remainder.is_empty() || remainder.starts_with(['/', '\\']))
+            .map(|remainder| {
+                remainder
+                    .split(['/', '\\'])
+                    .filter(|component| !component.is_empty())
+                    .fold(to.as_ref().to_path_buf(), |path, component| {
+                        path.join(component)
+                    })
+            });
+
+        if let Some(remapped_file) = remapped_file {
+            self.file = Some(remapped_file.to_string_lossy().into_owned());
+        }
+    }
+}
+
 impl Display for Location {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if let Some(file) = self.file.clone() {
@@ -50,7 +102,16 @@ impl Display for Location {
 
 /// An object containing a de-inlined stack frame.
 /// Exceptions/interrupts are also a frame.
-#[derive(Debug, Clone)]
+///
+/// Two frames are equal when they are of the same function at the same location and have the same variables.
+/// The values of the variables, the stack pointer and the `inlined_into` index are not compared,
+/// so the frames of a recursive function are equal to each other. See [collapse_recursion].
+///
+/// Frames can be serialized, e.g. to JSON with `frames_to_json` (with the `json` feature).
+/// The value of a variable is serialized as a tree of nodes with their name, type name, archetype,
+/// value or error and children.
+#[derive(Debug, Clone, Serialize)]
+#[serde(bound = "")]
 pub struct Frame<ADDR: funty::Integral> {
     /// The name of the function the frame is in
     pub function: String,
@@ -60,23 +121,82 @@ pub struct Frame<ADDR: funty::Integral> {
     pub frame_type: FrameType,
     /// The variables and their values that are present in the frame
     pub variables: Vec<Variable<ADDR>>,
+    /// The variables that are present in the frame, but that could not be decoded
+    pub omitted_variables: Vec<OmittedVariable>,
+    /// If this is an inline function, this is the index of the frame of the real function it has been inlined into.
+    /// This is the index in the list of frames that is returned from the trace.
+    pub inlined_into: Option<usize>,
+    /// The value of the stack pointer in this frame, if it is known
+    #[serde(serialize_with = "serialize_address")]
+    pub stack_pointer: Option<ADDR>,
+    /// The values of the registers in this frame, before it was unwound to its caller.
+    /// These are the [platform::Platform::FRAME_REGISTERS] of which the value is known in this frame.
+    ///
+    /// This is a list of pairs instead of a `VecRegisterData`, because the registers that the callee didn't have to preserve
+    /// are unknown in the caller frames. A `VecRegisterData` can only hold a contiguous range of known registers.
+    #[serde(serialize_with = "serialize_registers")]
+    pub registers: Vec<(gimli::Register, ADDR)>,
+    /// The amount of bytes of stack the function of this frame uses, if it is known.
+    ///
+    /// This is the difference between the stack pointer of this frame and the stack pointer of its caller.
+    /// Inline functions use the stack of the function they're inlined into, so they don't have a size.
+    pub frame_size: Option<u64>,
+}
+
+/// Options for how a frame is displayed, see [Frame::display]
+#[derive(Debug, Clone)]
+pub struct FrameDisplayOptions {
+    /// When true, any variable that is a parameter will be shown
+    pub show_parameters: bool,
+    /// When true, any variable that is inlined will be shown
+    pub show_inlined_vars: bool,
+    /// When true, any variable that is zero-sized will be shown
+    pub show_zero_sized_vars: bool,
+    /// When true, the variables that could not be decoded are shown with the reason
+    pub show_omitted_vars: bool,
+    /// When true, the values of the [Frame::registers] are shown
+    pub show_registers: bool,
+    /// Gets the name of a register. Use the [platform::Platform::register_name] of the platform that was traced.
+    ///
+    /// Registers without a name are shown with their DWARF register number.
+    pub register_name: fn(gimli::Register) -> Option<&'static str>,
+    /// The colors of the text
+    pub theme: Theme,
+    /// How the values of the variables are rendered
+    pub render_options: RenderOptions,
+}
+
+impl Default for FrameDisplayOptions {
+    fn default() -> Self {
+        Self {
+            show_parameters: true,
+            show_inlined_vars: false,
+            show_zero_sized_vars: false,
+            show_omitted_vars: false,
+            show_registers: false,
+            register_name: |_| None,
+            theme: Theme::None,
+            render_options: RenderOptions::default(),
+        }
+    }
 }
 
 impl<ADDR: funty::Integral> Frame<ADDR> {
-    /// Get a string that can be displayed to a user
+    /// Replaces the `from` prefix of the file paths of the frame and its variables with `to`.
     ///
-    /// - `show_parameters`: When true, any variable that is a parameter will be shown
-    /// - `show_inlined_vars`: When true, any variable that is inlined will be shown
-    /// - `show_zero_sized_vars`: When true, any variable that is zero-sized will be shown
-    pub fn display(
-        &self,
-        show_parameters: bool,
-        show_inlined_vars: bool,
-        show_zero_sized_vars: bool,
-        theme: Theme,
-    ) -> String {
+    /// See [Location::remap_path_prefix].
+    pub fn remap_path_prefix(&mut self, from: &str, to: impl AsRef<Path>) {
+        self.location.remap_path_prefix(from, &to);
+        for variable in &mut self.variables {
+            variable.location.remap_path_prefix(from, &to);
+        }
+    }
+
+    /// Get a string that can be displayed to a user
+    pub fn display(&self, options: &FrameDisplayOptions) -> String {
         use std::fmt::Write;
 
+        let theme = options.theme;
         let mut display = String::new();
 
         writeln!(
@@ -92,17 +212,52 @@ impl<ADDR: funty::Integral> Frame<ADDR> {
             writeln!(display, "  at {}", theme.color_url(location_text)).unwrap();
         }
 
+        if options.show_registers && !self.registers.is_empty() {
+            let width = std::mem::size_of::<ADDR>() * 2 + 2;
+            let registers = self
+                .registers
+                .iter()
+                .map(|(register, value)| {
+                    let name = (options.register_name)(*register)
+                        .map(str::to_lowercase)
+                        .unwrap_or_else(|| register.0.to_string());
+                    format!(
+                        "{name} = {}",
+                        theme.color_numeric_value(format!("{value:#0width$X}"))
+                    )
+                })
+                .collect::<Vec<_>>();
+            writeln!(display, "  registers: {}", registers.join(", ")).unwrap();
+        }
+
         let filtered_variables = self.variables.iter().filter(|v| {
-            (show_inlined_vars || !v.kind.inlined)
-                && (show_zero_sized_vars || !v.kind.zero_sized)
-                && (show_parameters || !v.kind.parameter)
+            (options.show_inlined_vars || !v.kind.inlined)
+                && (options.show_zero_sized_vars || !v.kind.zero_sized)
+                && (options.show_parameters || !v.kind.parameter)
                 // Hide the vtables
                 && v.type_value.data().variable_type.archetype != Archetype::ObjectMemberPointer
         });
-        if filtered_variables.clone().count() > 0 {
+        let omitted_variables = match options.show_omitted_vars {
+            true => self.omitted_variables.as_slice(),
+            false => &[],
+        };
+        if filtered_variables.clone().count() > 0 || !omitted_variables.is_empty() {
             writeln!(display, "  variables:").unwrap();
             for variable in filtered_variables {
-                writeln!(display, "    {}", variable.display(theme)).unwrap();
+                writeln!(
+                    display,
+                    "    {}",
+                    variable.display(theme, &options.render_options)
+                )
+                .unwrap();
+            }
+            for omitted_variable in omitted_variables {
+                writeln!(
+                    display,
+                    "    {}",
+                    theme.color_info(format!("(omitted: {omitted_variable})"))
+                )
+                .unwrap();
             }
         }
 
@@ -110,8 +265,301 @@ impl<ADDR: funty::Integral> Frame<ADDR> {
     }
 }
 
-/// The type of a frame
+impl<ADDR: funty::Integral> PartialEq for Frame<ADDR> {
+    fn eq(&self, other: &Self) -> bool {
+        self.function == other.function
+            && self.location == other.location
+            && self.frame_type == other.frame_type
+            && self.variables == other.variables
+    }
+}
+
+impl<ADDR: funty::Integral> Eq for Frame<ADDR> {}
+
+impl<ADDR: funty::Integral> Hash for Frame<ADDR> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.function.hash(state);
+        self.location.hash(state);
+        self.frame_type.hash(state);
+        self.variables.hash(state);
+    }
+}
+
+/// A frame that directly follows itself one or more times in a trace
 #[derive(Debug, Clone)]
+pub struct CollapsedFrame<ADDR: funty::Integral> {
+    /// The first of the equal frames
+    pub frame: Frame<ADDR>,
+    /// The amount of times the frame is in the trace in a row. This is 1 if the frame isn't repeated.
+    pub count: usize,
+}
+
+impl<ADDR: funty::Integral> CollapsedFrame<ADDR> {
+    /// Get a string that can be displayed to a user.
+    ///
+    /// This is the same as [Frame::display] with a line that says how many times the frame is repeated.
+    pub fn display(&self, options: &FrameDisplayOptions) -> String {
+        let mut display = self.frame.display(options);
+
+        if self.count > 1 {
+            display.push_str(&format!(
+                "  {}\n",
+                options
+                    .theme
+                    .color_info(format!("(×{} recursive)", self.count))
+            ));
+        }
+
+        display
+    }
+}
+
+/// Replaces the runs of equal frames in a trace with a single frame and the amount of times it was repeated.
+///
+/// A deep recursion (like the one of a stack overflow) can result in a huge amount of frames.
+/// Frames are compared by their function, location and variables, but not the values. See [Frame].
+///
+/// The `inlined_into` indices of the frames are updated to the indices in the returned list.
+pub fn collapse_recursion<ADDR: funty::Integral>(
+    frames: Vec<Frame<ADDR>>,
+) -> Vec<CollapsedFrame<ADDR>> {
+    let mut collapsed_frames: Vec<CollapsedFrame<ADDR>> = Vec::new();
+    // The index in the collapsed frames for every original frame
+    let mut new_indices = Vec::with_capacity(frames.len());
+
+    for frame in frames {
+        match collapsed_frames.last_mut() {
+            Some(last) if last.frame == frame => last.count += 1,
+            _ => collapsed_frames.push(CollapsedFrame { frame, count: 1 }),
+        }
+
+        new_indices.push(collapsed_frames.len() - 1);
+    }
+
+    for collapsed_frame in collapsed_frames.iter_mut() {
+        collapsed_frame.frame.inlined_into = collapsed_frame
+            .frame
+            .inlined_into
+            .and_then(|index| new_indices.get(index).copied());
+    }
+
+    collapsed_frames
+}
+
+/// Calculates the maximum stack usage that can be seen in the frames of a trace.
+///
+/// - `frames`: The frames of a trace
+/// - `initial_sp`: The stack pointer at the start of the program, which is the top of the stack.
+///   For Cortex-M this can be read from the elf file that was used for the trace with [platform::cortex_m::initial_stack_pointer].
+///
+/// Returns None if none of the frames have a known stack pointer.
+pub fn stack_usage<ADDR: funty::Integral>(
+    frames: &[Frame<ADDR>],
+    initial_sp: ADDR,
+) -> Option<ADDR> {
+    // The stack grows down, so the lowest stack pointer is where the stack was deepest
+    frames
+        .iter()
+        .filter_map(|frame| frame.stack_pointer)
+        .min()
+        .map(|deepest_sp| initial_sp.saturating_sub(deepest_sp))
+}
+
+/// Gets the amount of stack every function of a trace uses, see [Frame::frame_size].
+///
+/// The functions are in the order of the frames. A function that recurses is in it once for every call.
+/// Functions of which the frame size is not known are left out.
+pub fn frame_sizes<ADDR: funty::Integral>(frames: &[Frame<ADDR>]) -> Vec<(&str, u64)> {
+    frames
+        .iter()
+        .filter_map(|frame| Some((frame.function.as_str(), frame.frame_size?)))
+        .collect()
+}
+
+/// Serializes the frames of a trace to JSON.
+///
+/// This is a list of the frames with all of their fields. See [Frame] for how the variables are serialized.
+#[cfg(feature = "json")]
+pub fn frames_to_json<ADDR: funty::Integral>(frames: &[Frame<ADDR>]) -> String {
+    // Everything is serialized as strings, numbers, lists and objects with string keys, so this can't fail
+    serde_json::to_string(frames).unwrap()
+}
+
+/// Serializes the registers as a list of pairs of the DWARF register number and the value as a number
+fn serialize_registers<ADDR: funty::Integral, S: Serializer>(
+    registers: &[(gimli::Register, ADDR)],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(
+        registers
+            .iter()
+            .map(|(register, value)| (register.0, value.as_u64())),
+    )
+}
+
+/// Serializes an address as a number, regardless of its type
+fn serialize_address<ADDR: funty::Integral, S: Serializer>(
+    address: &Option<ADDR>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    address
+        .map(|address| address.as_u64())
+        .serialize(serializer)
+}
+
+/// A trace as a tree, for exporting it to tools that visualize it like flamegraph viewers
+///
+/// The root is the whole trace. It has the outermost frame as child, which has the frame it called as child, and so on.
+/// Inline frames are children of the frame they're inlined into. The variables of a frame are leaves next to its callee.
+/// The static variables are in a separate child of the root.
+///
+/// The JSON of `to_json` (with the `json` feature) uses the format of d3-flame-graph. Every node is an object with these fields:
+/// - `name`: The function name, or for a variable its name, type and value (`name: type = value`)
+/// - `kind`: `trace`, `function`, `inline_function`, `exception`, `corrupted`, `truncated`, `static` or `variable`
+/// - `location`: The code location as `file:line:column`. Left out when it's not known.
+/// - `value`: The amount of leaves in the node, or 1 if it has no children. Viewers use it as the width of the node.
+/// - `children`: The child nodes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TraceTree {
+    /// The name of the node
+    pub name: String,
+    /// The kind of the node
+    pub kind: TraceTreeKind,
+    /// The code location of the frame or variable
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    /// The amount of leaves in the node, or 1 if it has no children
+    pub value: usize,
+    /// The child nodes
+    pub children: Vec<TraceTree>,
+}
+
+/// The kind of a node of a [TraceTree]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceTreeKind {
+    /// The root of the tree
+    Trace,
+    /// A [FrameType::Function] frame
+    Function,
+    /// A [FrameType::InlineFunction] frame
+    InlineFunction,
+    /// A [FrameType::Exception] frame
+    Exception,
+    /// A [FrameType::Corrupted] frame
+    Corrupted,
+    /// A [FrameType::Truncated] frame
+    Truncated,
+    /// The [FrameType::Static] frame
+    Static,
+    /// A variable of a frame
+    Variable,
+}
+
+impl TraceTree {
+    /// Builds the tree of the frames of a trace. The values of the variables are rendered with the given options.
+    pub fn new<ADDR: funty::Integral>(
+        frames: &[Frame<ADDR>],
+        render_options: &RenderOptions,
+    ) -> Self {
+        let mut static_nodes = Vec::new();
+        // The frames go from the innermost to the outermost, so every frame gets the previous one as child
+        let mut callee = None;
+
+        for frame in frames {
+            let mut children = frame
+                .variables
+                .iter()
+                .map(|variable| Self::variable_node(variable, render_options))
+                .collect::<Vec<_>>();
+
+            let kind = match frame.frame_type {
+                FrameType::Function => TraceTreeKind::Function,
+                FrameType::InlineFunction => TraceTreeKind::InlineFunction,
+                FrameType::Exception => TraceTreeKind::Exception,
+                FrameType::Corrupted(_) => TraceTreeKind::Corrupted,
+                FrameType::Truncated { .. } => TraceTreeKind::Truncated,
+                FrameType::Static => {
+                    static_nodes.push(Self::node(
+                        frame.function.clone(),
+                        TraceTreeKind::Static,
+                        None,
+                        children,
+                    ));
+                    continue;
+                }
+            };
+
+            children.extend(callee.take());
+            callee = Some(Self::node(
+                frame.function.clone(),
+                kind,
+                Self::location_text(&frame.location),
+                children,
+            ));
+        }
+
+        Self::node(
+            "trace".into(),
+            TraceTreeKind::Trace,
+            None,
+            callee.into_iter().chain(static_nodes).collect(),
+        )
+    }
+
+    /// Serializes the tree to JSON
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> String {
+        // The tree only consists of strings, numbers and lists, so this can't fail
+        serde_json::to_string(self).unwrap()
+    }
+
+    fn node(
+        name: String,
+        kind: TraceTreeKind,
+        location: Option<String>,
+        children: Vec<TraceTree>,
+    ) -> Self {
+        Self {
+            name,
+            kind,
+            location,
+            value: children
+                .iter()
+                .map(|child| child.value)
+                .sum::<usize>()
+                .max(1),
+            children,
+        }
+    }
+
+    fn variable_node<ADDR: funty::Integral>(
+        variable: &Variable<ADDR>,
+        render_options: &RenderOptions,
+    ) -> Self {
+        let name = format!(
+            "{}: {} = {}",
+            variable.name,
+            variable.type_name(),
+            render_type_value_tree_with_options(&variable.type_value, Theme::None, render_options)
+        );
+
+        Self::node(
+            name,
+            TraceTreeKind::Variable,
+            Self::location_text(&variable.location),
+            Vec::new(),
+        )
+    }
+
+    fn location_text(location: &Location) -> Option<String> {
+        Some(location.to_string()).filter(|text| !text.is_empty())
+    }
+}
+
+/// The type of a frame
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum FrameType {
     /// A real function
     Function,
@@ -121,6 +569,13 @@ pub enum FrameType {
     Exception,
     /// The frame could not be (fully) read, so the frame is corrupted. The string says what the problem is.
     Corrupted(String),
+    /// The stack of the caller has not been captured, so the unwinding stops here.
+    /// The stack pointer looks fine, but the capture of the stack ends before it.
+    /// Capturing a larger part of the stack lets the trace continue.
+    Truncated {
+        /// The first address of the stack that is needed, but has not been captured
+        missing_from: u64,
+    },
     /// This is not really a frame, but has all the statically available data
     Static,
 }
@@ -132,25 +587,38 @@ impl Display for FrameType {
             FrameType::InlineFunction => write!(f, "Inline Function"),
             FrameType::Exception => write!(f, "Exception"),
             FrameType::Corrupted(reason) => write!(f, "Corrupted: \"{reason}\""),
+            FrameType::Truncated { missing_from } => write!(
+                f,
+                "Truncated: the stack has not been captured from {missing_from:#X}"
+            ),
             FrameType::Static => write!(f, "Static"),
         }
     }
 }
 
 /// A variable that was found in the tracing procedure
-#[derive(Debug, Clone)]
+///
+/// Two variables are equal when they have the same name, kind, type and location. The value is not compared.
+#[derive(Debug, Clone, Serialize)]
+#[serde(bound = "")]
 pub struct Variable<ADDR: funty::Integral> {
     /// The name of the variable
     pub name: String,
     /// The kind of variable (normal, parameter, etc)
     pub kind: VariableKind,
+    #[serde(serialize_with = "type_value_tree::serialize_type_value_tree")]
     pub type_value: TypeValueTree<ADDR>,
     /// The code location of where this variable is declared
     pub location: Location,
 }
 
 impl<ADDR: funty::Integral> Variable<ADDR> {
-    pub fn display(&self, theme: Theme) -> String {
+    /// The name of the type of the variable
+    fn type_name(&self) -> &str {
+        &self.type_value.root().data().variable_type.name
+    }
+
+    pub fn display(&self, theme: Theme, render_options: &RenderOptions) -> String {
         let mut kind_text = self.kind.to_string();
         if !kind_text.is_empty() {
             kind_text = theme.color_info(format!("({}) ", kind_text)).to_string();
@@ -166,12 +634,52 @@ impl<ADDR: funty::Integral> Variable<ADDR> {
             kind_text,
             theme.color_variable_name(&self.name),
             theme.color_type_name(&self.type_value.root().data().variable_type.name),
-            render_type_value_tree(&self.type_value, theme),
+            render_type_value_tree_with_options(&self.type_value, theme, render_options),
             location_text,
         )
     }
 }
 
+impl<ADDR: funty::Integral> PartialEq for Variable<ADDR> {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.kind == other.kind
+            && self.type_name() == other.type_name()
+            && self.location == other.location
+    }
+}
+
+impl<ADDR: funty::Integral> Eq for Variable<ADDR> {}
+
+impl<ADDR: funty::Integral> Hash for Variable<ADDR> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        self.kind.hash(state);
+        self.type_name().hash(state);
+        self.location.hash(state);
+    }
+}
+
+/// A variable that was found in the debug info, but that could not be decoded and so is left out of its frame
+#[derive(Debug, Clone, Serialize)]
+pub struct OmittedVariable {
+    /// The name of the variable, if it could be read
+    pub name: Option<String>,
+    /// Why the variable could not be decoded
+    pub reason: String,
+}
+
+impl Display for OmittedVariable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} — {}",
+            self.name.as_deref().unwrap_or("<unnamed>"),
+            self.reason
+        )
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum VariableLocationResult {
     /// The DW_AT_location attribute is missing
@@ -180,14 +688,106 @@ pub enum VariableLocationResult {
     LocationListNotFound,
     /// This variable is not present in memory at this point
     NoLocationFound,
+    /// The variable is thread local, but no TLS base has been set on the device memory
+    TlsBaseUnknown,
+    /// The location depends on a register of which the value is not known in this frame
+    MissingRegister(stackdump_core::device_memory::MissingRegisterError),
     /// A required step of the location evaluation logic has not been implemented yet
     LocationEvaluationStepNotImplemented(Rc<EvaluationResult<DefaultReader>>),
     /// The variable is split up into multiple pieces of memory
     LocationsFound(Vec<Piece<DefaultReader, usize>>),
 }
 
+/// Limits for how much work is done to decode the variables of a trace.
+///
+/// A dump can't always be trusted. When it's corrupted (or crafted), the decoding can end up following
+/// very long pointer chains or reading huge amounts of memory.
+/// When one of the limits is reached, the values that are decoded after that get a
+/// [VariableDataError::BudgetExceeded](type_value_tree::VariableDataError::BudgetExceeded) error,
+/// but the trace itself is still completed.
+///
+/// The default budget has no limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeBudget {
+    /// The maximum amount of bytes that are read from the device memory for all variables combined
+    pub max_bytes_read: u64,
+    /// The maximum amount of values that are decoded for all variables combined
+    pub max_nodes: u64,
+    /// The maximum amount of pointers that are followed in a row, e.g. in a linked list
+    pub max_pointer_depth: u32,
+}
+
+impl DecodeBudget {
+    /// A budget without any limits
+    pub const UNLIMITED: Self = Self {
+        max_bytes_read: u64::MAX,
+        max_nodes: u64::MAX,
+        max_pointer_depth: u32::MAX,
+    };
+}
+
+impl Default for DecodeBudget {
+    fn default() -> Self {
+        Self::UNLIMITED
+    }
+}
+
+/// Options for decoding the variables of a trace
+#[derive(Debug, Clone, Default)]
+pub struct DecodeOptions {
+    /// The limits for how much work is done to decode the variables
+    pub budget: DecodeBudget,
+    /// The name of the member that has the length of the flexible array member of a struct, by the name of the struct type.
+    ///
+    /// A C struct can end with a flexible array member (`struct Message { int len; char data[]; }`).
+    /// The length of the array is only known at runtime and its elements are stored after the struct.
+    /// If a struct is not in here, a member called `len`, `length` or `count` is used when the struct has one.
+    pub flexible_array_lengths: HashMap<String, String>,
+    /// When true, the variables are not decoded at all and the frames only have their function and location.
+    ///
+    /// Decoding the variables is most of the work of a trace, so this is a lot faster.
+    /// See [platform::trace_frames_only].
+    pub skip_variables: bool,
+    /// The maximum amount of frames of a trace, including the inline frames but not the static frame.
+    ///
+    /// When a corrupted stack makes the unwinding go on and on, the trace is stopped with a corrupted frame.
+    /// Going around in a loop is always detected, so `None` means there is no limit.
+    pub max_frames: Option<usize>,
+    /// How the names of the functions of the frames are demangled
+    pub demangle_mode: DemangleMode,
+    /// The name of the section with the vector table of a Cortex-M program.
+    /// If None, the names in [platform::cortex_m::VECTOR_TABLE_SECTIONS] are tried.
+    /// A named section that doesn't exist makes the trace fail.
+    pub vector_table_section: Option<String>,
+    /// Arrays with more elements than this are expanded lazily.
+    ///
+    /// The type of a variable is built once and then cloned for every variable that has it.
+    /// Normally an array gets a node for each of its elements when its type is built, so big arrays make those clones slow.
+    /// A lazy array only has the type of its elements until its data is read and then its elements are created.
+    /// The result is the same, so this is only about how fast the trace is.
+    /// If None, all arrays are expanded when their type is built.
+    pub lazy_array_threshold: Option<u64>,
+}
+
+/// How the names of the functions of the frames are demangled
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Default, strum_macros::Display, strum_macros::EnumString,
+)]
+#[strum(serialize_all = "lowercase")]
+pub enum DemangleMode {
+    /// The language of the function decides how its name is demangled
+    #[default]
+    Auto,
+    /// The names are not demangled, so they are the same as the symbols in e.g. a linker map
+    Raw,
+    /// All names are demangled as Rust names. Names that aren't are kept as they are.
+    Rust,
+    /// All names are demangled as C++ names. Names that aren't are kept as they are.
+    Cpp,
+}
+
 /// Type representing what kind of variable something is
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize)]
 pub struct VariableKind {
     /// The variable is a zero-sized type
     pub zero_sized: bool,
@@ -214,3 +814,180 @@ impl Display for VariableKind {
         write!(f, "{}", elements.join(" "))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::{cortex_m::CortexMPlatform, Platform};
+
+    fn frame(function: &str, line: u64, inlined_into: Option<usize>) -> Frame<u32> {
+        Frame {
+            function: function.into(),
+            location: Location {
+                file: Some("src/main.rs".into()),
+                line: Some(line),
+                column: None,
+            },
+            frame_type: FrameType::Function,
+            variables: Vec::new(),
+            omitted_variables: Vec::new(),
+            inlined_into,
+            stack_pointer: Some(0x2000_0000 - line as u32),
+            registers: vec![(gimli::Arm::SP, 0x2000_0000 - line as u32)],
+            frame_size: None,
+        }
+    }
+
+    #[test]
+    fn collapse_recursion() {
+        let frames = vec![
+            frame("inlined", 1, Some(1)),
+            frame("recurse", 2, None),
+            frame("recurse", 2, None),
+            frame("recurse", 2, None),
+            frame("inlined", 1, Some(5)),
+            frame("recurse", 2, None),
+            frame("main", 3, None),
+        ];
+
+        assert_eq!(frames[1], frames[2]);
+        assert_ne!(frames[1], frames[6]);
+
+        let collapsed_frames = super::collapse_recursion(frames);
+
+        assert_eq!(
+            collapsed_frames
+                .iter()
+                .map(|f| (f.frame.function.as_str(), f.count, f.frame.inlined_into))
+                .collect::<Vec<_>>(),
+            [
+                ("inlined", 1, Some(1)),
+                ("recurse", 3, None),
+                ("inlined", 1, Some(3)),
+                ("recurse", 1, None),
+                ("main", 1, None),
+            ]
+        );
+        assert!(collapsed_frames[1]
+            .display(&FrameDisplayOptions::default())
+            .ends_with("(×3 recursive)\n"));
+        assert!(collapsed_frames[1]
+            .display(&FrameDisplayOptions {
+                show_registers: true,
+                ..Default::default()
+            })
+            .contains("  registers: 13 = 0x1FFFFFFE\n"));
+        assert!(collapsed_frames[1]
+            .display(&FrameDisplayOptions {
+                show_registers: true,
+                register_name: CortexMPlatform::register_name,
+                ..Default::default()
+            })
+            .contains("  registers: r13 = 0x1FFFFFFE\n"));
+    }
+
+    #[test]
+    fn stack_usage() {
+        const INITIAL_SP: u32 = 0x2000_0000;
+
+        // The frames of `frame` have their stack pointer a line number below the initial stack pointer
+        let frames = vec![
+            frame("inner", 24, None),
+            frame("outer", 8, None),
+            frame("main", 0, None),
+        ];
+        assert_eq!(super::stack_usage(&frames, INITIAL_SP), Some(24));
+
+        // Without any known stack pointer there's nothing to go by
+        let mut unknown = frame("main", 0, None);
+        unknown.stack_pointer = None;
+        assert_eq!(super::stack_usage::<u32>(&[], INITIAL_SP), None);
+        assert_eq!(super::stack_usage(&[unknown], INITIAL_SP), None);
+
+        // A stack that has overflowed into the memory below it is still measured from the top
+        let mut overflowed = frame("overflowed", 0, None);
+        overflowed.stack_pointer = Some(0x1FFF_0000);
+        assert_eq!(
+            super::stack_usage(&[overflowed], INITIAL_SP),
+            Some(0x1_0000)
+        );
+
+        // A stack pointer above the top of the stack doesn't wrap around
+        let mut above = frame("above", 0, None);
+        above.stack_pointer = Some(INITIAL_SP + 0x100);
+        assert_eq!(super::stack_usage(&[above], INITIAL_SP), Some(0));
+    }
+
+    #[test]
+    fn trace_tree() {
+        let mut inlined = frame("inlined", 1, Some(1));
+        inlined.frame_type = FrameType::InlineFunction;
+        let mut statics = frame("Static", 0, None);
+        statics.frame_type = FrameType::Static;
+        statics.location = Location::default();
+        let frames = vec![
+            inlined,
+            frame("function", 2, None),
+            frame("main", 3, None),
+            statics,
+        ];
+
+        let tree = TraceTree::new(&frames, &RenderOptions::default());
+
+        assert_eq!(tree.kind, TraceTreeKind::Trace);
+        assert_eq!(tree.value, 2);
+        assert_eq!(tree.children.len(), 2);
+
+        let main = &tree.children[0];
+        assert_eq!(main.name, "main");
+        assert_eq!(main.location.as_deref(), Some("src/main.rs:3"));
+        let function = &main.children[0];
+        assert_eq!(function.name, "function");
+        let inlined = &function.children[0];
+        assert_eq!(inlined.kind, TraceTreeKind::InlineFunction);
+        assert!(inlined.children.is_empty());
+
+        assert_eq!(tree.children[1].kind, TraceTreeKind::Static);
+
+        #[cfg(feature = "json")]
+        assert!(tree.to_json().starts_with(
+            r#"{"name":"trace","kind":"trace","value":2,"children":[{"name":"main","kind":"function","location":"src/main.rs:3","value":1,"#
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn frames_to_json() {
+        let mut main = frame("main", 3, None);
+        main.variables.push(Variable {
+            name: "x".into(),
+            kind: VariableKind::default(),
+            type_value: TypeValueTree::new(type_value_tree::TypeValue {
+                variable_type: type_value_tree::variable_type::VariableType {
+                    name: "u32".into(),
+                    archetype: Archetype::BaseType(gimli::DW_ATE_unsigned),
+                    ..Default::default()
+                },
+                variable_value: Ok(type_value_tree::value::Value::Uint(5)),
+                ..Default::default()
+            }),
+            location: Location::default(),
+        });
+        let mut corrupted = frame("corrupted", 4, None);
+        corrupted.frame_type = FrameType::Corrupted("no unwind info".into());
+
+        assert_eq!(
+            super::frames_to_json(&[main, corrupted]),
+            concat!(
+                r#"[{"function":"main","location":{"file":"src/main.rs","line":3,"column":null},"frame_type":"function","#,
+                r#""variables":[{"name":"x","kind":{"zero_sized":false,"inlined":false,"parameter":false},"#,
+                r#""type_value":{"name":"","type":"u32","archetype":"base_type","value":5,"children":[]},"#,
+                r#""location":{"file":null,"line":null,"column":null}}],"#,
+                r#""omitted_variables":[],"inlined_into":null,"stack_pointer":536870909,"registers":[[13,536870909]],"frame_size":null},"#,
+                r#"{"function":"corrupted","location":{"file":"src/main.rs","line":4,"column":null},"#,
+                r#""frame_type":{"corrupted":"no unwind info"},"variables":[],"omitted_variables":[],"inlined_into":null,"stack_pointer":536870908,"#,
+                r#""registers":[[13,536870908]],"frame_size":null}]"#,
+            )
+        );
+    }
+}
@@ -2,92 +2,625 @@
 
 use super::{Platform, UnwindResult};
 use crate::error::TraceError;
-use crate::{Frame, FrameType};
-use addr2line::object::{Object, ObjectSection, ObjectSymbol};
+use crate::{DecodeOptions, DefaultReader, Frame, FrameType};
+use addr2line::object::{Object, ObjectSection, ObjectSymbol, SectionKind, SymbolKind};
 use core::ops::Range;
 use gimli::{
-    BaseAddresses, CfaRule, DebugFrame, EndianSlice, LittleEndian, RegisterRule, RunTimeEndian,
-    UnwindContext, UnwindSection, UnwindTableRow,
+    BaseAddresses, CfaRule, DebugFrame, EhFrame, EndianSlice, LittleEndian, Reader, RegisterRule,
+    RunTimeEndian, UnwindContext, UnwindSection, UnwindTableRow,
 };
-use stackdump_core::device_memory::DeviceMemory;
+use stackdump_core::{device_memory::DeviceMemory, register_data::cortex_m as stack_pointers};
 
 const THUMB_BIT: u32 = 1;
 const EXC_RETURN_MARKER: u32 = 0xFF00_0000;
+const EXC_RETURN_ES_MASK: u32 = 1 << 0;
+const EXC_RETURN_SPSEL_MASK: u32 = 1 << 2;
 const EXC_RETURN_FTYPE_MASK: u32 = 1 << 4;
+const EXC_RETURN_DCRS_MASK: u32 = 1 << 5;
+const EXC_RETURN_S_MASK: u32 = 1 << 6;
+/// The bit in the stacked PSR that tells that a word of padding was added to align the stack
+const STACKED_PSR_ALIGNED_MASK: u32 = 1 << 9;
+/// The amount of words of r0 - r3, r12, lr, pc and the psr on the stack
+const BASIC_FRAME_WORDS: u64 = 8;
+/// The amount of fpu registers of s0 - s15, the fpscr and a reserved one on the stack
+const FPU_FRAME_REGISTERS: u64 = 18;
+/// The amount of words of the integrity signature, a reserved word and r4 - r11 on the stack
+const ADDITIONAL_STATE_CONTEXT_WORDS: u64 = 10;
+/// The registers that a function doesn't have to preserve for its caller (AAPCS)
+const CALLER_SAVED_REGISTERS: [gimli::Register; 5] = [
+    gimli::Arm::R0,
+    gimli::Arm::R1,
+    gimli::Arm::R2,
+    gimli::Arm::R3,
+    gimli::Arm::R12,
+];
+/// The registers that a function has to preserve for its caller (AAPCS)
+const CALLEE_SAVED_REGISTERS: [gimli::Register; 8] = [
+    gimli::Arm::R4,
+    gimli::Arm::R5,
+    gimli::Arm::R6,
+    gimli::Arm::R7,
+    gimli::Arm::R8,
+    gimli::Arm::R9,
+    gimli::Arm::R10,
+    gimli::Arm::R11,
+];
+
+/// The EXC_RETURN value that is in the LR when an exception handler returns.
+/// It tells how the exception frame was stacked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ExcReturn(u32);
+
+impl ExcReturn {
+    /// Returns None if the value of the LR is not an EXC_RETURN value
+    fn from_lr(lr: u32) -> Option<Self> {
+        if lr >= EXC_RETURN_MARKER {
+            Some(Self(lr))
+        } else {
+            None
+        }
+    }
+
+    /// ES: The exception was taken to the secure state (ARMv8-M with the security extension)
+    fn secure_exception(self) -> bool {
+        self.0 & EXC_RETURN_ES_MASK != 0
+    }
+
+    /// SPSEL: The frame was stacked on a process stack instead of on a main stack
+    fn process_stack(self) -> bool {
+        self.0 & EXC_RETURN_SPSEL_MASK != 0
+    }
+
+    /// FType: The frame contains the floating point context
+    fn extended_frame(self) -> bool {
+        self.0 & EXC_RETURN_FTYPE_MASK == 0
+    }
+
+    /// DCRS: The callee-saved registers were stacked as well.
+    /// ARMv8-M does this when the secure state is interrupted by a non-secure exception.
+    fn additional_state_context(self) -> bool {
+        self.0 & EXC_RETURN_DCRS_MASK == 0
+    }
+
+    /// S: The frame was stacked on a secure stack (ARMv8-M with the security extension)
+    fn secure_stack(self) -> bool {
+        self.0 & EXC_RETURN_S_MASK != 0
+    }
+
+    /// The registers of the stack pointer the frame was stacked on.
+    /// The banked register of the security state comes first, the register of a chip without the security extension second.
+    ///
+    /// ARMv7-M always has the ES and S bits set, so it gets the secure registers that won't have been captured.
+    fn frame_stack_pointers(self) -> [gimli::Register; 2] {
+        match (self.secure_stack(), self.process_stack()) {
+            (true, true) => [stack_pointers::PSP_S, stack_pointers::PSP],
+            (true, false) => [stack_pointers::MSP_S, stack_pointers::MSP],
+            (false, true) => [stack_pointers::PSP_NS, stack_pointers::PSP],
+            (false, false) => [stack_pointers::MSP_NS, stack_pointers::MSP],
+        }
+    }
+
+    /// The registers of the main stack pointer the exception handler ran on, in the same order as [Self::frame_stack_pointers]
+    fn handler_stack_pointers(self) -> [gimli::Register; 2] {
+        if self.secure_exception() {
+            [stack_pointers::MSP_S, stack_pointers::MSP]
+        } else {
+            [stack_pointers::MSP_NS, stack_pointers::MSP]
+        }
+    }
+}
+
+/// Returns the first of the stack pointer registers that has been captured
+fn captured_stack_pointer(
+    device_memory: &DeviceMemory<u32>,
+    registers: [gimli::Register; 2],
+) -> Option<gimli::Register> {
+    registers
+        .into_iter()
+        .find(|register| device_memory.register(*register).is_ok())
+}
+
+/// Reads the initial stack pointer from the vector table of the elf file.
+///
+/// This is the top of the stack, which can be used to calculate the stack usage with [crate::stack_usage].
+pub fn initial_stack_pointer<'data>(
+    elf: &addr2line::object::File<'data, &'data [u8]>,
+) -> Result<u32, TraceError> {
+    Ok(read_vector_table(elf, None)?[0])
+}
+
+/// Scores how well the elf file matches the captured registers and memory of a device. Higher is better.
+///
+/// - 2 points if the PC is in a function, or 1 if it is in the code but not in a known function
+/// - 1 point if the LR is a return address in the code
+/// - 1 point if the SP is in the stack
+/// - 2 points if captured memory of the code or read-only data (like the vector table) is the same as in the elf file
+///
+/// If captured memory of the code or read-only data is different from the elf file, it can't be the right elf file and the score is 0.
+pub fn elf_match_score<'data>(
+    elf: &addr2line::object::File<'data, &'data [u8]>,
+    device_memory: &DeviceMemory<u32>,
+) -> Result<u32, TraceError> {
+    let mut score = 0;
+
+    for section in elf.sections().filter(|section| {
+        matches!(
+            section.kind(),
+            SectionKind::Text | SectionKind::ReadOnlyData | SectionKind::ReadOnlyString
+        ) && section.size() > 0
+    }) {
+        let captured_data = device_memory
+            .read_slice_ref(section.address()..section.address() + section.size())
+            .ok()
+            .flatten();
+
+        if let Some(captured_data) = captured_data {
+            if *captured_data != *section.data()? {
+                return Ok(0);
+            }
+            score = 2;
+        }
+    }
+
+    let text_address_ranges = text_address_ranges(elf);
+    let in_text = |address: u32| {
+        text_address_ranges
+            .iter()
+            .any(|range| range.contains(&address))
+    };
+
+    if let Ok(pc) = device_memory.register(gimli::Arm::PC) {
+        let pc = pc & !THUMB_BIT;
+        let in_function = elf.symbols().any(|symbol| {
+            symbol.kind() == SymbolKind::Text
+                && (symbol.address()..symbol.address() + symbol.size()).contains(&(pc as u64))
+        });
+
+        if in_function {
+            score += 2;
+        } else if in_text(pc) {
+            score += 1;
+        }
+    }
+
+    if let Ok(lr) = device_memory.register(gimli::Arm::LR) {
+        if lr & EXC_RETURN_MARKER != EXC_RETURN_MARKER && in_text(lr & !THUMB_BIT) {
+            score += 1;
+        }
+    }
+
+    if let Ok(sp) = device_memory.register(gimli::Arm::SP) {
+        let stack_range = read_vector_table(elf, None)
+            .ok()
+            .and_then(|vector_table| vector_table.first().copied())
+            .and_then(|initial_stack_pointer| {
+                stack_range(&super::elf_segment_ranges(elf), initial_stack_pointer)
+            });
+
+        if let Some(stack_range) = stack_range {
+            // The stack range is exclusive, but an empty stack has the SP at the initial stack pointer
+            if stack_range.start <= sp as u64 && sp as u64 <= stack_range.end {
+                score += 1;
+            }
+        }
+    }
+
+    Ok(score)
+}
+
+/// Selects the elf file that best matches the captured registers and memory of a device.
+///
+/// This is useful when there are multiple firmware versions and it's not known which one the device was running.
+/// The elf files are scored with [elf_match_score] and the index of the best one is returned.
+/// When there's a tie, the first one is picked.
+///
+/// Returns [TraceError::NoMatchingElf] if none of the elf files match at all.
+pub fn select_elf(elfs: &[&[u8]], device_memory: &DeviceMemory<u32>) -> Result<usize, TraceError> {
+    let mut best_elf = None;
+
+    for (index, elf_data) in elfs.iter().enumerate() {
+        let elf = addr2line::object::File::parse(*elf_data)?;
+        let score = elf_match_score(&elf, device_memory)?;
+
+        log::debug!("Elf file {index} has a match score of {score}");
+
+        match best_elf {
+            Some((_, best_score)) if best_score >= score => {}
+            _ if score > 0 => best_elf = Some((index, score)),
+            _ => {}
+        }
+    }
+
+    best_elf
+        .map(|(index, _)| index)
+        .ok_or(TraceError::NoMatchingElf)
+}
+
+/// Gets the range the stack can be in.
+///
+/// The stack is not part of any segment. It grows down from the initial stack pointer
+/// to (at most) the end of the segment below it, which is normally the end of the statics in RAM.
+fn stack_range(memory_ranges: &[Range<u64>], initial_stack_pointer: u32) -> Option<Range<u64>> {
+    let initial_stack_pointer = initial_stack_pointer as u64;
+    memory_ranges
+        .iter()
+        .map(|memory_range| memory_range.end)
+        .filter(|end| *end <= initial_stack_pointer)
+        .max()
+        .map(|stack_bottom| stack_bottom..initial_stack_pointer)
+        .filter(|stack_range| !stack_range.is_empty())
+}
+
+/// The names of the section with the vector table that are tried when no name is given.
+///
+/// `.vector_table` is the name cortex-m-rt uses, the others are used by the startup code of many vendor SDKs.
+pub const VECTOR_TABLE_SECTIONS: &[&str] = &[".vector_table", ".isr_vector", ".vectors"];
+
+/// Reads the vector table from the section with the given name, or from the first of the [VECTOR_TABLE_SECTIONS] if there is no name.
+///
+/// The vector table has at least the initial stack pointer and the reset vector.
+fn read_vector_table<'data>(
+    elf: &addr2line::object::File<'data, &'data [u8]>,
+    section_name: Option<&str>,
+) -> Result<Vec<u32>, TraceError> {
+    let section_names = match section_name {
+        Some(section_name) => vec![section_name],
+        None => VECTOR_TABLE_SECTIONS.to_vec(),
+    };
+
+    section_names
+        .iter()
+        .filter_map(|section_name| elf.section_by_name(section_name))
+        .filter(|section| section.size() >= 8)
+        .map(|section| {
+            Ok(section
+                .data()?
+                .chunks_exact(4)
+                .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+                .collect())
+        })
+        .next()
+        .unwrap_or_else(|| Err(TraceError::MissingElfSection(section_names.join("` or `"))))
+}
+
+/// Gets the address ranges of the code.
+///
+/// Next to `.text` this includes the `.text.*` sections, because the compiler can split functions and place the cold parts
+/// in a section like `.text.unlikely`. If the linker script doesn't merge those into `.text`, they stay separate.
+fn text_address_ranges<'data>(
+    elf: &addr2line::object::File<'data, &'data [u8]>,
+) -> Vec<Range<u32>> {
+    elf.sections()
+        .filter(|section| {
+            section
+                .name()
+                .map(|name| name == ".text" || name.starts_with(".text."))
+                .unwrap_or(false)
+        })
+        .map(|section| section.address() as u32..(section.address() + section.size()) as u32)
+        .filter(|range| !range.is_empty())
+        .collect()
+}
+
+/// Gets the address ranges of the reset function.
+///
+/// The reset function may have been split by the compiler as well. The cold parts get their own symbol
+/// with a `.cold` suffix (e.g. `Reset.cold.1`), so those are included too.
+fn reset_vector_address_ranges<'data>(
+    elf: &addr2line::object::File<'data, &'data [u8]>,
+    reset_vector_address: u32,
+) -> Vec<Range<u32>> {
+    let symbol_range = |symbol: &addr2line::object::Symbol<'data, '_, &'data [u8]>| -> Range<u32> {
+        symbol.address() as u32..(symbol.address() + symbol.size()) as u32
+    };
+
+    let reset_vector_symbol = elf
+        .symbols()
+        .find(|sym| sym.address() as u32 == reset_vector_address);
+
+    match reset_vector_symbol {
+        Some(reset_vector_symbol) => {
+            let cold_prefix = reset_vector_symbol
+                .name()
+                .map(|name| format!("{name}.cold"))
+                .ok();
+
+            std::iter::once(symbol_range(&reset_vector_symbol))
+                .chain(
+                    elf.symbols()
+                        .filter(|sym| match (&cold_prefix, sym.name()) {
+                            (Some(cold_prefix), Ok(name)) => name.starts_with(cold_prefix.as_str()),
+                            _ => false,
+                        })
+                        .map(|sym| symbol_range(&sym)),
+                )
+                .collect()
+        }
+        None => Vec::new(),
+    }
+}
+
+/// The section with the call frame information that is used for the unwinding
+enum FrameSection<'data> {
+    DebugFrame(DebugFrame<EndianSlice<'data, LittleEndian>>),
+    EhFrame(EhFrame<EndianSlice<'data, LittleEndian>>),
+}
+
+impl<'data> FrameSection<'data> {
+    /// Reads the `.debug_frame` section or, if it's not there, the `.eh_frame` section.
+    /// The `.eh_frame` section can have addresses relative to other sections, so the bases are returned as well.
+    fn from_elf(
+        elf: &addr2line::object::File<'data, &'data [u8]>,
+    ) -> Result<(Self, BaseAddresses), TraceError> {
+        let address_size = std::mem::size_of::<Word<'data>>() as u8;
+
+        if let Some(section) = elf.section_by_name(".debug_frame") {
+            let mut debug_frame = DebugFrame::new(section.data()?, LittleEndian);
+            debug_frame.set_address_size(address_size);
+            return Ok((Self::DebugFrame(debug_frame), BaseAddresses::default()));
+        }
+
+        let section = elf
+            .section_by_name(".eh_frame")
+            .ok_or_else(|| TraceError::MissingElfSection(".debug_frame` or `.eh_frame".into()))?;
+        let mut eh_frame = EhFrame::new(section.data()?, LittleEndian);
+        eh_frame.set_address_size(address_size);
+
+        let mut bases = BaseAddresses::default().set_eh_frame(section.address());
+        if let Some(text) = elf.section_by_name(".text") {
+            bases = bases.set_text(text.address());
+        }
+        if let Some(got) = elf.section_by_name(".got") {
+            bases = bases.set_got(got.address());
+        }
+
+        Ok((Self::EhFrame(eh_frame), bases))
+    }
+
+    fn unwind_info_for_address<'ctx>(
+        &self,
+        bases: &BaseAddresses,
+        unwind_context: &'ctx mut UnwindContext<EndianSlice<'data, LittleEndian>>,
+        address: u64,
+    ) -> gimli::Result<&'ctx UnwindTableRow<EndianSlice<'data, LittleEndian>>> {
+        match self {
+            Self::DebugFrame(debug_frame) => debug_frame.unwind_info_for_address(
+                bases,
+                unwind_context,
+                address,
+                DebugFrame::cie_from_offset,
+            ),
+            Self::EhFrame(eh_frame) => eh_frame.unwind_info_for_address(
+                bases,
+                unwind_context,
+                address,
+                EhFrame::cie_from_offset,
+            ),
+        }
+    }
+}
 
 pub struct CortexMPlatform<'data> {
-    debug_frame: DebugFrame<EndianSlice<'data, LittleEndian>>,
-    reset_vector_address_range: Range<u32>,
-    text_address_range: Range<u32>,
+    frame_section: FrameSection<'data>,
+    initial_stack_pointer: u32,
+    reset_vector_address_ranges: Vec<Range<u32>>,
+    text_address_ranges: Vec<Range<u32>>,
     bases: BaseAddresses,
     unwind_context: UnwindContext<EndianSlice<'data, LittleEndian>>,
 }
 
+/// The word of the platform. The registers and the values on the stack have this size.
+type Word<'data> = <CortexMPlatform<'data> as Platform<'data>>::Word;
+
 impl<'data> CortexMPlatform<'data> {
+    /// The size of a word on the stack
+    const WORD_SIZE: u64 = std::mem::size_of::<Word<'data>>() as u64;
+    /// The size of the fpu registers s0 - s31. They are stacked in the extended frame.
+    const FPU_REGISTER_SIZE: u64 = 4;
+
+    /// Reads the word at the given address of the stack
+    fn read_stack_word(
+        device_memory: &DeviceMemory<Word<'data>>,
+        address: u64,
+    ) -> Result<Word<'data>, TraceError> {
+        device_memory
+            .read_uint::<Word<'data>>(address, RunTimeEndian::Little)?
+            .ok_or(TraceError::MissingMemory(address))
+    }
+
     fn apply_unwind_info(
         device_memory: &mut DeviceMemory<<Self as Platform<'data>>::Word>,
         unwind_info: UnwindTableRow<EndianSlice<LittleEndian>>,
     ) -> Result<bool, TraceError> {
-        let updated = match unwind_info.cfa() {
+        let new_cfa = match unwind_info.cfa() {
             CfaRule::RegisterAndOffset { register, offset } => {
-                let new_cfa = (device_memory.register(*register)? as i64 + *offset) as u32;
-                let old_cfa = device_memory.register(gimli::Arm::SP)?;
-                let changed = new_cfa != old_cfa;
-                *device_memory.register_mut(gimli::Arm::SP)? = new_cfa;
-                changed
+                (device_memory.register(*register)? as i64 + *offset) as u32
+            }
+            CfaRule::Expression(expression) => {
+                Self::evaluate_unwind_expression(device_memory, expression, None)?
             }
-            CfaRule::Expression(_) => todo!("CfaRule::Expression"),
         };
 
+        // The rules are relative to the registers of the current frame,
+        // so all of them are evaluated before any register is changed.
+        // A `None` value means that the value of the register in the previous frame is unknown.
+        let mut new_register_values = Vec::new();
         for (reg, rule) in unwind_info.registers() {
-            match rule {
-                RegisterRule::Undefined => unreachable!(),
-                RegisterRule::Offset(offset) => {
-                    let cfa = device_memory.register(gimli::Arm::SP)?;
-                    let addr = (i64::from(cfa) + offset) as u64;
-                    let new_value = device_memory
-                        .read_u32(addr, RunTimeEndian::Little)?
-                        .ok_or(TraceError::MissingMemory(addr))?;
-                    *device_memory.register_mut(*reg)? = new_value;
+            let new_value = match rule {
+                RegisterRule::Undefined => None,
+                RegisterRule::SameValue => continue,
+                RegisterRule::Offset(offset) => Some(Self::read_stack_word(
+                    device_memory,
+                    (i64::from(new_cfa) + offset) as Word<'data> as u64,
+                )?),
+                RegisterRule::ValOffset(offset) => Some((i64::from(new_cfa) + offset) as u32),
+                RegisterRule::Register(register) => Some(device_memory.register(*register)?),
+                RegisterRule::Expression(expression) => {
+                    let address =
+                        Self::evaluate_unwind_expression(device_memory, expression, Some(new_cfa))?;
+                    Some(Self::read_stack_word(device_memory, address as u64)?)
+                }
+                RegisterRule::ValExpression(expression) => Some(Self::evaluate_unwind_expression(
+                    device_memory,
+                    expression,
+                    Some(new_cfa),
+                )?),
+                RegisterRule::Constant(value) => Some(*value as u32),
+                // Like `Architectural`, which has no meaning on Arm
+                rule => {
+                    return Err(TraceError::OperationNotImplemented {
+                        operation: format!(
+                            "The unwind rule {rule:?} of register {}",
+                            gimli::Arm::register_name(*reg).unwrap_or("unknown")
+                        ),
+                        file: file!(),
+                        line: line!(),
+                    })
                 }
-                _ => unimplemented!(),
+            };
+            new_register_values.push((*reg, new_value));
+        }
+
+        let old_cfa = device_memory.register(gimli::Arm::SP)?;
+        let updated = new_cfa != old_cfa;
+        *device_memory.register_mut(gimli::Arm::SP)? = new_cfa;
+
+        for (register, new_value) in new_register_values {
+            match new_value {
+                Some(new_value) => *device_memory.register_mut(register)? = new_value,
+                None => device_memory.invalidate_register(register),
             }
         }
 
         Ok(updated)
     }
 
+    /// Evaluates a DWARF expression of the unwind info, like the ones newer LLVM versions emit for some frames.
+    ///
+    /// The expressions of register rules start with the CFA on the stack, which is given with `cfa`.
+    fn evaluate_unwind_expression(
+        device_memory: &DeviceMemory<<Self as Platform<'data>>::Word>,
+        expression: &gimli::Expression<EndianSlice<LittleEndian>>,
+        cfa: Option<u32>,
+    ) -> Result<u32, TraceError> {
+        let encoding = gimli::Encoding {
+            format: gimli::Format::Dwarf32,
+            version: 4,
+            address_size: 4,
+        };
+
+        // The expression evaluation works with the reader of the debug info, so the expression is copied into one
+        let expression = gimli::Expression(DefaultReader::new(
+            expression.0.to_slice()?.as_ref().into(),
+            RunTimeEndian::Little,
+        ));
+
+        let mut evaluation = expression.evaluation(encoding);
+        if let Some(cfa) = cfa {
+            evaluation.set_initial_value(cfa as u64);
+        }
+
+        let pieces = crate::variables::evaluate_expression(
+            encoding,
+            device_memory,
+            None,
+            Default::default(),
+            evaluation,
+        )?;
+
+        match pieces.as_slice() {
+            [gimli::Piece {
+                location: gimli::Location::Address { address },
+                ..
+            }] => Ok(*address as u32),
+            [gimli::Piece {
+                location: gimli::Location::Value { value },
+                ..
+            }] => Ok(value.to_u64(u64::MAX)? as u32),
+            _ => Err(TraceError::OperationNotImplemented {
+                operation: format!("An unwind expression that results in {pieces:?}"),
+                file: file!(),
+                line: line!(),
+            }),
+        }
+    }
+
     fn is_last_frame(
         &self,
         device_memory: &DeviceMemory<<Self as Platform<'data>>::Word>,
     ) -> Result<bool, TraceError> {
+        let pc = device_memory.register(gimli::Arm::PC)?;
+
         Ok(device_memory.register(gimli::Arm::LR)? == 0
             || (!self
-                .text_address_range
-                .contains(device_memory.register_ref(gimli::Arm::PC)?)
+                .text_address_ranges
+                .iter()
+                .any(|text_address_range| text_address_range.contains(&pc))
                 && device_memory.register(gimli::Arm::LR)? < EXC_RETURN_MARKER))
     }
 
+    /// Checks if the stack pointer can't be read only because the capture of the stack ends before it.
+    /// If so, returns the first address of the stack that is needed but has not been captured.
+    ///
+    /// The stack grows down, so a plausible stack pointer is word aligned, below the initial stack pointer
+    /// and above the start of a captured memory range.
+    fn truncated_stack(&self, device_memory: &DeviceMemory<Word<'data>>, sp: u64) -> Option<u64> {
+        if !sp.is_multiple_of(Self::WORD_SIZE) || sp >= self.initial_stack_pointer as u64 {
+            return None;
+        }
+
+        if !device_memory
+            .captured_ranges()
+            .iter()
+            .any(|range| range.start <= sp)
+        {
+            return None;
+        }
+
+        device_memory
+            .gaps(sp..sp + Self::WORD_SIZE)
+            .first()
+            .map(|gap| gap.start)
+    }
+
     /// Assumes we are at an exception point in the stack unwinding.
     /// Reads the registers that were stored on the stack and updates our current register representation with it.
     ///
+    /// The frame is read from the stack that the EXC_RETURN value points to if its stack pointer has been captured.
+    /// Otherwise the frame is assumed to be on the current stack.
+    ///
     /// Returns Ok if everything went fine or an error with an address if the stack could not be read
     fn update_registers_with_exception_stack(
         device_memory: &mut DeviceMemory<<Self as Platform<'data>>::Word>,
-        fpu: bool,
+        exc_return: ExcReturn,
     ) -> Result<(), TraceError> {
-        let current_sp = device_memory.register(gimli::Arm::SP)?;
+        // The handler ran on a main stack, so that stack pointer has the value of the current stack pointer
+        if let Some(register) =
+            captured_stack_pointer(device_memory, exc_return.handler_stack_pointers())
+        {
+            *device_memory.register_mut(register)? = device_memory.register(gimli::Arm::SP)?;
+        }
 
-        fn read_stack_var(
-            device_memory: &DeviceMemory<u32>,
-            starting_sp: u32,
-            index: usize,
-        ) -> Result<u32, TraceError> {
-            device_memory
-                .read_u32(starting_sp as u64 + index as u64 * 4, RunTimeEndian::Little)?
-                .ok_or(TraceError::MissingMemory(
-                    starting_sp as u64 + index as u64 * 4,
-                ))
+        // The frame can be on another stack, like the process stack or a stack of the other security state
+        let frame_stack_pointer =
+            captured_stack_pointer(device_memory, exc_return.frame_stack_pointers());
+        if let Some(register) = frame_stack_pointer {
+            *device_memory.register_mut(gimli::Arm::SP)? = device_memory.register(register)?;
+        }
+
+        let mut current_sp = device_memory.register(gimli::Arm::SP)? as u64;
+        let read_stack_var =
+            |device_memory: &DeviceMemory<Word<'data>>, starting_sp: u64, index: u64| {
+                Self::read_stack_word(device_memory, starting_sp + index * Self::WORD_SIZE)
+            };
+
+        if exc_return.additional_state_context() {
+            // ARMv8-M stacked the integrity signature, a reserved word and r4 - r11 before the normal frame
+            for (index, register) in (2..).zip(CALLEE_SAVED_REGISTERS) {
+                *device_memory.register_mut(register)? =
+                    read_stack_var(device_memory, current_sp, index)?;
+            }
+            current_sp += ADDITIONAL_STATE_CONTEXT_WORDS * Self::WORD_SIZE;
         }
 
         *device_memory.register_mut(gimli::Arm::R0)? =
@@ -104,50 +637,40 @@ impl<'data> CortexMPlatform<'data> {
             read_stack_var(device_memory, current_sp, 5)?;
         *device_memory.register_mut(gimli::Arm::PC)? =
             read_stack_var(device_memory, current_sp, 6)?;
-        // At stack place 7 is the PSR register, but we don't need that, so we skip it
+        // At stack place 7 is the PSR register. We only need it to know if the stack was realigned
+        let psr = read_stack_var(device_memory, current_sp, 7)?;
+
+        let mut frame_size = BASIC_FRAME_WORDS * Self::WORD_SIZE;
+
+        if exc_return.extended_frame() {
+            // s0 - s15 follow the basic frame. They're only restored when the fpu registers have been captured
+            // and fit in a word, because the fpu registers are stored in the register data as words.
+            if Self::FPU_REGISTER_SIZE == Self::WORD_SIZE {
+                let fpu_frame_sp = current_sp + frame_size;
+                for index in 0..16 {
+                    let value = read_stack_var(device_memory, fpu_frame_sp, index)?;
+                    if let Ok(register) =
+                        device_memory.register_mut(gimli::Register(gimli::Arm::S0.0 + index as u16))
+                    {
+                        *register = value;
+                    }
+                }
+            }
+            // After s15 are the fpscr register and a reserved one, but we don't need those, so we skip them
+
+            frame_size += FPU_FRAME_REGISTERS * Self::FPU_REGISTER_SIZE;
+        }
+
+        // The stack was aligned to 8 bytes with an extra word of padding
+        if psr & STACKED_PSR_ALIGNED_MASK as Word<'data> != 0 {
+            frame_size += Self::WORD_SIZE;
+        }
 
         // Adjust the sp with the size of what we've read
-        *device_memory.register_mut(gimli::Arm::SP)? = device_memory.register(gimli::Arm::SP)?
-            + 8 * std::mem::size_of::<<Self as Platform>::Word>() as <Self as Platform>::Word;
-
-        if fpu {
-            *device_memory.register_mut(gimli::Arm::D0)? =
-                read_stack_var(device_memory, current_sp, 8)?;
-            *device_memory.register_mut(gimli::Arm::D1)? =
-                read_stack_var(device_memory, current_sp, 9)?;
-            *device_memory.register_mut(gimli::Arm::D2)? =
-                read_stack_var(device_memory, current_sp, 10)?;
-            *device_memory.register_mut(gimli::Arm::D3)? =
-                read_stack_var(device_memory, current_sp, 11)?;
-            *device_memory.register_mut(gimli::Arm::D4)? =
-                read_stack_var(device_memory, current_sp, 12)?;
-            *device_memory.register_mut(gimli::Arm::D5)? =
-                read_stack_var(device_memory, current_sp, 13)?;
-            *device_memory.register_mut(gimli::Arm::D6)? =
-                read_stack_var(device_memory, current_sp, 14)?;
-            *device_memory.register_mut(gimli::Arm::D7)? =
-                read_stack_var(device_memory, current_sp, 15)?;
-            *device_memory.register_mut(gimli::Arm::D8)? =
-                read_stack_var(device_memory, current_sp, 16)?;
-            *device_memory.register_mut(gimli::Arm::D9)? =
-                read_stack_var(device_memory, current_sp, 17)?;
-            *device_memory.register_mut(gimli::Arm::D10)? =
-                read_stack_var(device_memory, current_sp, 18)?;
-            *device_memory.register_mut(gimli::Arm::D11)? =
-                read_stack_var(device_memory, current_sp, 19)?;
-            *device_memory.register_mut(gimli::Arm::D12)? =
-                read_stack_var(device_memory, current_sp, 20)?;
-            *device_memory.register_mut(gimli::Arm::D13)? =
-                read_stack_var(device_memory, current_sp, 21)?;
-            *device_memory.register_mut(gimli::Arm::D14)? =
-                read_stack_var(device_memory, current_sp, 22)?;
-            *device_memory.register_mut(gimli::Arm::D15)? =
-                read_stack_var(device_memory, current_sp, 23)?;
-            // At stack place 24 is the fpscr register, but we don't need that, so we skip it
-
-            // Adjust the sp with the size of what we've read
-            *device_memory.register_mut(gimli::Arm::SP)? =
-                device_memory.register(gimli::Arm::SP)? + 17 * std::mem::size_of::<u32>() as u32;
+        let new_sp = (current_sp + frame_size) as Word<'data>;
+        *device_memory.register_mut(gimli::Arm::SP)? = new_sp;
+        if let Some(register) = frame_stack_pointer {
+            *device_memory.register_mut(register)? = new_sp;
         }
 
         Ok(())
@@ -157,73 +680,107 @@ impl<'data> CortexMPlatform<'data> {
 impl<'data> Platform<'data> for CortexMPlatform<'data> {
     type Word = u32;
 
+    const PROGRAM_COUNTER: gimli::Register = gimli::Arm::PC;
+    const STACK_POINTER: gimli::Register = gimli::Arm::SP;
+    const FRAME_REGISTERS: Range<u16> = gimli::Arm::R0.0..gimli::Arm::R15.0 + 1;
+
     fn create_context(elf: &addr2line::object::File<'data, &'data [u8]>) -> Result<Self, TraceError>
     where
         Self: Sized,
     {
-        let debug_info_sector_data = elf
-            .section_by_name(".debug_frame")
-            .ok_or_else(|| TraceError::MissingElfSection(".debug_frame".into()))?
-            .data()?;
-        let mut debug_frame =
-            addr2line::gimli::DebugFrame::new(debug_info_sector_data, LittleEndian);
-        debug_frame.set_address_size(std::mem::size_of::<Self::Word>() as u8);
-
-        let vector_table_section = elf
-            .section_by_name(".vector_table")
-            .ok_or_else(|| TraceError::MissingElfSection(".vector_table".into()))?;
-        let vector_table = vector_table_section
-            .data()?
-            .chunks_exact(4)
-            .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
-            .collect::<Vec<_>>();
-        let reset_vector_address = vector_table[1];
-        let reset_vector_address_range = elf
-            .symbols()
-            .find(|sym| sym.address() as u32 == reset_vector_address)
-            .map(|reset_vector_symbol| {
-                reset_vector_symbol.address() as u32
-                    ..reset_vector_symbol.address() as u32 + reset_vector_symbol.size() as u32
-            })
-            .unwrap_or(reset_vector_address..reset_vector_address);
-        let text_section = elf
-            .section_by_name(".text")
-            .ok_or_else(|| TraceError::MissingElfSection(".text".into()))?;
-        let text_address_range = (text_section.address() as u32)
-            ..(text_section.address() as u32 + text_section.size() as u32);
-
-        let bases = BaseAddresses::default();
+        Self::create_context_with_options(elf, &DecodeOptions::default())
+    }
+
+    fn create_context_with_options(
+        elf: &addr2line::object::File<'data, &'data [u8]>,
+        options: &DecodeOptions,
+    ) -> Result<Self, TraceError>
+    where
+        Self: Sized,
+    {
+        let (frame_section, bases) = FrameSection::from_elf(elf)?;
+
+        // Without the vector table, the entry point of the elf file is normally the reset function as well.
+        // The initial stack pointer is only used to know where the stack is, so we can do without it.
+        // A section that has been named explicitly must be there though, because then the user expects it to be used.
+        let (initial_stack_pointer, reset_vector_address) =
+            match read_vector_table(elf, options.vector_table_section.as_deref()) {
+                Ok(vector_table) => (vector_table[0], vector_table[1]),
+                Err(e) if options.vector_table_section.is_some() => return Err(e),
+                Err(e) => {
+                    log::warn!(
+                        "{e}. The entry point of the elf file is used as the reset vector instead."
+                    );
+                    (0, elf.entry() as u32)
+                }
+            };
+        let reset_vector_address_ranges = reset_vector_address_ranges(elf, reset_vector_address);
+        let text_address_ranges = text_address_ranges(elf);
+        if text_address_ranges.is_empty() {
+            return Err(TraceError::MissingElfSection(".text".into()));
+        }
+
         let unwind_context = UnwindContext::new();
 
         Ok(Self {
-            debug_frame,
-            reset_vector_address_range,
-            text_address_range,
+            frame_section,
+            initial_stack_pointer,
+            reset_vector_address_ranges,
+            text_address_ranges,
             bases,
             unwind_context,
         })
     }
 
+    fn memory_ranges(&self, elf: &addr2line::object::File<'data, &'data [u8]>) -> Vec<Range<u64>> {
+        let mut memory_ranges = super::elf_segment_ranges(elf);
+
+        if let Some(stack_range) = stack_range(&memory_ranges, self.initial_stack_pointer) {
+            memory_ranges.push(stack_range);
+        }
+
+        memory_ranges
+    }
+
+    fn register_name(register: gimli::Register) -> Option<&'static str> {
+        gimli::Arm::register_name(register)
+    }
+
+    fn register_role(register: gimli::Register) -> Option<&'static str> {
+        // The names of the AAPCS. R7 is the frame pointer in Thumb code.
+        match register {
+            gimli::Arm::R0 => Some("a1"),
+            gimli::Arm::R1 => Some("a2"),
+            gimli::Arm::R2 => Some("a3"),
+            gimli::Arm::R3 => Some("a4"),
+            gimli::Arm::R7 => Some("fp"),
+            gimli::Arm::R12 => Some("ip"),
+            gimli::Arm::SP => Some("sp"),
+            gimli::Arm::LR => Some("lr"),
+            gimli::Arm::PC => Some("pc"),
+            _ => None,
+        }
+    }
+
     fn unwind(
         &mut self,
         device_memory: &mut DeviceMemory<Self::Word>,
         previous_frame: Option<&mut Frame<Self::Word>>,
     ) -> Result<super::UnwindResult<Self::Word>, TraceError> {
-        let unwind_info = self.debug_frame.unwind_info_for_address(
+        let unwind_info = self.frame_section.unwind_info_for_address(
             &self.bases,
             &mut self.unwind_context,
             device_memory.register(gimli::Arm::PC)? as u64,
-            DebugFrame::cie_from_offset,
         );
 
         let unwind_info = match unwind_info {
             Ok(unwind_info) => unwind_info.clone(),
             Err(_e) => {
-                return Ok(UnwindResult::Corrupted {error_frame: Some(Frame { function: "Unknown".into(), location: crate::Location { file: None, line: None, column: None }, frame_type: FrameType::Corrupted(format!("debug information for address {:#x} is missing. Likely fixes:
+                return Ok(UnwindResult::Corrupted {error_frame: Some(Box::new(Frame { function: "Unknown".into(), location: crate::Location { file: None, line: None, column: None }, frame_type: FrameType::Corrupted(format!("debug information for address {:#x} is missing. Likely fixes:
                 1. compile the Rust code with `debug = 1` or higher. This is configured in the `profile.{{release,bench}}` sections of Cargo.toml (`profile.{{dev,test}}` default to `debug = 2`)
                 2. use a recent version of the `cortex-m` crates (e.g. cortex-m 0.6.3 or newer). Check versions in Cargo.lock
                 3. if linking to C code, compile the C code with the `-g` flag", device_memory.register(gimli::Arm::PC)?)),
-                    variables: Vec::new(), }) });
+                    variables: Vec::new(), omitted_variables: Vec::new(), inlined_into: None, stack_pointer: None, registers: Vec::new(), frame_size: None, })) });
             }
         };
 
@@ -232,7 +789,7 @@ impl<'data> Platform<'data> for CortexMPlatform<'data> {
             Ok(stack_pointer_changed) => stack_pointer_changed,
             Err(e) => {
                 return Ok(UnwindResult::Corrupted {
-                    error_frame: Some(Frame {
+                    error_frame: Some(Box::new(Frame {
                         function: "Unknown".into(),
                         location: crate::Location {
                             file: None,
@@ -241,7 +798,12 @@ impl<'data> Platform<'data> for CortexMPlatform<'data> {
                         },
                         frame_type: FrameType::Corrupted(e.to_string()),
                         variables: Vec::new(),
-                    }),
+                        omitted_variables: Vec::new(),
+                        inlined_into: None,
+                        stack_pointer: None,
+                        registers: Vec::new(),
+                        frame_size: None,
+                    })),
                 });
             }
         };
@@ -257,7 +819,7 @@ impl<'data> Platform<'data> for CortexMPlatform<'data> {
             // If we unwound further we'd get the same frame again so we better stop
 
             return Ok(UnwindResult::Corrupted {
-                error_frame: Some(Frame {
+                error_frame: Some(Box::new(Frame {
                     function: "Unknown".into(),
                     location: crate::Location {
                         file: None,
@@ -268,27 +830,28 @@ impl<'data> Platform<'data> for CortexMPlatform<'data> {
                         "CFA did not change and LR and PC are equal".into(),
                     ),
                     variables: Vec::new(),
-                }),
+                    omitted_variables: Vec::new(),
+                    inlined_into: None,
+                    stack_pointer: None,
+                    registers: Vec::new(),
+                    frame_size: None,
+                })),
             });
         }
 
         // Stack is not corrupted, but unwinding is not done
         // Are we returning from an exception? (EXC_RETURN)
-        if device_memory.register(gimli::Arm::LR)? >= EXC_RETURN_MARKER {
+        if let Some(exc_return) = ExcReturn::from_lr(device_memory.register(gimli::Arm::LR)?) {
             // Yes, so the registers were pushed to the stack and we need to get them back
-
-            // Check the value to know if there are fpu registers to read
-            let fpu = device_memory.register(gimli::Arm::LR)? & EXC_RETURN_FTYPE_MASK > 0;
-
             if let Some(previous_frame) = previous_frame {
                 previous_frame.frame_type = FrameType::Exception;
             }
 
-            match Self::update_registers_with_exception_stack(device_memory, fpu) {
+            match Self::update_registers_with_exception_stack(device_memory, exc_return) {
                 Ok(()) => {}
                 Err(TraceError::MissingMemory(address)) => {
                     return Ok(UnwindResult::Corrupted {
-                        error_frame: Some(Frame {
+                        error_frame: Some(Box::new(Frame {
                             function: "Unknown".into(),
                             location: crate::Location {
                                 file: None,
@@ -300,20 +863,35 @@ impl<'data> Platform<'data> for CortexMPlatform<'data> {
                                 address
                             )),
                             variables: Vec::new(),
-                        }),
+                            omitted_variables: Vec::new(),
+                            inlined_into: None,
+                            stack_pointer: None,
+                            registers: Vec::new(),
+                            frame_size: None,
+                        })),
                     });
                 }
                 Err(e) => return Err(e),
             }
         } else {
             // No exception, so follow the LR back
-            *device_memory.register_mut(gimli::Arm::PC)? = device_memory.register(gimli::Arm::LR)?
+            *device_memory.register_mut(gimli::Arm::PC)? =
+                device_memory.register(gimli::Arm::LR)?;
+
+            // The callee-saved registers have been restored by the unwind info,
+            // but the caller-saved registers still have the values of the callee.
+            // What they were in the caller is lost, so make sure they're not used for its variables.
+            for register in CALLER_SAVED_REGISTERS {
+                device_memory.invalidate_register(register);
+            }
         }
 
         // Have we reached the reset vector?
+        let pc = device_memory.register(gimli::Arm::PC)?;
         if self
-            .reset_vector_address_range
-            .contains(device_memory.register_ref(gimli::Arm::PC)?)
+            .reset_vector_address_ranges
+            .iter()
+            .any(|reset_vector_address_range| reset_vector_address_range.contains(&pc))
         {
             // Yes, let's make that a frame as well
             // We'll also make an assumption that there's no frames before reset
@@ -324,25 +902,653 @@ impl<'data> Platform<'data> for CortexMPlatform<'data> {
             Ok(UnwindResult::Finished)
         } else {
             // Is our stack pointer in a weird place?
-            if device_memory
-                .read_u32(
-                    device_memory.register(gimli::Arm::SP)? as u64,
-                    RunTimeEndian::Little,
-                )?
-                .is_none()
-            {
-                Ok(UnwindResult::Corrupted {error_frame:Some(Frame {
-                    function: "Unknown".into(),
-                    location: crate::Location { file: None, line: None, column: None },
-                    frame_type: FrameType::Corrupted(
-                        format!("The stack pointer ({:#08X}) is corrupted or the dump does not contain the full stack", device_memory
-                        .register(gimli::Arm::SP)?),
+            let sp = device_memory.register(gimli::Arm::SP)? as u64;
+            if device_memory.read_u32(sp, RunTimeEndian::Little)?.is_none() {
+                let frame_type = match self.truncated_stack(device_memory, sp) {
+                    Some(missing_from) => FrameType::Truncated { missing_from },
+                    None => FrameType::Corrupted(
+                        format!("The stack pointer ({sp:#08X}) is corrupted or the dump does not contain the full stack. {}", describe_stack_coverage(device_memory, sp)),
                     ),
-                    variables: Vec::new(),
-                })})
+                };
+                Ok(UnwindResult::Corrupted {
+                    error_frame: Some(Box::new(Frame {
+                        function: "Unknown".into(),
+                        location: crate::Location {
+                            file: None,
+                            line: None,
+                            column: None,
+                        },
+                        frame_type,
+                        variables: Vec::new(),
+                        omitted_variables: Vec::new(),
+                        inlined_into: None,
+                        stack_pointer: None,
+                        registers: Vec::new(),
+                        frame_size: None,
+                    })),
+                })
             } else {
                 Ok(UnwindResult::Proceeded)
             }
         }
     }
 }
+
+/// Describes which memory has been captured relative to the stack pointer, so it becomes clear why the stack could not be read
+fn describe_stack_coverage(device_memory: &DeviceMemory<u32>, sp: u64) -> String {
+    let failed_address = device_memory
+        .gaps(sp..sp + 4)
+        .first()
+        .map(|gap| gap.start)
+        .unwrap_or(sp);
+
+    let captured_ranges = device_memory.captured_ranges();
+    let range_below = captured_ranges.iter().rev().find(|range| range.start <= sp);
+    let range_above = captured_ranges.iter().find(|range| range.start > sp);
+
+    let coverage = match (range_below, range_above) {
+        (Some(range), _) if range.end > sp => format!(
+            "captured {:#X}..{:#X} ({} bytes) but SP={sp:#X} is at the end of the capture",
+            range.start,
+            range.end,
+            range.end - range.start
+        ),
+        (Some(range), _) => format!(
+            "captured {:#X}..{:#X} ({} bytes) but SP={sp:#X} is above the capture",
+            range.start,
+            range.end,
+            range.end - range.start
+        ),
+        (None, Some(range)) => format!(
+            "captured {:#X}..{:#X} ({} bytes) but SP={sp:#X} is below the capture",
+            range.start,
+            range.end,
+            range.end - range.start
+        ),
+        (None, None) => format!("no memory around SP={sp:#X} has been captured"),
+    };
+
+    format!("Could not read address {failed_address:#X}: {coverage}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gimli::write::{
+        self, CallFrameInstruction, CommonInformationEntry, FrameDescriptionEntry, FrameTable,
+    };
+    use stackdump_core::{memory_region::VecMemoryRegion, register_data::VecRegisterData};
+
+    const HOT_TEXT: Range<u32> = 0x1000..0x2000;
+    const COLD_TEXT: Range<u32> = 0x8000..0x9000;
+    const STACK_START: u32 = 0x2000_0000;
+
+    /// Creates the frame table of a hot function and its cold part.
+    /// Both push the LR and 4 other bytes to the stack.
+    fn frame_table() -> FrameTable {
+        let encoding = gimli::Encoding {
+            format: gimli::Format::Dwarf32,
+            version: 1,
+            address_size: 4,
+        };
+
+        let mut frame_table = FrameTable::default();
+        let mut cie = CommonInformationEntry::new(encoding, 2, -4, gimli::Arm::LR);
+        cie.add_instruction(CallFrameInstruction::Cfa(gimli::Arm::SP, 0));
+        let cie_id = frame_table.add_cie(cie);
+
+        for text in [HOT_TEXT, COLD_TEXT] {
+            let mut fde = FrameDescriptionEntry::new(
+                write::Address::Constant(text.start as u64),
+                text.end - text.start,
+            );
+            fde.add_instruction(0, CallFrameInstruction::CfaOffset(8));
+            fde.add_instruction(0, CallFrameInstruction::Offset(gimli::Arm::LR, -4));
+            frame_table.add_fde(cie_id, fde);
+        }
+
+        frame_table
+    }
+
+    /// Creates a `.debug_frame` of the [frame_table]
+    fn debug_frame() -> Vec<u8> {
+        let mut debug_frame = write::DebugFrame::from(write::EndianVec::new(LittleEndian));
+        frame_table().write_debug_frame(&mut debug_frame).unwrap();
+        debug_frame.0.into_vec()
+    }
+
+    #[test]
+    fn select_elf() {
+        const ELF: &[u8] = include_bytes!("../../../../examples/data/nrf52840");
+        const DUMP: &[u8] = include_bytes!("../../../../examples/data/nrf52840.dump");
+
+        let elf = addr2line::object::File::parse(ELF).unwrap();
+        let mut device_memory = DeviceMemory::new();
+        stackdump_core::dump::Dump::from_dump_bytes(DUMP)
+            .unwrap()
+            .add_to_device_memory(&mut device_memory);
+
+        // The PC is in a function, the LR is in the code and the SP is in the stack
+        assert_eq!(elf_match_score(&elf, &device_memory).unwrap(), 4);
+        assert_eq!(super::select_elf(&[ELF], &device_memory).unwrap(), 0);
+
+        // A captured vector table that is different from the one in the elf file
+        let vector_table_section = elf.section_by_name(".vector_table").unwrap();
+        let mut vector_table = vector_table_section.data().unwrap().to_vec();
+        vector_table[4] ^= 0xFF;
+        device_memory.add_memory_region(VecMemoryRegion::new(
+            vector_table_section.address(),
+            vector_table,
+        ));
+        assert_eq!(elf_match_score(&elf, &device_memory).unwrap(), 0);
+
+        // Registers that don't point into the program at all
+        let mut device_memory = DeviceMemory::new();
+        device_memory
+            .add_register_data(VecRegisterData::new(gimli::Arm::R0, vec![0xFFFF_FFF0; 16]));
+        assert!(matches!(
+            super::select_elf(&[ELF], &device_memory),
+            Err(TraceError::NoMatchingElf)
+        ));
+    }
+
+    #[test]
+    fn vector_table_section() {
+        const ELF: &[u8] = include_bytes!("../../../../examples/data/nrf52840");
+        let elf = addr2line::object::File::parse(ELF).unwrap();
+
+        let default_context = CortexMPlatform::create_context(&elf).unwrap();
+        assert_ne!(default_context.initial_stack_pointer, 0);
+        assert_eq!(
+            initial_stack_pointer(&elf).unwrap(),
+            default_context.initial_stack_pointer
+        );
+
+        // An explicitly named section that doesn't exist is an error
+        let options = DecodeOptions {
+            vector_table_section: Some(".isr_vector".into()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            CortexMPlatform::create_context_with_options(&elf, &options),
+            Err(TraceError::MissingElfSection(name)) if name == ".isr_vector"
+        ));
+
+        let options = DecodeOptions {
+            vector_table_section: Some(".vector_table".into()),
+            ..Default::default()
+        };
+        let context = CortexMPlatform::create_context_with_options(&elf, &options).unwrap();
+        assert_eq!(
+            context.initial_stack_pointer,
+            default_context.initial_stack_pointer
+        );
+    }
+
+    #[test]
+    fn register_roles() {
+        assert_eq!(CortexMPlatform::register_role(gimli::Arm::R0), Some("a1"));
+        assert_eq!(CortexMPlatform::register_role(gimli::Arm::R7), Some("fp"));
+        assert_eq!(CortexMPlatform::register_role(gimli::Arm::R13), Some("sp"));
+        assert_eq!(CortexMPlatform::register_role(gimli::Arm::R4), None);
+    }
+
+    #[test]
+    fn unwind_into_cold_split_function() {
+        let debug_frame_data = debug_frame();
+        let mut debug_frame = DebugFrame::new(&debug_frame_data, LittleEndian);
+        debug_frame.set_address_size(4);
+
+        let mut platform = CortexMPlatform {
+            frame_section: FrameSection::DebugFrame(debug_frame),
+            initial_stack_pointer: STACK_START + 0x100,
+            reset_vector_address_ranges: Vec::new(),
+            text_address_ranges: vec![HOT_TEXT, COLD_TEXT],
+            bases: BaseAddresses::default(),
+            unwind_context: UnwindContext::new(),
+        };
+
+        // We're in the hot function which has been called from the cold part of another function
+        let return_address = (COLD_TEXT.start + 0x10) | THUMB_BIT;
+        let mut stack = vec![0; 0x100];
+        stack[4..8].copy_from_slice(&return_address.to_le_bytes());
+
+        let mut registers = vec![0u32; 16];
+        registers[13] = STACK_START;
+        registers[14] = return_address;
+        registers[15] = HOT_TEXT.start + 0x20;
+
+        let mut device_memory = DeviceMemory::new();
+        device_memory.add_memory_region(VecMemoryRegion::new(STACK_START as u64, stack));
+        device_memory.add_register_data(VecRegisterData::new(gimli::Arm::R0, registers));
+
+        let result = platform.unwind(&mut device_memory, None).unwrap();
+
+        // The cold part is code too, so the unwinding must continue there
+        assert!(matches!(result, UnwindResult::Proceeded));
+        assert_eq!(
+            device_memory.register(gimli::Arm::PC).unwrap(),
+            return_address
+        );
+        assert_eq!(
+            device_memory.register(gimli::Arm::SP).unwrap(),
+            STACK_START + 8
+        );
+
+        // The caller-saved registers are unknown in the caller, the callee-saved ones are kept
+        assert!(device_memory.register(gimli::Arm::R0).is_err());
+        assert!(device_memory.register(gimli::Arm::R12).is_err());
+        assert_eq!(device_memory.register(gimli::Arm::R4).unwrap(), 0);
+    }
+
+    #[test]
+    fn stack_coverage() {
+        let mut device_memory = DeviceMemory::<u32>::new();
+
+        // Nothing has been captured
+        assert_eq!(
+            describe_stack_coverage(&device_memory, STACK_START as u64),
+            "Could not read address 0x20000000: no memory around SP=0x20000000 has been captured"
+        );
+
+        device_memory.add_memory_region(VecMemoryRegion::new(STACK_START as u64, vec![0; 0x100]));
+
+        // The stack has been captured partially, the SP is in the capture but not all of its word is
+        assert_eq!(
+            describe_stack_coverage(&device_memory, STACK_START as u64 + 0xFE),
+            "Could not read address 0x20000100: captured 0x20000000..0x20000100 (256 bytes) but SP=0x200000FE is at the end of the capture"
+        );
+        // The capture is below the SP
+        assert_eq!(
+            describe_stack_coverage(&device_memory, STACK_START as u64 + 0x200),
+            "Could not read address 0x20000200: captured 0x20000000..0x20000100 (256 bytes) but SP=0x20000200 is above the capture"
+        );
+        // The capture is above the SP
+        assert_eq!(
+            describe_stack_coverage(&device_memory, 0x1000_0000),
+            "Could not read address 0x10000000: captured 0x20000000..0x20000100 (256 bytes) but SP=0x10000000 is below the capture"
+        );
+    }
+
+    #[test]
+    fn stack_ranges() {
+        let memory_ranges = [
+            0x0000_0000..0x0001_0000,
+            // Adjacent to the flash
+            0x0001_0000..0x0001_0100,
+            // The statics, of which the last two overlap
+            0x2000_0000..0x2000_0400,
+            0x2000_0200..0x2000_0800,
+        ];
+
+        // The stack starts at the end of the highest segment below the initial stack pointer
+        assert_eq!(
+            stack_range(&memory_ranges, 0x2001_0000),
+            Some(0x2000_0800..0x2001_0000)
+        );
+        // Without room between the statics and the initial stack pointer, there is no stack range
+        assert_eq!(stack_range(&memory_ranges, 0x2000_0800), None);
+        // Or without any segment below it
+        assert_eq!(stack_range(&memory_ranges[2..], 0x2000_0000), None);
+    }
+
+    #[test]
+    fn unwind_into_truncated_stack() {
+        let debug_frame_data = debug_frame();
+        let mut debug_frame = DebugFrame::new(&debug_frame_data, LittleEndian);
+        debug_frame.set_address_size(4);
+
+        let mut platform = CortexMPlatform {
+            frame_section: FrameSection::DebugFrame(debug_frame),
+            initial_stack_pointer: STACK_START + 0x100,
+            reset_vector_address_ranges: Vec::new(),
+            text_address_ranges: vec![HOT_TEXT, COLD_TEXT],
+            bases: BaseAddresses::default(),
+            unwind_context: UnwindContext::new(),
+        };
+
+        // Only the frame of the hot function has been captured, not the stack of its caller
+        let return_address = (COLD_TEXT.start + 0x10) | THUMB_BIT;
+        let mut stack = vec![0; 8];
+        stack[4..8].copy_from_slice(&return_address.to_le_bytes());
+
+        let mut registers = vec![0u32; 16];
+        registers[13] = STACK_START;
+        registers[14] = return_address;
+        registers[15] = HOT_TEXT.start + 0x20;
+
+        let mut device_memory = DeviceMemory::new();
+        device_memory.add_memory_region(VecMemoryRegion::new(STACK_START as u64, stack));
+        device_memory.add_register_data(VecRegisterData::new(gimli::Arm::R0, registers));
+
+        let result = platform.unwind(&mut device_memory, None).unwrap();
+
+        match result {
+            UnwindResult::Corrupted {
+                error_frame: Some(frame),
+            } => assert_eq!(
+                frame.frame_type,
+                FrameType::Truncated {
+                    missing_from: STACK_START as u64 + 8
+                }
+            ),
+            _ => panic!("Expected a truncated frame"),
+        }
+
+        // A stack pointer above the initial stack pointer is not a truncation
+        assert_eq!(
+            platform.truncated_stack(&device_memory, STACK_START as u64 + 0x200),
+            None
+        );
+        // Neither is one that is not word aligned
+        assert_eq!(
+            platform.truncated_stack(&device_memory, STACK_START as u64 + 0x12),
+            None
+        );
+    }
+
+    #[test]
+    fn unwind_with_eh_frame() {
+        let mut eh_frame = write::EhFrame::from(write::EndianVec::new(LittleEndian));
+        frame_table().write_eh_frame(&mut eh_frame).unwrap();
+        let eh_frame_data = eh_frame.0.into_vec();
+        let mut eh_frame = EhFrame::new(&eh_frame_data, LittleEndian);
+        eh_frame.set_address_size(4);
+
+        let mut platform = CortexMPlatform {
+            frame_section: FrameSection::EhFrame(eh_frame),
+            initial_stack_pointer: STACK_START + 0x100,
+            reset_vector_address_ranges: Vec::new(),
+            text_address_ranges: vec![HOT_TEXT, COLD_TEXT],
+            bases: BaseAddresses::default(),
+            unwind_context: UnwindContext::new(),
+        };
+
+        let return_address = (COLD_TEXT.start + 0x10) | THUMB_BIT;
+        let mut stack = vec![0; 0x100];
+        stack[4..8].copy_from_slice(&return_address.to_le_bytes());
+
+        let mut registers = vec![0u32; 16];
+        registers[13] = STACK_START;
+        registers[14] = return_address;
+        registers[15] = HOT_TEXT.start + 0x20;
+
+        let mut device_memory = DeviceMemory::new();
+        device_memory.add_memory_region(VecMemoryRegion::new(STACK_START as u64, stack));
+        device_memory.add_register_data(VecRegisterData::new(gimli::Arm::R0, registers));
+
+        let result = platform.unwind(&mut device_memory, None).unwrap();
+
+        // The unwinding is the same as with a `.debug_frame`
+        assert!(matches!(result, UnwindResult::Proceeded));
+        assert_eq!(
+            device_memory.register(gimli::Arm::PC).unwrap(),
+            return_address
+        );
+        assert_eq!(
+            device_memory.register(gimli::Arm::SP).unwrap(),
+            STACK_START + 8
+        );
+    }
+
+    #[test]
+    fn unwind_with_cfa_expression() {
+        let encoding = gimli::Encoding {
+            format: gimli::Format::Dwarf32,
+            version: 1,
+            address_size: 4,
+        };
+
+        // The hot function computes its CFA as SP + 8 and the cold one as the value stored at SP + 0x200
+        let mut frame_table = FrameTable::default();
+        let cie_id =
+            frame_table.add_cie(CommonInformationEntry::new(encoding, 2, -4, gimli::Arm::LR));
+        let mut hot_cfa_expression = write::Expression::new();
+        hot_cfa_expression.op_breg(gimli::Arm::SP, 8);
+        let mut cold_cfa_expression = write::Expression::new();
+        cold_cfa_expression.op_breg(gimli::Arm::SP, 0x200);
+        cold_cfa_expression.op(gimli::constants::DW_OP_deref);
+
+        for (text, cfa_expression) in [
+            (HOT_TEXT, hot_cfa_expression),
+            (COLD_TEXT, cold_cfa_expression),
+        ] {
+            let mut fde = FrameDescriptionEntry::new(
+                write::Address::Constant(text.start as u64),
+                text.end - text.start,
+            );
+            fde.add_instruction(0, CallFrameInstruction::CfaExpression(cfa_expression));
+            fde.add_instruction(0, CallFrameInstruction::Offset(gimli::Arm::LR, -4));
+            frame_table.add_fde(cie_id, fde);
+        }
+        let mut debug_frame = write::DebugFrame::from(write::EndianVec::new(LittleEndian));
+        frame_table.write_debug_frame(&mut debug_frame).unwrap();
+        let debug_frame_data = debug_frame.0.into_vec();
+
+        let mut debug_frame = DebugFrame::new(&debug_frame_data, LittleEndian);
+        debug_frame.set_address_size(4);
+
+        let mut platform = CortexMPlatform {
+            frame_section: FrameSection::DebugFrame(debug_frame),
+            initial_stack_pointer: STACK_START + 0x100,
+            reset_vector_address_ranges: Vec::new(),
+            text_address_ranges: vec![HOT_TEXT, COLD_TEXT],
+            bases: BaseAddresses::default(),
+            unwind_context: UnwindContext::new(),
+        };
+
+        let return_address = (COLD_TEXT.start + 0x10) | THUMB_BIT;
+        let mut stack = vec![0; 0x100];
+        stack[4..8].copy_from_slice(&return_address.to_le_bytes());
+
+        let mut registers = vec![0u32; 16];
+        registers[13] = STACK_START;
+        registers[14] = return_address;
+        registers[15] = HOT_TEXT.start + 0x20;
+
+        let mut device_memory = DeviceMemory::new();
+        device_memory.add_memory_region(VecMemoryRegion::new(STACK_START as u64, stack));
+        device_memory.add_register_data(VecRegisterData::new(gimli::Arm::R0, registers));
+
+        let result = platform.unwind(&mut device_memory, None).unwrap();
+        assert!(matches!(result, UnwindResult::Proceeded));
+        assert_eq!(
+            device_memory.register(gimli::Arm::PC).unwrap(),
+            return_address
+        );
+        assert_eq!(
+            device_memory.register(gimli::Arm::SP).unwrap(),
+            STACK_START + 8
+        );
+
+        // The CFA of the cold function is read from memory that has not been captured.
+        // That makes the frame corrupted instead of stopping the whole trace.
+        let result = platform.unwind(&mut device_memory, None).unwrap();
+        assert!(matches!(
+            result,
+            UnwindResult::Corrupted {
+                error_frame: Some(frame)
+            } if matches!(frame.frame_type, FrameType::Corrupted(_))
+        ));
+    }
+
+    #[test]
+    fn unwind_with_register_rules() {
+        let encoding = gimli::Encoding {
+            format: gimli::Format::Dwarf32,
+            version: 1,
+            address_size: 4,
+        };
+
+        // Expressions of register rules start with the CFA on the stack
+        let mut r7_expression = write::Expression::new();
+        r7_expression.op_constu(8);
+        r7_expression.op(gimli::constants::DW_OP_minus);
+        let mut r8_expression = write::Expression::new();
+        r8_expression.op_constu(0x100);
+        r8_expression.op(gimli::constants::DW_OP_plus);
+
+        let mut frame_table = FrameTable::default();
+        let mut cie = CommonInformationEntry::new(encoding, 2, -4, gimli::Arm::LR);
+        cie.add_instruction(CallFrameInstruction::Cfa(gimli::Arm::SP, 16));
+        let cie_id = frame_table.add_cie(cie);
+        let mut fde = FrameDescriptionEntry::new(
+            write::Address::Constant(HOT_TEXT.start as u64),
+            HOT_TEXT.end - HOT_TEXT.start,
+        );
+        for instruction in [
+            CallFrameInstruction::Offset(gimli::Arm::LR, -4),
+            CallFrameInstruction::ValOffset(gimli::Arm::R4, -16),
+            // R5 and R6 are swapped, which only works if the old values are used
+            CallFrameInstruction::Register(gimli::Arm::R5, gimli::Arm::R6),
+            CallFrameInstruction::Register(gimli::Arm::R6, gimli::Arm::R5),
+            CallFrameInstruction::Expression(gimli::Arm::R7, r7_expression),
+            CallFrameInstruction::ValExpression(gimli::Arm::R8, r8_expression),
+        ] {
+            fde.add_instruction(0, instruction);
+        }
+        frame_table.add_fde(cie_id, fde);
+        let mut debug_frame = write::DebugFrame::from(write::EndianVec::new(LittleEndian));
+        frame_table.write_debug_frame(&mut debug_frame).unwrap();
+        let debug_frame_data = debug_frame.0.into_vec();
+
+        let mut debug_frame = DebugFrame::new(&debug_frame_data, LittleEndian);
+        debug_frame.set_address_size(4);
+
+        let mut platform = CortexMPlatform {
+            frame_section: FrameSection::DebugFrame(debug_frame),
+            initial_stack_pointer: STACK_START + 0x100,
+            reset_vector_address_ranges: Vec::new(),
+            text_address_ranges: vec![HOT_TEXT],
+            bases: BaseAddresses::default(),
+            unwind_context: UnwindContext::new(),
+        };
+
+        let return_address = (HOT_TEXT.start + 0x10) | THUMB_BIT;
+        let mut stack = vec![0; 0x100];
+        stack[8..12].copy_from_slice(&0x7777u32.to_le_bytes());
+        stack[12..16].copy_from_slice(&return_address.to_le_bytes());
+
+        let mut registers = (0..16).collect::<Vec<u32>>();
+        registers[13] = STACK_START;
+        registers[15] = HOT_TEXT.start + 0x20;
+
+        let mut device_memory = DeviceMemory::new();
+        device_memory.add_memory_region(VecMemoryRegion::new(STACK_START as u64, stack));
+        device_memory.add_register_data(VecRegisterData::new(gimli::Arm::R0, registers));
+
+        let result = platform.unwind(&mut device_memory, None).unwrap();
+        assert!(matches!(result, UnwindResult::Proceeded));
+
+        let cfa = STACK_START + 16;
+        assert_eq!(device_memory.register(gimli::Arm::SP).unwrap(), cfa);
+        assert_eq!(
+            device_memory.register(gimli::Arm::PC).unwrap(),
+            return_address
+        );
+        assert_eq!(device_memory.register(gimli::Arm::R4).unwrap(), cfa - 16);
+        assert_eq!(device_memory.register(gimli::Arm::R5).unwrap(), 6);
+        assert_eq!(device_memory.register(gimli::Arm::R6).unwrap(), 5);
+        assert_eq!(device_memory.register(gimli::Arm::R7).unwrap(), 0x7777);
+        assert_eq!(device_memory.register(gimli::Arm::R8).unwrap(), cfa + 0x100);
+        assert_eq!(device_memory.register(gimli::Arm::R10).unwrap(), 10);
+    }
+
+    /// Creates the device memory of a handler running on the main stack at [STACK_START].
+    /// The given stack is placed at `stack_address`.
+    fn exception_device_memory(
+        stack_address: u32,
+        stack: Vec<u32>,
+        stack_pointers: VecRegisterData<u32>,
+    ) -> DeviceMemory<'static, u32> {
+        let mut registers = vec![0u32; 16];
+        registers[13] = STACK_START;
+
+        let mut device_memory = DeviceMemory::new();
+        device_memory.add_memory_region(VecMemoryRegion::new(
+            stack_address as u64,
+            stack.iter().flat_map(|word| word.to_le_bytes()).collect(),
+        ));
+        device_memory.add_register_data(VecRegisterData::new(gimli::Arm::R0, registers));
+        device_memory.add_register_data(stack_pointers);
+        device_memory
+    }
+
+    #[test]
+    fn exception_frame_on_process_stack() {
+        const PSP: u32 = STACK_START + 0x1000;
+
+        // r0 - r3, r12, lr, pc, a psr with the padding bit set and the floating point context
+        let mut stack = vec![1, 2, 3, 4, 12, 0x1235, 0x1240, 1 << 9];
+        stack.extend(100..118);
+        let mut device_memory = exception_device_memory(
+            PSP,
+            stack,
+            VecRegisterData::new(stack_pointers::MSP, vec![0, PSP]),
+        );
+        device_memory.add_register_data(VecRegisterData::new(gimli::Arm::S0, vec![0; 32]));
+
+        // Thread mode, process stack, extended frame
+        let exc_return = ExcReturn::from_lr(0xFFFF_FFED).unwrap();
+        CortexMPlatform::update_registers_with_exception_stack(&mut device_memory, exc_return)
+            .unwrap();
+
+        assert_eq!(device_memory.register(gimli::Arm::R0).unwrap(), 1);
+        assert_eq!(device_memory.register(gimli::Arm::LR).unwrap(), 0x1235);
+        assert_eq!(device_memory.register(gimli::Arm::PC).unwrap(), 0x1240);
+        assert_eq!(device_memory.register(gimli::Arm::S0).unwrap(), 100);
+        assert_eq!(
+            device_memory
+                .register(gimli::Register(gimli::Arm::S0.0 + 15))
+                .unwrap(),
+            115
+        );
+        // Popped the basic frame, the floating point context and the padding
+        assert_eq!(
+            device_memory.register(gimli::Arm::SP).unwrap(),
+            PSP + 8 * 4 + 18 * 4 + 4
+        );
+        // The handler's stack is remembered
+        assert_eq!(
+            device_memory.register(stack_pointers::MSP).unwrap(),
+            STACK_START
+        );
+    }
+
+    #[test]
+    fn exception_frame_with_additional_state_context() {
+        const PSP_S: u32 = STACK_START + 0x1000;
+
+        // The integrity signature, a reserved word and r4 - r11 come before the normal basic frame
+        let mut stack = vec![0xFEFA_125B, 0];
+        stack.extend(4..12);
+        stack.extend([1, 2, 3, 4, 12, 0x1235, 0x1240, 0]);
+        let mut device_memory = exception_device_memory(
+            PSP_S,
+            stack,
+            VecRegisterData::new(stack_pointers::MSP_NS, vec![0, 0, 0, PSP_S]),
+        );
+
+        // A non-secure exception that interrupted the secure thread on its process stack
+        let exc_return = ExcReturn::from_lr(0xFFFF_FFDC).unwrap();
+        CortexMPlatform::update_registers_with_exception_stack(&mut device_memory, exc_return)
+            .unwrap();
+
+        assert_eq!(device_memory.register(gimli::Arm::R4).unwrap(), 4);
+        assert_eq!(device_memory.register(gimli::Arm::R11).unwrap(), 11);
+        assert_eq!(device_memory.register(gimli::Arm::R0).unwrap(), 1);
+        assert_eq!(device_memory.register(gimli::Arm::PC).unwrap(), 0x1240);
+        assert_eq!(
+            device_memory.register(gimli::Arm::SP).unwrap(),
+            PSP_S + 10 * 4 + 8 * 4
+        );
+        assert_eq!(
+            device_memory.register(stack_pointers::MSP_NS).unwrap(),
+            STACK_START
+        );
+        assert_eq!(
+            device_memory.register(stack_pointers::PSP_S).unwrap(),
+            PSP_S + 10 * 4 + 8 * 4
+        );
+    }
+}
@@ -1,9 +1,20 @@
-use crate::{error::TraceError, type_value_tree::TypeValueTree, Frame, FrameType, Location};
-use addr2line::object::{Object, ObjectSection, SectionKind};
+use crate::{
+    error::TraceError,
+    variables::{DecodeState, TypeCache},
+    DecodeOptions, DemangleMode, Frame, FrameType, Location,
+};
+use addr2line::object::{Object, ObjectSection, ObjectSegment, SectionKind};
 use funty::Fundamental;
-use gimli::{DebugInfoOffset, EndianRcSlice, RunTimeEndian};
-use stackdump_core::{device_memory::DeviceMemory, memory_region::VecMemoryRegion};
-use std::collections::HashMap;
+use gimli::{EndianRcSlice, RunTimeEndian};
+use stackdump_core::{
+    device_memory::{DeviceMemory, EvaluationFrame},
+    memory_region::{MemoryRegionKind, VecMemoryRegion},
+};
+use std::{
+    collections::{HashSet, VecDeque},
+    marker::PhantomData,
+    ops::Range,
+};
 
 pub mod cortex_m;
 
@@ -14,21 +25,51 @@ pub enum UnwindResult<ADDR: funty::Integral> {
     /// The unwinding can't continue because the stack is corrupted
     Corrupted {
         /// An optional frame that explains the corruption
-        error_frame: Option<Frame<ADDR>>,
+        error_frame: Option<Box<Frame<ADDR>>>,
     },
     /// The unwinding took another step and is not yet finished
     Proceeded,
 }
 
+/// A platform (architecture) that can be traced
+///
+/// Implement this to trace the dumps of a target that isn't supported out of the box.
+/// The minimal parts are:
+/// - [Self::Word]: The type of a register and an address
+/// - [Self::PROGRAM_COUNTER] and [Self::STACK_POINTER]: The DWARF register numbers that are used to find the frames
+/// - [Self::create_context]: Create the state that is needed for unwinding from the elf file, e.g. the parsed `.debug_frame`
+/// - [Self::unwind]: Bring the device memory back to the state of the previous frame
+///
+/// The rest of the trace, like finding the frames and decoding the variables, is shared by all platforms.
 pub trait Platform<'data> {
     type Word: funty::Integral;
 
+    /// The DWARF register number of the program counter
+    const PROGRAM_COUNTER: gimli::Register;
+    /// The DWARF register number of the stack pointer
+    const STACK_POINTER: gimli::Register;
+    /// The DWARF register numbers of the registers that are kept in every frame, see [Frame::registers]
+    const FRAME_REGISTERS: Range<u16> = 0..0;
+
     fn create_context(
         elf: &addr2line::object::File<'data, &'data [u8]>,
     ) -> Result<Self, TraceError>
     where
         Self: Sized;
 
+    /// Create the context with the options of the trace, like [DecodeOptions::vector_table_section].
+    ///
+    /// By default the options are ignored and [Self::create_context] is used.
+    fn create_context_with_options(
+        elf: &addr2line::object::File<'data, &'data [u8]>,
+        _options: &DecodeOptions,
+    ) -> Result<Self, TraceError>
+    where
+        Self: Sized,
+    {
+        Self::create_context(elf)
+    }
+
     /// Unwind the stack of the platform to the previous exception if possible
     ///
     /// The device memory is mutated so that it is brought back to the state it was before the previous exception.
@@ -40,20 +81,104 @@ pub trait Platform<'data> {
         device_memory: &mut DeviceMemory<Self::Word>,
         previous_frame: Option<&mut Frame<Self::Word>>,
     ) -> Result<UnwindResult<Self::Word>, TraceError>;
+
+    /// The address ranges of the memory of the device that are known from the elf file.
+    ///
+    /// This is used to warn about captured memory that doesn't belong to the device.
+    /// By default these are the ranges of the loadable segments of the elf file.
+    fn memory_ranges(&self, elf: &addr2line::object::File<'data, &'data [u8]>) -> Vec<Range<u64>> {
+        elf_segment_ranges(elf)
+    }
+
+    /// The role the register has in the ABI of the platform, like `sp` or `fp`, if it has one.
+    ///
+    /// This is shown next to the DWARF name of the register to make it easier to understand.
+    fn register_role(_register: gimli::Register) -> Option<&'static str> {
+        None
+    }
+
+    /// The DWARF name of the register, like `R0`, if it has one. This is used to show the [Frame::registers].
+    fn register_name(_register: gimli::Register) -> Option<&'static str> {
+        None
+    }
+}
+
+/// Gets the address ranges of all segments of the elf file that take up memory
+pub fn elf_segment_ranges<'data>(
+    elf: &addr2line::object::File<'data, &'data [u8]>,
+) -> Vec<Range<u64>> {
+    elf.segments()
+        .map(|segment| segment.address()..segment.address() + segment.size())
+        .filter(|range| !range.is_empty())
+        .collect()
+}
+
+/// Adds the initial values of the `.data` sections and the zeroes of the `.bss` sections of the elf file to the device memory.
+///
+/// When not all RAM has been captured, the statics that are not captured then read as their initial value instead of being missing.
+/// That is only correct for statics that haven't changed since startup, so this is meant for when the RAM image is known to be incomplete.
+/// The regions are added as image memory, so they have the lowest precedence and captured memory overrides them.
+///
+/// Sections named `.uninit`, like the one of `cortex-m-rt`, are left out, because they are not zeroed at startup.
+pub fn add_elf_data_regions<'data, W: funty::Integral>(
+    device_memory: &mut DeviceMemory<W>,
+    elf: &addr2line::object::File<'data, &'data [u8]>,
+) -> Result<(), TraceError> {
+    for section in elf.sections() {
+        let data = match section.kind() {
+            SectionKind::Data => section.uncompressed_data()?.to_vec(),
+            SectionKind::UninitializedData if !section.name()?.starts_with(".uninit") => {
+                vec![0; section.size() as usize]
+            }
+            _ => continue,
+        };
+
+        if !data.is_empty() {
+            device_memory.add_image_memory_region(VecMemoryRegion::new(section.address(), data));
+        }
+    }
+
+    Ok(())
+}
+
+/// Gets the GNU build id of the elf file, if it has one.
+///
+/// This can be compared with the build id in the meta data of a dump to find the elf file that belongs to the dump.
+pub fn elf_build_id(elf_data: &[u8]) -> Result<Option<Vec<u8>>, TraceError> {
+    let elf = addr2line::object::File::parse(elf_data)?;
+    Ok(elf.build_id()?.map(|build_id| build_id.to_vec()))
 }
 
 /// Create the stacktrace for the given platform.
 ///
 /// - device_memory: All the captured memory of the device.
-/// It is not necessary to include any data that is present in the elf file because that will automatically be added.
-/// It is required to have a decent chunk of the stack present. If not all of the stack is present,
-/// then eventually the tracing procedure will find a corrupt frame.
-/// The standard set of registers is also required to be present.
+///   It is not necessary to include any data that is present in the elf file because that will automatically be added.
+///   It is required to have a decent chunk of the stack present. If not all of the stack is present,
+///   then eventually the tracing procedure will find a corrupt frame.
+///   The standard set of registers is also required to be present.
 /// - elf_data: The raw bytes of the elf file.
-/// This must be the exact same elf file as the one the device was running. Even a recompilation of the exact same code can change the debug info.
+///   This must be the exact same elf file as the one the device was running. Even a recompilation of the exact same code can change the debug info.
 pub fn trace<'data, P: Platform<'data>>(
-    mut device_memory: DeviceMemory<P::Word>,
+    device_memory: DeviceMemory<P::Word>,
+    elf_data: &'data [u8],
+) -> Result<Vec<Frame<P::Word>>, TraceError>
+where
+    <P::Word as funty::Numeric>::Bytes: bitvec::view::BitView<Store = u8>,
+{
+    trace_with_options::<P>(device_memory, elf_data, DecodeOptions::default())
+}
+
+/// Create the stacktrace for the given platform with the given options.
+///
+/// This is the same as [trace], but with other options than the [DecodeOptions::default], like another [DemangleMode].
+///
+/// - device_memory: All the captured memory of the device. See [trace] for the requirements.
+/// - elf_data: The raw bytes of the elf file. See [trace] for the requirements.
+/// - options: The options for decoding the variables. See [trace_and_keep_memory].
+pub fn trace_with_options<'data, P: Platform<'data>>(
+    device_memory: DeviceMemory<P::Word>,
     elf_data: &'data [u8],
+    options: DecodeOptions,
 ) -> Result<Vec<Frame<P::Word>>, TraceError>
 where
     <P::Word as funty::Numeric>::Bytes: bitvec::view::BitView<Store = u8>,
@@ -61,17 +186,125 @@ where
     // Parse the elf data
     let elf = addr2line::object::File::parse(elf_data)?;
 
-    // Add all relevant memory sections present in the elf file to the device memory
+    trace_and_keep_memory::<P>(device_memory, &elf, options).map(|(frames, _)| frames)
+}
+
+/// Create the stacktrace for the given platform using an elf file that has already been parsed.
+///
+/// This is the same as [trace], but can be used to prevent parsing the elf file twice
+/// when you need the parsed file for something else as well.
+///
+/// - device_memory: All the captured memory of the device. See [trace] for the requirements.
+/// - elf: The parsed elf file.
+///   This must be the exact same elf file as the one the device was running.
+pub fn trace_with_object<'data, P: Platform<'data>>(
+    device_memory: DeviceMemory<P::Word>,
+    elf: &addr2line::object::File<'data, &'data [u8]>,
+) -> Result<Vec<Frame<P::Word>>, TraceError>
+where
+    <P::Word as funty::Numeric>::Bytes: bitvec::view::BitView<Store = u8>,
+{
+    trace_and_keep_memory::<P>(device_memory, elf, DecodeOptions::default())
+        .map(|(frames, _)| frames)
+}
+
+/// Create the stacktrace for the given platform without decoding any variables.
+///
+/// The frames only have their function and location, which is enough for e.g. grouping crashes by their backtrace.
+/// Decoding the variables is most of the work of a trace, so this is a lot faster than [trace_with_object].
+///
+/// - device_memory: All the captured memory of the device. See [trace] for the requirements.
+/// - elf: The parsed elf file.
+///   This must be the exact same elf file as the one the device was running.
+pub fn trace_frames_only<'data, P: Platform<'data>>(
+    device_memory: DeviceMemory<P::Word>,
+    elf: &addr2line::object::File<'data, &'data [u8]>,
+) -> Result<Vec<Frame<P::Word>>, TraceError>
+where
+    <P::Word as funty::Numeric>::Bytes: bitvec::view::BitView<Store = u8>,
+{
+    let options = DecodeOptions {
+        skip_variables: true,
+        ..Default::default()
+    };
+
+    trace_and_keep_memory::<P>(device_memory, elf, options).map(|(frames, _)| frames)
+}
+
+/// Create the stacktrace for the given platform and get back the device memory as it is at the end of the trace.
+///
+/// This is the same as [trace_with_object], but instead of dropping the device memory, it is returned with the frames.
+/// The registers in it have been unwound to the state of the last frame that could be unwound to.
+/// It also contains the memory sections of the elf file, because those are added for the trace.
+///
+/// This is useful for tooling that wants to look at the final state of the device after unwinding.
+///
+/// - device_memory: All the captured memory of the device. See [trace] for the requirements.
+/// - elf: The parsed elf file.
+///   This must be the exact same elf file as the one the device was running.
+/// - options: The options for decoding the variables. Use a limited [DecodeBudget](crate::DecodeBudget)
+///   and [DecodeOptions::max_frames] when the dump can't be trusted.
+///   [trace] and [trace_with_object] use the [DecodeOptions::default], which has an unlimited budget.
+#[allow(clippy::type_complexity)]
+pub fn trace_and_keep_memory<'data, 'memory, P: Platform<'data>>(
+    device_memory: DeviceMemory<'memory, P::Word>,
+    elf: &addr2line::object::File<'data, &'data [u8]>,
+    options: DecodeOptions,
+) -> Result<(Vec<Frame<P::Word>>, DeviceMemory<'memory, P::Word>), TraceError>
+where
+    <P::Word as funty::Numeric>::Bytes: bitvec::view::BitView<Store = u8>,
+{
+    let mut frame_iterator = trace_iter::<P>(device_memory, elf, options)?;
+    let frames = frame_iterator.by_ref().collect::<Result<Vec<_>, _>>()?;
+
+    // We're done
+    Ok((frames, frame_iterator.into_device_memory()))
+}
+
+/// Create the stacktrace for the given platform as an iterator that finds the frames when they're asked for.
+///
+/// This is the same as [trace_and_keep_memory], but nothing is unwound or decoded before it's needed.
+/// Every unwind step finds a function frame and the frames of the functions that are inlined into it.
+/// The frame with the static variables comes last, but it can be made earlier with [FrameIterator::static_frame].
+/// This is useful for e.g. a debugger that only shows the top frames until the user asks for more.
+///
+/// - device_memory: All the captured memory of the device. See [trace] for the requirements.
+/// - elf: The parsed elf file.
+///   This must be the exact same elf file as the one the device was running.
+/// - options: The options for decoding the variables. See [trace_and_keep_memory].
+pub fn trace_iter<'data, 'memory, P: Platform<'data>>(
+    mut device_memory: DeviceMemory<'memory, P::Word>,
+    elf: &addr2line::object::File<'data, &'data [u8]>,
+    options: DecodeOptions,
+) -> Result<FrameIterator<'data, 'memory, P>, TraceError>
+where
+    <P::Word as funty::Numeric>::Bytes: bitvec::view::BitView<Store = u8>,
+{
+    // Remember what has been captured, so we can check it against the elf file later
+    let captured_ranges = device_memory.captured_ranges();
+
+    // Overlapping captures are read from the one that was added last, which may not be what the user expects
+    for overlap in device_memory.overlapping_ranges() {
+        log::warn!(
+            "The captured memory regions overlap at {:#X}..{:#X}. The region that was added last is used there.",
+            overlap.start,
+            overlap.end
+        );
+    }
+
+    // Add all relevant memory sections present in the elf file to the device memory.
+    // The captured memory is more up to date, so it takes precedence over these.
+    // They are marked as flash, because that's where the program and its read-only data are.
     for section in elf.sections().filter(|section| {
         matches!(
             section.kind(),
             SectionKind::Text | SectionKind::ReadOnlyData | SectionKind::ReadOnlyString
         )
     }) {
-        device_memory.add_memory_region(VecMemoryRegion::new(
-            section.address(),
-            section.uncompressed_data()?.to_vec(),
-        ));
+        device_memory.add_image_memory_region(
+            VecMemoryRegion::new(section.address(), section.uncompressed_data()?.to_vec())
+                .with_kind(MemoryRegionKind::Flash),
+        );
     }
 
     let endian = if elf.is_little_endian() {
@@ -96,111 +329,310 @@ where
         Ok(gimli::EndianRcSlice::new(std::rc::Rc::from(&*data), endian))
     }
 
-    let dwarf = gimli::Dwarf::load(|id| load_section(id, &elf, endian))?;
-
-    // Create the vector we'll be adding our found frames to
-    let mut frames = Vec::new();
+    let dwarf = gimli::Dwarf::load(|id| load_section(id, elf, endian))?;
 
     // To find the frames, we need the addr2line context which does a lot of the work for us
     let addr2line_context =
-        addr2line::Context::from_dwarf(gimli::Dwarf::load(|id| load_section(id, &elf, endian))?)?;
+        addr2line::Context::from_dwarf(gimli::Dwarf::load(|id| load_section(id, elf, endian))?)?;
 
     // To unwind, we need the platform context
-    let mut platform_context = P::create_context(&elf)?;
-
-    let mut type_cache = Default::default();
-
-    // Now we need to keep looping until we unwound to the start of the program
-    loop {
-        // Get the frames of the current state
-        match add_current_frames::<P>(
-            &device_memory,
-            &addr2line_context,
-            &mut frames,
-            &mut type_cache,
-        ) {
-            Ok(_) => {}
-            Err(e @ TraceError::DwarfUnitNotFound { pc: _ }) => {
-                frames.push(Frame {
-                    function: "Unknown".into(),
-                    location: Location::default(),
-                    frame_type: FrameType::Corrupted(e.to_string()),
-                    variables: Vec::default(),
-                });
-                break;
+    let platform_context = P::create_context_with_options(elf, &options)?;
+
+    // A region that is captured at the wrong address leads to very confusing traces, so we warn about it
+    let memory_ranges = platform_context.memory_ranges(elf);
+    for captured_range in captured_ranges.iter().filter(|captured_range| {
+        !memory_ranges
+            .iter()
+            .any(|memory_range| memory_range.contains(&captured_range.start))
+    }) {
+        log::warn!(
+            "The captured memory at {:#X}..{:#X} does not start in any of the memory ranges of the elf file ({}). Was it captured at the right address?",
+            captured_range.start,
+            captured_range.end,
+            memory_ranges
+                .iter()
+                .map(|memory_range| format!("{:#X}..{:#X}", memory_range.start, memory_range.end))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    Ok(FrameIterator {
+        device_memory,
+        dwarf,
+        addr2line_context,
+        platform_context,
+        type_cache: Default::default(),
+        max_frames: options.max_frames,
+        state: DecodeState::new(options),
+        visited_states: HashSet::new(),
+        pending_frames: VecDeque::new(),
+        found_frames: 0,
+        stack_finished: false,
+        static_frame_taken: false,
+        phantom: PhantomData,
+    })
+}
+
+/// An iterator over the frames of a trace, see [trace_iter].
+///
+/// When the trace fails, the error is yielded and the iteration ends.
+pub struct FrameIterator<'data, 'memory, P: Platform<'data>> {
+    device_memory: DeviceMemory<'memory, P::Word>,
+    dwarf: gimli::Dwarf<EndianRcSlice<RunTimeEndian>>,
+    addr2line_context: addr2line::Context<EndianRcSlice<RunTimeEndian>>,
+    platform_context: P,
+    type_cache: TypeCache<P::Word>,
+    max_frames: Option<usize>,
+    state: DecodeState,
+    /// A corrupted stack can make the unwinding go around in circles.
+    /// Coming back to the same pc and sp means we'd keep going forever, so we remember where we've been.
+    visited_states: HashSet<(u64, u64)>,
+    /// The frames that have been found, but not yet yielded
+    pending_frames: VecDeque<Frame<P::Word>>,
+    /// The amount of stack frames that have been found, including the pending ones
+    found_frames: usize,
+    stack_finished: bool,
+    static_frame_taken: bool,
+    phantom: PhantomData<&'data ()>,
+}
+
+impl<'data, 'memory, P: Platform<'data>> FrameIterator<'data, 'memory, P>
+where
+    <P::Word as funty::Numeric>::Bytes: bitvec::view::BitView<Store = u8>,
+{
+    /// Makes the frame with the static variables.
+    ///
+    /// This is normally the last frame of the iterator. When it's made with this function, the iterator won't yield it anymore.
+    pub fn static_frame(&mut self) -> Result<Frame<P::Word>, TraceError> {
+        self.static_frame_taken = true;
+
+        let (static_variables, omitted_static_variables) = if self.state.skip_variables() {
+            Default::default()
+        } else {
+            self.state.set_frame(None);
+            crate::variables::find_static_variables(
+                &self.dwarf,
+                &self.device_memory,
+                &mut self.type_cache,
+                &mut self.state,
+            )?
+        };
+
+        Ok(Frame {
+            function: "Static".into(),
+            location: Location {
+                file: None,
+                line: None,
+                column: None,
+            },
+            frame_type: FrameType::Static,
+            variables: static_variables,
+            omitted_variables: omitted_static_variables,
+            inlined_into: None,
+            stack_pointer: None,
+            registers: Vec::new(),
+            frame_size: None,
+        })
+    }
+
+    /// Gets the device memory back.
+    ///
+    /// The registers in it have been unwound to the state of the last frame that has been found.
+    /// It also contains the memory sections of the elf file, because those are added for the trace.
+    pub fn into_device_memory(self) -> DeviceMemory<'memory, P::Word> {
+        self.device_memory
+    }
+
+    /// Finds the frames of the current state and unwinds to the previous one.
+    ///
+    /// The variables of the frames are decoded before the unwinding, so they're read with the registers of their own frame.
+    /// The frames of the caller are found in the next step, after the unwinding has restored the registers the callee saved.
+    fn unwind_step(&mut self) -> Result<(), TraceError> {
+        let mut frames = Vec::new();
+
+        let pc = self.device_memory.register(P::PROGRAM_COUNTER)?.as_u64();
+        let sp = self.device_memory.register(P::STACK_POINTER)?.as_u64();
+        if !self.visited_states.insert((pc, sp)) {
+            frames.push(corrupted_frame("unwind loop detected".into()));
+            self.stack_finished = true;
+        } else if let Some(max_frames) = self
+            .max_frames
+            .filter(|max_frames| self.found_frames >= *max_frames)
+        {
+            frames.push(corrupted_frame(format!(
+                "the maximum of {max_frames} frames has been reached"
+            )));
+            self.stack_finished = true;
+        } else {
+            // Get the frames of the current state
+            match add_current_frames::<P>(
+                &self.device_memory,
+                &self.addr2line_context,
+                self.found_frames,
+                &mut frames,
+                &mut self.type_cache,
+                &mut self.state,
+            ) {
+                Ok(_) => {}
+                Err(e @ TraceError::DwarfUnitNotFound { pc: _ }) => {
+                    frames.push(corrupted_frame(e.to_string()));
+                    self.stack_finished = true;
+                }
+                Err(e) => return Err(e),
             }
-            Err(e) => return Err(e),
         }
 
-        // Try to unwind
-        match platform_context.unwind(&mut device_memory, frames.last_mut())? {
-            UnwindResult::Finished => {
-                frames.push(Frame {
-                    function: "RESET".into(),
-                    location: crate::Location {
-                        file: None,
-                        line: None,
-                        column: None,
-                    },
-                    frame_type: FrameType::Function,
-                    variables: Vec::new(),
-                });
-                break;
-            }
-            UnwindResult::Corrupted {
-                error_frame: Some(error_frame),
-            } => {
-                frames.push(error_frame);
-                break;
-            }
-            UnwindResult::Corrupted { error_frame: None } => {
-                break;
+        if !self.stack_finished {
+            // Try to unwind
+            match self
+                .platform_context
+                .unwind(&mut self.device_memory, frames.last_mut())?
+            {
+                UnwindResult::Finished => {
+                    frames.push(Frame {
+                        function: "RESET".into(),
+                        location: crate::Location {
+                            file: None,
+                            line: None,
+                            column: None,
+                        },
+                        frame_type: FrameType::Function,
+                        variables: Vec::new(),
+                        omitted_variables: Vec::new(),
+                        inlined_into: None,
+                        stack_pointer: None,
+                        registers: Vec::new(),
+                        frame_size: None,
+                    });
+                    self.stack_finished = true;
+                }
+                UnwindResult::Corrupted {
+                    error_frame: Some(error_frame),
+                } => {
+                    frames.push(*error_frame);
+                    self.stack_finished = true;
+                }
+                UnwindResult::Corrupted { error_frame: None } => {
+                    self.stack_finished = true;
+                }
+                UnwindResult::Proceeded => {
+                    // The stack pointer is now the one of the caller, so the difference is what the function used
+                    let caller_sp = self.device_memory.register(P::STACK_POINTER)?;
+                    if let Some(frame) = frames.last_mut() {
+                        frame.frame_size = frame
+                            .stack_pointer
+                            .filter(|sp| *sp <= caller_sp)
+                            .map(|sp| (caller_sp - sp).as_u64());
+                    }
+                }
             }
-            UnwindResult::Proceeded => {
-                continue;
+        }
+
+        self.found_frames += frames.len();
+        self.pending_frames.extend(frames);
+
+        Ok(())
+    }
+}
+
+impl<'data, 'memory, P: Platform<'data>> Iterator for FrameIterator<'data, 'memory, P>
+where
+    <P::Word as funty::Numeric>::Bytes: bitvec::view::BitView<Store = u8>,
+{
+    type Item = Result<Frame<P::Word>, TraceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Keep unwinding until there's a frame, because a step doesn't always find one
+        while self.pending_frames.is_empty() && !self.stack_finished {
+            if let Err(e) = self.unwind_step() {
+                self.stack_finished = true;
+                self.static_frame_taken = true;
+                return Some(Err(e));
             }
         }
+
+        if let Some(frame) = self.pending_frames.pop_front() {
+            return Some(Ok(frame));
+        }
+
+        // We're done with the stack data, but we can also decode the static variables and make a frame out of that
+        if !self.static_frame_taken {
+            return Some(self.static_frame());
+        }
+
+        None
     }
+}
 
-    // We're done with the stack data, but we can also decode the static variables and make a frame out of that
-    let static_variables =
-        crate::variables::find_static_variables(&dwarf, &device_memory, &mut type_cache)?;
-    let static_frame = Frame {
-        function: "Static".into(),
-        location: Location {
-            file: None,
-            line: None,
-            column: None,
-        },
-        frame_type: FrameType::Static,
-        variables: static_variables,
+/// A frame that says the trace can't go on because of the given reason
+fn corrupted_frame<W: funty::Integral>(reason: String) -> Frame<W> {
+    Frame {
+        function: "Unknown".into(),
+        location: Location::default(),
+        frame_type: FrameType::Corrupted(reason),
+        variables: Vec::default(),
+        omitted_variables: Vec::default(),
+        inlined_into: None,
+        stack_pointer: None,
+        registers: Vec::new(),
+        frame_size: None,
+    }
+}
+
+/// Gets the name of the function, demangled in the given way
+fn function_name(
+    function: &addr2line::FunctionName<EndianRcSlice<RunTimeEndian>>,
+    demangle_mode: DemangleMode,
+) -> Option<String> {
+    let language = match demangle_mode {
+        DemangleMode::Auto => return function.demangle().ok().map(|name| name.into_owned()),
+        DemangleMode::Raw => return function.raw_name().ok().map(|name| name.into_owned()),
+        DemangleMode::Rust => gimli::DW_LANG_Rust,
+        DemangleMode::Cpp => gimli::DW_LANG_C_plus_plus,
     };
-    frames.push(static_frame);
 
-    // We're done
-    Ok(frames)
+    let raw_name = function.raw_name().ok()?;
+    Some(addr2line::demangle(&raw_name, language).unwrap_or_else(|| raw_name.into_owned()))
+}
+
+/// Reads the [Platform::FRAME_REGISTERS] that have a known value
+fn frame_registers<'a, P: Platform<'a>>(
+    device_memory: &DeviceMemory<P::Word>,
+) -> Vec<(gimli::Register, P::Word)> {
+    P::FRAME_REGISTERS
+        .map(gimli::Register)
+        .filter_map(|register| {
+            device_memory
+                .register(register)
+                .ok()
+                .map(|value| (register, value))
+        })
+        .collect()
 }
 
 fn add_current_frames<'a, P: Platform<'a>>(
     device_memory: &DeviceMemory<P::Word>,
     addr2line_context: &addr2line::Context<EndianRcSlice<RunTimeEndian>>,
+    first_index: usize,
     frames: &mut Vec<Frame<P::Word>>,
-    type_cache: &mut HashMap<DebugInfoOffset, Result<TypeValueTree<P::Word>, TraceError>>,
+    type_cache: &mut TypeCache<P::Word>,
+    state: &mut DecodeState,
 ) -> Result<(), TraceError>
 where
     <P::Word as funty::Numeric>::Bytes: bitvec::view::BitView<Store = u8>,
 {
     // Find the frames of the current register context
     let mut context_frames = addr2line_context
-        .find_frames(device_memory.register(gimli::Arm::PC)?.as_u64())
+        .find_frames(device_memory.register(P::PROGRAM_COUNTER)?.as_u64())
         .skip_all_loads()?;
 
     // Get the debug compilation unit of the current register context
     let (dwarf, unit) = addr2line_context
-        .find_dwarf_and_unit(device_memory.register(gimli::Arm::PC)?.as_u64())
+        .find_dwarf_and_unit(device_memory.register(P::PROGRAM_COUNTER)?.as_u64())
         .skip_all_loads()
         .ok_or(TraceError::DwarfUnitNotFound {
-            pc: device_memory.register(gimli::Arm::PC)?.as_u64(),
+            pc: device_memory.register(P::PROGRAM_COUNTER)?.as_u64(),
         })?;
 
     // Get the abbreviations of the unit
@@ -221,8 +653,12 @@ where
             .unwrap_or_default();
 
         let mut variables = Vec::new();
+        let mut omitted_variables = Vec::new();
 
-        if let Some(die_offset) = context_frame.dw_die_offset {
+        if let Some(die_offset) = context_frame
+            .dw_die_offset
+            .filter(|_| !state.skip_variables())
+        {
             let mut entries = match unit.header.entries_tree(&abbreviations, Some(die_offset)) {
                 Ok(entries) => entries,
                 Err(_) => {
@@ -231,13 +667,18 @@ where
             };
 
             if let Ok(entry_root) = entries.root() {
-                variables = crate::variables::find_variables_in_function(
+                state.set_frame(Some(EvaluationFrame {
+                    index: first_index + frames.len(),
+                    pc: device_memory.register(P::PROGRAM_COUNTER)?.as_u64(),
+                }));
+                (variables, omitted_variables) = crate::variables::find_variables_in_function(
                     dwarf,
                     unit,
                     &abbreviations,
                     device_memory,
                     entry_root,
                     type_cache,
+                    state,
                 )?;
             }
         }
@@ -245,11 +686,16 @@ where
         frames.push(Frame {
             function: context_frame
                 .function
-                .and_then(|f| f.demangle().ok().map(|f| f.into_owned()))
+                .and_then(|f| function_name(&f, state.demangle_mode()))
                 .unwrap_or_else(|| "UNKNOWN".into()),
             location: crate::Location { file, line, column },
             frame_type: FrameType::InlineFunction,
             variables,
+            omitted_variables,
+            inlined_into: None,
+            stack_pointer: Some(device_memory.register(P::STACK_POINTER)?),
+            registers: frame_registers::<P>(device_memory),
+            frame_size: None,
         });
 
         added_frames += 1;
@@ -258,7 +704,620 @@ where
     if added_frames > 0 {
         // The last frame of `find_frames` is always a real function. All frames before are inline functions.
         frames.last_mut().unwrap().frame_type = FrameType::Function;
+
+        // Link the inline functions to the real function they've been inlined into
+        let function_index = frames.len() - 1;
+        for inline_frame in &mut frames[function_index + 1 - added_frames..function_index] {
+            inline_frame.inlined_into = Some(first_index + function_index);
+        }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::cortex_m::CortexMPlatform;
+    use stackdump_core::{dump::Dump, register_data::VecRegisterData};
+
+    const ELF: &[u8] = include_bytes!("../../../examples/data/nrf52840");
+    const DUMP: &[u8] = include_bytes!("../../../examples/data/nrf52840.dump");
+
+    fn device_memory() -> DeviceMemory<'static, u32> {
+        let mut device_memory = DeviceMemory::new();
+        Dump::from_dump_bytes(DUMP)
+            .unwrap()
+            .add_to_device_memory(&mut device_memory);
+        device_memory
+    }
+
+    #[test]
+    fn trace_and_keep_memory() {
+        let elf = addr2line::object::File::parse(ELF).unwrap();
+
+        let device_memory = device_memory();
+        let captured_sp = device_memory.register(gimli::Arm::SP).unwrap();
+
+        let (frames, device_memory) = super::trace_and_keep_memory::<CortexMPlatform>(
+            device_memory,
+            &elf,
+            DecodeOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            frames.len(),
+            trace_with_object::<CortexMPlatform>(self::device_memory(), &elf)
+                .unwrap()
+                .len()
+        );
+
+        // The registers have been unwound, so the stack has shrunk back
+        assert!(device_memory.register(gimli::Arm::SP).unwrap() > captured_sp);
+        // The captured memory is still there
+        assert!(device_memory.read_u8(captured_sp as u64).unwrap().is_some());
+        assert_eq!(
+            device_memory.memory_kind(captured_sp as u64),
+            Some(MemoryRegionKind::Ram)
+        );
+        // And the program from the elf file has been added as flash
+        assert_eq!(
+            device_memory.memory_kind(elf.entry()),
+            Some(MemoryRegionKind::Flash)
+        );
+    }
+
+    #[test]
+    fn inlined_into() {
+        let elf = addr2line::object::File::parse(ELF).unwrap();
+
+        // At this address two functions have been inlined into `NVIC::unmask`
+        let mut device_memory = device_memory();
+        *device_memory.register_mut(gimli::Arm::PC).unwrap() = 0x49C;
+        let frames = trace_with_object::<CortexMPlatform>(device_memory, &elf).unwrap();
+        assert!(matches!(frames[0].frame_type, FrameType::InlineFunction));
+        assert!(matches!(frames[1].frame_type, FrameType::InlineFunction));
+        assert_eq!(frames[0].inlined_into, Some(2));
+        assert_eq!(frames[1].inlined_into, Some(2));
+        assert!(frames[2].function.contains("unmask"));
+
+        // Inline functions point to the first real function after them, the other frames don't point anywhere
+        for (index, frame) in frames.iter().enumerate() {
+            match frame.frame_type {
+                FrameType::InlineFunction => {
+                    let function_index = frame.inlined_into.unwrap();
+                    assert!(function_index > index);
+                    assert!(matches!(
+                        frames[function_index].frame_type,
+                        FrameType::Function
+                    ));
+                    assert!(frames[index + 1..function_index]
+                        .iter()
+                        .all(|frame| matches!(frame.frame_type, FrameType::InlineFunction)));
+                }
+                _ => assert_eq!(frame.inlined_into, None),
+            }
+        }
+
+        // Without debug info for the program counter no frames can be found, so there's nothing to link
+        let mut device_memory = self::device_memory();
+        *device_memory.register_mut(gimli::Arm::PC).unwrap() = 0xFFFF_FFF0;
+        let frames = trace_with_object::<CortexMPlatform>(device_memory, &elf).unwrap();
+        assert!(matches!(frames[0].frame_type, FrameType::Corrupted(_)));
+        assert!(frames.iter().all(|frame| frame.inlined_into.is_none()));
+    }
+
+    #[test]
+    fn add_elf_data_regions() {
+        let elf = addr2line::object::File::parse(ELF).unwrap();
+
+        let mut device_memory = DeviceMemory::<u32>::new();
+        super::add_elf_data_regions(&mut device_memory, &elf).unwrap();
+
+        // The .bss is zeroed, the .uninit after it is not
+        assert_eq!(
+            device_memory.read_u32(0x2000_0000, gimli::RunTimeEndian::Little),
+            Ok(Some(0))
+        );
+        assert_eq!(device_memory.read_u8(0x2000_085F), Ok(Some(0)));
+        assert_eq!(device_memory.read_u8(0x2000_0860), Ok(None));
+
+        // Captured memory takes precedence
+        device_memory.add_memory_region(VecMemoryRegion::new(0x2000_0000, vec![1, 2, 3, 4]));
+        assert_eq!(
+            device_memory.read_u32(0x2000_0000, gimli::RunTimeEndian::Little),
+            Ok(Some(0x04030201))
+        );
+    }
+
+    #[test]
+    fn frame_registers() {
+        let elf = addr2line::object::File::parse(ELF).unwrap();
+
+        let captured_registers = device_memory();
+        let frames = trace_with_object::<CortexMPlatform>(device_memory(), &elf).unwrap();
+
+        // The first frame has the registers as they were captured
+        let first = &frames[0];
+        assert_eq!(first.registers.len(), 16);
+        for (register, value) in &first.registers {
+            assert_eq!(captured_registers.register(*register), Ok(*value));
+        }
+
+        // Every frame that has a stack pointer, has it in its registers too
+        for frame in frames.iter().filter(|frame| frame.stack_pointer.is_some()) {
+            assert_eq!(
+                frame
+                    .registers
+                    .iter()
+                    .find(|(register, _)| *register == gimli::Arm::SP)
+                    .map(|(_, value)| *value),
+                frame.stack_pointer
+            );
+        }
+    }
+
+    #[test]
+    fn trace_iter() {
+        let elf = addr2line::object::File::parse(ELF).unwrap();
+
+        let frames = trace_with_object::<CortexMPlatform>(device_memory(), &elf).unwrap();
+
+        // All frames come out of the iterator in the same order
+        let iter_frames =
+            super::trace_iter::<CortexMPlatform>(device_memory(), &elf, DecodeOptions::default())
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+        assert_eq!(iter_frames, frames);
+        assert_eq!(
+            iter_frames
+                .iter()
+                .map(|frame| frame.inlined_into)
+                .collect::<Vec<_>>(),
+            frames
+                .iter()
+                .map(|frame| frame.inlined_into)
+                .collect::<Vec<_>>()
+        );
+
+        // The static frame can be made before the stack frames and isn't yielded again
+        let mut frame_iterator =
+            super::trace_iter::<CortexMPlatform>(device_memory(), &elf, DecodeOptions::default())
+                .unwrap();
+        assert_eq!(
+            frame_iterator.static_frame().unwrap(),
+            *frames.last().unwrap()
+        );
+        assert_eq!(frame_iterator.next().unwrap().unwrap(), frames[0]);
+        assert_eq!(frame_iterator.count(), frames.len() - 2);
+    }
+
+    #[test]
+    fn frame_sizes() {
+        let elf = addr2line::object::File::parse(ELF).unwrap();
+
+        let frames = trace_with_object::<CortexMPlatform>(device_memory(), &elf).unwrap();
+
+        // A function uses the stack down from where its caller was
+        for (index, frame) in frames.iter().enumerate() {
+            match frame.frame_type {
+                FrameType::InlineFunction => assert_eq!(frame.frame_size, None),
+                _ => {
+                    if let Some(frame_size) = frame.frame_size {
+                        let caller_sp = frames[index + 1..]
+                            .iter()
+                            .find_map(|frame| frame.stack_pointer)
+                            .unwrap();
+                        assert_eq!(
+                            frame_size,
+                            (caller_sp - frame.stack_pointer.unwrap()) as u64
+                        );
+                    }
+                }
+            }
+        }
+
+        let frame_sizes = crate::frame_sizes(&frames);
+        assert_eq!(
+            frame_sizes.first(),
+            Some(&("stackdump_capture::cortex_m::capture_core_registers", 160))
+        );
+        assert_eq!(frame_sizes.iter().map(|(_, size)| size).sum::<u64>(), 3228);
+    }
+
+    #[test]
+    fn demangle_mode() {
+        let elf = addr2line::object::File::parse(ELF).unwrap();
+
+        let function_names = |demangle_mode| {
+            let options = DecodeOptions {
+                demangle_mode,
+                skip_variables: true,
+                ..Default::default()
+            };
+            super::trace_and_keep_memory::<CortexMPlatform>(device_memory(), &elf, options)
+                .unwrap()
+                .0
+                .into_iter()
+                .map(|frame| frame.function)
+                .collect::<Vec<_>>()
+        };
+
+        let auto_names = function_names(DemangleMode::Auto);
+        let raw_names = function_names(DemangleMode::Raw);
+
+        // The example is a Rust program, so demangling it as Rust is what auto does
+        assert_eq!(function_names(DemangleMode::Rust), auto_names);
+        assert_ne!(raw_names, auto_names);
+        assert!(raw_names.iter().any(|name| name.starts_with("_ZN")));
+        // Legacy Rust symbols are C++ symbols too, but the C++ demangler doesn't know the hash at the end is special
+        assert!(function_names(DemangleMode::Cpp)
+            .contains(&"nrf52840::do_loop::h4bf27c912d82adec".to_string()));
+    }
+
+    /// A platform that never gets anywhere when unwinding
+    struct StuckPlatform;
+
+    impl<'data> Platform<'data> for StuckPlatform {
+        type Word = u32;
+
+        const PROGRAM_COUNTER: gimli::Register = gimli::Arm::PC;
+        const STACK_POINTER: gimli::Register = gimli::Arm::SP;
+
+        fn create_context(
+            _elf: &addr2line::object::File<'data, &'data [u8]>,
+        ) -> Result<Self, TraceError> {
+            Ok(Self)
+        }
+
+        fn unwind(
+            &mut self,
+            _device_memory: &mut DeviceMemory<Self::Word>,
+            _previous_frame: Option<&mut Frame<Self::Word>>,
+        ) -> Result<UnwindResult<Self::Word>, TraceError> {
+            Ok(UnwindResult::Proceeded)
+        }
+    }
+
+    #[test]
+    fn unwind_loop() {
+        let elf = addr2line::object::File::parse(ELF).unwrap();
+
+        let frames = trace_with_object::<StuckPlatform>(device_memory(), &elf).unwrap();
+
+        // The frames of the first state, then the loop and the static frame
+        let first_frames = frames
+            .iter()
+            .position(|frame| frame.frame_type == FrameType::Function)
+            .unwrap()
+            + 1;
+        assert_eq!(frames.len(), first_frames + 2);
+        assert_eq!(
+            frames[first_frames].frame_type,
+            FrameType::Corrupted("unwind loop detected".into())
+        );
+    }
+
+    #[test]
+    fn max_frames() {
+        let elf = addr2line::object::File::parse(ELF).unwrap();
+
+        let all_frames = trace_with_object::<CortexMPlatform>(device_memory(), &elf).unwrap();
+        let (frames, _) = super::trace_and_keep_memory::<CortexMPlatform>(
+            device_memory(),
+            &elf,
+            DecodeOptions {
+                max_frames: Some(2),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        // The frames stop at the first unwind after the maximum is reached
+        let stopped_at = frames
+            .iter()
+            .position(|frame| matches!(frame.frame_type, FrameType::Corrupted(_)))
+            .unwrap();
+        assert!(stopped_at >= 2 && stopped_at < all_frames.len());
+        assert_eq!(
+            frames[stopped_at].frame_type,
+            FrameType::Corrupted("the maximum of 2 frames has been reached".into())
+        );
+        assert_eq!(frames.len(), stopped_at + 2);
+    }
+
+    #[test]
+    fn elf_build_id() {
+        // The example is linked without a build id
+        assert_eq!(super::elf_build_id(ELF).unwrap(), None);
+        assert!(super::elf_build_id(&[0; 16]).is_err());
+    }
+
+    #[test]
+    fn trace_frames_only() {
+        let elf = addr2line::object::File::parse(ELF).unwrap();
+
+        let frames = trace_with_object::<CortexMPlatform>(device_memory(), &elf).unwrap();
+        let frames_only =
+            super::trace_frames_only::<CortexMPlatform>(device_memory(), &elf).unwrap();
+
+        // The same frames are found, but without variables
+        assert!(frames.iter().any(|frame| !frame.variables.is_empty()));
+        assert!(frames_only
+            .iter()
+            .all(|frame| frame.variables.is_empty() && frame.omitted_variables.is_empty()));
+        assert_eq!(
+            frames
+                .iter()
+                .map(|frame| (&frame.function, &frame.location))
+                .collect::<Vec<_>>(),
+            frames_only
+                .iter()
+                .map(|frame| (&frame.function, &frame.location))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    /// Creates an elf file with only a `PT_LOAD` program header for every `(address, file size, memory size)`
+    fn elf_with_segments(segments: &[(u64, u64, u64)]) -> Vec<u8> {
+        use object::{elf, write::elf::*};
+
+        let mut buffer = Vec::new();
+        let mut writer = Writer::new(object::Endianness::Little, false, &mut buffer);
+
+        writer.reserve_file_header();
+        writer.reserve_program_headers(segments.len() as u32);
+        let data_offset = writer.reserve(
+            segments
+                .iter()
+                .map(|(_, file_size, _)| *file_size as usize)
+                .sum(),
+            4,
+        );
+
+        writer
+            .write_file_header(&FileHeader {
+                os_abi: elf::ELFOSABI_NONE,
+                abi_version: 0,
+                e_type: elf::ET_EXEC,
+                e_machine: elf::EM_ARM,
+                e_entry: 0,
+                e_flags: 0,
+            })
+            .unwrap();
+
+        writer.write_align_program_headers();
+        let mut offset = data_offset as u64;
+        for (address, file_size, memory_size) in segments {
+            writer.write_program_header(&ProgramHeader {
+                p_type: elf::PT_LOAD,
+                p_flags: elf::PF_R | elf::PF_W,
+                p_offset: offset,
+                p_vaddr: *address,
+                p_paddr: *address,
+                p_filesz: *file_size,
+                p_memsz: *memory_size,
+                p_align: 4,
+            });
+            offset += file_size;
+        }
+
+        writer.write_align(4);
+        writer.write(&vec![0; (offset - data_offset as u64) as usize]);
+
+        buffer
+    }
+
+    #[test]
+    fn segment_ranges() {
+        let elf_data = elf_with_segments(&[
+            // Flash
+            (0x0000_0000, 0x100, 0x100),
+            // Adjacent to the flash
+            (0x0000_0100, 0x20, 0x20),
+            // Overlapping with the flash
+            (0x0000_0080, 0x100, 0x100),
+            // Zero size
+            (0x1000_0000, 0, 0),
+            // Only zeroes, like `.bss`, so nothing of it is in the file
+            (0x2000_0000, 0, 0x400),
+            // Partly in the file, like `.data` followed by `.bss`
+            (0x2000_0400, 0x10, 0x40),
+        ]);
+        let elf = addr2line::object::File::parse(&*elf_data).unwrap();
+
+        // The ranges are kept as they are, overlapping and adjacent ranges are not merged
+        assert_eq!(
+            elf_segment_ranges(&elf),
+            vec![
+                0x0000_0000..0x0000_0100,
+                0x0000_0100..0x0000_0120,
+                0x0000_0080..0x0000_0180,
+                0x2000_0000..0x2000_0400,
+                0x2000_0400..0x2000_0440,
+            ]
+        );
+    }
+
+    #[test]
+    fn segment_ranges_of_example() {
+        let elf = addr2line::object::File::parse(ELF).unwrap();
+        let segment_ranges = elf_segment_ranges(&elf);
+
+        assert!(!segment_ranges.is_empty());
+        assert!(segment_ranges.iter().all(|range| !range.is_empty()));
+
+        // All captured memory of the example dump is in RAM, which the elf file describes
+        // with the segments of the statics and the stack above them
+        let platform_context = CortexMPlatform::create_context(&elf).unwrap();
+        let memory_ranges = platform_context.memory_ranges(&elf);
+        assert_eq!(memory_ranges.len(), segment_ranges.len() + 1);
+        for captured_range in device_memory().captured_ranges() {
+            assert!(memory_ranges
+                .iter()
+                .any(|memory_range| memory_range.contains(&captured_range.start)));
+        }
+    }
+
+    /// A platform on which `callee` has been called by `caller`.
+    /// The unwinding restores R5, like a callee-saved register is restored from the stack.
+    struct CallerCalleePlatform;
+
+    const CALLEE: Range<u64> = 0x1000..0x1100;
+    const CALLER: Range<u64> = 0x1100..0x1200;
+
+    impl<'data> Platform<'data> for CallerCalleePlatform {
+        type Word = u32;
+
+        const PROGRAM_COUNTER: gimli::Register = gimli::Arm::PC;
+        const STACK_POINTER: gimli::Register = gimli::Arm::SP;
+
+        fn create_context(
+            _elf: &addr2line::object::File<'data, &'data [u8]>,
+        ) -> Result<Self, TraceError> {
+            Ok(Self)
+        }
+
+        fn unwind(
+            &mut self,
+            device_memory: &mut DeviceMemory<u32>,
+            _previous_frame: Option<&mut Frame<u32>>,
+        ) -> Result<UnwindResult<u32>, TraceError> {
+            if CALLER.contains(&(device_memory.register(gimli::Arm::PC)? as u64)) {
+                return Ok(UnwindResult::Finished);
+            }
+
+            *device_memory.register_mut(gimli::Arm::R5)? = 5;
+            *device_memory.register_mut(gimli::Arm::SP)? += 8;
+            *device_memory.register_mut(gimli::Arm::PC)? = CALLER.start as u32 + 0x10;
+            Ok(UnwindResult::Proceeded)
+        }
+    }
+
+    /// Creates an elf file with the debug info of `callee` and `caller`, which both have a `local` in R5
+    fn elf_with_register_locals() -> Vec<u8> {
+        use gimli::write::{self, Address, AttributeValue, Expression};
+        use object::write::Object;
+
+        let encoding = gimli::Encoding {
+            format: gimli::Format::Dwarf32,
+            version: 4,
+            address_size: 4,
+        };
+        let mut dwarf = write::Dwarf::new();
+        let unit_id = dwarf
+            .units
+            .add(write::Unit::new(encoding, write::LineProgram::none()));
+        let unit = dwarf.units.get_mut(unit_id);
+        let root = unit.root();
+        unit.get_mut(root).set(
+            gimli::constants::DW_AT_low_pc,
+            AttributeValue::Address(Address::Constant(CALLEE.start)),
+        );
+        unit.get_mut(root).set(
+            gimli::constants::DW_AT_high_pc,
+            AttributeValue::Udata(CALLER.end - CALLEE.start),
+        );
+
+        let u32_type = unit.add(root, gimli::constants::DW_TAG_base_type);
+        let entry = unit.get_mut(u32_type);
+        entry.set(
+            gimli::constants::DW_AT_name,
+            AttributeValue::String("u32".into()),
+        );
+        entry.set(
+            gimli::constants::DW_AT_encoding,
+            AttributeValue::Encoding(gimli::constants::DW_ATE_unsigned),
+        );
+        entry.set(gimli::constants::DW_AT_byte_size, AttributeValue::Udata(4));
+
+        for (name, range) in [("callee", CALLEE), ("caller", CALLER)] {
+            let function = unit.add(root, gimli::constants::DW_TAG_subprogram);
+            let entry = unit.get_mut(function);
+            entry.set(
+                gimli::constants::DW_AT_name,
+                AttributeValue::String(name.into()),
+            );
+            entry.set(
+                gimli::constants::DW_AT_low_pc,
+                AttributeValue::Address(Address::Constant(range.start)),
+            );
+            entry.set(
+                gimli::constants::DW_AT_high_pc,
+                AttributeValue::Udata(range.end - range.start),
+            );
+
+            let local = unit.add(function, gimli::constants::DW_TAG_variable);
+            let entry = unit.get_mut(local);
+            entry.set(
+                gimli::constants::DW_AT_name,
+                AttributeValue::String("local".into()),
+            );
+            entry.set(
+                gimli::constants::DW_AT_type,
+                AttributeValue::UnitRef(u32_type),
+            );
+            let mut location = Expression::new();
+            location.op_reg(gimli::Arm::R5);
+            entry.set(
+                gimli::constants::DW_AT_location,
+                AttributeValue::Exprloc(location),
+            );
+        }
+
+        let mut sections = write::Sections::new(write::EndianVec::new(gimli::LittleEndian));
+        dwarf.write(&mut sections).unwrap();
+
+        let mut elf = Object::new(
+            object::BinaryFormat::Elf,
+            object::Architecture::Arm,
+            object::Endianness::Little,
+        );
+        sections
+            .for_each(|id, data| {
+                if !data.slice().is_empty() {
+                    let section = elf.add_section(
+                        Vec::new(),
+                        id.name().as_bytes().to_vec(),
+                        object::SectionKind::Debug,
+                    );
+                    elf.set_section_data(section, data.slice().to_vec(), 1);
+                }
+                Ok::<_, ()>(())
+            })
+            .unwrap();
+        elf.write().unwrap()
+    }
+
+    #[test]
+    fn caller_variables_use_unwound_registers() {
+        let elf_data = elf_with_register_locals();
+        let elf = addr2line::object::File::parse(elf_data.as_slice()).unwrap();
+
+        let mut registers = vec![0u32; 16];
+        registers[5] = 55;
+        registers[13] = 0x2000_0000;
+        registers[15] = CALLEE.start as u32 + 0x10;
+        let mut device_memory = DeviceMemory::new();
+        device_memory.add_register_data(VecRegisterData::new(gimli::Arm::R0, registers));
+
+        let frames = trace_with_object::<CallerCalleePlatform>(device_memory, &elf).unwrap();
+        let local_of = |function: &str| {
+            let frame = frames
+                .iter()
+                .find(|frame| frame.function == function)
+                .unwrap();
+            crate::type_value_tree::rendering::render_type_value_tree(
+                &frame.variables[0].type_value,
+                crate::render_colors::Theme::None,
+            )
+            .to_string()
+        };
+
+        // The callee has changed R5 after saving the value of the caller, which the unwinding has restored
+        assert_eq!(local_of("callee"), "55");
+        assert_eq!(local_of("caller"), "5");
+    }
+}
@@ -1,5 +1,6 @@
 use self::{value::Value, variable_type::VariableType};
-use stackdump_core::device_memory::MemoryReadError;
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+use stackdump_core::device_memory::{MemoryReadError, MissingRegisterError};
 use std::{fmt::Debug, ops::Range};
 use thiserror::Error;
 
@@ -16,6 +17,11 @@ pub struct TypeValue<ADDR: funty::Integral> {
     pub variable_type: VariableType,
     pub bit_range: Range<u64>,
     pub variable_value: Result<Value<ADDR>, VariableDataError>,
+    /// The bytes the value was decoded from.
+    ///
+    /// This is only kept for values that are decoded from their bytes directly, like base types and pointers,
+    /// so the bytes can be shown when the decoding failed.
+    pub raw_data: Option<Vec<u8>>,
 }
 
 impl<ADDR: funty::Integral> TypeValue<ADDR> {
@@ -35,10 +41,55 @@ impl<ADDR: funty::Integral> Default for TypeValue<ADDR> {
             variable_type: Default::default(),
             bit_range: Default::default(),
             variable_value: Err(VariableDataError::Unknown),
+            raw_data: None,
         }
     }
 }
 
+/// Gets the value of the discriminant of a tagged union.
+/// An enumeration or typedef doesn't have the value itself, so then it's taken from its child.
+pub(crate) fn discriminant_value<ADDR: funty::Integral>(
+    discriminant: &TypeValueNode<ADDR>,
+) -> Result<&Value<ADDR>, &VariableDataError> {
+    match (&discriminant.data().variable_value, discriminant.front()) {
+        (Ok(Value::Enumeration | Value::Typedef), Some(child)) => discriminant_value(child),
+        (value, _) => value.as_ref(),
+    }
+}
+
+/// Serializes the tree as nested nodes with their name, type name, archetype, value or error and children
+pub(crate) fn serialize_type_value_tree<ADDR: funty::Integral, S: Serializer>(
+    tree: &TypeValueTree<ADDR>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    SerializableNode(tree.root()).serialize(serializer)
+}
+
+struct SerializableNode<'a, ADDR: funty::Integral>(&'a TypeValueNode<ADDR>);
+
+impl<ADDR: funty::Integral> Serialize for SerializableNode<'_, ADDR> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let data = self.0.data();
+
+        let mut node = serializer.serialize_struct("TypeValue", 5)?;
+        node.serialize_field("name", &data.name)?;
+        node.serialize_field("type", &data.variable_type.name)?;
+        node.serialize_field(
+            "archetype",
+            <&'static str>::from(&data.variable_type.archetype),
+        )?;
+        match &data.variable_value {
+            Ok(value) => node.serialize_field("value", value)?,
+            Err(e) => node.serialize_field("error", &e.to_string())?,
+        }
+        node.serialize_field(
+            "children",
+            &self.0.iter().map(SerializableNode).collect::<Vec<_>>(),
+        )?;
+        node.end()
+    }
+}
+
 #[derive(Error, Debug, Clone, PartialEq)]
 pub enum VariableDataError {
     #[error("Data has invalid size of {bits} bits")]
@@ -48,18 +99,40 @@ pub enum VariableDataError {
         base_type: gimli::DwAte,
         data: bitvec::prelude::BitVec<u8, bitvec::order::Lsb0>,
     },
+    #[error("invalid bool ({value:#04X})")]
+    InvalidBool { value: u128 },
+    #[error("invalid char ({value:#X})")]
+    InvalidChar { value: u32 },
     #[error("Pointer data is invalid")]
     InvalidPointerData,
     #[error("nullptr")]
     NullPointer,
+    #[error("null reference")]
+    NullReference,
+    #[error("Points to peripheral memory")]
+    PeripheralMemory,
     #[error("Some memory could not be read: {0}")]
     MemoryReadError(#[from] MemoryReadError),
     #[error("Data not available")]
     NoDataAvailable,
     #[error("Data not available: {0}")]
     NoDataAvailableAt(String),
+    #[error("Data not available: {0}")]
+    MissingRegister(#[from] MissingRegisterError),
     #[error("Optimized away")]
     OptimizedAway,
+    #[error("TLS base unknown")]
+    TlsBaseUnknown,
+    #[error("Points to a value that is not in memory")]
+    ImplicitPointerNotInMemory,
+    #[error("Decode budget exceeded: {0}")]
+    BudgetExceeded(&'static str),
+    #[error("The collection at {address:#X} with {length} elements of {element_byte_size} bytes does not fit in the address space")]
+    CollectionOverflow {
+        address: u64,
+        length: u64,
+        element_byte_size: u64,
+    },
     #[error("Required step of location evaluation logic not implemented: {0}")]
     UnimplementedLocationEvaluationStep(String),
     #[error("Unknown")]
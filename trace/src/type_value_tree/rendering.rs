@@ -1,26 +1,141 @@
-use super::{value::Value, variable_type::Archetype, TypeValueNode, TypeValueTree};
+use super::{
+    discriminant_value,
+    value::Value,
+    variable_type::{Archetype, VariantDiscriminant},
+    TypeValueNode, TypeValueTree,
+};
 use crate::{
     render_colors::{Theme, ThemeColors},
-    type_value_tree::VariableDataError,
+    variables::div_ceil,
 };
 use colored::{ColoredString, Colorize};
 use phf::phf_map;
+use std::num::FpCategory;
+
+/// The radix that integers are rendered in
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Default, strum_macros::Display, strum_macros::EnumString,
+)]
+#[strum(serialize_all = "lowercase")]
+pub enum IntRadix {
+    /// Decimal, e.g. `15`
+    #[default]
+    Dec,
+    /// Hexadecimal, e.g. `0x0F` for a `u8`
+    Hex,
+    /// Binary, e.g. `0b00001111` for a `u8`
+    Bin,
+}
+
+/// Options for how the values of a type value tree are rendered
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    /// The radix that integers are rendered in
+    pub int_radix: IntRadix,
+    /// Parts of type names of which the integers are rendered in hexadecimal when the `int_radix` is decimal.
+    ///
+    /// This is meant for types like flags and masks, which are hard to read in decimal.
+    /// The matching is case insensitive. A match on e.g. a struct or typedef applies to all integers in it.
+    pub hex_type_patterns: Vec<String>,
+    /// How deep objects and arrays are rendered. Deeper ones are rendered as `{ … }` and `[…]`.
+    ///
+    /// The value itself is at depth 0, so with a max depth of 1 the fields of a struct are shown, but not the fields of those fields.
+    /// If None, everything is rendered.
+    pub max_depth: Option<usize>,
+    /// How many elements of an array are rendered. The rest is summarized, like `[1, 2, 3, … (+4093 more)]`.
+    ///
+    /// If None, all elements are rendered. The elements are always all decoded, this only limits the rendering.
+    pub max_array_elements: Option<usize>,
+    /// Render the bytes that values were decoded from after them, like `5 <raw: [05, 00]>`.
+    ///
+    /// The bytes of values that could not be decoded are always rendered.
+    pub show_raw: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            int_radix: IntRadix::Dec,
+            hex_type_patterns: vec!["flag".into(), "mask".into()],
+            max_depth: None,
+            max_array_elements: None,
+            show_raw: false,
+        }
+    }
+}
 
+/// Everything the rendering of a node depends on
+#[derive(Clone, Copy)]
+struct RenderContext<'a> {
+    theme: Theme,
+    options: &'a RenderOptions,
+    /// The radix of the integers of the current node, which can differ from the one in the options
+    int_radix: IntRadix,
+    /// The amount of objects and arrays the current node is in
+    depth: usize,
+}
+
+impl<'a> RenderContext<'a> {
+    /// Returns the context for the fields or elements of the current node, or None if they are too deep to be rendered
+    fn enter(self) -> Option<Self> {
+        match self.options.max_depth {
+            Some(max_depth) if self.depth >= max_depth => None,
+            _ => Some(Self {
+                depth: self.depth + 1,
+                ..self
+            }),
+        }
+    }
+}
+
+/// Renders the type value tree with the [RenderOptions::default]
 pub fn render_type_value_tree<ADDR: funty::Integral>(
     type_value_tree: &TypeValueTree<ADDR>,
     theme: Theme,
 ) -> ColoredString {
-    render_unknown(type_value_tree.root(), theme)
+    render_type_value_tree_with_options(type_value_tree, theme, &RenderOptions::default())
+}
+
+/// Renders the type value tree with the given options
+pub fn render_type_value_tree_with_options<ADDR: funty::Integral>(
+    type_value_tree: &TypeValueTree<ADDR>,
+    theme: Theme,
+    options: &RenderOptions,
+) -> ColoredString {
+    let context = RenderContext {
+        theme,
+        options,
+        int_radix: options.int_radix,
+        depth: 0,
+    };
+
+    render_unknown(type_value_tree.root(), context)
 }
 
 fn render_unknown<ADDR: funty::Integral>(
     type_value_node: &TypeValueNode<ADDR>,
-    theme: Theme,
+    mut context: RenderContext,
 ) -> ColoredString {
+    if context.int_radix == IntRadix::Dec {
+        let type_name = type_value_node.data().variable_type.name.to_lowercase();
+        if context
+            .options
+            .hex_type_patterns
+            .iter()
+            .any(|pattern| type_name.contains(&pattern.to_lowercase()))
+        {
+            context.int_radix = IntRadix::Hex;
+        }
+    }
+
     if let Err(e) = &type_value_node.data().variable_value {
-        return format!("{{{}}}", theme.color_invalid(e.to_string()))
-            .as_str()
-            .into();
+        let error = format!("{{{}}}", context.theme.color_invalid(e.to_string()));
+        return match &type_value_node.data().raw_data {
+            Some(raw_data) => format!("{error} {}", render_raw_data(raw_data, context)),
+            None => error,
+        }
+        .as_str()
+        .into();
     };
 
     let const_string = match type_value_node.data().variable_type.const_type {
@@ -33,71 +148,174 @@ fn render_unknown<ADDR: funty::Integral>(
         false => "",
     };
 
+    let restrict_string = match type_value_node.data().variable_type.restrict {
+        true => "restrict ",
+        false => "",
+    };
+
+    let atomic_string = match type_value_node.data().variable_type.atomic {
+        true => "atomic ",
+        false => "",
+    };
+
     let type_value_string = match type_value_node.data().variable_type.archetype {
-        Archetype::TaggedUnion => render_tagged_union(type_value_node, theme),
+        Archetype::TaggedUnion => render_tagged_union(type_value_node, context),
         Archetype::Structure
         | Archetype::Union
         | Archetype::Class
-        | Archetype::ObjectMemberPointer => render_object(type_value_node, theme),
-        Archetype::BaseType(_) => render_base_type(type_value_node, theme),
-        Archetype::Pointer(_) => render_pointer(type_value_node, theme),
-        Archetype::Array => render_array(type_value_node, theme),
-        Archetype::Typedef => render_typedef(type_value_node, theme),
-        Archetype::Enumeration => render_enumeration(type_value_node, theme),
-        Archetype::Enumerator | Archetype::TaggedUnionVariant => {
+        | Archetype::ObjectMemberPointer => render_object(type_value_node, context),
+        Archetype::BaseType(_) => render_base_type(type_value_node, context),
+        Archetype::Pointer(_) => render_pointer(type_value_node, context),
+        Archetype::Array => render_array(type_value_node, context),
+        Archetype::Typedef => render_typedef(type_value_node, context),
+        Archetype::Enumeration => render_enumeration(type_value_node, context),
+        Archetype::Enumerator | Archetype::TaggedUnionVariant(_) => {
             unreachable!("Should never appear during rendering directly")
         }
         Archetype::Subroutine => "_".into(),
-        Archetype::Unknown => "?".into(),
+        Archetype::Unspecified => context
+            .theme
+            .color_type_name(&type_value_node.data().variable_type.name),
+        Archetype::Unknown => render_unknown_archetype(type_value_node, context),
     };
 
-    format!("{const_string}{volatile_string}{type_value_string}").normal()
+    // The address of a pointer is already shown in hex and its pointee comes after it, so its bytes are left out
+    let raw_string = match &type_value_node.data().raw_data {
+        Some(raw_data)
+            if context.options.show_raw
+                && !matches!(
+                    type_value_node.data().variable_type.archetype,
+                    Archetype::Pointer(_)
+                ) =>
+        {
+            format!(" {}", render_raw_data(raw_data, context))
+        }
+        _ => String::new(),
+    };
+
+    format!("{const_string}{volatile_string}{restrict_string}{atomic_string}{type_value_string}{raw_string}")
+        .normal()
 }
 
 fn render_tagged_union<ADDR: funty::Integral>(
     type_value_node: &TypeValueNode<ADDR>,
-    theme: Theme,
+    context: RenderContext,
 ) -> ColoredString {
-    let discriminant = type_value_node.front().unwrap().data();
-    assert_eq!(&discriminant.name, "discriminant");
-    let discriminant_value = match &discriminant.variable_value {
+    let discriminant = type_value_node.front().unwrap();
+    assert_eq!(&discriminant.data().name, "discriminant");
+    let discriminant_value = match discriminant_value(discriminant) {
         Ok(value) => value,
-        Err(e) => return format!("{{{}}}", theme.color_invalid(e)).as_str().into(),
+        Err(e) => {
+            return format!("{{{}}}", context.theme.color_invalid(e))
+                .as_str()
+                .into()
+        }
     };
 
-    let active_variant = match type_value_node
-        .iter()
-        .skip(1)
-        .find(|variant| variant.data().variable_value.as_ref() == Ok(discriminant_value))
-    {
+    let active_variant = match type_value_node.iter().skip(1).find(|variant| {
+        matches!(
+            &variant.data().variable_type.archetype,
+            Archetype::TaggedUnionVariant(variant_discriminant)
+                if variant_discriminant.matches(discriminant_value)
+        )
+    }) {
         Some(variant) => Some(variant),
         None => {
             // Let's look for the default variant
             type_value_node.iter().skip(1).find(|variant| {
-                variant.data().variable_value == Err(VariableDataError::NoDataAvailable)
+                variant.data().variable_type.archetype
+                    == Archetype::TaggedUnionVariant(VariantDiscriminant::Default)
             })
         }
     };
 
     match active_variant {
-        Some(active_variant) => render_unknown(active_variant.front().unwrap(), theme),
+        Some(active_variant) => {
+            let variant_value = active_variant.front().unwrap();
+
+            match type_value_node.data().variable_type.name.split('<').next() {
+                Some("Option" | "Result") => render_tuple_variant(variant_value, context),
+                _ => render_unknown(variant_value, context),
+            }
+        }
         None => format!(
             "{{{} {}}}",
-            theme.color_invalid("invalid discriminant:"),
-            theme.color_invalid(discriminant_value)
+            context.theme.color_invalid("invalid discriminant:"),
+            context.theme.color_invalid(discriminant_value)
         )
         .as_str()
         .into(),
     }
 }
 
+/// Renders a variant like `Some(5)` or `None` instead of as an object with `__0` fields.
+/// Variants with named fields are rendered as a normal object.
+fn render_tuple_variant<ADDR: funty::Integral>(
+    variant_value: &TypeValueNode<ADDR>,
+    context: RenderContext,
+) -> ColoredString {
+    let is_tuple = variant_value
+        .iter()
+        .enumerate()
+        .all(|(index, field)| field.data().name == format!("__{index}"));
+
+    if !is_tuple {
+        return render_unknown(variant_value, context);
+    }
+
+    let mut output = context
+        .theme
+        .color_enum_member(&variant_value.data().variable_type.name)
+        .to_string();
+
+    if variant_value.iter().next().is_none() {
+        return output.as_str().into();
+    }
+
+    let context = match context.enter() {
+        Some(context) => context,
+        None => {
+            output.push_str("(…)");
+            return output.as_str().into();
+        }
+    };
+
+    output.push('(');
+    output.push_str(
+        &variant_value
+            .iter()
+            .map(|field| render_unknown(field, context).to_string())
+            .collect::<Vec<_>>()
+            .join(", "),
+    );
+    output.push(')');
+
+    output.as_str().into()
+}
+
 fn render_object<ADDR: funty::Integral>(
     type_value_node: &TypeValueNode<ADDR>,
-    theme: Theme,
+    context: RenderContext,
 ) -> ColoredString {
+    // Zero-sized objects don't have any data to show, so we keep them short instead of rendering empty objects
+    if type_value_node.data().bit_length() == 0 {
+        let type_name = &type_value_node.data().variable_type.name;
+
+        if type_name.split('<').next() == Some("PhantomData") {
+            return context.theme.color_type_name(type_name);
+        } else {
+            return context.theme.color_info("<zst>");
+        }
+    }
+
     // Check if the object is a string
     if let Ok(s @ Value::String(_, _)) = type_value_node.data().variable_value.as_ref() {
-        return theme.color_string_value(s);
+        return context.theme.color_string_value(s);
+    }
+
+    // Check if the object has been read as an array (like a vec)
+    if let Ok(Value::Array) = type_value_node.data().variable_value.as_ref() {
+        return render_array(type_value_node, context);
     }
 
     // Check if the object is transparent
@@ -116,15 +334,24 @@ fn render_object<ADDR: funty::Integral>(
 
         for field in type_value_node.iter() {
             if &field.data().name == field_name {
-                return render_unknown(field, theme);
+                return render_unknown(field, context);
             }
         }
     }
 
-    let mut output = theme
+    let mut output = context
+        .theme
         .color_type_name(&type_value_node.data().variable_type.name)
         .to_string();
 
+    let context = match context.enter() {
+        Some(context) => context,
+        None => {
+            output.push_str(" { … }");
+            return output.as_str().into();
+        }
+    };
+
     output.push_str(" { ");
 
     // The fields of the object can be are the children in the tree
@@ -134,8 +361,8 @@ fn render_object<ADDR: funty::Integral>(
             .map(|field| {
                 format!(
                     "{}: {}",
-                    theme.color_variable_name(&field.data().name),
-                    render_unknown(field, theme)
+                    context.theme.color_variable_name(&field.data().name),
+                    render_unknown(field, context)
                 )
             })
             .collect::<Vec<_>>()
@@ -149,14 +376,111 @@ fn render_object<ADDR: funty::Integral>(
 
 fn render_base_type<ADDR: funty::Integral>(
     type_value_node: &TypeValueNode<ADDR>,
-    theme: Theme,
+    context: RenderContext,
+) -> ColoredString {
+    // The C `char` is a character, but `signed char` and `unsigned char` (like `uint8_t`) are mostly used as numbers
+    let is_c_char = type_value_node.data().variable_type.name == "char"
+        && type_value_node.data().bit_length() == 8;
+
+    match type_value_node.data().variable_value.as_ref().unwrap() {
+        Value::Uint(value) if is_c_char => render_c_char(*value as u8, context),
+        Value::Int(value) if is_c_char => render_c_char(*value as u8, context),
+        value @ Value::Float32(float) => render_float(
+            value,
+            float.classify(),
+            float.is_sign_negative(),
+            format!("{:#010x}", float.to_bits()),
+            context,
+        ),
+        value @ Value::Float64(float) => render_float(
+            value,
+            float.classify(),
+            float.is_sign_negative(),
+            format!("{:#018x}", float.to_bits()),
+            context,
+        ),
+        Value::Uint(value) => render_integer(
+            *value,
+            type_value_node.data().bit_length(),
+            value.to_string(),
+            context,
+        ),
+        // A negative value is rendered as its two's complement in the other radixes
+        Value::Int(value) => render_integer(
+            *value as u128,
+            type_value_node.data().bit_length(),
+            value.to_string(),
+            context,
+        ),
+        value => context.theme.color_numeric_value(value),
+    }
+}
+
+/// Renders a C `char` like `'a'`. Bytes that aren't printable ASCII are escaped, like `'\x80'`.
+fn render_c_char(value: u8, context: RenderContext) -> ColoredString {
+    context
+        .theme
+        .color_numeric_value(format!("'{}'", value.escape_ascii()))
+}
+
+/// Renders an integer in the radix of the context.
+/// The amount of digits is based on the bit size of the type, so a `u8` of 15 is rendered as `0x0F`.
+///
+/// - `bits`: The bits of the integer. For a signed integer, it's the two's complement.
+/// - `decimal`: The decimal representation of the integer
+fn render_integer(
+    bits: u128,
+    bit_length: u64,
+    decimal: String,
+    context: RenderContext,
 ) -> ColoredString {
-    theme.color_numeric_value(type_value_node.data().variable_value.as_ref().unwrap())
+    if bit_length == 0 || bit_length > 128 {
+        return context.theme.color_numeric_value(decimal);
+    }
+
+    let bits = bits & (u128::MAX >> (128 - bit_length));
+
+    match context.int_radix {
+        IntRadix::Dec => context.theme.color_numeric_value(decimal),
+        IntRadix::Hex => context.theme.color_numeric_value(format!(
+            "{bits:#0width$X}",
+            width = 2 + div_ceil(bit_length, 4) as usize
+        )),
+        IntRadix::Bin => context
+            .theme
+            .color_numeric_value(format!("{bits:#0width$b}", width = 2 + bit_length as usize)),
+    }
+}
+
+/// Renders a float value. The special values are annotated, because a variable that has silently become
+/// a NaN or infinity is easy to miss when it's rendered as a plain number.
+///
+/// - `bits`: The formatted bits of the float, shown for NaN so its payload can be seen
+fn render_float<ADDR: funty::Integral>(
+    value: &Value<ADDR>,
+    category: FpCategory,
+    is_negative: bool,
+    bits: String,
+    context: RenderContext,
+) -> ColoredString {
+    match category {
+        FpCategory::Nan => context.theme.color_info(format!("NaN({bits})")),
+        FpCategory::Infinite if is_negative => context.theme.color_info("-inf"),
+        FpCategory::Infinite => context.theme.color_info("+inf"),
+        FpCategory::Subnormal => format!(
+            "{} {}",
+            context.theme.color_info("subnormal"),
+            context.theme.color_numeric_value(value)
+        )
+        .as_str()
+        .into(),
+        FpCategory::Zero | FpCategory::Normal => context.theme.color_numeric_value(value),
+    }
 }
 
 fn render_pointer<ADDR: funty::Integral>(
     type_value_node: &TypeValueNode<ADDR>,
-    theme: Theme,
+    context: RenderContext,
 ) -> ColoredString {
     let pointer_address = match type_value_node.data().variable_value.as_ref().unwrap() {
         super::value::Value::Address(addr) => addr,
@@ -164,10 +488,18 @@ fn render_pointer<ADDR: funty::Integral>(
     };
 
     let pointee = type_value_node.front().unwrap();
+
+    // A reference is used like the value it refers to, so it's shown like that as well
+    if type_value_node.data().variable_type.reference {
+        return render_unknown(pointee, context);
+    }
+
     format!(
         "*{} = {}",
-        theme.color_numeric_value(format!("{pointer_address:#X}")),
-        render_unknown(pointee, theme)
+        context
+            .theme
+            .color_numeric_value(format!("{pointer_address:#X}")),
+        render_unknown(pointee, context)
     )
     .as_str()
     .into()
@@ -175,59 +507,94 @@ fn render_pointer<ADDR: funty::Integral>(
 
 fn render_array<ADDR: funty::Integral>(
     type_value_node: &TypeValueNode<ADDR>,
-    theme: Theme,
+    context: RenderContext,
 ) -> ColoredString {
     let mut output = String::new();
 
+    let context = match context.enter() {
+        Some(context) => context,
+        None if type_value_node.has_no_child() => return "[]".into(),
+        None => return "[…]".into(),
+    };
+
     output.push('[');
 
     // The values are the children of the tree
-    output.push_str(
-        &type_value_node
-            .iter()
-            .map(|element| render_unknown(element, theme).to_string())
-            .collect::<Vec<_>>()
-            .join(", "),
-    );
+    let element_count = type_value_node.degree();
+    let rendered_count = context
+        .options
+        .max_array_elements
+        .unwrap_or(usize::MAX)
+        .min(element_count);
+    let mut elements = type_value_node
+        .iter()
+        .take(rendered_count)
+        .map(|element| render_unknown(element, context).to_string())
+        .collect::<Vec<_>>();
+    if rendered_count < element_count {
+        elements.push(format!("… (+{} more)", element_count - rendered_count));
+    }
+    output.push_str(&elements.join(", "));
 
     output.push(']');
 
     output.as_str().into()
 }
 
+fn render_raw_data(raw_data: &[u8], context: RenderContext) -> String {
+    format!(
+        "<raw: {}>",
+        context
+            .theme
+            .color_numeric_value(format!("{raw_data:02X?}"))
+    )
+}
+
+fn render_unknown_archetype<ADDR: funty::Integral>(
+    type_value_node: &TypeValueNode<ADDR>,
+    context: RenderContext,
+) -> ColoredString {
+    match type_value_node.data().variable_value.as_ref() {
+        Ok(Value::Raw(raw_data)) => render_raw_data(raw_data, context).as_str().into(),
+        _ => "?".into(),
+    }
+}
+
 fn render_typedef<ADDR: funty::Integral>(
     type_value_node: &TypeValueNode<ADDR>,
-    theme: Theme,
+    context: RenderContext,
 ) -> ColoredString {
     // When important, the typename has already been printed.
     // We just really only want to see the value, so we act like a transparent type.
 
     render_unknown(
         type_value_node.front().expect("Typedefs have a child"),
-        theme,
+        context,
     )
 }
 
 fn render_enumeration<ADDR: funty::Integral>(
     type_value_node: &TypeValueNode<ADDR>,
-    theme: Theme,
+    context: RenderContext,
 ) -> ColoredString {
     let base_value = match &type_value_node.front().unwrap().data().variable_value {
         Ok(base_value) => base_value,
         Err(e) => {
-            return format!("{{{}}}", theme.color_invalid(e)).as_str().into();
+            return format!("{{{}}}", context.theme.color_invalid(e))
+                .as_str()
+                .into();
         }
     };
 
     for enumerator in type_value_node.iter().skip(1) {
         if let Ok(enumerator_value) = enumerator.data().variable_value.as_ref() {
             if enumerator_value == base_value {
-                return theme.color_enum_member(&enumerator.data().name);
+                return context.theme.color_enum_member(&enumerator.data().name);
             }
         }
     }
 
-    theme.color_numeric_value(base_value)
+    context.theme.color_numeric_value(base_value)
 }
 
 /// List with the known transparent types (or types that are effectively transparent)
@@ -251,4 +618,59 @@ static TRANSPARENT_TYPES: phf::Map<&'static str, &'static str> = phf_map! {
     "AtomicU32" => "v",
     "AtomicU64" => "v",
     "AtomicUsize" => "v",
+    "NonNull" => "pointer",
+    "NonZero" => "__0",
+    "NonZeroU8" => "__0",
+    "NonZeroU16" => "__0",
+    "NonZeroU32" => "__0",
+    "NonZeroU64" => "__0",
+    "NonZeroU128" => "__0",
+    "NonZeroUsize" => "__0",
+    "NonZeroI8" => "__0",
+    "NonZeroI16" => "__0",
+    "NonZeroI32" => "__0",
+    "NonZeroI64" => "__0",
+    "NonZeroI128" => "__0",
+    "NonZeroIsize" => "__0",
+    // Newer versions of Rust wrap the integer of `NonZero` in one of these
+    "NonZeroU8Inner" => "__0",
+    "NonZeroU16Inner" => "__0",
+    "NonZeroU32Inner" => "__0",
+    "NonZeroU64Inner" => "__0",
+    "NonZeroU128Inner" => "__0",
+    "NonZeroUsizeInner" => "__0",
+    "NonZeroI8Inner" => "__0",
+    "NonZeroI16Inner" => "__0",
+    "NonZeroI32Inner" => "__0",
+    "NonZeroI64Inner" => "__0",
+    "NonZeroI128Inner" => "__0",
+    "NonZeroIsizeInner" => "__0",
 };
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::type_value_tree::{TypeValue, VariableDataError};
+
+    #[test]
+    fn unknown_archetype() {
+        // A value of a type that isn't decoded, but of which the bytes were read
+        let mut type_value = TypeValue::<u32> {
+            bit_range: 0..24,
+            variable_value: Ok(Value::Raw(vec![0x01, 0xAB, 0xFF])),
+            ..Default::default()
+        };
+        assert_eq!(
+            render_type_value_tree(&TypeValueTree::new(type_value.clone()), Theme::None)
+                .to_string(),
+            "<raw: [01, AB, FF]>"
+        );
+
+        // Without the bytes only the error is shown
+        type_value.variable_value = Err(VariableDataError::Unknown);
+        assert_eq!(
+            render_type_value_tree(&TypeValueTree::new(type_value), Theme::None).to_string(),
+            "{Unknown}"
+        );
+    }
+}
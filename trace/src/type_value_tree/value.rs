@@ -1,4 +1,5 @@
-use std::fmt::Display;
+use serde::{Serialize, Serializer};
+use std::fmt::{Display, LowerExp};
 
 #[derive(Debug, Clone)]
 pub enum Value<ADDR: funty::Integral> {
@@ -8,9 +9,13 @@ pub enum Value<ADDR: funty::Integral> {
     Char(char),
     Int(i128),
     Uint(u128),
-    Float(f64),
+    /// A single precision float. It's kept as an `f32` so the exact bits (e.g. of a NaN) are preserved.
+    Float32(f32),
+    Float64(f64),
     Address(ADDR),
     String(Vec<u8>, StringFormat),
+    /// The raw bytes of a value of which the type could not be decoded
+    Raw(Vec<u8>),
     Array,
     Enumeration,
     Typedef,
@@ -22,12 +27,11 @@ impl<ADDR: funty::Integral> Display for Value<ADDR> {
             Value::Unit => write!(f, "()"),
             Value::Object | Value::Enumeration | Value::Typedef => write!(f, "{{}}"),
             Value::Bool(v) => write!(f, "{v}"),
-            Value::Char(v) => write!(f, "{v}"),
+            Value::Char(v) => write!(f, "{v:?}"),
             Value::Int(v) => write!(f, "{v}"),
             Value::Uint(v) => write!(f, "{v}"),
-            Value::Float(v) if *v > 1000000000.0 => write!(f, "{v:e}"),
-            Value::Float(v) if *v < 1.0 / 1000000000.0 => write!(f, "{v:e}"),
-            Value::Float(v) => write!(f, "{v}"),
+            Value::Float32(v) => write_float(f, *v, *v as f64),
+            Value::Float64(v) => write_float(f, *v, *v),
             Value::Address(v) => write!(f, "{v:#X}"),
             Value::String(bytes, StringFormat::Ascii | StringFormat::Utf8) => {
                 write!(
@@ -42,11 +46,33 @@ impl<ADDR: funty::Integral> Display for Value<ADDR> {
                         ))
                 )
             }
+            Value::Raw(bytes) => write!(f, "{bytes:02X?}"),
             Value::Array => write!(f, "[]"),
         }
     }
 }
 
+/// Values are serialized as the plain value they hold.
+/// The ones that only get their meaning from the children in the tree, like objects and arrays, are serialized as none.
+impl<ADDR: funty::Integral> Serialize for Value<ADDR> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Unit | Value::Object | Value::Array | Value::Enumeration | Value::Typedef => {
+                serializer.serialize_none()
+            }
+            Value::Bool(v) => serializer.serialize_bool(*v),
+            Value::Char(v) => serializer.serialize_char(*v),
+            Value::Int(v) => serializer.serialize_i128(*v),
+            Value::Uint(v) => serializer.serialize_u128(*v),
+            Value::Float32(v) => serializer.serialize_f32(*v),
+            Value::Float64(v) => serializer.serialize_f64(*v),
+            Value::Address(v) => serializer.serialize_u64(v.as_u64()),
+            Value::String(bytes, _) => serializer.serialize_str(&String::from_utf8_lossy(bytes)),
+            Value::Raw(bytes) => bytes.serialize(serializer),
+        }
+    }
+}
+
 impl<ADDR: funty::Integral> PartialEq for Value<ADDR> {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
@@ -56,14 +82,29 @@ impl<ADDR: funty::Integral> PartialEq for Value<ADDR> {
             (Self::Int(l0), Self::Uint(r0)) if *l0 >= 0 => *l0 as u128 == *r0,
             (Self::Uint(l0), Self::Uint(r0)) => l0 == r0,
             (Self::Uint(l0), Self::Int(r0)) if *r0 >= 0 => *r0 as u128 == *l0,
-            (Self::Float(l0), Self::Float(r0)) => l0 == r0,
+            (Self::Float32(l0), Self::Float32(r0)) => l0 == r0,
+            (Self::Float64(l0), Self::Float64(r0)) => l0 == r0,
             (Self::Address(l0), Self::Address(r0)) => l0 == r0,
             (Self::String(l0, l1), Self::String(r0, r1)) => l0 == r0 && l1 == r1,
+            (Self::Raw(l0), Self::Raw(r0)) => l0 == r0,
             _ => core::mem::discriminant(self) == core::mem::discriminant(other),
         }
     }
 }
 
+/// Writes a float, using the scientific notation for very big and very small numbers
+fn write_float<F: Display + LowerExp>(
+    f: &mut std::fmt::Formatter<'_>,
+    value: F,
+    value_f64: f64,
+) -> std::fmt::Result {
+    if !(1.0 / 1000000000.0..=1000000000.0).contains(&value_f64) {
+        write!(f, "{value:e}")
+    } else {
+        write!(f, "{value}")
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum StringFormat {
     Ascii,
@@ -1,4 +1,7 @@
+use super::value::Value;
+use crate::DefaultReader;
 use gimli::{DebugInfoOffset, DwAte};
+use std::ops::RangeInclusive;
 
 #[derive(Debug, Clone, Default)]
 pub struct VariableType {
@@ -8,9 +11,48 @@ pub struct VariableType {
     pub volatile: bool,
     /// Is the type const? (This is a C-ism)
     pub const_type: bool,
+    /// Is the pointer restrict? (This is a C-ism)
+    pub restrict: bool,
+    /// Is the type atomic? (This is a C-ism too, `_Atomic`)
+    pub atomic: bool,
+    /// Is the pointer a reference? (This is a C++-ism)
+    /// A reference is shown as the value it refers to instead of as an address.
+    pub reference: bool,
+    /// If set, the data of the object is not stored in the object itself, but at the location this evaluates to.
+    /// (This is used for e.g. allocatable arrays in Fortran and Ada)
+    pub data_location: Option<DataLocation>,
+    /// How the elements of an array are laid out. Only set for arrays.
+    pub array_layout: Option<ArrayLayout>,
 }
 
+/// The layout of the elements of an array
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArrayLayout {
+    /// The index of the first element
+    pub lower_bound: i64,
+    /// The amount of elements
+    pub count: u64,
+    /// The elements are `stride` bits apart, which can be more than the size of an element
+    pub stride: u64,
+    /// If true, the elements have not been created yet and the only child in the tree is the type of the elements.
+    /// That child is replaced with all of the elements when the data is read.
+    ///
+    /// See [crate::DecodeOptions::lazy_array_threshold].
+    pub lazy: bool,
+}
+
+/// The `DW_AT_data_location` of a type.
+///
+/// This is an expression that takes the address of the object (which is something like a descriptor)
+/// and calculates the location of the actual data.
 #[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataLocation {
+    pub expression: gimli::Expression<DefaultReader>,
+    pub encoding: gimli::Encoding,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, strum_macros::IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
 pub enum Archetype {
     Structure,
     Union,
@@ -25,17 +67,61 @@ pub enum Archetype {
     /// The type is not directly encoded in the tree because linked lists exists.
     /// We need to catch that to avoid recursions of linked lists.
     Pointer(DebugInfoOffset),
+    /// An array. The layout of its elements is in [VariableType::array_layout].
     Array,
     TaggedUnion,
-    TaggedUnionVariant,
+    /// A variant of a tagged union that is active when the discriminant has one of the given values
+    TaggedUnionVariant(VariantDiscriminant),
     Enumeration,
     Enumerator,
     Subroutine,
+    /// A type without any data of which we only know the name.
+    /// For example the never type `!` in Rust or `nullptr_t` in C++.
+    Unspecified,
+    #[default]
     Unknown,
 }
 
-impl Default for Archetype {
-    fn default() -> Self {
-        Self::Unknown
+/// The values of the discriminant of a tagged union for which a variant is active
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum VariantDiscriminant {
+    /// The variant is active when none of the other variants are
+    #[default]
+    Default,
+    /// The variant is active when the discriminant is in one of the ranges.
+    ///
+    /// A `DW_AT_discr_value` is a range of one value.
+    /// A `DW_AT_discr_list` can have multiple values and ranges, which Rust uses for niches.
+    Values(Vec<RangeInclusive<i128>>),
+}
+
+impl VariantDiscriminant {
+    /// Returns true if the variant is selected by the given discriminant value.
+    /// The default variant doesn't match any value.
+    ///
+    /// A range with a start above its end wraps around the discriminant type,
+    /// so it matches everything from the start up and everything up to the end.
+    pub fn matches<ADDR: funty::Integral>(&self, discriminant_value: &Value<ADDR>) -> bool {
+        let discriminant_value = match discriminant_value {
+            Value::Int(value) => *value,
+            Value::Uint(value) => match i128::try_from(*value) {
+                Ok(value) => value,
+                Err(_) => return false,
+            },
+            Value::Bool(value) => *value as i128,
+            Value::Char(value) => *value as i128,
+            _ => return false,
+        };
+
+        match self {
+            VariantDiscriminant::Default => false,
+            VariantDiscriminant::Values(ranges) => ranges.iter().any(|range| {
+                if range.start() <= range.end() {
+                    range.contains(&discriminant_value)
+                } else {
+                    discriminant_value >= *range.start() || discriminant_value <= *range.end()
+                }
+            }),
+        }
     }
 }
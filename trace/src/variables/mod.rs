@@ -11,23 +11,30 @@ use crate::{
     get_entry_type_reference_tree_recursive,
     gimli_extensions::{AttributeExt, DebuggingInformationEntryExt},
     type_value_tree::{
+        discriminant_value,
         value::{StringFormat, Value},
-        variable_type::{Archetype, VariableType},
+        variable_type::{Archetype, ArrayLayout, DataLocation, VariableType, VariantDiscriminant},
         TypeValue, TypeValueNode, TypeValueTree, VariableDataError,
     },
-    DefaultReader, Location, Variable, VariableKind, VariableLocationResult,
+    DecodeBudget, DecodeOptions, DefaultReader, DemangleMode, Location, OmittedVariable, Variable,
+    VariableKind, VariableLocationResult,
 };
 use bitvec::prelude::*;
 use gimli::{
     Abbreviations, Attribute, AttributeValue, DebugInfoOffset, DebuggingInformationEntry, Dwarf,
     EntriesTree, Evaluation, EvaluationResult, Piece, Reader, Unit, UnitHeader, UnitOffset,
 };
-use stackdump_core::device_memory::DeviceMemory;
-use std::{collections::HashMap, pin::Pin};
+use stackdump_core::{
+    device_memory::{DeviceMemory, EvaluationFrame, EvaluationStep},
+    memory_region::MemoryRegionKind,
+};
+use std::{collections::HashMap, ops::Range, pin::Pin, rc::Rc};
 
+#[cfg(test)]
+mod tests;
 mod type_value_tree_building;
 
-fn div_ceil(lhs: u64, rhs: u64) -> u64 {
+pub(crate) fn div_ceil(lhs: u64, rhs: u64) -> u64 {
     let d = lhs / rhs;
     let r = lhs % rhs;
     if r > 0 && rhs > 0 {
@@ -36,6 +43,123 @@ fn div_ceil(lhs: u64, rhs: u64) -> u64 {
         d
     }
 }
+
+/// The type value trees that have been built, by the offset of their type entry.
+///
+/// The trees are shared, so looking one up doesn't copy it. It's only copied when a variable gets its own tree to fill in.
+pub(crate) type TypeCache<W> = HashMap<DebugInfoOffset, Result<Rc<TypeValueTree<W>>, TraceError>>;
+
+/// The names of the members that are used for the length of a flexible array member if there's no hint for the struct
+const DEFAULT_FLEXIBLE_ARRAY_LENGTHS: [&str; 3] = ["len", "length", "count"];
+
+/// The [DecodeOptions] and how much of the [DecodeBudget] has been used up
+#[derive(Debug)]
+pub struct DecodeState {
+    budget: DecodeBudget,
+    flexible_array_lengths: HashMap<String, String>,
+    skip_variables: bool,
+    demangle_mode: DemangleMode,
+    lazy_array_threshold: Option<u64>,
+    bytes_read: u64,
+    nodes: u64,
+    pointer_depth: u32,
+    frame: Option<EvaluationFrame>,
+}
+
+impl DecodeState {
+    pub fn new(options: DecodeOptions) -> Self {
+        Self {
+            budget: options.budget,
+            flexible_array_lengths: options.flexible_array_lengths,
+            skip_variables: options.skip_variables,
+            demangle_mode: options.demangle_mode,
+            lazy_array_threshold: options.lazy_array_threshold,
+            bytes_read: 0,
+            nodes: 0,
+            pointer_depth: 0,
+            frame: None,
+        }
+    }
+
+    /// Sets the frame of which the variables are decoded, or None for the static variables.
+    ///
+    /// This is given to the evaluation step resolver of the device memory.
+    pub fn set_frame(&mut self, frame: Option<EvaluationFrame>) {
+        self.frame = frame;
+    }
+
+    /// Returns true if the variables should not be decoded at all
+    pub fn skip_variables(&self) -> bool {
+        self.skip_variables
+    }
+
+    pub fn demangle_mode(&self) -> DemangleMode {
+        self.demangle_mode
+    }
+
+    /// Returns true if an array with `count` elements is expanded lazily, see [DecodeOptions::lazy_array_threshold]
+    fn is_lazy_array(&self, count: u64) -> bool {
+        self.lazy_array_threshold
+            .is_some_and(|threshold| count > threshold)
+    }
+
+    /// Returns true if the member with the given name can have the length of the flexible array member of the struct type
+    fn is_flexible_array_length(&self, struct_type_name: &str, member_name: &str) -> bool {
+        match self.flexible_array_lengths.get(struct_type_name) {
+            Some(length_member_name) => length_member_name == member_name,
+            None => DEFAULT_FLEXIBLE_ARRAY_LENGTHS.contains(&member_name),
+        }
+    }
+
+    /// Registers that `bytes` bytes are going to be read.
+    /// If that doesn't fit in the budget anymore, nothing is registered and an error is returned.
+    fn read_bytes(&mut self, bytes: u64) -> Result<(), VariableDataError> {
+        match self.bytes_read.checked_add(bytes) {
+            Some(bytes_read) if bytes_read <= self.budget.max_bytes_read => {
+                self.bytes_read = bytes_read;
+                Ok(())
+            }
+            _ => Err(VariableDataError::BudgetExceeded("max_bytes_read")),
+        }
+    }
+
+    /// Checks if `bytes` more bytes fit in the budget without registering them
+    fn check_bytes(&self, bytes: u64) -> Result<(), VariableDataError> {
+        match self.bytes_read.checked_add(bytes) {
+            Some(bytes_read) if bytes_read <= self.budget.max_bytes_read => Ok(()),
+            _ => Err(VariableDataError::BudgetExceeded("max_bytes_read")),
+        }
+    }
+
+    /// Checks if `nodes` more nodes fit in the budget without registering them
+    fn check_nodes(&self, nodes: u64) -> Result<(), VariableDataError> {
+        match self.nodes.checked_add(nodes) {
+            Some(total_nodes) if total_nodes <= self.budget.max_nodes => Ok(()),
+            _ => Err(VariableDataError::BudgetExceeded("max_nodes")),
+        }
+    }
+
+    /// Registers a node that is going to be decoded
+    fn decode_node(&mut self) -> Result<(), VariableDataError> {
+        self.check_nodes(1)?;
+        self.nodes += 1;
+        Ok(())
+    }
+
+    /// Registers that a pointer is followed. Must be paired with a call to [Self::leave_pointer].
+    fn enter_pointer(&mut self) -> Result<(), VariableDataError> {
+        if self.pointer_depth >= self.budget.max_pointer_depth {
+            return Err(VariableDataError::BudgetExceeded("max_pointer_depth"));
+        }
+        self.pointer_depth += 1;
+        Ok(())
+    }
+
+    fn leave_pointer(&mut self) {
+        self.pointer_depth -= 1;
+    }
+}
+
 /// Gets the string value from the `DW_AT_name` attribute of the given entry
 fn get_entry_name(
     dwarf: &Dwarf<DefaultReader>,
@@ -44,6 +168,8 @@ fn get_entry_name(
 ) -> Result<String, TraceError> {
     if entry.tag() == gimli::constants::DW_TAG_volatile_type
         || entry.tag() == gimli::constants::DW_TAG_const_type
+        || entry.tag() == gimli::constants::DW_TAG_restrict_type
+        || entry.tag() == gimli::constants::DW_TAG_atomic_type
     {
         // These tags don't have a name of their own,
         // so we must follow the the DW_AT_type attribute that points to another entry
@@ -51,10 +177,12 @@ fn get_entry_name(
         let abbreviations = dwarf.abbreviations(&unit.header)?;
 
         get_entry_type_reference_tree_recursive!(
-            variable_type_value_tree = (dwarf, unit, &abbreviations, entry)
+            variable_type_value_tree,
+            type_unit,
+            type_abbreviations = (dwarf, unit, &abbreviations, entry)
         );
 
-        get_entry_name(dwarf, unit, variable_type_value_tree?.root()?.entry())
+        get_entry_name(dwarf, type_unit, variable_type_value_tree?.root()?.entry())
     } else {
         // Find the attribute
         let name_attr = entry.required_attr(&unit.header, gimli::constants::DW_AT_name);
@@ -68,11 +196,11 @@ fn get_entry_name(
             }
             Err(_) if entry.tag() == gimli::constants::DW_TAG_array_type => {
                 // Arrays can be anonymous
-                return Ok("array".into());
+                Ok("array".into())
             }
             Err(_) if entry.tag() == gimli::constants::DW_TAG_subroutine_type => {
                 // Subroutines can be anonymous
-                return Ok("subroutine".into());
+                Ok("subroutine".into())
             }
             Err(e) => Err(e),
         }
@@ -191,7 +319,7 @@ pub(crate) enum GetEntryTreeError {
 }
 
 impl GetEntryTreeError {
-    fn as_trace_error(self) -> TraceError {
+    fn into_trace_error(self) -> TraceError {
         match self {
             Self::TraceError(e) => e,
             Self::WrongUnit(_) => TraceError::UnitNotFoundAgain,
@@ -199,55 +327,64 @@ impl GetEntryTreeError {
     }
 }
 
+/// Gets an entry tree through one of the `get_entry_*_reference_tree` functions.
+///
+/// The referenced entry may live in another compilation unit than the entry that references it.
+/// In that case the target unit is loaded and the given unit and abbreviations names are rebound to it,
+/// so that the entries of the tree are always read with the unit they belong to.
 #[macro_export]
-macro_rules! get_entry_abstract_origin_reference_tree_recursive {
-    ($tree_name:ident = ($dwarf:expr, $unit:expr, $abbreviations:expr, $entry:expr)) => {
-        let mut __unit_header = $unit.header.clone();
+macro_rules! get_entry_reference_tree_recursive {
+    ($get_tree:path, $tree_name:ident, $unit_name:ident, $abbreviations_name:ident = ($dwarf:expr, $unit:expr, $abbreviations:expr, $entry:expr)) => {
+        let __target_unit: gimli::Unit<$crate::DefaultReader, usize>;
+        #[allow(unused_mut, unused_variables)]
+        let mut $unit_name: &gimli::Unit<$crate::DefaultReader, usize> = $unit;
+        #[allow(unused_mut, unused_variables)]
+        let mut $abbreviations_name: &gimli::Abbreviations = $abbreviations;
         #[allow(unused_mut)]
-        let mut $tree_name = match $crate::variables::get_entry_abstract_origin_reference_tree(
-            $dwarf,
-            &__unit_header,
-            $abbreviations,
-            $entry,
-        ) {
-            Err(crate::variables::GetEntryTreeError::WrongUnit(target_unit)) => {
-                __unit_header = target_unit;
-                crate::variables::get_entry_abstract_origin_reference_tree(
-                    $dwarf,
-                    &__unit_header,
-                    $abbreviations,
-                    $entry,
-                )
+        let mut $tree_name =
+            match $get_tree($dwarf, &$unit_name.header, $abbreviations_name, $entry) {
+                Err($crate::variables::GetEntryTreeError::WrongUnit(target_unit_header)) => {
+                    match gimli::Unit::new($dwarf, target_unit_header) {
+                        Ok(target_unit) => {
+                            __target_unit = target_unit;
+                            $unit_name = &__target_unit;
+                            $abbreviations_name = &__target_unit.abbreviations;
+                            $get_tree($dwarf, &$unit_name.header, $abbreviations_name, $entry)
+                        }
+                        Err(e) => Err($crate::variables::GetEntryTreeError::TraceError(e.into())),
+                    }
+                }
+                value => value,
             }
-            value => value,
-        }
-        .map_err(|e| e.as_trace_error());
+            .map_err(|e| e.into_trace_error());
+    };
+}
+
+/// Gets the entry tree of the `DW_AT_abstract_origin` attribute of the given entry.
+/// The unit and abbreviations that must be used to read the tree are bound to the given names.
+#[macro_export]
+macro_rules! get_entry_abstract_origin_reference_tree_recursive {
+    ($tree_name:ident, $unit_name:ident, $abbreviations_name:ident = ($dwarf:expr, $unit:expr, $abbreviations:expr, $entry:expr)) => {
+        $crate::get_entry_reference_tree_recursive!(
+            $crate::variables::get_entry_abstract_origin_reference_tree,
+            $tree_name,
+            $unit_name,
+            $abbreviations_name = ($dwarf, $unit, $abbreviations, $entry)
+        );
     };
 }
 
+/// Gets the entry tree of the `DW_AT_type` attribute of the given entry.
+/// The unit and abbreviations that must be used to read the tree are bound to the given names.
 #[macro_export]
 macro_rules! get_entry_type_reference_tree_recursive {
-    ($tree_name:ident = ($dwarf:expr, $unit:expr, $abbreviations:expr, $entry:expr)) => {
-        let mut __unit_header = $unit.header.clone();
-        #[allow(unused_mut)]
-        let mut $tree_name = match $crate::variables::get_entry_type_reference_tree(
-            $dwarf,
-            &__unit_header,
-            $abbreviations,
-            $entry,
-        ) {
-            Err(crate::variables::GetEntryTreeError::WrongUnit(target_unit)) => {
-                __unit_header = target_unit;
-                crate::variables::get_entry_type_reference_tree(
-                    $dwarf,
-                    &__unit_header,
-                    $abbreviations,
-                    $entry,
-                )
-            }
-            value => value,
-        }
-        .map_err(|e| e.as_trace_error());
+    ($tree_name:ident, $unit_name:ident, $abbreviations_name:ident = ($dwarf:expr, $unit:expr, $abbreviations:expr, $entry:expr)) => {
+        $crate::get_entry_reference_tree_recursive!(
+            $crate::variables::get_entry_type_reference_tree,
+            $tree_name,
+            $unit_name,
+            $abbreviations_name = ($dwarf, $unit, $abbreviations, $entry)
+        );
     };
 }
 
@@ -256,6 +393,7 @@ fn try_read_frame_base<W: funty::Integral>(
     unit: &Unit<DefaultReader, usize>,
     device_memory: &DeviceMemory<W>,
     entry: &DebuggingInformationEntry<DefaultReader, usize>,
+    frame: Option<EvaluationFrame>,
 ) -> Result<Option<W>, TraceError>
 where
     <W as funty::Numeric>::Bytes: bitvec::view::BitView<Store = u8>,
@@ -266,11 +404,13 @@ where
         device_memory,
         entry.attr(gimli::constants::DW_AT_frame_base)?,
         None,
+        frame,
     )?;
     let frame_base_data = get_variable_data(
         device_memory,
         core::mem::size_of::<W>() as u64 * 8,
         frame_base_location,
+        None,
     );
 
     Ok(frame_base_data.ok().map(|data| data.load_le()))
@@ -375,13 +515,53 @@ fn read_data_member_location(
     unit_header: &UnitHeader<DefaultReader, usize>,
     entry: &DebuggingInformationEntry<DefaultReader, usize>,
 ) -> Result<u64, TraceError> {
+    // Bitfield members (since DWARF 4) give the offset in bits from the start of the object
+    if let Some(data_bit_offset) = entry.attr(gimli::constants::DW_AT_data_bit_offset)? {
+        return data_bit_offset.required_udata_value();
+    }
+
     // TODO: Sometimes this is not a simple number, but a location expression.
-    // As of writing this has not come up, but I can imagine this is the case for C bitfields.
-    // It is the offset in bits from the base.
+    // As of writing this has not come up.
+    // A member at the start of the object doesn't need a location, like the members of a union.
+    let byte_offset = match entry.attr(gimli::constants::DW_AT_data_member_location)? {
+        Some(location) => location.required_udata_value()?,
+        None => 0,
+    };
+
+    // Bitfield members of DWARF 2 and 3 are in a storage unit of `DW_AT_byte_size` bytes at the member location.
+    // The `DW_AT_bit_offset` is the offset from the most significant bit of that unit to the most significant bit
+    // of the member, so on a little endian target it's counted from the other end.
+    if let Some(bit_offset) = entry.attr(gimli::constants::DW_AT_bit_offset)? {
+        let storage_bits = entry
+            .required_attr(unit_header, gimli::constants::DW_AT_byte_size)?
+            .required_udata_value()?
+            * 8;
+        let bit_size = entry
+            .required_attr(unit_header, gimli::constants::DW_AT_bit_size)?
+            .required_udata_value()?;
+
+        return Ok(byte_offset * 8
+            + storage_bits
+                .saturating_sub(bit_offset.required_udata_value()?)
+                .saturating_sub(bit_size));
+    }
+
+    Ok(byte_offset * 8)
+}
+
+/// Reads the `DW_AT_data_location` of a type entry, if it has one
+fn read_data_location(
+    unit: &Unit<DefaultReader, usize>,
+    entry: &DebuggingInformationEntry<DefaultReader, usize>,
+) -> Result<Option<DataLocation>, TraceError> {
     Ok(entry
-        .required_attr(unit_header, gimli::constants::DW_AT_data_member_location)?
-        .required_udata_value()?
-        * 8)
+        .attr(gimli::constants::DW_AT_data_location)?
+        .map(|data_location| data_location.required_exprloc_value())
+        .transpose()?
+        .map(|expression| DataLocation {
+            expression,
+            encoding: unit.encoding(),
+        }))
 }
 
 /// Decodes the type of an entry into a type value tree, however, the value is not yet filled in.
@@ -393,7 +573,8 @@ fn build_type_value_tree<W: funty::Integral>(
     unit: &Unit<DefaultReader, usize>,
     abbreviations: &Abbreviations,
     node: gimli::EntriesTreeNode<DefaultReader>,
-    type_cache: &mut HashMap<DebugInfoOffset, Result<TypeValueTree<W>, TraceError>>,
+    type_cache: &mut TypeCache<W>,
+    state: &DecodeState,
 ) -> Result<TypeValueTree<W>, TraceError> {
     // Get the root entry and its tag
     let entry = node.entry();
@@ -407,7 +588,10 @@ fn build_type_value_tree<W: funty::Integral>(
             entry.tag()
         );
 
-        return (*existing_type).clone();
+        return match existing_type {
+            Ok(existing_type) => Ok(TypeValueTree::clone(existing_type)),
+            Err(e) => Err(e.clone()),
+        };
     }
 
     log::trace!(
@@ -425,6 +609,7 @@ fn build_type_value_tree<W: funty::Integral>(
             abbreviations,
             node,
             type_cache,
+            state,
         ),
         tag @ gimli::constants::DW_TAG_structure_type
         | tag @ gimli::constants::DW_TAG_union_type
@@ -434,26 +619,61 @@ fn build_type_value_tree<W: funty::Integral>(
             abbreviations,
             node,
             type_cache,
+            state,
             tag,
         ),
         gimli::constants::DW_TAG_base_type => {
             type_value_tree_building::build_base_type(dwarf, unit, node)
         }
-        gimli::constants::DW_TAG_pointer_type => {
-            type_value_tree_building::build_pointer(dwarf, unit, abbreviations, node, type_cache)
-        }
-        gimli::constants::DW_TAG_array_type => {
-            type_value_tree_building::build_array(dwarf, unit, abbreviations, node, type_cache)
-        }
-        gimli::constants::DW_TAG_typedef => {
-            type_value_tree_building::build_typedef(dwarf, unit, abbreviations, node, type_cache)
+        gimli::constants::DW_TAG_pointer_type => type_value_tree_building::build_pointer(
+            dwarf,
+            unit,
+            abbreviations,
+            node,
+            type_cache,
+            state,
+        ),
+        gimli::constants::DW_TAG_reference_type => type_value_tree_building::build_reference(
+            dwarf,
+            unit,
+            abbreviations,
+            node,
+            type_cache,
+            state,
+        ),
+        gimli::constants::DW_TAG_rvalue_reference_type => {
+            type_value_tree_building::build_rvalue_reference(
+                dwarf,
+                unit,
+                abbreviations,
+                node,
+                type_cache,
+                state,
+            )
         }
+        gimli::constants::DW_TAG_array_type => type_value_tree_building::build_array(
+            dwarf,
+            unit,
+            abbreviations,
+            node,
+            type_cache,
+            state,
+        ),
+        gimli::constants::DW_TAG_typedef => type_value_tree_building::build_typedef(
+            dwarf,
+            unit,
+            abbreviations,
+            node,
+            type_cache,
+            state,
+        ),
         gimli::constants::DW_TAG_enumeration_type => type_value_tree_building::build_enumeration(
             dwarf,
             unit,
             abbreviations,
             node,
             type_cache,
+            state,
         ),
         gimli::constants::DW_TAG_subroutine_type => {
             let mut type_value_tree = TypeValueTree::new(TypeValue::default());
@@ -468,57 +688,217 @@ fn build_type_value_tree<W: funty::Integral>(
             abbreviations,
             node,
             type_cache,
+            state,
+        ),
+        gimli::constants::DW_TAG_const_type => type_value_tree_building::build_const_type(
+            dwarf,
+            unit,
+            abbreviations,
+            node,
+            type_cache,
+            state,
         ),
-        gimli::constants::DW_TAG_const_type => {
-            type_value_tree_building::build_const_type(dwarf, unit, abbreviations, node, type_cache)
+        gimli::constants::DW_TAG_restrict_type => type_value_tree_building::build_restrict_type(
+            dwarf,
+            unit,
+            abbreviations,
+            node,
+            type_cache,
+            state,
+        ),
+        gimli::constants::DW_TAG_atomic_type => type_value_tree_building::build_atomic_type(
+            dwarf,
+            unit,
+            abbreviations,
+            node,
+            type_cache,
+            state,
+        ),
+        gimli::constants::DW_TAG_unspecified_type => {
+            type_value_tree_building::build_unspecified_type(dwarf, unit, node)
         }
         tag => Err(TraceError::TagNotImplemented {
             tag_name: tag.to_string(),
-            entry_debug_info_offset: entry.offset().to_debug_info_offset(&unit.header).unwrap().0,
+            entry_debug_info_offset: entry_die_offset.0,
         }),
     };
 
-    type_cache
-        .entry(entry_die_offset)
-        .or_insert_with(|| result.clone());
-
-    result
+    match result {
+        Ok(type_value_tree) => {
+            // A pointer type is already in the cache, so then the tree doesn't have to be copied
+            let type_value_tree = Rc::new(type_value_tree);
+            type_cache
+                .entry(entry_die_offset)
+                .or_insert_with(|| Ok(type_value_tree.clone()));
+            Ok(Rc::unwrap_or_clone(type_value_tree))
+        }
+        Err(e) => {
+            type_cache
+                .entry(entry_die_offset)
+                .or_insert_with(|| Err(e.clone()));
+            Err(e)
+        }
+    }
 }
 
 /// Runs the location evaluation of gimli.
 ///
 /// - `location`: The `DW_AT_location` attribute value of the entry of the variable we want to get the location of.
-/// This may be a None if the variable has no location attribute.
+///   This may be a None if the variable has no location attribute.
+///
+/// Implicit pointers are resolved to the address of the object they point at, if that is in memory.
 fn evaluate_location<W: funty::Integral>(
     dwarf: &Dwarf<DefaultReader>,
     unit: &Unit<DefaultReader, usize>,
     device_memory: &DeviceMemory<W>,
     location: Option<Attribute<DefaultReader>>,
     frame_base: Option<W>,
+    frame: Option<EvaluationFrame>,
+) -> Result<VariableLocationResult, TraceError>
+where
+    <W as funty::Numeric>::Bytes: bitvec::view::BitView<Store = u8>,
+{
+    let mut pieces = match evaluate_unresolved_location(
+        dwarf,
+        unit,
+        device_memory,
+        location,
+        frame_base,
+        frame,
+    )? {
+        VariableLocationResult::LocationsFound(pieces) => pieces,
+        result => return Ok(result),
+    };
+
+    for piece in pieces.iter_mut() {
+        if let gimli::Location::ImplicitPointer { value, byte_offset } = piece.location {
+            if let Some(address) = resolve_implicit_pointer(
+                dwarf,
+                unit,
+                device_memory,
+                frame_base,
+                frame,
+                value,
+                byte_offset,
+            )? {
+                piece.location = gimli::Location::Value {
+                    value: gimli::Value::Generic(address),
+                };
+            }
+        }
+    }
+
+    Ok(VariableLocationResult::LocationsFound(pieces))
+}
+
+/// Finds the address an implicit pointer points at.
+///
+/// An implicit pointer has no value of its own. It points at the object of another entry, like a variable,
+/// so the address of that object is what the pointer value would have been.
+/// Returns None if the object is not in memory, e.g. because it's in a register or has a constant value.
+fn resolve_implicit_pointer<W: funty::Integral>(
+    dwarf: &Dwarf<DefaultReader>,
+    unit: &Unit<DefaultReader, usize>,
+    device_memory: &DeviceMemory<W>,
+    frame_base: Option<W>,
+    frame: Option<EvaluationFrame>,
+    target: DebugInfoOffset,
+    byte_offset: i64,
+) -> Result<Option<u64>, TraceError>
+where
+    <W as funty::Numeric>::Bytes: bitvec::view::BitView<Store = u8>,
+{
+    // The target is almost always in the same unit, but it doesn't have to be
+    let target_unit;
+    let (unit, target_offset) = match target.to_unit_offset(&unit.header) {
+        Some(target_offset) => (unit, target_offset),
+        None => {
+            let mut units = dwarf.units();
+            let target_unit_header = loop {
+                match units.next()? {
+                    Some(unit_header) if target.to_unit_offset(&unit_header).is_some() => {
+                        break unit_header
+                    }
+                    Some(_) => continue,
+                    None => {
+                        return Err(TraceError::DebugInfoOffsetUnitNotFound {
+                            debug_info_offset: target.0,
+                        })
+                    }
+                }
+            };
+            target_unit = Unit::new(dwarf, target_unit_header)?;
+            let target_offset = target
+                .to_unit_offset(&target_unit.header)
+                .ok_or(TraceError::UnitNotFoundAgain)?;
+            (&target_unit, target_offset)
+        }
+    };
+
+    let target_entry = unit.entry(target_offset)?;
+    // The location of the target is not resolved any further, so a chain of implicit pointers can't recurse forever
+    let target_location = evaluate_unresolved_location(
+        dwarf,
+        unit,
+        device_memory,
+        target_entry.attr(gimli::constants::DW_AT_location)?,
+        frame_base,
+        frame,
+    )?;
+
+    Ok(match target_location {
+        VariableLocationResult::LocationsFound(pieces) => match pieces.as_slice() {
+            [Piece {
+                location: gimli::Location::Address { address },
+                ..
+            }] => Some(address.wrapping_add_signed(byte_offset)),
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+/// Same as [evaluate_location], but implicit pointers are left as they are
+fn evaluate_unresolved_location<W: funty::Integral>(
+    dwarf: &Dwarf<DefaultReader>,
+    unit: &Unit<DefaultReader, usize>,
+    device_memory: &DeviceMemory<W>,
+    location: Option<Attribute<DefaultReader>>,
+    frame_base: Option<W>,
+    frame: Option<EvaluationFrame>,
 ) -> Result<VariableLocationResult, TraceError>
 where
     <W as funty::Numeric>::Bytes: bitvec::view::BitView<Store = u8>,
 {
     // First, we need to have the actual value
-    let location = match location {
-        Some(location) => location.value(),
+    let location_attr = match location {
+        Some(location_attr) => location_attr,
         None => return Ok(VariableLocationResult::NoLocationAttribute),
     };
+    let location = location_attr.value();
 
     // Then we need to get the location expression. This expression can then later be evaluated by gimli.
     let location_expression = match location {
         AttributeValue::Block(ref data) => gimli::Expression(data.clone()),
         AttributeValue::Exprloc(ref data) => data.clone(),
-        AttributeValue::LocationListsRef(l) => {
-            let mut locations = dwarf.locations(unit, l)?;
+        // DWARF 4 refers to a list in `.debug_loc` and DWARF 5 to one in `.debug_loclists`, by offset or by index.
+        // Gimli resolves the index and applies the base address entries, so we get the absolute pc ranges.
+        AttributeValue::LocationListsRef(_) | AttributeValue::DebugLocListsIndex(_) => {
+            let mut locations = match dwarf.attr_locations(unit, location.clone())? {
+                Some(locations) => locations,
+                None => {
+                    return Err(TraceError::WrongAttributeValueType {
+                        attribute_name: location_attr.name().to_string(),
+                        expected_type_name: "location list",
+                        gotten_value: format!("{location:X?}"),
+                    })
+                }
+            };
+            let check_pc = device_memory.register(gimli::Arm::PC)?.as_u64();
             let mut location = None;
 
-            while let Ok(Some(maybe_location)) = locations.next() {
-                let check_pc = device_memory.register(gimli::Arm::PC)?;
-
-                if check_pc.as_u64() >= maybe_location.range.begin
-                    && check_pc.as_u64() < maybe_location.range.end
-                {
+            while let Some(maybe_location) = locations.next()? {
+                if check_pc >= maybe_location.range.begin && check_pc < maybe_location.range.end {
                     location = Some(maybe_location);
                     break;
                 }
@@ -534,10 +914,15 @@ where
     };
 
     // Turn the expression into an evaluation
+    let context = EvaluationContext {
+        frame,
+        unit_offset: unit.header.offset().as_debug_info_offset(),
+    };
     let result = evaluate_expression(
-        unit,
+        unit.encoding(),
         device_memory,
         frame_base,
+        context,
         location_expression.evaluation(unit.encoding()),
     );
 
@@ -545,16 +930,29 @@ where
         Err(TraceError::LocationEvaluationStepNotImplemented(step)) => {
             Ok(VariableLocationResult::LocationEvaluationStepNotImplemented(step))
         }
+        Err(TraceError::UnknownTlsBase) => Ok(VariableLocationResult::TlsBaseUnknown),
+        Err(TraceError::MissingRegister(e)) => Ok(VariableLocationResult::MissingRegister(e)),
         Err(e) => Err(e),
         Ok(pieces) if pieces.is_empty() => Ok(VariableLocationResult::NoLocationFound),
         Ok(pieces) => Ok(VariableLocationResult::LocationsFound(pieces)),
     }
 }
 
-fn evaluate_expression<W: funty::Integral>(
-    unit: &Unit<DefaultReader, usize>,
+/// Where an expression is evaluated, which is needed to ask the evaluation step resolver for a value
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct EvaluationContext {
+    /// The frame of which the variables are decoded. None for the static variables and the unwinding.
+    pub frame: Option<EvaluationFrame>,
+    /// The offset of the unit of the entry with the expression, which the unit offsets in the expression are relative to.
+    /// None if the expression isn't of an entry, like a data location.
+    pub unit_offset: Option<DebugInfoOffset>,
+}
+
+pub(crate) fn evaluate_expression<W: funty::Integral>(
+    encoding: gimli::Encoding,
     device_memory: &DeviceMemory<W>,
     frame_base: Option<W>,
+    context: EvaluationContext,
     mut evaluation: Evaluation<DefaultReader>,
 ) -> Result<Vec<Piece<DefaultReader, usize>>, TraceError>
 where
@@ -584,22 +982,30 @@ where
                     frame_base.ok_or(TraceError::UnknownFrameBase)?.as_u64(),
                 )?;
             }
+            EvaluationResult::RequiresTls(_) => {
+                // Thread local variables are stored at an offset from the TLS base of the current thread
+                result =
+                    resume_with_resolved_step(device_memory, context, &mut evaluation, &result)?
+                        .ok_or(TraceError::UnknownTlsBase)?;
+            }
             EvaluationResult::RequiresRelocatedAddress(address) => {
                 // We have no relocations of code
                 result = evaluation.resume_with_relocated_address(address)?;
             }
             EvaluationResult::RequiresEntryValue(ex) => {
                 let entry_pieces = evaluate_expression(
-                    unit,
+                    encoding,
                     device_memory,
                     frame_base,
-                    ex.evaluation(unit.encoding()),
+                    context,
+                    ex.evaluation(encoding),
                 )?;
 
                 let entry_data = get_variable_data(
                     device_memory,
                     W::BITS as u64,
                     VariableLocationResult::LocationsFound(entry_pieces),
+                    None,
                 )?;
 
                 result = evaluation.resume_with_entry_value(gimli::Value::Generic(
@@ -629,9 +1035,16 @@ where
                 result = evaluation.resume_with_memory(value)?;
             }
             r => {
-                return Err(TraceError::LocationEvaluationStepNotImplemented(
-                    std::rc::Rc::new(r),
-                ))
+                // The user may know the value we need
+                result =
+                    match resume_with_resolved_step(device_memory, context, &mut evaluation, &r)? {
+                        Some(result) => result,
+                        None => {
+                            return Err(TraceError::LocationEvaluationStepNotImplemented(
+                                std::rc::Rc::new(r),
+                            ))
+                        }
+                    };
             }
         }
     }
@@ -639,6 +1052,50 @@ where
     Ok(evaluation.result())
 }
 
+/// Resumes the evaluation with the value the [EvaluationStepResolver](stackdump_core::device_memory::EvaluationStepResolver)
+/// of the device memory gives for the step.
+///
+/// Returns None if the step can't be resolved like that or if the resolver doesn't know the value.
+fn resume_with_resolved_step<W: funty::Integral>(
+    device_memory: &DeviceMemory<W>,
+    context: EvaluationContext,
+    evaluation: &mut Evaluation<DefaultReader>,
+    result: &EvaluationResult<DefaultReader>,
+) -> Result<Option<EvaluationResult<DefaultReader>>, TraceError> {
+    let step = match result {
+        EvaluationResult::RequiresFrameBase => EvaluationStep::FrameBase,
+        EvaluationResult::RequiresCallFrameCfa => EvaluationStep::CallFrameCfa,
+        EvaluationResult::RequiresParameterRef(unit_offset) => match context.unit_offset {
+            Some(offset) => EvaluationStep::ParameterRef {
+                offset: DebugInfoOffset(offset.0 + unit_offset.0),
+            },
+            None => return Ok(None),
+        },
+        EvaluationResult::RequiresIndexedAddress { index, .. } => EvaluationStep::IndexedAddress {
+            index: index.0 as u64,
+        },
+        EvaluationResult::RequiresTls(_) => EvaluationStep::TlsBase,
+        _ => return Ok(None),
+    };
+
+    let value = match device_memory.resolve_evaluation_step(step, context.frame) {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+
+    Ok(Some(match result {
+        EvaluationResult::RequiresFrameBase => evaluation.resume_with_frame_base(value)?,
+        EvaluationResult::RequiresCallFrameCfa => evaluation.resume_with_call_frame_cfa(value)?,
+        EvaluationResult::RequiresParameterRef(_) => evaluation.resume_with_parameter_ref(value)?,
+        EvaluationResult::RequiresIndexedAddress { .. } => {
+            evaluation.resume_with_indexed_address(value)?
+        }
+        // The value is the TLS base, the variable is at an offset from it
+        EvaluationResult::RequiresTls(offset) => evaluation.resume_with_tls(value + offset)?,
+        _ => return Ok(None),
+    }))
+}
+
 /// Reads the data of a piece of memory
 ///
 /// The [Piece] is an indirect result of the [evaluate_location] function.
@@ -657,14 +1114,23 @@ where
     let mut data = match piece.location.clone() {
         gimli::Location::Empty => return Err(VariableDataError::OptimizedAway),
         gimli::Location::Register { register } => Some(
+            // The register is turned into bytes in the byte order of the target device
             device_memory
-                .register(register)
-                .map(|r| r.to_ne_bytes().view_bits().to_bitvec()) // TODO: Is this correct? Shouldn't this be the endianness of the target device?
-                .map_err(|e| VariableDataError::NoDataAvailableAt(e.to_string()))?,
+                .register_bytes(register)
+                .map(|bytes| bytes.view_bits().to_bitvec())
+                .map_err(VariableDataError::MissingRegister)?,
         ),
-        gimli::Location::Address { address } => device_memory
-            .read_slice(address..(address + variable_size))?
-            .map(|b| b.view_bits().to_bitvec()),
+        gimli::Location::Address { address } => {
+            // A piece only needs its own bytes. The rest of the variable can be somewhere else, like in a register.
+            let read_size = piece
+                .size_in_bits
+                .map(|size| div_ceil(piece.bit_offset.unwrap_or(0) + size, 8))
+                .unwrap_or(variable_size);
+
+            device_memory
+                .read_slice_ref(address..(address + read_size))?
+                .map(|b| b.view_bits().to_bitvec())
+        }
         gimli::Location::Value { value } => {
             let mut data = BitVec::new();
 
@@ -687,15 +1153,9 @@ where
         gimli::Location::Bytes { value } => value
             .get(0..variable_size as usize)
             .map(|b| b.view_bits().to_bitvec()),
-        gimli::Location::ImplicitPointer {
-            value: _,
-            byte_offset: _,
-        } => {
-            return Err(VariableDataError::OperationNotImplemented {
-                operation: "`ImplicitPointer` location not yet supported".into(),
-                file: file!(),
-                line: line!(),
-            })
+        // The implicit pointers that point into memory have already been resolved to an address value
+        gimli::Location::ImplicitPointer { .. } => {
+            return Err(VariableDataError::ImplicitPointerNotInMemory)
         }
     };
 
@@ -717,10 +1177,13 @@ where
 /// - `device_memory`: All the captured memory of the device
 /// - `variable_size`: The size of the variable in bits
 /// - `variable_location`: The location of the variable
+/// - `budget`: The budget the read bytes are counted against.
+///   Can be `None` for reads of a single word that aren't part of a variable, like the frame base.
 fn get_variable_data<W: funty::Integral>(
     device_memory: &DeviceMemory<W>,
     variable_size: u64,
     variable_location: VariableLocationResult,
+    state: Option<&mut DecodeState>,
 ) -> Result<BitVec<u8, Lsb0>, VariableDataError>
 where
     <W as funty::Numeric>::Bytes: bitvec::view::BitView<Store = u8>,
@@ -729,12 +1192,18 @@ where
         VariableLocationResult::NoLocationAttribute => Err(VariableDataError::OptimizedAway),
         VariableLocationResult::LocationListNotFound => Err(VariableDataError::OptimizedAway),
         VariableLocationResult::NoLocationFound => Err(VariableDataError::OptimizedAway),
+        VariableLocationResult::TlsBaseUnknown => Err(VariableDataError::TlsBaseUnknown),
+        VariableLocationResult::MissingRegister(e) => Err(VariableDataError::MissingRegister(e)),
         VariableLocationResult::LocationsFound(pieces) => {
             let mut data = BitVec::new();
 
             // Ceil-div with 8 to get the bytes we need to read
             let variable_size_bytes = div_ceil(variable_size, 8);
 
+            if let Some(state) = state {
+                state.read_bytes(variable_size_bytes)?;
+            }
+
             // Get all the data of the pieces
             for piece in pieces {
                 let piece_data = get_piece_data(device_memory, &piece, variable_size_bytes)?;
@@ -764,32 +1233,49 @@ fn read_base_type<W: funty::Integral>(
     data: &BitSlice<u8, Lsb0>,
 ) -> Result<Value<W>, VariableDataError> {
     match encoding {
+        // Integers can have any amount of bits when they're a bitfield member
         gimli::constants::DW_ATE_unsigned | gimli::constants::DW_ATE_unsigned_char => {
             match data.len() {
-                8 => Ok(Value::Uint(data.load_le::<u8>() as _)),
-                16 => Ok(Value::Uint(data.load_le::<u16>() as _)),
-                32 => Ok(Value::Uint(data.load_le::<u32>() as _)),
-                64 => Ok(Value::Uint(data.load_le::<u64>() as _)),
-                128 => Ok(Value::Uint(data.load_le::<u128>() as _)),
+                1..=128 => Ok(Value::Uint(data.load_le::<u128>())),
                 _ => Err(VariableDataError::InvalidSize { bits: data.len() }),
             }
         }
         gimli::constants::DW_ATE_signed | gimli::constants::DW_ATE_signed_char => {
             match data.len() {
-                8 => Ok(Value::Int(data.load_le::<u8>() as _)),
-                16 => Ok(Value::Int(data.load_le::<u16>() as _)),
-                32 => Ok(Value::Int(data.load_le::<u32>() as _)),
-                64 => Ok(Value::Int(data.load_le::<u64>() as _)),
-                128 => Ok(Value::Int(data.load_le::<u128>() as _)),
+                1..=128 => {
+                    // Shift the sign bit to the top and back to sign extend the value
+                    let unused_bits = 128 - data.len() as u32;
+                    Ok(Value::Int(
+                        (data.load_le::<u128>() << unused_bits) as i128 >> unused_bits,
+                    ))
+                }
                 _ => Err(VariableDataError::InvalidSize { bits: data.len() }),
             }
         }
         gimli::constants::DW_ATE_float => match data.len() {
-            32 => Ok(Value::Float(f32::from_bits(data.load_le::<u32>()) as _)),
-            64 => Ok(Value::Float(f64::from_bits(data.load_le::<u64>()) as _)),
+            32 => Ok(Value::Float32(f32::from_bits(data.load_le::<u32>()))),
+            64 => Ok(Value::Float64(f64::from_bits(data.load_le::<u64>()))),
+            _ => Err(VariableDataError::InvalidSize { bits: data.len() }),
+        },
+        gimli::constants::DW_ATE_boolean => match data.len() {
+            1..=128 => match data.load_le::<u128>() {
+                0 => Ok(Value::Bool(false)),
+                1 => Ok(Value::Bool(true)),
+                // Anything else is not a valid bool and is likely the result of undefined behaviour
+                value => Err(VariableDataError::InvalidBool { value }),
+            },
+            _ => Err(VariableDataError::InvalidSize { bits: data.len() }),
+        },
+        // The `char` of Rust
+        gimli::constants::DW_ATE_UTF => match data.len() {
+            8 | 16 | 32 => {
+                let value = data.load_le::<u32>();
+                char::from_u32(value)
+                    .map(Value::Char)
+                    .ok_or(VariableDataError::InvalidChar { value })
+            }
             _ => Err(VariableDataError::InvalidSize { bits: data.len() }),
         },
-        gimli::constants::DW_ATE_boolean => Ok(Value::Bool(data.iter().any(|v| *v))),
         gimli::constants::DW_ATE_address => match data.len() {
             8 => Ok(Value::Address(
                 data.load_le::<u8>().try_into().ok().unwrap(),
@@ -813,12 +1299,24 @@ fn read_base_type<W: funty::Integral>(
 }
 
 /// Read some bit data into the value of the give variable. If there is an error, that error will be placed in the value field as well
+///
+/// - `data_address`: The address in device memory the `data` was read from, if it was read from memory at all
+/// - `budget`: The budget that is used up by decoding. When it runs out, the value is set to a [VariableDataError::BudgetExceeded] error.
 fn read_variable_data<W: funty::Integral>(
     mut variable: Pin<&mut TypeValueNode<W>>,
     data: &BitSlice<u8, Lsb0>,
+    data_address: Option<u64>,
     device_memory: &DeviceMemory<W>,
-    type_cache: &mut HashMap<DebugInfoOffset, Result<TypeValueTree<W>, TraceError>>,
-) {
+    type_cache: &mut TypeCache<W>,
+    state: &mut DecodeState,
+) where
+    <W as funty::Numeric>::Bytes: bitvec::view::BitView<Store = u8>,
+{
+    if let Err(e) = state.decode_node() {
+        variable.data_mut().variable_value = Err(e);
+        return;
+    }
+
     // We may not have enough data in some cases
     // I don't know why that is, so let's just print a warning
     if variable.data().bit_length() > data.len() as u64 {
@@ -835,45 +1333,68 @@ fn read_variable_data<W: funty::Integral>(
             // The first child must be the descriminator and not one of the variants
             assert!(variable.front_mut().unwrap().data().name == "discriminant");
 
+            let (data, data_address) = object_data(variable.data(), data, data_address);
+
             // We have to read the discriminator, then select the active variant and then read that
             read_variable_data(
                 variable.front_mut().unwrap(),
                 data,
+                data_address,
                 device_memory,
                 type_cache,
+                state,
             );
 
-            let discriminator_value = match &variable.front().unwrap().data().variable_value {
+            let discriminator_value = match discriminant_value(variable.front().unwrap()) {
                 Ok(value) => value.clone(),
-                _ => {
+                Err(_) => {
                     return;
                 }
             };
 
             // We know the discriminator value, so now we need to hunt for the active variant.
             // There may not be one though
-            let active_variant = variable
-                .iter_mut()
-                .skip(1)
-                .find(|variant| variant.data().variable_value.as_ref() == Ok(&discriminator_value));
+            let active_variant = variable.iter_mut().skip(1).find(|variant| {
+                matches!(
+                    &variant.data().variable_type.archetype,
+                    Archetype::TaggedUnionVariant(variant_discriminant)
+                        if variant_discriminant.matches(&discriminator_value)
+                )
+            });
 
             if let Some(active_variant) = active_variant {
-                read_variable_data(active_variant, data, device_memory, type_cache);
-            } else if let Some(default_variant) = variable
-                .iter_mut()
-                .skip(1)
-                .find(|variant| variant.data().variable_value.is_err())
-            {
+                read_variable_data(
+                    active_variant,
+                    data,
+                    data_address,
+                    device_memory,
+                    type_cache,
+                    state,
+                );
+            } else if let Some(default_variant) = variable.iter_mut().skip(1).find(|variant| {
+                variant.data().variable_type.archetype
+                    == Archetype::TaggedUnionVariant(VariantDiscriminant::Default)
+            }) {
                 // There is no active variant, so we need to go for the default
-                read_variable_data(default_variant, data, device_memory, type_cache);
+                read_variable_data(
+                    default_variant,
+                    data,
+                    data_address,
+                    device_memory,
+                    type_cache,
+                    state,
+                );
             }
         }
-        Archetype::TaggedUnionVariant => {
+        Archetype::TaggedUnionVariant(_) => {
+            let (data, data_address) = object_data(variable.data(), data, data_address);
             read_variable_data(
                 variable.front_mut().unwrap(),
                 data,
+                data_address,
                 device_memory,
                 type_cache,
+                state,
             );
         }
         Archetype::Structure
@@ -882,9 +1403,39 @@ fn read_variable_data<W: funty::Integral>(
         | Archetype::ObjectMemberPointer => {
             // Every member of this object is a child in the tree.
             // We simply need to read every child.
+            // A flexible array member is read after the other members, because its length is one of them.
+
+            let (data, data_address) = object_data(variable.data(), data, data_address);
+
+            let flexible_array_member = match variable.back().map(|member| member.data()) {
+                Some(TypeValue {
+                    variable_type:
+                        VariableType {
+                            archetype: Archetype::Array,
+                            array_layout: Some(ArrayLayout { count: 0, .. }),
+                            data_location: None,
+                            ..
+                        },
+                    ..
+                }) if data_address.is_some() => variable.pop_back(),
+                _ => None,
+            };
 
             for child in variable.iter_mut() {
-                read_variable_data(child, data, device_memory, type_cache);
+                read_variable_data(child, data, data_address, device_memory, type_cache, state);
+            }
+
+            if let Some(mut flexible_array_member) = flexible_array_member {
+                read_flexible_array_member(
+                    variable.as_ref(),
+                    flexible_array_member.root_mut(),
+                    data,
+                    data_address,
+                    device_memory,
+                    type_cache,
+                    state,
+                );
+                variable.push_back(flexible_array_member);
             }
 
             if &variable.data().variable_type.name == "&str" {
@@ -905,15 +1456,16 @@ fn read_variable_data<W: funty::Integral>(
                         if *length < 64 * 1024 =>
                     {
                         // We can read the data. This works because the length field denotes the byte size, not the char size
-                        let data = device_memory
-                            .read_slice(pointer.as_u64()..pointer.as_u64() + *length as u64);
-                        if let Ok(Some(data)) = data {
-                            variable.data_mut().variable_value =
-                                Ok(Value::String(data, StringFormat::Utf8));
-                        } else {
+                        let data = state.read_bytes(*length as u64).map(|()| {
+                            device_memory
+                                .read_slice(pointer.as_u64()..pointer.as_u64() + *length as u64)
+                        });
+                        variable.data_mut().variable_value = match data {
+                            Ok(Ok(Some(data))) => Ok(Value::String(data, StringFormat::Utf8)),
+                            Err(e) => Err(e),
                             // There's something wrong. Fall back to treating the string as an object
-                            variable.data_mut().variable_value = Ok(Value::Object);
-                        }
+                            _ => Ok(Value::Object),
+                        };
                     }
                     (Ok(Ok(Value::Address(_))), Ok(Ok(Value::Uint(length))))
                         if *length >= 64 * 1024 =>
@@ -934,6 +1486,27 @@ fn read_variable_data<W: funty::Integral>(
                         variable.data_mut().variable_value = Ok(Value::Object);
                     }
                 }
+            } else if variable.data().variable_type.name.split('<').next() == Some("Vec") {
+                // This is a vec. If we can read its elements, it will be turned into an array.
+                // If not, it stays a normal object
+                match read_vec_elements(variable.as_mut(), device_memory, type_cache, state) {
+                    Some(Ok(())) => {}
+                    Some(Err(e)) => variable.data_mut().variable_value = Err(e),
+                    None => variable.data_mut().variable_value = Ok(Value::Object),
+                }
+            } else if variable.data().variable_type.name.split('<').next() == Some("VecDeque") {
+                // Same as a vec, but the elements are stored in a ring buffer
+                match read_vec_deque_elements(variable.as_mut(), device_memory, type_cache, state) {
+                    Some(Ok(())) => {}
+                    Some(Err(e)) => variable.data_mut().variable_value = Err(e),
+                    None => variable.data_mut().variable_value = Ok(Value::Object),
+                }
+            } else if &variable.data().variable_type.name == "String" {
+                // A string is a wrapper around a `Vec<u8>` which has already been read as one of the children
+                variable.data_mut().variable_value = match read_string_bytes(&variable) {
+                    Some(bytes) => Ok(Value::String(bytes, StringFormat::Utf8)),
+                    None => Ok(Value::Object),
+                };
             } else {
                 // This is a normal object
                 variable.data_mut().variable_value = Ok(Value::Object);
@@ -943,11 +1516,12 @@ fn read_variable_data<W: funty::Integral>(
             if variable.data().bit_length() == 0 && variable.data().variable_type.name == "()" {
                 variable.data_mut().variable_value = Ok(Value::Unit);
             } else {
-                variable.data_mut().variable_value =
-                    match data.get(variable.data().bit_range_usize()) {
-                        Some(data) => read_base_type(encoding, data),
-                        None => Err(VariableDataError::NoDataAvailable),
-                    };
+                let data = data.get(variable.data().bit_range_usize());
+                variable.data_mut().variable_value = match data {
+                    Some(data) => read_base_type(encoding, data),
+                    None => Err(VariableDataError::NoDataAvailable),
+                };
+                variable.data_mut().raw_data = data.map(bits_to_bytes);
             }
         }
         Archetype::Pointer(die_offset) => {
@@ -955,22 +1529,37 @@ fn read_variable_data<W: funty::Integral>(
             // The pointee is not part of this tree yet and has to be looked up through the type_cache.
             // This is done so that we cannot get an infinite recursive type due to e.g. linked lists.
 
-            variable.data_mut().variable_value = match data.get(variable.data().bit_range_usize()) {
+            let pointer_data = data.get(variable.data().bit_range_usize());
+            variable.data_mut().variable_value = match pointer_data {
                 Some(data) => read_base_type(gimli::constants::DW_ATE_address, data),
                 None => Err(VariableDataError::NoDataAvailable),
             };
+            variable.data_mut().raw_data = pointer_data.map(bits_to_bytes);
 
             let address = match variable.data().variable_value {
                 Ok(Value::Address(addr)) => Ok(addr),
                 _ => Err(VariableDataError::InvalidPointerData),
             };
 
+            // If the pointer has a data location, then the pointee is found by evaluating that instead
+            let address = match (&variable.data().variable_type.data_location, address) {
+                (Some(data_location), Ok(_)) => evaluate_data_location(
+                    data_location,
+                    data_address.map(|address| address + variable.data().bit_range.start / 8),
+                    device_memory,
+                    state.frame,
+                )
+                .and_then(|address| {
+                    W::try_from(address).map_err(|_| VariableDataError::InvalidPointerData)
+                }),
+                (_, address) => address,
+            };
+
             let pointee_tree_clone = match type_cache
                 .get(&die_offset)
                 .expect("Pointers must have their pointee type cached")
-                .clone()
             {
-                Ok(pointee_tree_clone) => pointee_tree_clone,
+                Ok(pointee_tree) => TypeValueTree::clone(pointee_tree),
                 Err(_) => TypeValueTree::new(TypeValue {
                     name: "Pointee".into(),
                     variable_type: VariableType {
@@ -979,8 +1568,13 @@ fn read_variable_data<W: funty::Integral>(
                     },
                     bit_range: 0..0,
                     variable_value: Err(VariableDataError::Unknown),
+                    raw_data: None,
                 }),
             };
+            // A reference can't be null, so then the reference itself is invalid
+            let null_reference = variable.data().variable_type.reference
+                && matches!(address, Ok(address) if address == W::ZERO);
+
             variable.push_back(pointee_tree_clone);
             let mut pointee = variable.back_mut().unwrap();
 
@@ -988,39 +1582,137 @@ fn read_variable_data<W: funty::Integral>(
                 Ok(address) if address == W::ZERO => {
                     pointee.data_mut().variable_value = Err(VariableDataError::NullPointer)
                 }
-                Ok(address) => {
-                    let pointee_data = device_memory.read_slice(
-                        address.as_u64()
-                            ..address.as_u64() + div_ceil(pointee.data().bit_range.end, 8),
-                    );
-
-                    match pointee_data {
-                        Ok(Some(pointee_data)) => {
-                            read_variable_data(
+                // The memory of peripherals is not program data, so we don't decode it as the pointee type
+                Ok(address)
+                    if device_memory.memory_kind(address.as_u64())
+                        == Some(MemoryRegionKind::Peripheral) =>
+                {
+                    pointee.data_mut().variable_value = Err(VariableDataError::PeripheralMemory)
+                }
+                // Following pointers can go on for a long time, e.g. with a long (or circular) linked list
+                Ok(address) => match state.enter_pointer() {
+                    Ok(()) => {
+                        let pointee_byte_size = div_ceil(pointee.data().bit_range.end, 8);
+                        let pointee_data = state.read_bytes(pointee_byte_size).and_then(|()| {
+                            device_memory
+                                .read_slice_ref(
+                                    address.as_u64()..address.as_u64() + pointee_byte_size,
+                                )?
+                                .ok_or(VariableDataError::NoDataAvailable)
+                        });
+
+                        match pointee_data {
+                            Ok(pointee_data) => read_variable_data(
                                 pointee,
                                 pointee_data.view_bits(),
+                                Some(address.as_u64()),
                                 device_memory,
                                 type_cache,
-                            );
-                        }
-                        Ok(None) => {
-                            pointee.data_mut().variable_value =
-                                Err(VariableDataError::NoDataAvailable);
-                        }
-                        Err(e) => {
-                            pointee.data_mut().variable_value = Err(e.into());
+                                state,
+                            ),
+                            Err(e) => pointee.data_mut().variable_value = Err(e),
                         }
+
+                        state.leave_pointer();
                     }
-                }
+                    Err(e) => pointee.data_mut().variable_value = Err(e),
+                },
                 Err(e) => pointee.data_mut().variable_value = Err(e),
             }
+
+            if null_reference {
+                variable.data_mut().variable_value = Err(VariableDataError::NullReference);
+            }
         }
         Archetype::Array => {
+            let layout = variable
+                .data()
+                .variable_type
+                .array_layout
+                .expect("Arrays have a layout");
+            let ArrayLayout {
+                lower_bound,
+                count,
+                stride,
+                lazy,
+            } = layout;
+
+            // A lazy array only has the element type as child, so we need to create the elements now
+            let base_element_type_tree = lazy.then(|| {
+                variable
+                    .pop_front()
+                    .expect("Lazy arrays have their element type as child")
+            });
+
+            // Every element gets its own node, so don't read them if they won't fit in the budget
+            if let Err(e) = state.check_nodes(count) {
+                variable.data_mut().variable_value = Err(e);
+                return;
+            }
+
+            // Normally the elements are part of the array data, but if the array has a data location,
+            // the array is only a descriptor and the elements are stored somewhere else
+            let elements_data;
+            let (data, data_address) = match &variable.data().variable_type.data_location {
+                None => object_data(variable.data(), data, data_address),
+                Some(data_location) => {
+                    let elements = evaluate_data_location(
+                        data_location,
+                        data_address.map(|address| address + variable.data().bit_range.start / 8),
+                        device_memory,
+                        state.frame,
+                    )
+                    .and_then(|address| {
+                        let elements_byte_size = div_ceil(stride * count, 8);
+                        state.read_bytes(elements_byte_size)?;
+                        device_memory
+                            .read_slice_ref(address..address + elements_byte_size)?
+                            .map(|data| (address, data))
+                            .ok_or(VariableDataError::NoDataAvailable)
+                    });
+
+                    match elements {
+                        Ok((address, data)) => {
+                            elements_data = data;
+                            (elements_data.view_bits(), Some(address))
+                        }
+                        Err(e) => {
+                            variable.data_mut().variable_value = Err(e);
+                            return;
+                        }
+                    }
+                }
+            };
+
             variable.data_mut().variable_value = Ok(Value::Array);
+
+            if let Some(base_element_type_tree) = base_element_type_tree {
+                for (position, data_index) in
+                    (lower_bound..(lower_bound + count as i64)).enumerate()
+                {
+                    variable.push_back(type_value_tree_building::array_element(
+                        &base_element_type_tree,
+                        data_index,
+                        position as u64 * stride,
+                    ));
+                }
+                variable.data_mut().variable_type.array_layout = Some(ArrayLayout {
+                    lazy: false,
+                    ..layout
+                });
+            }
+
             // The tree has all children that we have to read. These are the elements of the array
             for mut element in variable.iter_mut() {
                 match data.get(element.data().bit_range_usize()) {
-                    Some(_) => read_variable_data(element, data, device_memory, type_cache),
+                    Some(_) => read_variable_data(
+                        element,
+                        data,
+                        data_address,
+                        device_memory,
+                        type_cache,
+                        state,
+                    ),
                     None => {
                         element.data_mut().variable_value = Err(VariableDataError::NoDataAvailable)
                     }
@@ -1029,39 +1721,504 @@ fn read_variable_data<W: funty::Integral>(
         }
         Archetype::Enumeration => {
             variable.data_mut().variable_value = Ok(Value::Enumeration);
+            let (data, data_address) = object_data(variable.data(), data, data_address);
+
+            // An enum in a bitfield has less bits than its base integer.
+            // The bits are extended to the size of the base integer so it can be read normally.
+            let enum_bits = variable.data().bit_length();
+            let base = variable.front().expect("Enumerations have a child").data();
+            let extended_data;
+            let data = if enum_bits < base.bit_length() && data.len() as u64 == enum_bits {
+                let signed = matches!(
+                    base.variable_type.archetype,
+                    Archetype::BaseType(
+                        gimli::constants::DW_ATE_signed | gimli::constants::DW_ATE_signed_char
+                    )
+                );
+                let fill = signed && data.last().map(|bit| *bit).unwrap_or_default();
+
+                let mut bits = data.to_bitvec();
+                bits.resize(base.bit_range.end as usize, fill);
+                extended_data = bits;
+                extended_data.as_bitslice()
+            } else {
+                data
+            };
 
             // The first child of the enumeration is the base integer. We only have to read that one.
             read_variable_data(
                 variable.front_mut().expect("Enumerations have a child"),
                 data,
+                data_address,
                 device_memory,
                 type_cache,
+                state,
             );
         }
         Archetype::Typedef => {
             variable.data_mut().variable_value = Ok(Value::Typedef);
+            let (data, data_address) = object_data(variable.data(), data, data_address);
 
             // The first child of the enumeration is the base integer. We only have to read that one.
             read_variable_data(
                 variable.front_mut().expect("Typedefs have a child"),
                 data,
+                data_address,
                 device_memory,
                 type_cache,
+                state,
             );
         }
         Archetype::Enumerator => {
             // Ignore, we don't have to do anything
         }
+        Archetype::Unspecified => {
+            // Ignore, there's no data to read
+        }
         Archetype::Subroutine => {
             variable.data_mut().variable_value = Ok(Value::Object);
             // Ignore, there's nothing to do
         }
         Archetype::Unknown => {
-            // Ignore, we don't know what to do
+            // We don't know how to decode this, but if we know where the data is,
+            // we can at least show the raw bytes instead of only an error
+            if variable.data().bit_length() > 0 {
+                if let Some(data) = data.get(variable.data().bit_range_usize()) {
+                    variable.data_mut().variable_value = Ok(Value::Raw(bits_to_bytes(data)));
+                }
+            }
+        }
+    }
+}
+
+/// Packs the bits into bytes. The last byte is padded with zeroes if the amount of bits is not a multiple of 8.
+fn bits_to_bytes(data: &BitSlice<u8, Lsb0>) -> Vec<u8> {
+    data.chunks(8).map(|byte| byte.load_le::<u8>()).collect()
+}
+
+/// Gets the data of an object out of the data of its parent, together with the address of the object.
+///
+/// The bit ranges of the children of the object are relative to the object, so this is the data the children are read from.
+fn object_data<'data, W: funty::Integral>(
+    object: &TypeValue<W>,
+    data: &'data BitSlice<u8, Lsb0>,
+    data_address: Option<u64>,
+) -> (&'data BitSlice<u8, Lsb0>, Option<u64>) {
+    // Not all data may be available. The children that fall outside of it will report that.
+    let start = (object.bit_range.start as usize).min(data.len());
+    let end = (object.bit_range.end as usize).clamp(start, data.len());
+
+    (
+        &data[start..end],
+        data_address.map(|address| address + object.bit_range.start / 8),
+    )
+}
+
+/// Evaluates the data location of an object and returns the address at which the data of the object is stored
+///
+/// - `object_address`: The address of the object that has the data location. This is the starting point of the evaluation.
+fn evaluate_data_location<W: funty::Integral>(
+    data_location: &DataLocation,
+    object_address: Option<u64>,
+    device_memory: &DeviceMemory<W>,
+    frame: Option<EvaluationFrame>,
+) -> Result<u64, VariableDataError>
+where
+    <W as funty::Numeric>::Bytes: bitvec::view::BitView<Store = u8>,
+{
+    // The expression is relative to the object, so we can't do anything if the object is not in memory
+    let object_address = object_address.ok_or(VariableDataError::NoDataAvailable)?;
+
+    let mut evaluation = data_location
+        .expression
+        .clone()
+        .evaluation(data_location.encoding);
+    evaluation.set_object_address(object_address);
+
+    let context = EvaluationContext {
+        frame,
+        unit_offset: None,
+    };
+    let pieces = match evaluate_expression(
+        data_location.encoding,
+        device_memory,
+        None,
+        context,
+        evaluation,
+    ) {
+        Ok(pieces) => pieces,
+        Err(TraceError::LocationEvaluationStepNotImplemented(step)) => {
+            return Err(VariableDataError::UnimplementedLocationEvaluationStep(
+                format!("{:?}", step),
+            ))
+        }
+        Err(TraceError::UnknownTlsBase) => return Err(VariableDataError::TlsBaseUnknown),
+        Err(TraceError::MemoryReadError(e)) => return Err(VariableDataError::MemoryReadError(e)),
+        Err(TraceError::MissingRegister(e)) => return Err(VariableDataError::MissingRegister(e)),
+        Err(e) => return Err(VariableDataError::NoDataAvailableAt(e.to_string())),
+    };
+
+    match pieces.as_slice() {
+        [Piece {
+            location: gimli::Location::Address { address },
+            ..
+        }] => Ok(*address),
+        _ => Err(VariableDataError::OperationNotImplemented {
+            operation: format!("Data location that doesn't evaluate to one address: {pieces:X?}"),
+            file: file!(),
+            line: line!(),
+        }),
+    }
+}
+
+/// Reads the flexible array member of a C struct, like `data` in `struct Message { int len; char data[]; }`.
+///
+/// In the debug info this is an array of 0 elements at the end of the struct.
+/// The real length is the value of another member of the struct (see [DecodeOptions::flexible_array_lengths])
+/// and the elements are stored in memory after the struct.
+///
+/// If the length is not found, the array is read as the empty array the debug info says it is.
+#[allow(clippy::too_many_arguments)]
+fn read_flexible_array_member<W: funty::Integral>(
+    object: Pin<&TypeValueNode<W>>,
+    mut array: Pin<&mut TypeValueNode<W>>,
+    data: &BitSlice<u8, Lsb0>,
+    data_address: Option<u64>,
+    device_memory: &DeviceMemory<W>,
+    type_cache: &mut TypeCache<W>,
+    state: &mut DecodeState,
+) where
+    <W as funty::Numeric>::Bytes: bitvec::view::BitView<Store = u8>,
+{
+    let length = object
+        .iter()
+        .filter(|member| {
+            state.is_flexible_array_length(&object.data().variable_type.name, &member.data().name)
+        })
+        .find_map(|member| read_unsigned_value(member));
+
+    let (length, data_address) = match (length, data_address) {
+        (Some(length), Some(data_address)) if length > 0 => (length, data_address),
+        _ => {
+            read_variable_data(array, data, data_address, device_memory, type_cache, state);
+            return;
+        }
+    };
+
+    let stride = match array.data().variable_type.array_layout {
+        Some(ArrayLayout { stride, .. }) => stride,
+        None => unreachable!("Flexible array members are arrays"),
+    };
+    let elements_bit_size = stride.saturating_mul(length);
+    let address = data_address + array.data().bit_range.start / 8;
+
+    // The data is read from the start of the object, because that's what the bit range of the array is relative to
+    let object_data = state
+        .check_nodes(length)
+        .and_then(|()| {
+            let elements_byte_size = div_ceil(elements_bit_size, 8);
+            state.read_bytes(elements_byte_size)?;
+            device_memory
+                .read_slice_ref(data_address..address + elements_byte_size)?
+                .ok_or(VariableDataError::NoDataAvailable)
+        })
+        .inspect(|_| {
+            let array = array.data_mut();
+            array.bit_range.end = array.bit_range.start + elements_bit_size;
+            if let Some(layout) = &mut array.variable_type.array_layout {
+                layout.count = length;
+            }
+        });
+
+    match object_data {
+        Ok(object_data) => read_variable_data(
+            array,
+            object_data.view_bits(),
+            Some(data_address),
+            device_memory,
+            type_cache,
+            state,
+        ),
+        Err(e) => array.data_mut().variable_value = Err(e),
+    }
+}
+
+/// Gets the value of an integer that is not negative, looking through typedefs like `size_t`
+fn read_unsigned_value<W: funty::Integral>(node: &TypeValueNode<W>) -> Option<u64> {
+    match &node.data().variable_value {
+        Ok(Value::Uint(value)) => u64::try_from(*value).ok(),
+        Ok(Value::Int(value)) => u64::try_from(*value).ok(),
+        Ok(Value::Typedef) => read_unsigned_value(node.front()?),
+        _ => None,
+    }
+}
+
+/// Reads the elements of a `Vec<T>` and replaces the fields of the vec with them, so that it looks like an array.
+///
+/// A vec has a `len` field and a `buf` field that is a `RawVec`. The `RawVec` has a `ptr` field that points to the elements.
+/// That pointer is wrapped in a `Unique` and/or `NonNull`, which we need to look through.
+///
+/// Returns `None` if the layout of the vec is not recognized or if the elements could not be read.
+/// In that case the vec is left untouched.
+/// Returns an error if the length of the vec is corrupted, see [read_heap_elements].
+fn read_vec_elements<W: funty::Integral>(
+    variable: Pin<&mut TypeValueNode<W>>,
+    device_memory: &DeviceMemory<W>,
+    type_cache: &mut TypeCache<W>,
+    state: &mut DecodeState,
+) -> Option<Result<(), VariableDataError>>
+where
+    <W as funty::Numeric>::Bytes: bitvec::view::BitView<Store = u8>,
+{
+    let length = read_field_value(&variable, "len")?;
+    let (address, element_type) =
+        read_raw_vec_pointer(variable.iter().find(|field| field.data().name == "buf")?)?;
+
+    read_heap_elements(
+        variable,
+        address,
+        element_type,
+        0..length,
+        length,
+        device_memory,
+        type_cache,
+        state,
+    )
+}
+
+/// A `VecDeque` is a ring buffer in a `RawVec`, just like the one of a vec.
+/// The elements start at `head` and wrap around at the capacity of the buffer.
+///
+/// Older versions of the std library don't have a `len` field, but store the elements from `tail` up to `head`.
+///
+/// Returns `None` if the layout of the deque is not recognized or if the elements could not be read.
+/// In that case the deque is left untouched.
+/// Returns an error if the length of the deque is corrupted, see [read_heap_elements].
+fn read_vec_deque_elements<W: funty::Integral>(
+    variable: Pin<&mut TypeValueNode<W>>,
+    device_memory: &DeviceMemory<W>,
+    type_cache: &mut TypeCache<W>,
+    state: &mut DecodeState,
+) -> Option<Result<(), VariableDataError>>
+where
+    <W as funty::Numeric>::Bytes: bitvec::view::BitView<Store = u8>,
+{
+    let buf = variable.iter().find(|field| field.data().name == "buf")?;
+    let capacity = read_field_value(buf, "cap")?;
+    let (address, element_type) = read_raw_vec_pointer(buf)?;
+
+    let head = read_field_value(&variable, "head")?;
+    let (start, length) = match read_field_value(&variable, "len") {
+        Some(length) => (head, length),
+        None => {
+            // The old layout has a capacity that is a power of two
+            let tail = read_field_value(&variable, "tail")?;
+            (tail, head.wrapping_sub(tail) & capacity.wrapping_sub(1))
+        }
+    };
+
+    if length > capacity || (capacity > 0 && start >= capacity) {
+        return None;
+    }
+
+    read_heap_elements(
+        variable,
+        address,
+        element_type,
+        start..start + length,
+        capacity,
+        device_memory,
+        type_cache,
+        state,
+    )
+}
+
+/// Gets the unsigned value of a field, looking through wrappers like the `Cap(usize)` of a `RawVec`
+fn read_field_value<W: funty::Integral>(node: &TypeValueNode<W>, name: &str) -> Option<u64> {
+    let mut field = node.iter().find(|field| field.data().name == name)?;
+
+    loop {
+        match &field.data().variable_value {
+            Ok(Value::Uint(value)) => return u64::try_from(*value).ok(),
+            Ok(Value::Object) if field.iter().count() == 1 => field = field.front()?,
+            _ => return None,
+        }
+    }
+}
+
+/// Gets the address of the elements and the type of the elements that a `RawVec` points to
+fn read_raw_vec_pointer<W: funty::Integral>(
+    raw_vec: &TypeValueNode<W>,
+) -> Option<(W, DebugInfoOffset)> {
+    let mut pointer = raw_vec.iter().find(|field| field.data().name == "ptr")?;
+
+    loop {
+        match (
+            &pointer.data().variable_type.archetype,
+            &pointer.data().variable_value,
+        ) {
+            (Archetype::Pointer(element_type), Ok(Value::Address(address))) => {
+                break Some((*address, *element_type))
+            }
+            (Archetype::Structure, _) => {
+                pointer = pointer
+                    .iter()
+                    .find(|field| field.data().name == "pointer")?;
+            }
+            _ => return None,
         }
     }
 }
 
+/// Replaces the fields of a heap allocated collection with its elements, so that it looks like an array.
+///
+/// The elements are at the `indices` of the buffer at `address`. Indices past the `capacity` wrap around to the start.
+///
+/// The length and the address come from the device memory, so they can be corrupted.
+/// If the elements don't fit in the address space, a [VariableDataError::CollectionOverflow] is returned.
+#[allow(clippy::too_many_arguments)]
+fn read_heap_elements<W: funty::Integral>(
+    mut variable: Pin<&mut TypeValueNode<W>>,
+    address: W,
+    element_type: DebugInfoOffset,
+    indices: Range<u64>,
+    capacity: u64,
+    device_memory: &DeviceMemory<W>,
+    type_cache: &mut TypeCache<W>,
+    state: &mut DecodeState,
+) -> Option<Result<(), VariableDataError>>
+where
+    <W as funty::Numeric>::Bytes: bitvec::view::BitView<Store = u8>,
+{
+    let length = indices.end - indices.start;
+
+    let element_tree = TypeValueTree::clone(type_cache.get(&element_type)?.as_ref().ok()?);
+    let element_byte_size = div_ceil(element_tree.data().bit_range.end, 8);
+
+    let overflow_error = || VariableDataError::CollectionOverflow {
+        address: address.as_u64(),
+        length,
+        element_byte_size,
+    };
+
+    let byte_size = match length.checked_mul(element_byte_size) {
+        Some(byte_size) => byte_size,
+        None => return Some(Err(overflow_error())),
+    };
+
+    // Also cap collections of zero sized types, since we'd still be creating a node for every element
+    if byte_size.max(length) >= 64 * 1024 {
+        log::warn!(
+            "We started decoding the collection {}, but it is {byte_size} bytes long",
+            variable.data().name,
+        );
+        return None;
+    }
+
+    if state.check_bytes(byte_size).is_err() {
+        log::warn!(
+            "We started decoding the collection {}, but its {byte_size} bytes don't fit in the decode budget",
+            variable.data().name,
+        );
+        return None;
+    }
+
+    if state.check_nodes(length).is_err() {
+        log::warn!(
+            "We started decoding the collection {}, but its {length} elements don't fit in the decode budget",
+            variable.data().name,
+        );
+        return None;
+    }
+
+    // The elements are in at most two parts of the buffer: until its end and from its start after wrapping around
+    let parts = [
+        indices.start..indices.end.min(capacity),
+        0..indices.end.saturating_sub(capacity),
+    ];
+
+    let mut parts_data = Vec::new();
+    for part in parts.iter().filter(|part| !part.is_empty()) {
+        let part_address_range =
+            match element_address_range(address.as_u64(), part, element_byte_size) {
+                Some(part_address_range) => part_address_range,
+                None => return Some(Err(overflow_error())),
+            };
+        let part_address = part_address_range.start;
+        let data = match part_address_range.end - part_address_range.start {
+            0 => std::borrow::Cow::Borrowed(&[][..]),
+            byte_size => {
+                state.read_bytes(byte_size).ok()?;
+                device_memory.read_slice_ref(part_address_range).ok()??
+            }
+        };
+        parts_data.push((part_address, part.end - part.start, data));
+    }
+
+    // We've got everything, so now we can replace the fields with the elements
+    while variable.pop_front().is_some() {}
+
+    let elements = parts_data
+        .iter()
+        .flat_map(|(part_address, count, data)| {
+            (0..*count).map(move |index| {
+                (
+                    part_address + index * element_byte_size,
+                    &data[(index * element_byte_size) as usize
+                        ..((index + 1) * element_byte_size) as usize],
+                )
+            })
+        })
+        .enumerate();
+
+    for (index, (element_address, element_data)) in elements {
+        let mut element = element_tree.clone();
+        element.root_mut().data_mut().name = index.to_string();
+        read_variable_data(
+            element.root_mut(),
+            element_data.view_bits(),
+            Some(element_address),
+            device_memory,
+            type_cache,
+            state,
+        );
+        variable.push_back(element);
+    }
+
+    variable.data_mut().variable_value = Ok(Value::Array);
+
+    Some(Ok(()))
+}
+
+/// The address range of the elements at the `indices` of the buffer at `address`.
+/// Returns None if the range doesn't fit in the address space.
+fn element_address_range(
+    address: u64,
+    indices: &Range<u64>,
+    element_byte_size: u64,
+) -> Option<Range<u64>> {
+    let start = address.checked_add(indices.start.checked_mul(element_byte_size)?)?;
+    let end = address.checked_add(indices.end.checked_mul(element_byte_size)?)?;
+    Some(start..end)
+}
+
+/// Gets the bytes of a `String` object of which the `vec` field has been read as an array
+fn read_string_bytes<W: funty::Integral>(variable: &TypeValueNode<W>) -> Option<Vec<u8>> {
+    let vec = variable.iter().find(|field| field.data().name == "vec")?;
+
+    if !matches!(vec.data().variable_value, Ok(Value::Array)) {
+        return None;
+    }
+
+    vec.iter()
+        .map(|element| match element.data().variable_value {
+            Ok(Value::Uint(byte)) => Some(byte as u8),
+            _ => None,
+        })
+        .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
 fn read_variable_entry<W: funty::Integral>(
     dwarf: &Dwarf<DefaultReader>,
     unit: &Unit<DefaultReader, usize>,
@@ -1069,13 +2226,16 @@ fn read_variable_entry<W: funty::Integral>(
     device_memory: &DeviceMemory<W>,
     frame_base: Option<W>,
     entry: &DebuggingInformationEntry<DefaultReader, usize>,
-    type_cache: &mut HashMap<DebugInfoOffset, Result<TypeValueTree<W>, TraceError>>,
-) -> Result<Option<Variable<W>>, TraceError>
+    type_cache: &mut TypeCache<W>,
+    state: &mut DecodeState,
+) -> Result<Result<Variable<W>, OmittedVariable>, TraceError>
 where
     <W as funty::Numeric>::Bytes: bitvec::view::BitView<Store = u8>,
 {
     get_entry_abstract_origin_reference_tree_recursive!(
-        abstract_origin_tree = (dwarf, unit, abbreviations, entry)
+        abstract_origin_tree,
+        abstract_origin_unit,
+        abstract_origin_abbreviations = (dwarf, unit, abbreviations, entry)
     );
     let mut abstract_origin_tree = abstract_origin_tree.ok();
 
@@ -1089,7 +2249,7 @@ where
 
     // Alternatively, get the name from the abstract origin
     let mut variable_name = match (variable_name, abstract_origin_entry) {
-        (Err(_), Some(entry)) => get_entry_name(dwarf, unit, entry),
+        (Err(_), Some(entry)) => get_entry_name(dwarf, abstract_origin_unit, entry),
         (variable_name, _) => variable_name,
     };
 
@@ -1100,28 +2260,49 @@ where
 
     // Get the type of the variable or its abstract origin
     get_entry_type_reference_tree_recursive!(
-        variable_type_tree = (dwarf, unit, abbreviations, entry)
+        variable_type_tree,
+        type_unit,
+        type_abbreviations = (dwarf, unit, abbreviations, entry)
     );
 
     get_entry_abstract_origin_reference_tree_recursive!(
-        abstract_origin_tree = (dwarf, unit, abbreviations, entry)
+        abstract_origin_tree,
+        origin_unit,
+        origin_abbreviations = (dwarf, unit, abbreviations, entry)
     );
 
     let variable_type_value_tree = (|| match (variable_type_tree, abstract_origin_tree) {
         (Ok(mut variable_type_tree), _) => {
             let type_root = variable_type_tree.root()?;
-            build_type_value_tree(dwarf, unit, abbreviations, type_root, type_cache)
+            build_type_value_tree(
+                dwarf,
+                type_unit,
+                type_abbreviations,
+                type_root,
+                type_cache,
+                state,
+            )
         }
         (_, Ok(mut abstract_origin_tree)) => {
             let abstract_entry = abstract_origin_tree.root()?.entry().clone();
             get_entry_type_reference_tree_recursive!(
-                abstract_variable_type_tree = (dwarf, unit, abbreviations, &abstract_entry)
+                abstract_variable_type_tree,
+                abstract_type_unit,
+                abstract_type_abbreviations =
+                    (dwarf, origin_unit, origin_abbreviations, &abstract_entry)
             );
 
             match abstract_variable_type_tree {
                 Ok(mut abstract_variable_type_tree) => {
                     let type_root = abstract_variable_type_tree.root()?;
-                    build_type_value_tree(dwarf, unit, abbreviations, type_root, type_cache)
+                    build_type_value_tree(
+                        dwarf,
+                        abstract_type_unit,
+                        abstract_type_abbreviations,
+                        type_root,
+                        type_cache,
+                        state,
+                    )
                 }
                 Err(e) => Err(e),
             }
@@ -1143,12 +2324,23 @@ where
     if let (None, Some(abstract_origin_entry)) =
         (&variable_file_location.file, abstract_origin_entry)
     {
-        variable_file_location = find_entry_location(dwarf, unit, abstract_origin_entry)?;
+        variable_file_location =
+            find_entry_location(dwarf, abstract_origin_unit, abstract_origin_entry)?;
     }
 
     match (variable_name, variable_type_value_tree) {
-        (Ok(variable_name), Ok(variable_type_value_tree)) if variable_kind.zero_sized => {
-            Ok(Some(Variable {
+        (Ok(variable_name), Ok(mut variable_type_value_tree)) if variable_kind.zero_sized => {
+            // A zero-sized variable has no location, but it can still be read because it has no data
+            read_variable_data(
+                variable_type_value_tree.root_mut(),
+                BitSlice::empty(),
+                None,
+                device_memory,
+                type_cache,
+                state,
+            );
+
+            Ok(Ok(Variable {
                 name: variable_name,
                 kind: variable_kind,
                 type_value: variable_type_value_tree,
@@ -1164,17 +2356,37 @@ where
             };
 
             // Get the location of the variable
-            let variable_location =
-                evaluate_location(dwarf, unit, device_memory, location_attr, frame_base)?;
+            let variable_location = evaluate_location(
+                dwarf,
+                unit,
+                device_memory,
+                location_attr,
+                frame_base,
+                state.frame,
+            )?;
 
             log::debug!(
                 "Reading variable data for `{variable_name}` at {variable_location:X?} of {} bits",
                 variable_type_value_tree.data().bit_length()
             );
+            // If the variable is stored in one piece of memory, then we know its address
+            let variable_address = match &variable_location {
+                VariableLocationResult::LocationsFound(pieces) => match pieces.as_slice() {
+                    [Piece {
+                        location: gimli::Location::Address { address },
+                        bit_offset: None,
+                        ..
+                    }] => Some(*address),
+                    _ => None,
+                },
+                _ => None,
+            };
+
             let variable_data = get_variable_data(
                 device_memory,
                 variable_type_value_tree.data().bit_length(),
                 variable_location,
+                Some(state),
             );
 
             match variable_data {
@@ -1182,8 +2394,10 @@ where
                 Ok(variable_data) => read_variable_data(
                     variable_type_value_tree.root_mut(),
                     &variable_data,
+                    variable_address,
                     device_memory,
                     type_cache,
+                    state,
                 ),
                 // We couldn't get the data, so set the value to the error we got
                 Err(e) => {
@@ -1194,7 +2408,7 @@ where
                 }
             }
 
-            Ok(Some(Variable {
+            Ok(Ok(Variable {
                 name: variable_name,
                 kind: variable_kind,
                 type_value: variable_type_value_tree,
@@ -1208,7 +2422,10 @@ where
                 entry.offset().to_debug_info_offset(&unit.header),
                 type_error
             );
-            Ok(None)
+            Ok(Err(OmittedVariable {
+                name: Some(variable_name),
+                reason: format!("could not read the type: {type_error}"),
+            }))
         }
         (Err(name_error), _) => {
             log::debug!(
@@ -1216,7 +2433,13 @@ where
                 entry.offset().to_debug_info_offset(&unit.header),
                 name_error
             );
-            Ok(None)
+            Ok(Err(OmittedVariable {
+                name: None,
+                reason: format!(
+                    "could not get the name of entry {:X?}: {name_error}",
+                    entry.offset().to_debug_info_offset(&unit.header)
+                ),
+            }))
         }
     }
 }
@@ -1227,8 +2450,9 @@ pub fn find_variables_in_function<W: funty::Integral>(
     abbreviations: &Abbreviations,
     device_memory: &DeviceMemory<W>,
     node: gimli::EntriesTreeNode<DefaultReader>,
-    type_cache: &mut HashMap<DebugInfoOffset, Result<TypeValueTree<W>, TraceError>>,
-) -> Result<Vec<Variable<W>>, TraceError>
+    type_cache: &mut TypeCache<W>,
+    state: &mut DecodeState,
+) -> Result<(Vec<Variable<W>>, Vec<OmittedVariable>), TraceError>
 where
     <W as funty::Numeric>::Bytes: bitvec::view::BitView<Store = u8>,
 {
@@ -1240,8 +2464,10 @@ where
         device_memory: &DeviceMemory<W>,
         node: gimli::EntriesTreeNode<DefaultReader>,
         variables: &mut Vec<Variable<W>>,
+        omitted_variables: &mut Vec<OmittedVariable>,
         mut frame_base: Option<W>,
-        type_cache: &mut HashMap<DebugInfoOffset, Result<TypeValueTree<W>, TraceError>>,
+        type_cache: &mut TypeCache<W>,
+        state: &mut DecodeState,
     ) -> Result<(), TraceError>
     where
         <W as funty::Numeric>::Bytes: bitvec::view::BitView<Store = u8>,
@@ -1253,14 +2479,16 @@ where
             unit.header.offset().as_debug_info_offset().unwrap().0 + entry.offset().0
         );
 
-        if let Some(new_frame_base) = try_read_frame_base(dwarf, unit, device_memory, entry)? {
+        if let Some(new_frame_base) =
+            try_read_frame_base(dwarf, unit, device_memory, entry, state.frame)?
+        {
             frame_base = Some(new_frame_base);
         }
 
         if entry.tag() == gimli::constants::DW_TAG_variable
             || entry.tag() == gimli::constants::DW_TAG_formal_parameter
         {
-            if let Some(variable) = read_variable_entry(
+            match read_variable_entry(
                 dwarf,
                 unit,
                 abbreviations,
@@ -1268,8 +2496,10 @@ where
                 frame_base,
                 entry,
                 type_cache,
+                state,
             )? {
-                variables.push(variable);
+                Ok(variable) => variables.push(variable),
+                Err(omitted_variable) => omitted_variables.push(omitted_variable),
             }
         }
 
@@ -1282,8 +2512,10 @@ where
                 device_memory,
                 child,
                 variables,
+                omitted_variables,
                 frame_base,
                 type_cache,
+                state,
             )?;
         }
 
@@ -1291,6 +2523,7 @@ where
     }
 
     let mut variables = Vec::new();
+    let mut omitted_variables = Vec::new();
     recursor(
         dwarf,
         unit,
@@ -1298,20 +2531,24 @@ where
         device_memory,
         node,
         &mut variables,
+        &mut omitted_variables,
         None,
         type_cache,
+        state,
     )?;
-    Ok(variables)
+    Ok((variables, omitted_variables))
 }
 
 pub fn find_static_variables<W: funty::Integral>(
     dwarf: &Dwarf<DefaultReader>,
     device_memory: &DeviceMemory<W>,
-    type_cache: &mut HashMap<DebugInfoOffset, Result<TypeValueTree<W>, TraceError>>,
-) -> Result<Vec<Variable<W>>, TraceError>
+    type_cache: &mut TypeCache<W>,
+    state: &mut DecodeState,
+) -> Result<(Vec<Variable<W>>, Vec<OmittedVariable>), TraceError>
 where
     <W as funty::Numeric>::Bytes: bitvec::view::BitView<Store = u8>,
 {
+    #[allow(clippy::too_many_arguments)]
     fn recursor<W: funty::Integral>(
         dwarf: &Dwarf<DefaultReader>,
         unit: &Unit<DefaultReader, usize>,
@@ -1319,7 +2556,9 @@ where
         device_memory: &DeviceMemory<W>,
         node: gimli::EntriesTreeNode<DefaultReader>,
         variables: &mut Vec<Variable<W>>,
-        type_cache: &mut HashMap<DebugInfoOffset, Result<TypeValueTree<W>, TraceError>>,
+        omitted_variables: &mut Vec<OmittedVariable>,
+        type_cache: &mut TypeCache<W>,
+        state: &mut DecodeState,
     ) -> Result<(), TraceError>
     where
         <W as funty::Numeric>::Bytes: bitvec::view::BitView<Store = u8>,
@@ -1335,14 +2574,18 @@ where
             | gimli::constants::DW_TAG_base_type
             | gimli::constants::DW_TAG_array_type
             | gimli::constants::DW_TAG_pointer_type
+            | gimli::constants::DW_TAG_reference_type
+            | gimli::constants::DW_TAG_rvalue_reference_type
             | gimli::constants::DW_TAG_subroutine_type
             | gimli::constants::DW_TAG_typedef
             | gimli::constants::DW_TAG_restrict_type
+            | gimli::constants::DW_TAG_atomic_type
             | gimli::constants::DW_TAG_const_type
             | gimli::constants::DW_TAG_union_type
+            | gimli::constants::DW_TAG_unspecified_type
             | gimli::constants::DW_TAG_volatile_type => return Ok(()),
             gimli::constants::DW_TAG_variable => {
-                if let Some(variable) = read_variable_entry(
+                match read_variable_entry(
                     dwarf,
                     unit,
                     abbreviations,
@@ -1350,8 +2593,24 @@ where
                     None,
                     entry,
                     type_cache,
-                )? {
-                    variables.push(variable);
+                    state,
+                ) {
+                    Ok(Ok(variable)) => variables.push(variable),
+                    Ok(Err(omitted_variable)) => omitted_variables.push(omitted_variable),
+                    // One broken static shouldn't take all the other statics down with it
+                    Err(e) => {
+                        let name = get_entry_name(dwarf, unit, entry).ok();
+                        log::warn!(
+                            "Could not read static variable `{}` of entry {:X?}: {}",
+                            name.as_deref().unwrap_or("<unnamed>"),
+                            entry.offset().to_debug_info_offset(&unit.header),
+                            e
+                        );
+                        omitted_variables.push(OmittedVariable {
+                            name,
+                            reason: e.to_string(),
+                        });
+                    }
                 }
             }
             tag => {
@@ -1373,7 +2632,9 @@ where
                 device_memory,
                 child,
                 variables,
+                omitted_variables,
                 type_cache,
+                state,
             )?;
         }
 
@@ -1381,19 +2642,33 @@ where
     }
 
     let mut variables = Vec::new();
+    let mut omitted_variables = Vec::new();
     let mut units = dwarf.units();
     while let Some(unit_header) = units.next()? {
-        let abbreviations = dwarf.abbreviations(&unit_header)?;
-        recursor(
-            dwarf,
-            &dwarf.unit(unit_header.clone())?,
-            &abbreviations,
-            device_memory,
-            unit_header.entries_tree(&abbreviations, None)?.root()?,
-            &mut variables,
-            type_cache,
-        )?;
+        let unit_result = (|| {
+            let abbreviations = dwarf.abbreviations(&unit_header)?;
+            recursor(
+                dwarf,
+                &dwarf.unit(unit_header.clone())?,
+                &abbreviations,
+                device_memory,
+                unit_header.entries_tree(&abbreviations, None)?.root()?,
+                &mut variables,
+                &mut omitted_variables,
+                type_cache,
+                state,
+            )
+        })();
+
+        // A unit that can't be read only loses its own statics. The ones found before the error are kept.
+        if let Err(e) = unit_result {
+            log::error!(
+                "Could not read the static variables of the unit at {:X?}: {}",
+                unit_header.offset(),
+                e
+            );
+        }
     }
 
-    Ok(variables)
+    Ok((variables, omitted_variables))
 }
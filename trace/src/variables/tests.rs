@@ -0,0 +1,2913 @@
+use super::{find_static_variables, DecodeState};
+use crate::{
+    render_colors::Theme,
+    type_value_tree::{
+        rendering::{
+            render_type_value_tree, render_type_value_tree_with_options, IntRadix, RenderOptions,
+        },
+        VariableDataError,
+    },
+    DecodeBudget, DecodeOptions, DefaultReader, Variable,
+};
+use gimli::{
+    write::{self, Address, AttributeValue, Expression, UnitEntryId, UnitId},
+    Encoding, EndianReader, Format, LittleEndian, RunTimeEndian, SectionId,
+};
+use stackdump_core::{
+    device_memory::{DeviceMemory, EvaluationFrame, EvaluationStep},
+    memory_region::{MemoryRegionKind, VecMemoryRegion},
+    register_data::VecRegisterData,
+};
+use std::{collections::HashMap, rc::Rc};
+
+/// Helper for creating small bits of debug info to test the variable decoding with
+struct DwarfBuilder {
+    dwarf: write::Dwarf,
+    unit: UnitId,
+}
+
+impl DwarfBuilder {
+    fn new() -> Self {
+        Self::with_version(4)
+    }
+
+    fn with_version(version: u16) -> Self {
+        let encoding = Encoding {
+            format: Format::Dwarf32,
+            version,
+            address_size: 4,
+        };
+
+        let mut dwarf = write::Dwarf::new();
+        let unit = dwarf
+            .units
+            .add(write::Unit::new(encoding, write::LineProgram::none()));
+
+        Self { dwarf, unit }
+    }
+
+    /// Adds a new compilation unit. All entries that are added after this go into the new unit.
+    fn add_unit(&mut self) -> UnitId {
+        let encoding = self.dwarf.units.get(self.unit).encoding();
+        self.unit = self
+            .dwarf
+            .units
+            .add(write::Unit::new(encoding, write::LineProgram::none()));
+        self.unit
+    }
+
+    fn root(&self) -> UnitEntryId {
+        self.dwarf.units.get(self.unit).root()
+    }
+
+    fn add(
+        &mut self,
+        parent: UnitEntryId,
+        tag: gimli::DwTag,
+        attributes: Vec<(gimli::DwAt, AttributeValue)>,
+    ) -> UnitEntryId {
+        let unit = self.dwarf.units.get_mut(self.unit);
+        let id = unit.add(parent, tag);
+        for (name, value) in attributes {
+            unit.get_mut(id).set(name, value);
+        }
+        id
+    }
+
+    fn add_base_type(&mut self, name: &str, encoding: gimli::DwAte, byte_size: u64) -> UnitEntryId {
+        self.add(
+            self.root(),
+            gimli::constants::DW_TAG_base_type,
+            vec![
+                (
+                    gimli::constants::DW_AT_name,
+                    AttributeValue::String(name.into()),
+                ),
+                (
+                    gimli::constants::DW_AT_encoding,
+                    AttributeValue::Encoding(encoding),
+                ),
+                (
+                    gimli::constants::DW_AT_byte_size,
+                    AttributeValue::Udata(byte_size),
+                ),
+            ],
+        )
+    }
+
+    fn add_type_modifier(&mut self, tag: gimli::DwTag, base_type: UnitEntryId) -> UnitEntryId {
+        self.add(
+            self.root(),
+            tag,
+            vec![(
+                gimli::constants::DW_AT_type,
+                AttributeValue::UnitRef(base_type),
+            )],
+        )
+    }
+
+    fn add_structure(
+        &mut self,
+        name: &str,
+        byte_size: u64,
+        members: &[(&str, UnitEntryId, u64)],
+    ) -> UnitEntryId {
+        let structure = self.add(
+            self.root(),
+            gimli::constants::DW_TAG_structure_type,
+            vec![
+                (
+                    gimli::constants::DW_AT_name,
+                    AttributeValue::String(name.into()),
+                ),
+                (
+                    gimli::constants::DW_AT_byte_size,
+                    AttributeValue::Udata(byte_size),
+                ),
+            ],
+        );
+
+        for (member_name, member_type, member_offset) in members {
+            self.add(
+                structure,
+                gimli::constants::DW_TAG_member,
+                vec![
+                    (
+                        gimli::constants::DW_AT_name,
+                        AttributeValue::String((*member_name).into()),
+                    ),
+                    (
+                        gimli::constants::DW_AT_type,
+                        AttributeValue::UnitRef(*member_type),
+                    ),
+                    (
+                        gimli::constants::DW_AT_data_member_location,
+                        AttributeValue::Udata(*member_offset),
+                    ),
+                ],
+            );
+        }
+
+        structure
+    }
+
+    fn add_enumeration(
+        &mut self,
+        name: &str,
+        base_type: UnitEntryId,
+        enumerators: &[(&str, i64)],
+    ) -> UnitEntryId {
+        let enumeration =
+            self.add_type_modifier(gimli::constants::DW_TAG_enumeration_type, base_type);
+        self.dwarf
+            .units
+            .get_mut(self.unit)
+            .get_mut(enumeration)
+            .set(
+                gimli::constants::DW_AT_name,
+                AttributeValue::String(name.into()),
+            );
+
+        for (enumerator_name, value) in enumerators {
+            self.add(
+                enumeration,
+                gimli::constants::DW_TAG_enumerator,
+                vec![
+                    (
+                        gimli::constants::DW_AT_name,
+                        AttributeValue::String((*enumerator_name).into()),
+                    ),
+                    (
+                        gimli::constants::DW_AT_const_value,
+                        AttributeValue::Sdata(*value),
+                    ),
+                ],
+            );
+        }
+
+        enumeration
+    }
+
+    /// Adds a Rust style enum with the discriminant at offset 0.
+    /// Every variant has a member of the given type that is active when the discriminant matches the attribute.
+    /// A variant without an attribute is the default one.
+    #[allow(clippy::type_complexity)]
+    fn add_tagged_union(
+        &mut self,
+        name: &str,
+        byte_size: u64,
+        discriminant_type: UnitEntryId,
+        variants: &[(&str, UnitEntryId, Option<(gimli::DwAt, AttributeValue)>)],
+    ) -> UnitEntryId {
+        let root = self.root();
+        let tagged_union = self.add(
+            root,
+            gimli::constants::DW_TAG_structure_type,
+            vec![
+                (
+                    gimli::constants::DW_AT_name,
+                    AttributeValue::String(name.into()),
+                ),
+                (
+                    gimli::constants::DW_AT_byte_size,
+                    AttributeValue::Udata(byte_size),
+                ),
+            ],
+        );
+        let variant_part = self.add(tagged_union, gimli::constants::DW_TAG_variant_part, vec![]);
+        let discriminant = self.add(
+            variant_part,
+            gimli::constants::DW_TAG_member,
+            vec![
+                (
+                    gimli::constants::DW_AT_type,
+                    AttributeValue::UnitRef(discriminant_type),
+                ),
+                (
+                    gimli::constants::DW_AT_data_member_location,
+                    AttributeValue::Udata(0),
+                ),
+            ],
+        );
+        self.dwarf
+            .units
+            .get_mut(self.unit)
+            .get_mut(variant_part)
+            .set(
+                gimli::constants::DW_AT_discr,
+                AttributeValue::UnitRef(discriminant),
+            );
+
+        for (variant_name, variant_type, discriminant_attribute) in variants {
+            let variant = self.add(
+                variant_part,
+                gimli::constants::DW_TAG_variant,
+                discriminant_attribute.iter().cloned().collect(),
+            );
+            self.add(
+                variant,
+                gimli::constants::DW_TAG_member,
+                vec![
+                    (
+                        gimli::constants::DW_AT_name,
+                        AttributeValue::String((*variant_name).into()),
+                    ),
+                    (
+                        gimli::constants::DW_AT_type,
+                        AttributeValue::UnitRef(*variant_type),
+                    ),
+                    (
+                        gimli::constants::DW_AT_data_member_location,
+                        AttributeValue::Udata(0),
+                    ),
+                ],
+            );
+        }
+
+        tagged_union
+    }
+
+    fn add_array(&mut self, element_type: UnitEntryId, count: u64) -> UnitEntryId {
+        let array = self.add_type_modifier(gimli::constants::DW_TAG_array_type, element_type);
+        self.add(
+            array,
+            gimli::constants::DW_TAG_subrange_type,
+            vec![
+                (
+                    gimli::constants::DW_AT_lower_bound,
+                    AttributeValue::Sdata(0),
+                ),
+                (gimli::constants::DW_AT_count, AttributeValue::Udata(count)),
+            ],
+        );
+        array
+    }
+
+    /// Adds a C flexible array member type (`T data[]`), which is an array without a count or upper bound
+    fn add_flexible_array(&mut self, element_type: UnitEntryId) -> UnitEntryId {
+        let array = self.add_type_modifier(gimli::constants::DW_TAG_array_type, element_type);
+        self.add(array, gimli::constants::DW_TAG_subrange_type, vec![]);
+        array
+    }
+
+    /// Adds a `Vec<T>` with the same layout as in the Rust std
+    fn add_vec(&mut self, element_type: UnitEntryId, element_type_name: &str) -> UnitEntryId {
+        let usize_type = self.add_base_type("usize", gimli::constants::DW_ATE_unsigned, 4);
+        let raw_vec = self.add_raw_vec(element_type, element_type_name);
+        self.add_structure(
+            &format!("Vec<{element_type_name}, alloc::alloc::Global>"),
+            12,
+            &[("buf", raw_vec, 0), ("len", usize_type, 8)],
+        )
+    }
+
+    fn add_vec_deque(&mut self, element_type: UnitEntryId, element_type_name: &str) -> UnitEntryId {
+        let usize_type = self.add_base_type("usize", gimli::constants::DW_ATE_unsigned, 4);
+        let raw_vec = self.add_raw_vec(element_type, element_type_name);
+        self.add_structure(
+            &format!("VecDeque<{element_type_name}, alloc::alloc::Global>"),
+            16,
+            &[
+                ("head", usize_type, 0),
+                ("len", usize_type, 4),
+                ("buf", raw_vec, 8),
+            ],
+        )
+    }
+
+    fn add_raw_vec(&mut self, element_type: UnitEntryId, element_type_name: &str) -> UnitEntryId {
+        let usize_type = self.add_base_type("usize", gimli::constants::DW_ATE_unsigned, 4);
+        let pointer = self.add_type_modifier(gimli::constants::DW_TAG_pointer_type, element_type);
+        let non_null = self.add_structure(
+            &format!("NonNull<{element_type_name}>"),
+            4,
+            &[("pointer", pointer, 0)],
+        );
+        let unique = self.add_structure(
+            &format!("Unique<{element_type_name}>"),
+            4,
+            &[("pointer", non_null, 0)],
+        );
+        self.add_structure(
+            &format!("RawVec<{element_type_name}, alloc::alloc::Global>"),
+            8,
+            &[("ptr", unique, 0), ("cap", usize_type, 4)],
+        )
+    }
+
+    fn add_static_variable(
+        &mut self,
+        name: &str,
+        variable_type: UnitEntryId,
+        address: u64,
+    ) -> UnitEntryId {
+        self.add(
+            self.root(),
+            gimli::constants::DW_TAG_variable,
+            vec![
+                (
+                    gimli::constants::DW_AT_name,
+                    AttributeValue::String(name.into()),
+                ),
+                (
+                    gimli::constants::DW_AT_type,
+                    AttributeValue::UnitRef(variable_type),
+                ),
+                (
+                    gimli::constants::DW_AT_location,
+                    AttributeValue::Exprloc({
+                        let mut expression = Expression::new();
+                        expression.op_addr(Address::Constant(address));
+                        expression
+                    }),
+                ),
+            ],
+        )
+    }
+
+    fn build(mut self) -> gimli::Dwarf<DefaultReader> {
+        let mut sections = write::Sections::new(write::EndianVec::new(LittleEndian));
+        self.dwarf.write(&mut sections).unwrap();
+
+        let mut section_data = HashMap::new();
+        sections
+            .for_each(|id, data| {
+                section_data.insert(id, data.slice().to_vec());
+                Ok::<_, ()>(())
+            })
+            .unwrap();
+
+        gimli::Dwarf::load(|id: SectionId| {
+            Ok::<_, ()>(EndianReader::new(
+                Rc::from(section_data.get(&id).cloned().unwrap_or_default()),
+                RunTimeEndian::Little,
+            ))
+        })
+        .unwrap()
+    }
+}
+
+fn read_static_variable(
+    dwarf: &gimli::Dwarf<DefaultReader>,
+    device_memory: &DeviceMemory<u32>,
+    name: &str,
+) -> Variable<u32> {
+    read_static_variable_with_options(dwarf, device_memory, name, DecodeOptions::default())
+}
+
+fn read_static_variable_with_budget(
+    dwarf: &gimli::Dwarf<DefaultReader>,
+    device_memory: &DeviceMemory<u32>,
+    name: &str,
+    budget: DecodeBudget,
+) -> Variable<u32> {
+    read_static_variable_with_options(
+        dwarf,
+        device_memory,
+        name,
+        DecodeOptions {
+            budget,
+            ..Default::default()
+        },
+    )
+}
+
+fn read_static_variable_with_options(
+    dwarf: &gimli::Dwarf<DefaultReader>,
+    device_memory: &DeviceMemory<u32>,
+    name: &str,
+    options: DecodeOptions,
+) -> Variable<u32> {
+    find_static_variables(
+        dwarf,
+        device_memory,
+        &mut HashMap::new(),
+        &mut DecodeState::new(options),
+    )
+    .unwrap()
+    .0
+    .into_iter()
+    .find(|variable| variable.name == name)
+    .unwrap()
+}
+
+#[test]
+fn references() {
+    let mut builder = DwarfBuilder::new();
+    let u32_type = builder.add_base_type("u32", gimli::constants::DW_ATE_unsigned, 4);
+    let reference = builder.add_type_modifier(gimli::constants::DW_TAG_reference_type, u32_type);
+    let rvalue_reference =
+        builder.add_type_modifier(gimli::constants::DW_TAG_rvalue_reference_type, u32_type);
+    builder.add_static_variable("REFERENCE", reference, 0x2000_0000);
+    builder.add_static_variable("RVALUE_REFERENCE", rvalue_reference, 0x2000_0004);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0000,
+        [
+            0x2000_0008u32.to_le_bytes(),
+            0u32.to_le_bytes(),
+            5u32.to_le_bytes(),
+        ]
+        .concat(),
+    ));
+
+    let reference = read_static_variable(&dwarf, &device_memory, "REFERENCE");
+    assert_eq!(reference.type_value.data().variable_type.name, "&u32");
+    assert_eq!(
+        render_type_value_tree(&reference.type_value, Theme::None).to_string(),
+        "5"
+    );
+
+    let rvalue_reference = read_static_variable(&dwarf, &device_memory, "RVALUE_REFERENCE");
+    assert_eq!(
+        rvalue_reference.type_value.data().variable_type.name,
+        "&&u32"
+    );
+    assert_eq!(
+        render_type_value_tree(&rvalue_reference.type_value, Theme::None).to_string(),
+        "{null reference} <raw: [00, 00, 00, 00]>"
+    );
+    assert!(matches!(
+        rvalue_reference.type_value.data().variable_value,
+        Err(VariableDataError::NullReference)
+    ));
+}
+
+#[test]
+fn chars() {
+    let mut builder = DwarfBuilder::new();
+    let rust_char = builder.add_base_type("char", gimli::constants::DW_ATE_UTF, 4);
+    let c_char = builder.add_base_type("char", gimli::constants::DW_ATE_unsigned_char, 1);
+    let unsigned_char =
+        builder.add_base_type("unsigned char", gimli::constants::DW_ATE_unsigned_char, 1);
+    builder.add_static_variable("RUST_CHAR", rust_char, 0x2000_0000);
+    builder.add_static_variable("INVALID_RUST_CHAR", rust_char, 0x2000_0004);
+    builder.add_static_variable("C_CHAR", c_char, 0x2000_0008);
+    builder.add_static_variable("NON_ASCII_C_CHAR", c_char, 0x2000_0009);
+    builder.add_static_variable("UNSIGNED_CHAR", unsigned_char, 0x2000_000A);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0000,
+        [
+            &('é' as u32).to_le_bytes()[..],
+            &0xD800u32.to_le_bytes(),
+            b"a",
+            &[0x80],
+            b"A",
+        ]
+        .concat(),
+    ));
+
+    let render = |name| {
+        let variable = read_static_variable(&dwarf, &device_memory, name);
+        render_type_value_tree(&variable.type_value, Theme::None).to_string()
+    };
+
+    assert_eq!(render("RUST_CHAR"), "'é'");
+    assert_eq!(
+        render("INVALID_RUST_CHAR"),
+        "{invalid char (0xD800)} <raw: [00, D8, 00, 00]>"
+    );
+    assert_eq!(render("C_CHAR"), "'a'");
+    assert_eq!(render("NON_ASCII_C_CHAR"), "'\\x80'");
+    assert_eq!(render("UNSIGNED_CHAR"), "65");
+}
+
+#[test]
+fn vec_and_string() {
+    let mut builder = DwarfBuilder::new();
+    let u32_type = builder.add_base_type("u32", gimli::constants::DW_ATE_unsigned, 4);
+    let u8_type = builder.add_base_type("u8", gimli::constants::DW_ATE_unsigned, 1);
+    let u32_vec = builder.add_vec(u32_type, "u32");
+    let u8_vec = builder.add_vec(u8_type, "u8");
+    let string = builder.add_structure("String", 12, &[("vec", u8_vec, 0)]);
+    builder.add_static_variable("VEC", u32_vec, 0x2000_0000);
+    builder.add_static_variable("STRING", string, 0x2000_000C);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0000,
+        [
+            // VEC: ptr, cap, len
+            0x2000_1000u32.to_le_bytes(),
+            4u32.to_le_bytes(),
+            3u32.to_le_bytes(),
+            // STRING: ptr, cap, len
+            0x2000_2000u32.to_le_bytes(),
+            8u32.to_le_bytes(),
+            5u32.to_le_bytes(),
+        ]
+        .concat(),
+    ));
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_1000,
+        [1u32.to_le_bytes(), 2u32.to_le_bytes(), 3u32.to_le_bytes()].concat(),
+    ));
+    device_memory.add_memory_region(VecMemoryRegion::new(0x2000_2000, b"hello".to_vec()));
+
+    let vec = read_static_variable(&dwarf, &device_memory, "VEC");
+    assert_eq!(
+        render_type_value_tree(&vec.type_value, Theme::None).to_string(),
+        "[1, 2, 3]"
+    );
+
+    let string = read_static_variable(&dwarf, &device_memory, "STRING");
+    assert_eq!(
+        render_type_value_tree(&string.type_value, Theme::None).to_string(),
+        "\"hello\""
+    );
+}
+
+#[test]
+fn vec_deque() {
+    let mut builder = DwarfBuilder::new();
+    let u32_type = builder.add_base_type("u32", gimli::constants::DW_ATE_unsigned, 4);
+    let u32_vec_deque = builder.add_vec_deque(u32_type, "u32");
+    builder.add_static_variable("DEQUE", u32_vec_deque, 0x2000_0000);
+    builder.add_static_variable("EMPTY_DEQUE", u32_vec_deque, 0x2000_0010);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0000,
+        [
+            // DEQUE: head, len, ptr, cap
+            2u32.to_le_bytes(),
+            3u32.to_le_bytes(),
+            0x2000_1000u32.to_le_bytes(),
+            4u32.to_le_bytes(),
+            // EMPTY_DEQUE: head, len, ptr, cap
+            0u32.to_le_bytes(),
+            0u32.to_le_bytes(),
+            4u32.to_le_bytes(),
+            0u32.to_le_bytes(),
+        ]
+        .concat(),
+    ));
+    // The elements start at index 2 and wrap around to index 0
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_1000,
+        [
+            3u32.to_le_bytes(),
+            0u32.to_le_bytes(),
+            1u32.to_le_bytes(),
+            2u32.to_le_bytes(),
+        ]
+        .concat(),
+    ));
+
+    let deque = read_static_variable(&dwarf, &device_memory, "DEQUE");
+    assert_eq!(
+        render_type_value_tree(&deque.type_value, Theme::None).to_string(),
+        "[1, 2, 3]"
+    );
+
+    let empty_deque = read_static_variable(&dwarf, &device_memory, "EMPTY_DEQUE");
+    assert_eq!(
+        render_type_value_tree(&empty_deque.type_value, Theme::None).to_string(),
+        "[]"
+    );
+}
+
+#[test]
+fn vec_without_heap_data() {
+    let mut builder = DwarfBuilder::new();
+    let u32_type = builder.add_base_type("u32", gimli::constants::DW_ATE_unsigned, 4);
+    let u32_vec = builder.add_vec(u32_type, "u32");
+    builder.add_static_variable("VEC", u32_vec, 0x2000_0000);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0000,
+        [
+            0x2000_1000u32.to_le_bytes(),
+            4u32.to_le_bytes(),
+            3u32.to_le_bytes(),
+        ]
+        .concat(),
+    ));
+
+    // The heap is not available, so the vec is shown as a normal object
+    let vec = read_static_variable(&dwarf, &device_memory, "VEC");
+    assert!(render_type_value_tree(&vec.type_value, Theme::None)
+        .to_string()
+        .starts_with("Vec<u32, alloc::alloc::Global> { buf: "));
+}
+
+#[test]
+fn vec_with_corrupted_length() {
+    let mut builder = DwarfBuilder::new();
+    let u32_type = builder.add_base_type("u32", gimli::constants::DW_ATE_unsigned, 4);
+    let huge_type = builder.add_structure("Huge", 1 << 59, &[]);
+    let u32_vec = builder.add_vec(u32_type, "u32");
+    let huge_vec = builder.add_vec(huge_type, "Huge");
+    builder.add_static_variable("VEC", u32_vec, 0x2000_0000);
+    builder.add_static_variable("HUGE_VEC", huge_vec, 0x2000_000C);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0000,
+        [
+            // VEC: ptr, cap, len
+            0x2000_1000u32.to_le_bytes(),
+            4u32.to_le_bytes(),
+            3u32.to_le_bytes(),
+            // HUGE_VEC: ptr, cap, len
+            0x2000_1000u32.to_le_bytes(),
+            64u32.to_le_bytes(),
+            64u32.to_le_bytes(),
+        ]
+        .concat(),
+    ));
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_1000,
+        [1u32.to_le_bytes(), 2u32.to_le_bytes(), 3u32.to_le_bytes()].concat(),
+    ));
+
+    // The byte size of the elements overflows
+    let huge_vec = read_static_variable(&dwarf, &device_memory, "HUGE_VEC");
+    assert_eq!(
+        render_type_value_tree(&huge_vec.type_value, Theme::None).to_string(),
+        "{The collection at 0x20001000 with 64 elements of 576460752303423488 bytes does not fit in the address space}"
+    );
+
+    // The 12 bytes of the vec fit in the budget, but its 12 bytes of elements don't.
+    // So nothing is read from the heap and the vec is shown as a normal object
+    let vec = read_static_variable_with_budget(
+        &dwarf,
+        &device_memory,
+        "VEC",
+        DecodeBudget {
+            max_bytes_read: 20,
+            ..DecodeBudget::UNLIMITED
+        },
+    );
+    assert!(render_type_value_tree(&vec.type_value, Theme::None)
+        .to_string()
+        .starts_with("Vec<u32, alloc::alloc::Global> { buf: "));
+}
+
+#[test]
+fn arrays() {
+    let mut builder = DwarfBuilder::new();
+    let u16_type = builder.add_base_type("u16", gimli::constants::DW_ATE_unsigned, 2);
+    let array = builder.add_array(u16_type, 3);
+    let arrays = builder.add_array(array, 2);
+    builder.add_static_variable("ARRAY", array, 0x2000_0000);
+    builder.add_static_variable("ARRAYS", arrays, 0x2000_0000);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0000,
+        [1u16, 2, 3, 4, 5, 6]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect(),
+    ));
+
+    let array = read_static_variable(&dwarf, &device_memory, "ARRAY");
+    assert_eq!(array.type_value.data().variable_type.name, "[u16;3]");
+    assert_eq!(
+        render_type_value_tree(&array.type_value, Theme::None).to_string(),
+        "[1, 2, 3]"
+    );
+
+    let array = read_static_variable(&dwarf, &device_memory, "ARRAYS");
+    assert_eq!(array.type_value.data().variable_type.name, "[[u16;3];2]");
+    assert_eq!(
+        render_type_value_tree(&array.type_value, Theme::None).to_string(),
+        "[[1, 2, 3], [4, 5, 6]]"
+    );
+}
+
+#[test]
+fn data_location_array() {
+    let mut builder = DwarfBuilder::new();
+    let u32_type = builder.add_base_type("u32", gimli::constants::DW_ATE_unsigned, 4);
+    let array = builder.add_array(u32_type, 3);
+    // The array object is a descriptor that holds a pointer to the elements
+    builder
+        .dwarf
+        .units
+        .get_mut(builder.unit)
+        .get_mut(array)
+        .set(
+            gimli::constants::DW_AT_data_location,
+            AttributeValue::Exprloc({
+                let mut expression = Expression::new();
+                expression.op(gimli::constants::DW_OP_push_object_address);
+                expression.op_deref();
+                expression
+            }),
+        );
+    builder.add_static_variable("ARRAY", array, 0x2000_0000);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0000,
+        0x2000_1000u32.to_le_bytes().to_vec(),
+    ));
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_1000,
+        [1u32.to_le_bytes(), 2u32.to_le_bytes(), 3u32.to_le_bytes()].concat(),
+    ));
+
+    let array = read_static_variable(&dwarf, &device_memory, "ARRAY");
+    assert_eq!(array.type_value.data().variable_type.name, "[u32;3]");
+    assert_eq!(
+        render_type_value_tree(&array.type_value, Theme::None).to_string(),
+        "[1, 2, 3]"
+    );
+}
+
+#[test]
+fn unspecified_types() {
+    let mut builder = DwarfBuilder::new();
+    let u32_type = builder.add_base_type("u32", gimli::constants::DW_ATE_unsigned, 4);
+    let never_type = builder.add(
+        builder.root(),
+        gimli::constants::DW_TAG_unspecified_type,
+        vec![],
+    );
+    let nullptr_type = builder.add(
+        builder.root(),
+        gimli::constants::DW_TAG_unspecified_type,
+        vec![(
+            gimli::constants::DW_AT_name,
+            AttributeValue::String("decltype(nullptr)".into()),
+        )],
+    );
+    let structure =
+        builder.add_structure("Foo", 4, &[("a", u32_type, 0), ("never", never_type, 4)]);
+    builder.add_static_variable("FOO", structure, 0x2000_0000);
+    builder.add_static_variable("NULLPTR", nullptr_type, 0x2000_0004);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0000,
+        5u32.to_le_bytes().to_vec(),
+    ));
+
+    let foo = read_static_variable(&dwarf, &device_memory, "FOO");
+    assert_eq!(
+        render_type_value_tree(&foo.type_value, Theme::None).to_string(),
+        "Foo { a: 5, never: ! }"
+    );
+
+    let nullptr = read_static_variable(&dwarf, &device_memory, "NULLPTR");
+    assert_eq!(nullptr.type_value.data().variable_type.name, "nullptr_t");
+    assert_eq!(
+        render_type_value_tree(&nullptr.type_value, Theme::None).to_string(),
+        "nullptr_t"
+    );
+}
+
+#[test]
+fn thread_local_variables() {
+    let mut builder = DwarfBuilder::new();
+    let u32_type = builder.add_base_type("u32", gimli::constants::DW_ATE_unsigned, 4);
+    builder.add(
+        builder.root(),
+        gimli::constants::DW_TAG_variable,
+        vec![
+            (
+                gimli::constants::DW_AT_name,
+                AttributeValue::String("THREAD_LOCAL".into()),
+            ),
+            (
+                gimli::constants::DW_AT_type,
+                AttributeValue::UnitRef(u32_type),
+            ),
+            (
+                gimli::constants::DW_AT_location,
+                AttributeValue::Exprloc({
+                    let mut expression = Expression::new();
+                    expression.op_constu(0x10);
+                    expression.op(gimli::constants::DW_OP_form_tls_address);
+                    expression
+                }),
+            ),
+        ],
+    );
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0010,
+        42u32.to_le_bytes().to_vec(),
+    ));
+
+    let thread_local = read_static_variable(&dwarf, &device_memory, "THREAD_LOCAL");
+    assert_eq!(
+        render_type_value_tree(&thread_local.type_value, Theme::None).to_string(),
+        "{TLS base unknown}"
+    );
+
+    device_memory.set_evaluation_step_resolver(|step, _| match step {
+        EvaluationStep::TlsBase => Some(0x2000_0000),
+        _ => None,
+    });
+    let thread_local = read_static_variable(&dwarf, &device_memory, "THREAD_LOCAL");
+    assert_eq!(
+        render_type_value_tree(&thread_local.type_value, Theme::None).to_string(),
+        "42"
+    );
+}
+
+#[test]
+fn resolved_evaluation_steps() {
+    let mut builder = DwarfBuilder::new();
+    let u32_type = builder.add_base_type("u32", gimli::constants::DW_ATE_unsigned, 4);
+    builder.add(
+        builder.root(),
+        gimli::constants::DW_TAG_variable,
+        vec![
+            (
+                gimli::constants::DW_AT_name,
+                AttributeValue::String("ON_STACK".into()),
+            ),
+            (
+                gimli::constants::DW_AT_type,
+                AttributeValue::UnitRef(u32_type),
+            ),
+            (
+                gimli::constants::DW_AT_location,
+                AttributeValue::Exprloc({
+                    let mut expression = Expression::new();
+                    expression.op(gimli::constants::DW_OP_call_frame_cfa);
+                    expression.op_plus_uconst(4);
+                    expression
+                }),
+            ),
+        ],
+    );
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0004,
+        42u32.to_le_bytes().to_vec(),
+    ));
+
+    let on_stack = read_static_variable(&dwarf, &device_memory, "ON_STACK");
+    assert!(render_type_value_tree(&on_stack.type_value, Theme::None)
+        .to_string()
+        .contains("RequiresCallFrameCfa"),);
+
+    device_memory.set_evaluation_step_resolver(|step, _| match step {
+        EvaluationStep::CallFrameCfa => Some(0x2000_0000),
+        _ => None,
+    });
+    let on_stack = read_static_variable(&dwarf, &device_memory, "ON_STACK");
+    assert_eq!(
+        render_type_value_tree(&on_stack.type_value, Theme::None).to_string(),
+        "42"
+    );
+}
+
+#[test]
+fn resolved_parameter_ref_in_frame() {
+    let mut builder = DwarfBuilder::new();
+    let u32_type = builder.add_base_type("u32", gimli::constants::DW_ATE_unsigned, 4);
+    let parameter = builder.add(
+        builder.root(),
+        gimli::constants::DW_TAG_formal_parameter,
+        vec![(
+            gimli::constants::DW_AT_name,
+            AttributeValue::String("parameter".into()),
+        )],
+    );
+    builder.add(
+        builder.root(),
+        gimli::constants::DW_TAG_variable,
+        vec![
+            (
+                gimli::constants::DW_AT_name,
+                AttributeValue::String("FROM_CALLER".into()),
+            ),
+            (
+                gimli::constants::DW_AT_type,
+                AttributeValue::UnitRef(u32_type),
+            ),
+            (
+                gimli::constants::DW_AT_location,
+                AttributeValue::Exprloc({
+                    let mut expression = Expression::new();
+                    expression.op_gnu_parameter_ref(parameter);
+                    expression.op(gimli::constants::DW_OP_stack_value);
+                    expression
+                }),
+            ),
+        ],
+    );
+    let dwarf = builder.build();
+
+    // The resolver gets the offset of the parameter in the debug info, not in its unit
+    let unit = dwarf.units().next().unwrap().unwrap();
+    let parameter_offset = {
+        let abbreviations = dwarf.abbreviations(&unit).unwrap();
+        let mut entries = unit.entries(&abbreviations);
+        loop {
+            let (_, entry) = entries.next_dfs().unwrap().unwrap();
+            if entry.tag() == gimli::constants::DW_TAG_formal_parameter {
+                break entry.offset().to_debug_info_offset(&unit).unwrap();
+            }
+        }
+    };
+    assert_ne!(parameter_offset.0, 0);
+
+    let caller = EvaluationFrame {
+        index: 1,
+        pc: 0x0800_0100,
+    };
+    let mut device_memory = DeviceMemory::<u32>::new();
+    device_memory.set_evaluation_step_resolver(move |step, frame| match (step, frame) {
+        (EvaluationStep::ParameterRef { offset }, Some(frame))
+            if offset == parameter_offset && frame == caller =>
+        {
+            Some(42)
+        }
+        _ => None,
+    });
+
+    let read_in_frame = |frame| {
+        let mut state = DecodeState::new(DecodeOptions::default());
+        state.set_frame(frame);
+        let (variables, _) =
+            find_static_variables(&dwarf, &device_memory, &mut HashMap::new(), &mut state).unwrap();
+        render_type_value_tree(&variables[0].type_value, Theme::None).to_string()
+    };
+
+    assert_eq!(read_in_frame(Some(caller)), "42");
+    assert!(read_in_frame(None).contains("RequiresParameterRef"));
+    assert!(read_in_frame(Some(EvaluationFrame { index: 0, ..caller }))
+        .contains("RequiresParameterRef"));
+}
+
+#[test]
+fn register_pieces() {
+    let mut builder = DwarfBuilder::new();
+    let u64_type = builder.add_base_type("u64", gimli::constants::DW_ATE_unsigned, 8);
+    let mut add_pieces_variable = |name: &str, add_pieces: &dyn Fn(&mut Expression)| {
+        builder.add(
+            builder.root(),
+            gimli::constants::DW_TAG_variable,
+            vec![
+                (
+                    gimli::constants::DW_AT_name,
+                    AttributeValue::String(name.into()),
+                ),
+                (
+                    gimli::constants::DW_AT_type,
+                    AttributeValue::UnitRef(u64_type),
+                ),
+                (
+                    gimli::constants::DW_AT_location,
+                    AttributeValue::Exprloc({
+                        let mut expression = Expression::new();
+                        add_pieces(&mut expression);
+                        expression
+                    }),
+                ),
+            ],
+        );
+    };
+    // The low half is in r0 and the high half in r1
+    add_pieces_variable("REGISTERS", &|expression| {
+        expression.op_reg(gimli::Arm::R0);
+        expression.op_piece(4);
+        expression.op_reg(gimli::Arm::R1);
+        expression.op_piece(4);
+    });
+    // The low half is in r2 and the high half in memory
+    add_pieces_variable("REGISTER_AND_MEMORY", &|expression| {
+        expression.op_reg(gimli::Arm::R2);
+        expression.op_piece(4);
+        expression.op_addr(Address::Constant(0x2000_0000));
+        expression.op_piece(4);
+    });
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_register_data(VecRegisterData::new(
+        gimli::Arm::R0,
+        vec![0x9ABC_DEF0, 0x1234_5678, 0x0000_0002],
+    ));
+    // Only the half in memory is captured
+    device_memory.add_memory_region(VecMemoryRegion::new(0x2000_0000, vec![1, 0, 0, 0]));
+
+    let registers = read_static_variable(&dwarf, &device_memory, "REGISTERS");
+    assert_eq!(
+        render_type_value_tree(&registers.type_value, Theme::None).to_string(),
+        0x1234_5678_9ABC_DEF0u64.to_string()
+    );
+
+    let register_and_memory = read_static_variable(&dwarf, &device_memory, "REGISTER_AND_MEMORY");
+    assert_eq!(
+        render_type_value_tree(&register_and_memory.type_value, Theme::None).to_string(),
+        0x1_0000_0002u64.to_string()
+    );
+}
+
+#[test]
+fn bools() {
+    let mut builder = DwarfBuilder::new();
+    let bool_type = builder.add_base_type("bool", gimli::constants::DW_ATE_boolean, 1);
+    let array = builder.add_array(bool_type, 3);
+    builder.add_static_variable("BOOLS", array, 0x2000_0000);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(0x2000_0000, vec![0, 1, 2]));
+
+    let bools = read_static_variable(&dwarf, &device_memory, "BOOLS");
+    assert_eq!(
+        render_type_value_tree(&bools.type_value, Theme::None).to_string(),
+        "[false, true, {invalid bool (0x02)} <raw: [02]>]"
+    );
+}
+
+#[test]
+fn signed_integers() {
+    let mut builder = DwarfBuilder::new();
+    let i8_type = builder.add_base_type("i8", gimli::constants::DW_ATE_signed, 1);
+    let i16_type = builder.add_base_type("i16", gimli::constants::DW_ATE_signed, 2);
+    let i32_type = builder.add_base_type("i32", gimli::constants::DW_ATE_signed, 4);
+    let values = builder.add_structure(
+        "Values",
+        8,
+        &[("a", i8_type, 0), ("b", i16_type, 2), ("c", i32_type, 4)],
+    );
+    builder.add_static_variable("VALUES", values, 0x2000_0000);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0000,
+        [
+            &[0xFF, 0][..],
+            &(-300i16).to_le_bytes(),
+            &i32::MIN.to_le_bytes(),
+        ]
+        .concat(),
+    ));
+
+    let values = read_static_variable(&dwarf, &device_memory, "VALUES");
+    assert_eq!(
+        render_type_value_tree(&values.type_value, Theme::None).to_string(),
+        "Values { a: -1, b: -300, c: -2147483648 }"
+    );
+}
+
+#[test]
+fn floats() {
+    let mut builder = DwarfBuilder::new();
+    let f32_type = builder.add_base_type("f32", gimli::constants::DW_ATE_float, 4);
+    let f64_type = builder.add_base_type("f64", gimli::constants::DW_ATE_float, 8);
+    let f32_array = builder.add_array(f32_type, 5);
+    builder.add_static_variable("F32S", f32_array, 0x2000_0000);
+    builder.add_static_variable("F64", f64_type, 0x2000_0020);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0000,
+        [
+            1.5f32,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            f32::from_bits(0x7FC0_0001),
+        ]
+        .iter()
+        .map(|value| value.to_bits())
+        // A subnormal value
+        .chain([0x0000_0001])
+        .flat_map(|bits| bits.to_le_bytes())
+        .chain([0; 12])
+        .chain(f64::from_bits(0x7FF0_0000_0000_0002).to_le_bytes())
+        .collect(),
+    ));
+
+    let f32s = read_static_variable(&dwarf, &device_memory, "F32S");
+    assert_eq!(
+        render_type_value_tree(&f32s.type_value, Theme::None).to_string(),
+        "[1.5, +inf, -inf, NaN(0x7fc00001), subnormal 1e-45]"
+    );
+
+    let f64_value = read_static_variable(&dwarf, &device_memory, "F64");
+    assert_eq!(
+        render_type_value_tree(&f64_value.type_value, Theme::None).to_string(),
+        "NaN(0x7ff0000000000002)"
+    );
+}
+
+#[test]
+fn cross_unit_type_references() {
+    let mut builder = DwarfBuilder::new();
+    let type_unit = builder.unit;
+    let u16_type = builder.add_base_type("u16", gimli::constants::DW_ATE_unsigned, 2);
+    let point = builder.add_structure("Point", 4, &[("x", u16_type, 0), ("y", u16_type, 2)]);
+
+    // The variables live in another unit and refer to the type with a DW_FORM_ref_addr
+    builder.add_unit();
+    let point_reference = AttributeValue::DebugInfoRef(write::Reference::Entry(type_unit, point));
+    let typedef = builder.add(
+        builder.root(),
+        gimli::constants::DW_TAG_typedef,
+        vec![
+            (
+                gimli::constants::DW_AT_name,
+                AttributeValue::String("Location".into()),
+            ),
+            (gimli::constants::DW_AT_type, point_reference.clone()),
+        ],
+    );
+    let pointer = builder.add(
+        builder.root(),
+        gimli::constants::DW_TAG_pointer_type,
+        vec![(gimli::constants::DW_AT_type, point_reference)],
+    );
+    builder.add_static_variable("LOCATION", typedef, 0x2000_0000);
+    builder.add_static_variable("POINT_POINTER", pointer, 0x2000_0004);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0000,
+        [
+            [1u16.to_le_bytes(), 2u16.to_le_bytes()].concat(),
+            0x2000_0000u32.to_le_bytes().to_vec(),
+        ]
+        .concat(),
+    ));
+
+    let location = read_static_variable(&dwarf, &device_memory, "LOCATION");
+    assert_eq!(location.type_value.data().variable_type.name, "Location");
+    assert_eq!(
+        render_type_value_tree(&location.type_value, Theme::None).to_string(),
+        "Point { x: 1, y: 2 }"
+    );
+
+    let point_pointer = read_static_variable(&dwarf, &device_memory, "POINT_POINTER");
+    assert_eq!(point_pointer.type_value.data().variable_type.name, "&Point");
+    assert_eq!(
+        render_type_value_tree(&point_pointer.type_value, Theme::None).to_string(),
+        "*0x20000000 = Point { x: 1, y: 2 }"
+    );
+}
+
+#[test]
+fn peripheral_pointers() {
+    let mut builder = DwarfBuilder::new();
+    let u32_type = builder.add_base_type("u32", gimli::constants::DW_ATE_unsigned, 4);
+    let pointer = builder.add_type_modifier(gimli::constants::DW_TAG_pointer_type, u32_type);
+    builder.add_static_variable("RAM_POINTER", pointer, 0x2000_0000);
+    builder.add_static_variable("PERIPHERAL_POINTER", pointer, 0x2000_0004);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0000,
+        [
+            0x2000_0008u32.to_le_bytes(),
+            0x4000_0000u32.to_le_bytes(),
+            5u32.to_le_bytes(),
+        ]
+        .concat(),
+    ));
+    device_memory.add_memory_region(
+        VecMemoryRegion::new(0x4000_0000, 6u32.to_le_bytes().to_vec())
+            .with_kind(MemoryRegionKind::Peripheral),
+    );
+
+    let ram_pointer = read_static_variable(&dwarf, &device_memory, "RAM_POINTER");
+    assert_eq!(
+        render_type_value_tree(&ram_pointer.type_value, Theme::None).to_string(),
+        "*0x20000008 = 5"
+    );
+
+    let peripheral_pointer = read_static_variable(&dwarf, &device_memory, "PERIPHERAL_POINTER");
+    assert_eq!(
+        render_type_value_tree(&peripheral_pointer.type_value, Theme::None).to_string(),
+        "*0x40000000 = {Points to peripheral memory}"
+    );
+}
+
+#[test]
+fn implicit_pointers() {
+    let mut builder = DwarfBuilder::new();
+    let u32_type = builder.add_base_type("u32", gimli::constants::DW_ATE_unsigned, 4);
+    let pointer = builder.add_type_modifier(gimli::constants::DW_TAG_pointer_type, u32_type);
+    let array = builder.add_array(u32_type, 2);
+    let values = builder.add_static_variable("VALUES", array, 0x2000_0000);
+    // A variable that only has a constant value, so it's not in memory
+    let constant = builder.add(
+        builder.root(),
+        gimli::constants::DW_TAG_variable,
+        vec![
+            (
+                gimli::constants::DW_AT_name,
+                AttributeValue::String("CONSTANT".into()),
+            ),
+            (
+                gimli::constants::DW_AT_type,
+                AttributeValue::UnitRef(u32_type),
+            ),
+            (
+                gimli::constants::DW_AT_const_value,
+                AttributeValue::Udata(7),
+            ),
+        ],
+    );
+
+    for (name, target, byte_offset) in [("SECOND_VALUE", values, 4), ("TO_CONSTANT", constant, 0)] {
+        builder.add(
+            builder.root(),
+            gimli::constants::DW_TAG_variable,
+            vec![
+                (
+                    gimli::constants::DW_AT_name,
+                    AttributeValue::String(name.into()),
+                ),
+                (
+                    gimli::constants::DW_AT_type,
+                    AttributeValue::UnitRef(pointer),
+                ),
+                (
+                    gimli::constants::DW_AT_location,
+                    AttributeValue::Exprloc({
+                        let mut expression = Expression::new();
+                        expression.op_implicit_pointer(
+                            write::Reference::Entry(builder.unit, target),
+                            byte_offset,
+                        );
+                        expression
+                    }),
+                ),
+            ],
+        );
+    }
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0000,
+        [5u32.to_le_bytes(), 6u32.to_le_bytes()].concat(),
+    ));
+
+    let second_value = read_static_variable(&dwarf, &device_memory, "SECOND_VALUE");
+    assert_eq!(
+        render_type_value_tree(&second_value.type_value, Theme::None).to_string(),
+        "*0x20000004 = 6"
+    );
+
+    let to_constant = read_static_variable(&dwarf, &device_memory, "TO_CONSTANT");
+    assert_eq!(
+        render_type_value_tree(&to_constant.type_value, Theme::None).to_string(),
+        "{Points to a value that is not in memory}"
+    );
+}
+
+#[test]
+fn niche_range_discriminant() {
+    let mut builder = DwarfBuilder::new();
+    let u8_type = builder.add_base_type("u8", gimli::constants::DW_ATE_unsigned, 1);
+    let non_zero = builder.add_structure("NonZeroU8", 1, &[("__0", u8_type, 0)]);
+    let none = builder.add_structure("None", 1, &[]);
+    let some = builder.add_structure("Some", 1, &[("__0", non_zero, 0)]);
+
+    // Option<NonZeroU8> is stored in the niche of the u8.
+    // None is 0 and Some is selected by the range 1..=255.
+    let option = builder.add_tagged_union(
+        "Option<NonZeroU8>",
+        1,
+        u8_type,
+        &[
+            (
+                "None",
+                none,
+                Some((
+                    gimli::constants::DW_AT_discr_value,
+                    AttributeValue::Data1(0),
+                )),
+            ),
+            (
+                "Some",
+                some,
+                Some((
+                    gimli::constants::DW_AT_discr_list,
+                    // DW_DSC_range 1 255 (in unsigned LEB128)
+                    AttributeValue::Block(vec![gimli::constants::DW_DSC_range.0, 1, 0xFF, 0x01]),
+                )),
+            ),
+        ],
+    );
+
+    builder.add_static_variable("NONE", option, 0x2000_0000);
+    builder.add_static_variable("SOME", option, 0x2000_0001);
+    builder.add_static_variable("SOME_HIGH", option, 0x2000_0002);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(0x2000_0000, vec![0, 5, 200]));
+
+    let render = |name| {
+        let variable = read_static_variable(&dwarf, &device_memory, name);
+        render_type_value_tree(&variable.type_value, Theme::None).to_string()
+    };
+
+    assert_eq!(render("NONE"), "None");
+    assert_eq!(render("SOME"), "Some(5)");
+    assert_eq!(render("SOME_HIGH"), "Some(200)");
+}
+
+#[test]
+fn wrapping_range_discriminant() {
+    let mut builder = DwarfBuilder::new();
+    let u8_type = builder.add_base_type("u8", gimli::constants::DW_ATE_unsigned, 1);
+    let wrapped = builder.add_structure("Wrapped", 1, &[]);
+    let two = builder.add_structure("Two", 1, &[]);
+
+    let tagged_union = builder.add_tagged_union(
+        "Niche",
+        1,
+        u8_type,
+        &[
+            (
+                "Wrapped",
+                wrapped,
+                Some((
+                    gimli::constants::DW_AT_discr_list,
+                    // DW_DSC_range 254 1 (in unsigned LEB128), which wraps around
+                    AttributeValue::Block(vec![gimli::constants::DW_DSC_range.0, 0xFE, 0x01, 0x01]),
+                )),
+            ),
+            (
+                "Two",
+                two,
+                Some((
+                    gimli::constants::DW_AT_discr_value,
+                    AttributeValue::Data1(2),
+                )),
+            ),
+        ],
+    );
+
+    builder.add_static_variable("HIGH", tagged_union, 0x2000_0000);
+    builder.add_static_variable("ZERO", tagged_union, 0x2000_0001);
+    builder.add_static_variable("ONE", tagged_union, 0x2000_0002);
+    builder.add_static_variable("TWO", tagged_union, 0x2000_0003);
+    builder.add_static_variable("MIDDLE", tagged_union, 0x2000_0004);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(0x2000_0000, vec![0xFF, 0, 1, 2, 100]));
+
+    let render = |name| {
+        let variable = read_static_variable(&dwarf, &device_memory, name);
+        render_type_value_tree(&variable.type_value, Theme::None).to_string()
+    };
+
+    assert_eq!(render("HIGH"), "Wrapped {  }");
+    assert_eq!(render("ZERO"), "Wrapped {  }");
+    assert_eq!(render("ONE"), "Wrapped {  }");
+    assert_eq!(render("TWO"), "Two {  }");
+    assert_eq!(render("MIDDLE"), "{invalid discriminant: 100}");
+}
+
+#[test]
+fn non_null_and_non_zero() {
+    let mut builder = DwarfBuilder::new();
+    let u32_type = builder.add_base_type("u32", gimli::constants::DW_ATE_unsigned, 4);
+    let pointer = builder.add_type_modifier(gimli::constants::DW_TAG_pointer_type, u32_type);
+    let non_null = builder.add_structure("NonNull<u32>", 4, &[("pointer", pointer, 0)]);
+    // Newer versions of Rust wrap the integer of `NonZero` in another type
+    let non_zero_inner = builder.add_structure("NonZeroU32Inner", 4, &[("__0", u32_type, 0)]);
+    let non_zero = builder.add_structure("NonZero<u32>", 4, &[("__0", non_zero_inner, 0)]);
+    let none = builder.add_structure("None", 4, &[]);
+    let some = builder.add_structure("Some", 4, &[("__0", non_null, 0)]);
+
+    // Option<NonNull<u32>> is stored in the niche of the pointer, so None is a null pointer
+    let option = builder.add_tagged_union(
+        "Option<NonNull<u32>>",
+        4,
+        u32_type,
+        &[
+            (
+                "None",
+                none,
+                Some((
+                    gimli::constants::DW_AT_discr_value,
+                    AttributeValue::Data1(0),
+                )),
+            ),
+            ("Some", some, None),
+        ],
+    );
+
+    builder.add_static_variable("NON_NULL", non_null, 0x2000_0000);
+    builder.add_static_variable("NON_ZERO", non_zero, 0x2000_0004);
+    builder.add_static_variable("SOME", option, 0x2000_0000);
+    builder.add_static_variable("NONE", option, 0x2000_0008);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0000,
+        [
+            0x2000_0004u32.to_le_bytes(),
+            42u32.to_le_bytes(),
+            0u32.to_le_bytes(),
+        ]
+        .concat(),
+    ));
+
+    let render = |name| {
+        let variable = read_static_variable(&dwarf, &device_memory, name);
+        render_type_value_tree(&variable.type_value, Theme::None).to_string()
+    };
+
+    assert_eq!(render("NON_NULL"), "*0x20000004 = 42");
+    assert_eq!(render("NON_ZERO"), "42");
+    assert_eq!(render("SOME"), "Some(*0x20000004 = 42)");
+    assert_eq!(render("NONE"), "None");
+}
+
+#[test]
+fn result_variants() {
+    let mut builder = DwarfBuilder::new();
+    let u8_type = builder.add_base_type("u8", gimli::constants::DW_ATE_unsigned, 1);
+    let u16_type = builder.add_base_type("u16", gimli::constants::DW_ATE_unsigned, 2);
+    let ok = builder.add_structure("Ok", 4, &[("__0", u16_type, 2)]);
+    let err = builder.add_structure("Err", 4, &[("__0", u8_type, 1)]);
+    let variants = [
+        (
+            "Ok",
+            ok,
+            Some((
+                gimli::constants::DW_AT_discr_value,
+                AttributeValue::Data1(0),
+            )),
+        ),
+        (
+            "Err",
+            err,
+            Some((
+                gimli::constants::DW_AT_discr_value,
+                AttributeValue::Data1(1),
+            )),
+        ),
+    ];
+    let result = builder.add_tagged_union("Result<u16, u8>", 4, u8_type, &variants);
+    // Other enums keep rendering their variants as objects
+    let other = builder.add_tagged_union("Outcome", 4, u8_type, &variants);
+
+    builder.add_static_variable("OK", result, 0x2000_0000);
+    builder.add_static_variable("ERR", result, 0x2000_0004);
+    builder.add_static_variable("OTHER", other, 0x2000_0000);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0000,
+        vec![0, 0, 0x34, 0x12, 1, 7, 0, 0],
+    ));
+
+    let render = |name| {
+        let variable = read_static_variable(&dwarf, &device_memory, name);
+        render_type_value_tree(&variable.type_value, Theme::None).to_string()
+    };
+
+    assert_eq!(render("OK"), "Ok(4660)");
+    assert_eq!(render("ERR"), "Err(7)");
+    assert_eq!(render("OTHER"), "Ok { __0: 4660 }");
+}
+
+#[test]
+fn enumeration_discriminant_list() {
+    let mut builder = DwarfBuilder::new();
+    let u8_type = builder.add_base_type("u8", gimli::constants::DW_ATE_unsigned, 1);
+    let kind = builder.add_enumeration(
+        "Kind",
+        u8_type,
+        &[("A", 0), ("B", 1), ("C", 2), ("D", 3), ("E", 4)],
+    );
+    let small = builder.add_structure("Small", 1, &[]);
+    let big = builder.add_structure("Big", 1, &[]);
+    let other = builder.add_structure("Other", 1, &[]);
+
+    // The discriminant is an enum, of which the value is in its base type
+    let tagged_union = builder.add_tagged_union(
+        "Size",
+        1,
+        kind,
+        &[
+            (
+                "Small",
+                small,
+                Some((
+                    gimli::constants::DW_AT_discr_list,
+                    // DW_DSC_label 0, DW_DSC_label 2
+                    AttributeValue::Block(vec![
+                        gimli::constants::DW_DSC_label.0,
+                        0,
+                        gimli::constants::DW_DSC_label.0,
+                        2,
+                    ]),
+                )),
+            ),
+            (
+                "Big",
+                big,
+                Some((
+                    gimli::constants::DW_AT_discr_list,
+                    // DW_DSC_range 3 4
+                    AttributeValue::Block(vec![gimli::constants::DW_DSC_range.0, 3, 4]),
+                )),
+            ),
+            ("Other", other, None),
+        ],
+    );
+
+    builder.add_static_variable("A", tagged_union, 0x2000_0000);
+    builder.add_static_variable("B", tagged_union, 0x2000_0001);
+    builder.add_static_variable("C", tagged_union, 0x2000_0002);
+    builder.add_static_variable("E", tagged_union, 0x2000_0003);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(0x2000_0000, vec![0, 1, 2, 4]));
+
+    let render = |name| {
+        let variable = read_static_variable(&dwarf, &device_memory, name);
+        render_type_value_tree(&variable.type_value, Theme::None).to_string()
+    };
+
+    assert_eq!(render("A"), "Small {  }");
+    assert_eq!(render("B"), "Other {  }");
+    assert_eq!(render("C"), "Small {  }");
+    assert_eq!(render("E"), "Big {  }");
+}
+
+#[test]
+fn zero_sized_objects() {
+    let mut builder = DwarfBuilder::new();
+    let u32_type = builder.add_base_type("u32", gimli::constants::DW_ATE_unsigned, 4);
+    let phantom_data = builder.add_structure("PhantomData<u32>", 0, &[]);
+    let unit_struct = builder.add_structure("Marker", 0, &[]);
+    let wrapper = builder.add_structure(
+        "Wrapper<u32>",
+        4,
+        &[("value", u32_type, 0), ("_marker", phantom_data, 4)],
+    );
+    builder.add_static_variable("PHANTOM_DATA", phantom_data, 0x2000_0000);
+    builder.add_static_variable("MARKER", unit_struct, 0x2000_0000);
+    builder.add_static_variable("WRAPPER", wrapper, 0x2000_0000);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0000,
+        5u32.to_le_bytes().to_vec(),
+    ));
+
+    let render = |name| {
+        let variable = read_static_variable(&dwarf, &device_memory, name);
+        render_type_value_tree(&variable.type_value, Theme::None).to_string()
+    };
+
+    assert_eq!(render("PHANTOM_DATA"), "PhantomData<u32>");
+    assert_eq!(render("MARKER"), "<zst>");
+    assert_eq!(
+        render("WRAPPER"),
+        "Wrapper<u32> { value: 5, _marker: PhantomData<u32> }"
+    );
+}
+
+#[test]
+fn omitted_variables() {
+    let mut builder = DwarfBuilder::new();
+    let u32_type = builder.add_base_type("u32", gimli::constants::DW_ATE_unsigned, 4);
+    // A base type without an encoding and size can't be read
+    let broken_type = builder.add(
+        builder.root(),
+        gimli::constants::DW_TAG_base_type,
+        vec![(
+            gimli::constants::DW_AT_name,
+            AttributeValue::String("broken".into()),
+        )],
+    );
+    builder.add_static_variable("VALUE", u32_type, 0x2000_0000);
+    builder.add_static_variable("BROKEN", broken_type, 0x2000_0000);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::<u32>::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0000,
+        5u32.to_le_bytes().to_vec(),
+    ));
+
+    let (variables, omitted_variables) = find_static_variables(
+        &dwarf,
+        &device_memory,
+        &mut HashMap::new(),
+        &mut DecodeState::new(DecodeOptions::default()),
+    )
+    .unwrap();
+
+    assert_eq!(variables.len(), 1);
+    assert_eq!(variables[0].name, "VALUE");
+    assert_eq!(omitted_variables.len(), 1);
+    assert_eq!(omitted_variables[0].name.as_deref(), Some("BROKEN"));
+    assert!(omitted_variables[0]
+        .reason
+        .starts_with("could not read the type:"));
+}
+
+#[test]
+fn dwarf5_location_list() {
+    let mut builder = DwarfBuilder::with_version(5);
+    let u32_type = builder.add_base_type("u32", gimli::constants::DW_ATE_unsigned, 4);
+    let address_expression = |address| {
+        let mut expression = Expression::new();
+        expression.op_addr(Address::Constant(address));
+        expression
+    };
+    // The offset pairs are relative to the base address entry
+    let location_list =
+        builder
+            .dwarf
+            .units
+            .get_mut(builder.unit)
+            .locations
+            .add(write::LocationList(vec![
+                write::Location::BaseAddress {
+                    address: Address::Constant(0x1000),
+                },
+                write::Location::OffsetPair {
+                    begin: 0x00,
+                    end: 0x10,
+                    data: address_expression(0x2000_0000),
+                },
+                write::Location::OffsetPair {
+                    begin: 0x10,
+                    end: 0x20,
+                    data: address_expression(0x2000_0004),
+                },
+            ]));
+    builder.add(
+        builder.root(),
+        gimli::constants::DW_TAG_variable,
+        vec![
+            (
+                gimli::constants::DW_AT_name,
+                AttributeValue::String("VALUE".into()),
+            ),
+            (
+                gimli::constants::DW_AT_type,
+                AttributeValue::UnitRef(u32_type),
+            ),
+            (
+                gimli::constants::DW_AT_location,
+                AttributeValue::LocationListRef(location_list),
+            ),
+        ],
+    );
+    let dwarf = builder.build();
+
+    let read_value = |pc: u32| {
+        let mut device_memory = DeviceMemory::new();
+        device_memory.add_memory_region(VecMemoryRegion::new(
+            0x2000_0000,
+            [5u32.to_le_bytes(), 7u32.to_le_bytes()].concat(),
+        ));
+        device_memory.add_register_data(VecRegisterData::new(gimli::Arm::PC, vec![pc]));
+        let variable = read_static_variable(&dwarf, &device_memory, "VALUE");
+        render_type_value_tree(&variable.type_value, Theme::None).to_string()
+    };
+
+    assert_eq!(read_value(0x1004), "5");
+    assert_eq!(read_value(0x1014), "7");
+    assert_eq!(read_value(0x1024), "{Optimized away}");
+}
+
+#[test]
+fn dwarf5_location_list_index() {
+    use gimli::write::Writer;
+
+    // The writer of gimli always refers to location lists by offset,
+    // so the sections are written by hand to get a `DW_FORM_loclistx` with a `DW_AT_loclists_base`.
+    let mut abbrev = write::EndianVec::new(LittleEndian);
+    for (code, tag, attributes) in [
+        (
+            1,
+            gimli::constants::DW_TAG_compile_unit,
+            &[(
+                gimli::constants::DW_AT_loclists_base,
+                gimli::constants::DW_FORM_sec_offset,
+            )][..],
+        ),
+        (
+            2,
+            gimli::constants::DW_TAG_base_type,
+            &[
+                (
+                    gimli::constants::DW_AT_name,
+                    gimli::constants::DW_FORM_string,
+                ),
+                (
+                    gimli::constants::DW_AT_encoding,
+                    gimli::constants::DW_FORM_data1,
+                ),
+                (
+                    gimli::constants::DW_AT_byte_size,
+                    gimli::constants::DW_FORM_data1,
+                ),
+            ][..],
+        ),
+        (
+            3,
+            gimli::constants::DW_TAG_variable,
+            &[
+                (
+                    gimli::constants::DW_AT_name,
+                    gimli::constants::DW_FORM_string,
+                ),
+                (gimli::constants::DW_AT_type, gimli::constants::DW_FORM_ref4),
+                (
+                    gimli::constants::DW_AT_location,
+                    gimli::constants::DW_FORM_loclistx,
+                ),
+            ][..],
+        ),
+    ] {
+        abbrev.write_uleb128(code).unwrap();
+        abbrev.write_uleb128(tag.0.into()).unwrap();
+        abbrev
+            .write_u8((tag == gimli::constants::DW_TAG_compile_unit) as u8)
+            .unwrap();
+        for (name, form) in attributes {
+            abbrev.write_uleb128(name.0.into()).unwrap();
+            abbrev.write_uleb128(form.0.into()).unwrap();
+        }
+        abbrev.write_u16(0).unwrap();
+    }
+    abbrev.write_u8(0).unwrap();
+
+    // The header of the location lists is followed by the table with the offsets of the lists
+    const LOCLISTS_BASE: u32 = 12;
+    let mut loclists = write::EndianVec::new(LittleEndian);
+    loclists.write_u32(0).unwrap();
+    loclists.write_u16(5).unwrap();
+    loclists.write_u8(4).unwrap();
+    loclists.write_u8(0).unwrap();
+    loclists.write_u32(1).unwrap();
+    // The only list is right after the offset table
+    loclists.write_u32(4).unwrap();
+    for (begin, end, address) in [(0x1000, 0x1010, 0x2000_0000), (0x1010, 0x1020, 0x2000_0004)] {
+        loclists
+            .write_u8(gimli::constants::DW_LLE_start_end.0)
+            .unwrap();
+        loclists.write_u32(begin).unwrap();
+        loclists.write_u32(end).unwrap();
+        loclists.write_uleb128(5).unwrap();
+        loclists.write_u8(gimli::constants::DW_OP_addr.0).unwrap();
+        loclists.write_u32(address).unwrap();
+    }
+    loclists
+        .write_u8(gimli::constants::DW_LLE_end_of_list.0)
+        .unwrap();
+    let length = loclists.len() as u32 - 4;
+    loclists.write_u32_at(0, length).unwrap();
+
+    let mut info = write::EndianVec::new(LittleEndian);
+    info.write_u32(0).unwrap();
+    info.write_u16(5).unwrap();
+    info.write_u8(gimli::constants::DW_UT_compile.0).unwrap();
+    info.write_u8(4).unwrap();
+    info.write_u32(0).unwrap();
+    info.write_uleb128(1).unwrap();
+    info.write_u32(LOCLISTS_BASE).unwrap();
+    let u32_type = info.len() as u32;
+    info.write_uleb128(2).unwrap();
+    info.write(b"u32\0").unwrap();
+    info.write_u8(gimli::constants::DW_ATE_unsigned.0).unwrap();
+    info.write_u8(4).unwrap();
+    info.write_uleb128(3).unwrap();
+    info.write(b"VALUE\0").unwrap();
+    info.write_u32(u32_type).unwrap();
+    info.write_uleb128(0).unwrap();
+    info.write_u8(0).unwrap();
+    let length = info.len() as u32 - 4;
+    info.write_u32_at(0, length).unwrap();
+
+    let section_data = HashMap::from([
+        (SectionId::DebugAbbrev, abbrev.into_vec()),
+        (SectionId::DebugInfo, info.into_vec()),
+        (SectionId::DebugLocLists, loclists.into_vec()),
+    ]);
+    let dwarf = gimli::Dwarf::load(|id: SectionId| {
+        Ok::<_, ()>(EndianReader::new(
+            Rc::from(section_data.get(&id).cloned().unwrap_or_default()),
+            RunTimeEndian::Little,
+        ))
+    })
+    .unwrap();
+
+    let read_value = |pc: u32| {
+        let mut device_memory = DeviceMemory::new();
+        device_memory.add_memory_region(VecMemoryRegion::new(
+            0x2000_0000,
+            [5u32.to_le_bytes(), 7u32.to_le_bytes()].concat(),
+        ));
+        device_memory.add_register_data(VecRegisterData::new(gimli::Arm::PC, vec![pc]));
+        let variable = read_static_variable(&dwarf, &device_memory, "VALUE");
+        render_type_value_tree(&variable.type_value, Theme::None).to_string()
+    };
+
+    assert_eq!(read_value(0x1004), "5");
+    assert_eq!(read_value(0x1014), "7");
+    assert_eq!(read_value(0x1024), "{Optimized away}");
+}
+
+/// The C example compiled with DWARF 5 by `examples/create_c_dwarf5.sh`
+const C_DWARF5_ELF: &[u8] = include_bytes!("../../../examples/data/c_dwarf5");
+
+fn load_elf_dwarf(elf: &addr2line::object::File) -> gimli::Dwarf<DefaultReader> {
+    use addr2line::object::{Object, ObjectSection};
+
+    gimli::Dwarf::load(|id: SectionId| {
+        let data = elf
+            .section_by_name(id.name())
+            .and_then(|section| section.uncompressed_data().ok())
+            .unwrap_or_default();
+        Ok::<_, ()>(EndianReader::new(Rc::from(&*data), RunTimeEndian::Little))
+    })
+    .unwrap()
+}
+
+#[test]
+fn c_dwarf5_statics() {
+    let elf = addr2line::object::File::parse(C_DWARF5_ELF).unwrap();
+    let dwarf = load_elf_dwarf(&elf);
+
+    let mut device_memory = DeviceMemory::new();
+    crate::platform::add_elf_data_regions(&mut device_memory, &elf).unwrap();
+
+    let render = |name| {
+        let variable = read_static_variable(&dwarf, &device_memory, name);
+        render_type_value_tree(&variable.type_value, Theme::None).to_string()
+    };
+
+    assert_eq!(render("POINT"), "Point { x: 1, y: -2 }");
+    assert_eq!(render("COLOR"), "BLUE");
+    assert_eq!(render("BYTES"), "[1, 2, 3, 4]");
+    assert!(render("POINT_POINTER").ends_with(" = const Point { x: 1, y: -2 }"));
+    assert_eq!(render("ZEROED"), "0");
+    // The fields of the anonymous members are part of the struct, also when the member is a typedef
+    assert_eq!(
+        render("PERIPHERAL"),
+        "Peripheral { id: 7, raw: 67305985, bytes: [1, 2, 3, 4], low: 5, both: [5, 6] }"
+    );
+}
+
+#[test]
+fn c_dwarf5_location_lists() {
+    let elf = addr2line::object::File::parse(C_DWARF5_ELF).unwrap();
+    let dwarf = load_elf_dwarf(&elf);
+
+    let unit = dwarf.unit(dwarf.units().next().unwrap().unwrap()).unwrap();
+    let abbreviations = dwarf.abbreviations(&unit.header).unwrap();
+
+    // Find the `sum` function, of which the variables are in registers that depend on the pc
+    let mut entries = unit.entries();
+    let sum_offset = loop {
+        let (_, entry) = entries.next_dfs().unwrap().unwrap();
+        if entry.tag() == gimli::constants::DW_TAG_subprogram
+            && super::get_entry_name(&dwarf, &unit, entry).ok().as_deref() == Some("sum")
+        {
+            break entry.offset();
+        }
+    };
+
+    let read_variables = |pc: u32| {
+        // Eax has the count and ebx the total in the loop
+        let mut registers = vec![0; 16];
+        registers[0] = 5;
+        registers[3] = 42;
+        registers[gimli::Arm::PC.0 as usize] = pc;
+
+        let mut device_memory = DeviceMemory::new();
+        device_memory.add_register_data(VecRegisterData::new(gimli::Register(0), registers));
+
+        let mut entries = unit
+            .header
+            .entries_tree(&abbreviations, Some(sum_offset))
+            .unwrap();
+        let (variables, _) = super::find_variables_in_function(
+            &dwarf,
+            &unit,
+            &abbreviations,
+            &device_memory,
+            entries.root().unwrap(),
+            &mut HashMap::new(),
+            &mut DecodeState::new(DecodeOptions::default()),
+        )
+        .unwrap();
+
+        variables
+            .iter()
+            .map(|variable| {
+                (
+                    variable.name.clone(),
+                    render_type_value_tree(&variable.type_value, Theme::None).to_string(),
+                )
+            })
+            .collect::<HashMap<_, _>>()
+    };
+
+    // In the loop
+    let variables = read_variables(0x0804_9045);
+    assert_eq!(variables["count"], "5");
+    assert_eq!(variables["total"], "42");
+
+    // At the start of the function the total is still a constant
+    let variables = read_variables(0x0804_9030);
+    assert_eq!(variables["count"], "5");
+    assert_eq!(variables["total"], "0");
+}
+
+#[test]
+fn broken_static_location() {
+    let mut builder = DwarfBuilder::new();
+    let u32_type = builder.add_base_type("u32", gimli::constants::DW_ATE_unsigned, 4);
+    // 0xFF is not a valid DWARF operation, so the location can't be evaluated
+    builder.add(
+        builder.root(),
+        gimli::constants::DW_TAG_variable,
+        vec![
+            (
+                gimli::constants::DW_AT_name,
+                AttributeValue::String("BROKEN".into()),
+            ),
+            (
+                gimli::constants::DW_AT_type,
+                AttributeValue::UnitRef(u32_type),
+            ),
+            (
+                gimli::constants::DW_AT_location,
+                AttributeValue::Exprloc(Expression::raw(vec![0xFF])),
+            ),
+        ],
+    );
+    builder.add_static_variable("FIRST", u32_type, 0x2000_0000);
+
+    // The statics of other units are still found
+    builder.add_unit();
+    let u32_type = builder.add_base_type("u32", gimli::constants::DW_ATE_unsigned, 4);
+    builder.add_static_variable("SECOND", u32_type, 0x2000_0000);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::<u32>::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0000,
+        5u32.to_le_bytes().to_vec(),
+    ));
+
+    let (variables, omitted_variables) = find_static_variables(
+        &dwarf,
+        &device_memory,
+        &mut HashMap::new(),
+        &mut DecodeState::new(DecodeOptions::default()),
+    )
+    .unwrap();
+
+    assert_eq!(
+        variables
+            .iter()
+            .map(|variable| variable.name.as_str())
+            .collect::<Vec<_>>(),
+        ["FIRST", "SECOND"]
+    );
+    assert_eq!(omitted_variables.len(), 1);
+    assert_eq!(omitted_variables[0].name.as_deref(), Some("BROKEN"));
+}
+
+#[test]
+fn static_members() {
+    let mut builder = DwarfBuilder::new();
+    let u32_type = builder.add_base_type("u32", gimli::constants::DW_ATE_unsigned, 4);
+    let counter = builder.add_structure("Counter", 4, &[("value", u32_type, 0)]);
+
+    // A static member up to DWARF 4
+    builder.add(
+        counter,
+        gimli::constants::DW_TAG_member,
+        vec![
+            (
+                gimli::constants::DW_AT_name,
+                AttributeValue::String("instances".into()),
+            ),
+            (
+                gimli::constants::DW_AT_type,
+                AttributeValue::UnitRef(u32_type),
+            ),
+            (gimli::constants::DW_AT_external, AttributeValue::Flag(true)),
+            (
+                gimli::constants::DW_AT_declaration,
+                AttributeValue::Flag(true),
+            ),
+        ],
+    );
+    // A static member in DWARF 5
+    builder.add(
+        counter,
+        gimli::constants::DW_TAG_variable,
+        vec![
+            (
+                gimli::constants::DW_AT_name,
+                AttributeValue::String("max".into()),
+            ),
+            (
+                gimli::constants::DW_AT_type,
+                AttributeValue::UnitRef(u32_type),
+            ),
+            (
+                gimli::constants::DW_AT_declaration,
+                AttributeValue::Flag(true),
+            ),
+        ],
+    );
+    // A normal member at the start of the object (so without a location) of which the flags are explicitly false
+    builder.add(
+        counter,
+        gimli::constants::DW_TAG_member,
+        vec![
+            (
+                gimli::constants::DW_AT_name,
+                AttributeValue::String("first".into()),
+            ),
+            (
+                gimli::constants::DW_AT_type,
+                AttributeValue::UnitRef(u32_type),
+            ),
+            (
+                gimli::constants::DW_AT_external,
+                AttributeValue::Flag(false),
+            ),
+            (
+                gimli::constants::DW_AT_declaration,
+                AttributeValue::Flag(false),
+            ),
+        ],
+    );
+    builder.add_static_variable("COUNTER", counter, 0x2000_0000);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0000,
+        5u32.to_le_bytes().to_vec(),
+    ));
+
+    let variable = read_static_variable(&dwarf, &device_memory, "COUNTER");
+    assert_eq!(
+        render_type_value_tree(&variable.type_value, Theme::None).to_string(),
+        "Counter { value: 5, first: 5 }"
+    );
+}
+
+#[test]
+fn decode_budget() {
+    let mut builder = DwarfBuilder::new();
+    let u32_type = builder.add_base_type("u32", gimli::constants::DW_ATE_unsigned, 4);
+    let u16_type = builder.add_base_type("u16", gimli::constants::DW_ATE_unsigned, 2);
+    // Pointers need a named pointee type, so every pointer gets a name
+    let mut add_pointer = |name: &str, pointee_type| {
+        builder.add(
+            builder.root(),
+            gimli::constants::DW_TAG_pointer_type,
+            vec![
+                (
+                    gimli::constants::DW_AT_name,
+                    AttributeValue::String(name.into()),
+                ),
+                (
+                    gimli::constants::DW_AT_type,
+                    AttributeValue::UnitRef(pointee_type),
+                ),
+            ],
+        )
+    };
+    let pointer_1 = add_pointer("*const u32", u32_type);
+    let pointer_2 = add_pointer("*const *const u32", pointer_1);
+    let pointer_3 = add_pointer("*const *const *const u32", pointer_2);
+    let array = builder.add_array(u16_type, 3);
+    builder.add_static_variable("POINTER", pointer_3, 0x2000_0000);
+    builder.add_static_variable("ARRAY", array, 0x2000_0010);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0000,
+        [0x2000_0004u32, 0x2000_0008, 0x2000_000C, 5]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .chain([1u16, 2, 3].iter().flat_map(|value| value.to_le_bytes()))
+            .collect(),
+    ));
+
+    let render = |name, budget| {
+        let variable = read_static_variable_with_budget(&dwarf, &device_memory, name, budget);
+        render_type_value_tree(&variable.type_value, Theme::None).to_string()
+    };
+
+    assert_eq!(
+        render("POINTER", DecodeBudget::default()),
+        "*0x20000004 = *0x20000008 = *0x2000000C = 5"
+    );
+    assert_eq!(
+        render(
+            "POINTER",
+            DecodeBudget {
+                max_pointer_depth: 2,
+                ..DecodeBudget::UNLIMITED
+            }
+        ),
+        "*0x20000004 = *0x20000008 = *0x2000000C = {Decode budget exceeded: max_pointer_depth}"
+    );
+    assert_eq!(render("ARRAY", DecodeBudget::default()), "[1, 2, 3]");
+    assert_eq!(
+        render(
+            "ARRAY",
+            DecodeBudget {
+                max_nodes: 3,
+                ..DecodeBudget::UNLIMITED
+            }
+        ),
+        "{Decode budget exceeded: max_nodes}"
+    );
+    assert_eq!(
+        render(
+            "ARRAY",
+            DecodeBudget {
+                max_bytes_read: 5,
+                ..DecodeBudget::UNLIMITED
+            }
+        ),
+        "{Decode budget exceeded: max_bytes_read}"
+    );
+}
+
+#[test]
+fn flexible_array_members() {
+    let mut builder = DwarfBuilder::new();
+    let u8_type = builder.add_base_type("u8", gimli::constants::DW_ATE_unsigned, 1);
+    let u16_type = builder.add_base_type("u16", gimli::constants::DW_ATE_unsigned, 2);
+    let u32_type = builder.add_base_type("u32", gimli::constants::DW_ATE_unsigned, 4);
+    let u16_flexible_array = builder.add_flexible_array(u16_type);
+    let u8_flexible_array = builder.add_flexible_array(u8_type);
+    let message = builder.add_structure(
+        "Message",
+        4,
+        &[("len", u32_type, 0), ("data", u16_flexible_array, 4)],
+    );
+    let buffer = builder.add_structure(
+        "Buffer",
+        1,
+        &[("size", u8_type, 0), ("bytes", u8_flexible_array, 1)],
+    );
+    builder.add_static_variable("MESSAGE", message, 0x2000_0000);
+    builder.add_static_variable("BUFFER", buffer, 0x2000_0010);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0000,
+        [
+            &3u32.to_le_bytes()[..],
+            &[1, 0, 2, 0, 3, 0],
+            &[0; 6],
+            &[2, 0xAA, 0xBB],
+        ]
+        .concat(),
+    ));
+
+    let render = |name, options| {
+        let variable = read_static_variable_with_options(&dwarf, &device_memory, name, options);
+        render_type_value_tree(&variable.type_value, Theme::None).to_string()
+    };
+
+    // The length is found in the `len` member
+    assert_eq!(
+        render("MESSAGE", DecodeOptions::default()),
+        "Message { len: 3, data: [1, 2, 3] }"
+    );
+    // Without a hint, the `size` member isn't known to be the length
+    assert_eq!(
+        render("BUFFER", DecodeOptions::default()),
+        "Buffer { size: 2, bytes: [] }"
+    );
+    assert_eq!(
+        render(
+            "BUFFER",
+            DecodeOptions {
+                flexible_array_lengths: [("Buffer".into(), "size".into())].into(),
+                ..Default::default()
+            }
+        ),
+        "Buffer { size: 2, bytes: [170, 187] }"
+    );
+}
+
+#[test]
+fn int_radix() {
+    let mut builder = DwarfBuilder::new();
+    let u8_type = builder.add_base_type("u8", gimli::constants::DW_ATE_unsigned, 1);
+    let i16_type = builder.add_base_type("i16", gimli::constants::DW_ATE_signed, 2);
+    let values = builder.add_structure("Values", 4, &[("a", u8_type, 0), ("b", i16_type, 2)]);
+    let flags = builder.add_structure("IrqFlags", 1, &[("bits", u8_type, 0)]);
+    builder.add_static_variable("VALUES", values, 0x2000_0000);
+    builder.add_static_variable("FLAGS", flags, 0x2000_0004);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0000,
+        vec![15, 0, 0xFF, 0xFF, 0x0F],
+    ));
+
+    let render = |name, int_radix| {
+        let variable = read_static_variable(&dwarf, &device_memory, name);
+        render_type_value_tree_with_options(
+            &variable.type_value,
+            Theme::None,
+            &RenderOptions {
+                int_radix,
+                ..Default::default()
+            },
+        )
+        .to_string()
+    };
+
+    assert_eq!(render("VALUES", IntRadix::Dec), "Values { a: 15, b: -1 }");
+    // The digits are based on the size of the type and negative values are shown as two's complement
+    assert_eq!(
+        render("VALUES", IntRadix::Hex),
+        "Values { a: 0x0F, b: 0xFFFF }"
+    );
+    assert_eq!(
+        render("VALUES", IntRadix::Bin),
+        "Values { a: 0b00001111, b: 0b1111111111111111 }"
+    );
+    // Flags are shown in hex by default
+    assert_eq!(render("FLAGS", IntRadix::Dec), "IrqFlags { bits: 0x0F }");
+    assert_eq!(
+        render("FLAGS", IntRadix::Bin),
+        "IrqFlags { bits: 0b00001111 }"
+    );
+}
+
+#[test]
+fn anonymous_members() {
+    // The way GCC describes this C struct:
+    //
+    // struct Message {
+    //     uint8_t kind;
+    //     union {
+    //         uint32_t raw;
+    //         float value;
+    //     };
+    //     struct {
+    //         uint16_t x;
+    //         uint16_t y;
+    //     };
+    // };
+    let mut builder = DwarfBuilder::new();
+    let u8_type = builder.add_base_type("uint8_t", gimli::constants::DW_ATE_unsigned, 1);
+    let u16_type = builder.add_base_type("uint16_t", gimli::constants::DW_ATE_unsigned, 2);
+    let u32_type = builder.add_base_type("uint32_t", gimli::constants::DW_ATE_unsigned, 4);
+    let f32_type = builder.add_base_type("float", gimli::constants::DW_ATE_float, 4);
+
+    let add_anonymous_type = |builder: &mut DwarfBuilder,
+                              tag: gimli::DwTag,
+                              byte_size: u64,
+                              members: &[(&str, UnitEntryId, u64)]| {
+        let anonymous_type = builder.add(
+            builder.root(),
+            tag,
+            vec![(
+                gimli::constants::DW_AT_byte_size,
+                AttributeValue::Udata(byte_size),
+            )],
+        );
+        for (member_name, member_type, member_offset) in members {
+            builder.add(
+                anonymous_type,
+                gimli::constants::DW_TAG_member,
+                vec![
+                    (
+                        gimli::constants::DW_AT_name,
+                        AttributeValue::String((*member_name).into()),
+                    ),
+                    (
+                        gimli::constants::DW_AT_type,
+                        AttributeValue::UnitRef(*member_type),
+                    ),
+                    (
+                        gimli::constants::DW_AT_data_member_location,
+                        AttributeValue::Udata(*member_offset),
+                    ),
+                ],
+            );
+        }
+        anonymous_type
+    };
+    let anonymous_union = add_anonymous_type(
+        &mut builder,
+        gimli::constants::DW_TAG_union_type,
+        4,
+        &[("raw", u32_type, 0), ("value", f32_type, 0)],
+    );
+    let anonymous_struct = add_anonymous_type(
+        &mut builder,
+        gimli::constants::DW_TAG_structure_type,
+        4,
+        &[("x", u16_type, 0), ("y", u16_type, 2)],
+    );
+
+    let message = builder.add_structure("Message", 12, &[("kind", u8_type, 0)]);
+    for (anonymous_type, offset) in [(anonymous_union, 4), (anonymous_struct, 8)] {
+        builder.add(
+            message,
+            gimli::constants::DW_TAG_member,
+            vec![
+                (
+                    gimli::constants::DW_AT_type,
+                    AttributeValue::UnitRef(anonymous_type),
+                ),
+                (
+                    gimli::constants::DW_AT_data_member_location,
+                    AttributeValue::Udata(offset),
+                ),
+            ],
+        );
+    }
+    builder.add_static_variable("MESSAGE", message, 0x2000_0000);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0000,
+        [[7, 0, 0, 0], 1.5f32.to_le_bytes(), [2, 0, 3, 0]].concat(),
+    ));
+
+    let variable = read_static_variable(&dwarf, &device_memory, "MESSAGE");
+    assert_eq!(
+        render_type_value_tree(&variable.type_value, Theme::None).to_string(),
+        "Message { kind: 7, raw: 1069547520, value: 1.5, x: 2, y: 3 }"
+    );
+}
+
+#[test]
+fn lazy_arrays() {
+    let mut builder = DwarfBuilder::new();
+    let u16_type = builder.add_base_type("u16", gimli::constants::DW_ATE_unsigned, 2);
+    let small_array = builder.add_array(u16_type, 3);
+    let big_array = builder.add_array(u16_type, 8);
+    builder.add_static_variable("SMALL", small_array, 0x2000_0000);
+    builder.add_static_variable("BIG", big_array, 0x2000_0000);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::<u32>::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0000,
+        (1u16..=8).flat_map(|value| value.to_le_bytes()).collect(),
+    ));
+
+    let mut type_cache = HashMap::new();
+    let (variables, _) = find_static_variables(
+        &dwarf,
+        &device_memory,
+        &mut type_cache,
+        &mut DecodeState::new(DecodeOptions {
+            lazy_array_threshold: Some(4),
+            ..Default::default()
+        }),
+    )
+    .unwrap();
+
+    let cached_array = |name: &str| {
+        type_cache
+            .values()
+            .filter_map(|tree| tree.as_ref().ok())
+            .find(|tree| tree.data().variable_type.name == name)
+            .unwrap()
+    };
+
+    // The big array only has its element type until its data is read
+    let big_array = cached_array("[u16;8]");
+    assert!(big_array.data().variable_type.array_layout.unwrap().lazy);
+    assert_eq!(big_array.root().degree(), 1);
+    assert_eq!(big_array.front().unwrap().data().name, "base");
+
+    // The small array is under the threshold, so it has all of its elements right away
+    let small_array = cached_array("[u16;3]");
+    assert!(!small_array.data().variable_type.array_layout.unwrap().lazy);
+    assert_eq!(
+        small_array
+            .iter()
+            .map(|element| element.data().name.as_str())
+            .collect::<Vec<_>>(),
+        ["0", "1", "2"]
+    );
+
+    // Once read, both are fully expanded
+    let render = |name| {
+        let variable = variables
+            .iter()
+            .find(|variable| variable.name == name)
+            .unwrap();
+        render_type_value_tree(&variable.type_value, Theme::None).to_string()
+    };
+    assert_eq!(render("SMALL"), "[1, 2, 3]");
+    assert_eq!(render("BIG"), "[1, 2, 3, 4, 5, 6, 7, 8]");
+}
+
+#[test]
+fn arrays_of_structs() {
+    let mut builder = DwarfBuilder::new();
+    let u8_type = builder.add_base_type("u8", gimli::constants::DW_ATE_unsigned, 1);
+    let u16_type = builder.add_base_type("u16", gimli::constants::DW_ATE_unsigned, 2);
+    let u32_type = builder.add_base_type("u32", gimli::constants::DW_ATE_unsigned, 4);
+
+    // A struct with padding at the end: `struct Padded { u32 value; u8 flag; }`
+    let padded =
+        builder.add_structure("Padded", 8, &[("value", u32_type, 0), ("flag", u8_type, 4)]);
+    let padded_array = builder.add_array(padded, 3);
+    builder.add_static_variable("PADDED", padded_array, 0x2000_0000);
+
+    // The same struct without a byte size, so only the array knows the padding
+    let unsized_padded = builder.add(
+        builder.root(),
+        gimli::constants::DW_TAG_structure_type,
+        vec![(
+            gimli::constants::DW_AT_name,
+            AttributeValue::String("Unsized".into()),
+        )],
+    );
+    for (name, member_type, offset) in [("value", u32_type, 0), ("flag", u8_type, 4)] {
+        builder.add(
+            unsized_padded,
+            gimli::constants::DW_TAG_member,
+            vec![
+                (
+                    gimli::constants::DW_AT_name,
+                    AttributeValue::String(name.into()),
+                ),
+                (
+                    gimli::constants::DW_AT_type,
+                    AttributeValue::UnitRef(member_type),
+                ),
+                (
+                    gimli::constants::DW_AT_data_member_location,
+                    AttributeValue::Udata(offset),
+                ),
+            ],
+        );
+    }
+    let sized_array = builder.add_array(unsized_padded, 3);
+    builder
+        .dwarf
+        .units
+        .get_mut(builder.unit)
+        .get_mut(sized_array)
+        .set(gimli::constants::DW_AT_byte_size, AttributeValue::Udata(24));
+    builder.add_static_variable("SIZED", sized_array, 0x2000_0000);
+    let strided_array = builder.add_array(unsized_padded, 3);
+    builder
+        .dwarf
+        .units
+        .get_mut(builder.unit)
+        .get_mut(strided_array)
+        .set(
+            gimli::constants::DW_AT_byte_stride,
+            AttributeValue::Udata(8),
+        );
+    builder.add_static_variable("STRIDED", strided_array, 0x2000_0000);
+
+    // Members and elements that are not at the start of their parent
+    let inner = builder.add_structure("Inner", 4, &[("x", u16_type, 0), ("y", u16_type, 2)]);
+    let inner_array = builder.add_array(inner, 2);
+    let outer = builder.add_structure(
+        "Outer",
+        16,
+        &[
+            ("value", u32_type, 0),
+            ("inner", inner, 4),
+            ("array", inner_array, 8),
+        ],
+    );
+    builder.add_static_variable("OUTER", outer, 0x2000_0100);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0000,
+        (1..=3u32)
+            .flat_map(|i| [i.to_le_bytes(), [i as u8 * 10, 0xFF, 0xFF, 0xFF]].concat())
+            .collect(),
+    ));
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0100,
+        [1u16, 0, 2, 3, 4, 5, 6, 7]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect(),
+    ));
+
+    for name in ["PADDED", "SIZED", "STRIDED"] {
+        let variable = read_static_variable(&dwarf, &device_memory, name);
+        assert_eq!(
+            render_type_value_tree(&variable.type_value, Theme::None)
+                .to_string()
+                .replace("Unsized", "Padded"),
+            "[Padded { value: 1, flag: 10 }, Padded { value: 2, flag: 20 }, Padded { value: 3, flag: 30 }]",
+            "{name}"
+        );
+    }
+
+    let variable = read_static_variable(&dwarf, &device_memory, "OUTER");
+    assert_eq!(
+        render_type_value_tree(&variable.type_value, Theme::None).to_string(),
+        "Outer { value: 1, inner: Inner { x: 2, y: 3 }, array: [Inner { x: 4, y: 5 }, Inner { x: 6, y: 7 }] }"
+    );
+}
+
+#[test]
+fn max_depth() {
+    let mut builder = DwarfBuilder::new();
+    let u8_type = builder.add_base_type("u8", gimli::constants::DW_ATE_unsigned, 1);
+    let inner = builder.add_structure("Inner", 1, &[("value", u8_type, 0)]);
+    let inner_array = builder.add_array(inner, 2);
+    let outer = builder.add_structure(
+        "Outer",
+        4,
+        &[
+            ("value", u8_type, 0),
+            ("inner", inner, 1),
+            ("array", inner_array, 2),
+        ],
+    );
+    builder.add_static_variable("OUTER", outer, 0x2000_0000);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(0x2000_0000, vec![1, 2, 3, 4]));
+
+    let variable = read_static_variable(&dwarf, &device_memory, "OUTER");
+    let render = |max_depth| {
+        render_type_value_tree_with_options(
+            &variable.type_value,
+            Theme::None,
+            &RenderOptions {
+                max_depth,
+                ..Default::default()
+            },
+        )
+        .to_string()
+    };
+
+    assert_eq!(render(Some(0)), "Outer { … }");
+    assert_eq!(
+        render(Some(1)),
+        "Outer { value: 1, inner: Inner { … }, array: […] }"
+    );
+    assert_eq!(
+        render(Some(2)),
+        "Outer { value: 1, inner: Inner { value: 2 }, array: [Inner { … }, Inner { … }] }"
+    );
+    assert_eq!(
+        render(None),
+        "Outer { value: 1, inner: Inner { value: 2 }, array: [Inner { value: 3 }, Inner { value: 4 }] }"
+    );
+}
+
+#[test]
+fn max_array_elements() {
+    let mut builder = DwarfBuilder::new();
+    let u8_type = builder.add_base_type("u8", gimli::constants::DW_ATE_unsigned, 1);
+    let array = builder.add_array(u8_type, 6);
+    builder.add_static_variable("BUFFER", array, 0x2000_0000);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(0x2000_0000, vec![1, 2, 3, 4, 5, 6]));
+
+    let variable = read_static_variable(&dwarf, &device_memory, "BUFFER");
+    let render = |max_array_elements| {
+        render_type_value_tree_with_options(
+            &variable.type_value,
+            Theme::None,
+            &RenderOptions {
+                max_array_elements,
+                ..Default::default()
+            },
+        )
+        .to_string()
+    };
+
+    // All elements are decoded, only the rendering is limited
+    assert_eq!(variable.type_value.root().degree(), 6);
+    assert_eq!(render(Some(0)), "[… (+6 more)]");
+    assert_eq!(render(Some(3)), "[1, 2, 3, … (+3 more)]");
+    assert_eq!(render(Some(6)), "[1, 2, 3, 4, 5, 6]");
+    assert_eq!(render(None), "[1, 2, 3, 4, 5, 6]");
+}
+
+#[test]
+fn bitfield_enums() {
+    let mut builder = DwarfBuilder::new();
+    let u8_type = builder.add_base_type("u8", gimli::constants::DW_ATE_unsigned, 1);
+    let i8_type = builder.add_base_type("i8", gimli::constants::DW_ATE_signed, 1);
+    let mode = builder.add_enumeration("Mode", u8_type, &[("Slow", 1), ("Fast", 2)]);
+    let level = builder.add_enumeration("Level", i8_type, &[("Low", -1), ("High", 3)]);
+    let level_typedef = builder.add_type_modifier(gimli::constants::DW_TAG_typedef, level);
+    builder
+        .dwarf
+        .units
+        .get_mut(builder.unit)
+        .get_mut(level_typedef)
+        .set(
+            gimli::constants::DW_AT_name,
+            AttributeValue::String("level_t".into()),
+        );
+
+    // Both enums only use the lowest 3 bits of their byte
+    let packed = builder.add_structure("Packed", 2, &[]);
+    for (name, member_type, offset) in [("mode", mode, 0), ("level", level_typedef, 1)] {
+        builder.add(
+            packed,
+            gimli::constants::DW_TAG_member,
+            vec![
+                (
+                    gimli::constants::DW_AT_name,
+                    AttributeValue::String(name.into()),
+                ),
+                (
+                    gimli::constants::DW_AT_type,
+                    AttributeValue::UnitRef(member_type),
+                ),
+                (
+                    gimli::constants::DW_AT_data_member_location,
+                    AttributeValue::Udata(offset),
+                ),
+                (gimli::constants::DW_AT_bit_size, AttributeValue::Udata(3)),
+            ],
+        );
+    }
+    builder.add_static_variable("PACKED", packed, 0x2000_0000);
+    let dwarf = builder.build();
+
+    // The upper bits are garbage that must be ignored. The level is sign extended to -1.
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0000,
+        vec![0b1111_1010, 0b0101_0111],
+    ));
+
+    let variable = read_static_variable(&dwarf, &device_memory, "PACKED");
+    assert_eq!(
+        render_type_value_tree(&variable.type_value, Theme::None).to_string(),
+        "Packed { mode: Fast, level: Low }"
+    );
+}
+
+#[test]
+fn bitfields() {
+    let mut builder = DwarfBuilder::new();
+    let u32_type = builder.add_base_type("u32", gimli::constants::DW_ATE_unsigned, 4);
+    let i32_type = builder.add_base_type("i32", gimli::constants::DW_ATE_signed, 4);
+
+    let settings = builder.add_structure("Settings", 2, &[]);
+    for (name, member_type, bit_offset, bit_size) in [
+        ("a", u32_type, 0, 3),
+        ("b", u32_type, 3, 3),
+        ("c", i32_type, 6, 4),
+    ] {
+        builder.add(
+            settings,
+            gimli::constants::DW_TAG_member,
+            vec![
+                (
+                    gimli::constants::DW_AT_name,
+                    AttributeValue::String(name.into()),
+                ),
+                (
+                    gimli::constants::DW_AT_type,
+                    AttributeValue::UnitRef(member_type),
+                ),
+                (
+                    gimli::constants::DW_AT_data_bit_offset,
+                    AttributeValue::Udata(bit_offset),
+                ),
+                (
+                    gimli::constants::DW_AT_bit_size,
+                    AttributeValue::Udata(bit_size),
+                ),
+            ],
+        );
+    }
+    // The DWARF 2 style: the top 2 bits of the byte at offset 1
+    builder.add(
+        settings,
+        gimli::constants::DW_TAG_member,
+        vec![
+            (
+                gimli::constants::DW_AT_name,
+                AttributeValue::String("d".into()),
+            ),
+            (
+                gimli::constants::DW_AT_type,
+                AttributeValue::UnitRef(u32_type),
+            ),
+            (
+                gimli::constants::DW_AT_data_member_location,
+                AttributeValue::Udata(1),
+            ),
+            (gimli::constants::DW_AT_byte_size, AttributeValue::Udata(1)),
+            (gimli::constants::DW_AT_bit_offset, AttributeValue::Udata(0)),
+            (gimli::constants::DW_AT_bit_size, AttributeValue::Udata(2)),
+        ],
+    );
+    builder.add_static_variable("SETTINGS", settings, 0x2000_0000);
+    let dwarf = builder.build();
+
+    // a = 0b101, b = 0b010, c = 0b1101 (-3) which crosses the byte boundary, d = 0b11
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0000,
+        vec![0b0101_0101, 0b1100_0011],
+    ));
+
+    let variable = read_static_variable(&dwarf, &device_memory, "SETTINGS");
+    assert_eq!(
+        render_type_value_tree(&variable.type_value, Theme::None).to_string(),
+        "Settings { a: 5, b: 2, c: -3, d: 3 }"
+    );
+}
+
+#[test]
+fn type_qualifiers() {
+    let mut builder = DwarfBuilder::new();
+    let u32_type = builder.add_base_type("u32", gimli::constants::DW_ATE_unsigned, 4);
+    let volatile_type = builder.add_type_modifier(gimli::constants::DW_TAG_volatile_type, u32_type);
+    let const_volatile_type =
+        builder.add_type_modifier(gimli::constants::DW_TAG_const_type, volatile_type);
+    let pointer_type = builder.add_type_modifier(gimli::constants::DW_TAG_pointer_type, u32_type);
+    let restrict_type =
+        builder.add_type_modifier(gimli::constants::DW_TAG_restrict_type, pointer_type);
+    let atomic_type = builder.add_type_modifier(gimli::constants::DW_TAG_atomic_type, u32_type);
+    let set_type = builder.add_type_modifier(gimli::constants::DW_TAG_set_type, u32_type);
+    builder.add_static_variable("REGISTER", const_volatile_type, 0x2000_0000);
+    builder.add_static_variable("RESTRICT_POINTER", restrict_type, 0x2000_0004);
+    builder.add_static_variable("COUNTER", atomic_type, 0x2000_0000);
+    builder.add_static_variable("SET", set_type, 0x2000_0000);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(
+        0x2000_0000,
+        [5u32.to_le_bytes(), 0x2000_0000u32.to_le_bytes()].concat(),
+    ));
+
+    // The qualifiers are carried by the underlying type
+    let register = read_static_variable(&dwarf, &device_memory, "REGISTER");
+    let register_type = &register.type_value.data().variable_type;
+    assert_eq!(register_type.name, "u32");
+    assert!(register_type.const_type && register_type.volatile);
+    assert_eq!(
+        render_type_value_tree(&register.type_value, Theme::None).to_string(),
+        "const volatile 5"
+    );
+
+    let restrict_pointer = read_static_variable(&dwarf, &device_memory, "RESTRICT_POINTER");
+    assert!(restrict_pointer.type_value.data().variable_type.restrict);
+    assert_eq!(
+        render_type_value_tree(&restrict_pointer.type_value, Theme::None).to_string(),
+        "restrict *0x20000000 = 5"
+    );
+
+    let counter = read_static_variable(&dwarf, &device_memory, "COUNTER");
+    assert!(counter.type_value.data().variable_type.atomic);
+    assert_eq!(
+        render_type_value_tree(&counter.type_value, Theme::None).to_string(),
+        "atomic 5"
+    );
+
+    // An unknown tag doesn't panic, but omits the variable
+    let (_, omitted_variables) = find_static_variables(
+        &dwarf,
+        &device_memory,
+        &mut HashMap::new(),
+        &mut DecodeState::new(DecodeOptions::default()),
+    )
+    .unwrap();
+    assert_eq!(omitted_variables.len(), 1);
+    assert_eq!(omitted_variables[0].name.as_deref(), Some("SET"));
+    assert!(omitted_variables[0]
+        .reason
+        .contains("The tag `DW_TAG_set_type`"));
+}
+
+#[test]
+fn raw_data() {
+    let mut builder = DwarfBuilder::new();
+    let u16_type = builder.add_base_type("u16", gimli::constants::DW_ATE_unsigned, 2);
+    let float_type = builder.add_base_type("f24", gimli::constants::DW_ATE_float, 3);
+    let point = builder.add_structure("Point", 4, &[("x", u16_type, 0), ("y", u16_type, 2)]);
+    builder.add_static_variable("POINT", point, 0x2000_0000);
+    builder.add_static_variable("FLOAT", float_type, 0x2000_0000);
+    let dwarf = builder.build();
+
+    let mut device_memory = DeviceMemory::new();
+    device_memory.add_memory_region(VecMemoryRegion::new(0x2000_0000, vec![1, 0, 2, 0]));
+
+    // Values that can't be decoded always show their bytes
+    let float = read_static_variable(&dwarf, &device_memory, "FLOAT");
+    assert_eq!(float.type_value.data().raw_data, Some(vec![1, 0, 2]));
+    assert_eq!(
+        render_type_value_tree(&float.type_value, Theme::None).to_string(),
+        "{Data has invalid size of 24 bits} <raw: [01, 00, 02]>"
+    );
+
+    // Other values only with the option. Objects don't keep their bytes, their fields do.
+    let point = read_static_variable(&dwarf, &device_memory, "POINT");
+    assert_eq!(point.type_value.data().raw_data, None);
+    assert_eq!(
+        render_type_value_tree(&point.type_value, Theme::None).to_string(),
+        "Point { x: 1, y: 2 }"
+    );
+    assert_eq!(
+        render_type_value_tree_with_options(
+            &point.type_value,
+            Theme::None,
+            &RenderOptions {
+                show_raw: true,
+                ..Default::default()
+            },
+        )
+        .to_string(),
+        "Point { x: 1 <raw: [01, 00]>, y: 2 <raw: [02, 00]> }"
+    );
+}
@@ -2,19 +2,22 @@ use crate::{
     error::TraceError,
     get_entry_type_reference_tree_recursive,
     gimli_extensions::{AttributeExt, DebuggingInformationEntryExt},
-    type_value_tree::{variable_type::Archetype, TypeValue, TypeValueTree},
-    variables::build_type_value_tree,
+    type_value_tree::{
+        variable_type::{Archetype, ArrayLayout},
+        TypeValue, TypeValueTree,
+    },
+    variables::{build_type_value_tree, read_data_location, DecodeState, TypeCache},
     DefaultReader,
 };
-use gimli::{Abbreviations, DebugInfoOffset, Dwarf, Unit};
-use std::collections::HashMap;
+use gimli::{Abbreviations, Dwarf, Unit};
 
 pub fn build_array<W: funty::Integral>(
     dwarf: &Dwarf<DefaultReader>,
     unit: &Unit<DefaultReader, usize>,
     abbreviations: &Abbreviations,
     node: gimli::EntriesTreeNode<DefaultReader>,
-    type_cache: &mut HashMap<DebugInfoOffset, Result<TypeValueTree<W>, TraceError>>,
+    type_cache: &mut TypeCache<W>,
+    state: &DecodeState,
 ) -> Result<TypeValueTree<W>, TraceError> {
     let mut type_value_tree = TypeValueTree::new(TypeValue::default());
     let mut type_value = type_value_tree.root_mut();
@@ -28,13 +31,22 @@ pub fn build_array<W: funty::Integral>(
     // Arrays have one child entry that contains information about the indexing of the array.
 
     get_entry_type_reference_tree_recursive!(
-        base_element_type_tree = (dwarf, unit, abbreviations, entry)
+        base_element_type_tree,
+        type_unit,
+        type_abbreviations = (dwarf, unit, abbreviations, entry)
     );
 
     let mut base_element_type_tree = base_element_type_tree.map(|mut type_tree| {
-        type_tree
-            .root()
-            .map(|root| build_type_value_tree(dwarf, unit, abbreviations, root, type_cache))
+        type_tree.root().map(|root| {
+            build_type_value_tree(
+                dwarf,
+                type_unit,
+                type_abbreviations,
+                root,
+                type_cache,
+                state,
+            )
+        })
     })???;
 
     base_element_type_tree.root_mut().data_mut().name = "base".into();
@@ -42,6 +54,12 @@ pub fn build_array<W: funty::Integral>(
     let byte_size = entry
         .attr(gimli::constants::DW_AT_byte_size)?
         .and_then(|bsize| bsize.udata_value());
+    let array_stride = read_stride(entry)?;
+
+    // The data of the array may live somewhere else than the array object itself.
+    // In that case the array object is a descriptor that (at least) contains a pointer to the data.
+    let data_location = read_data_location(unit, entry)?;
+
     let element_bitsize = base_element_type_tree.data().bit_length();
 
     let mut children = node.children();
@@ -50,12 +68,19 @@ pub fn build_array<W: funty::Integral>(
         .ok_or(TraceError::ExpectedChildNotPresent { entry_tag })?;
     let child_entry = child.entry();
 
+    // C doesn't always have a lower bound, because it's always 0 there
     let lower_bound = child_entry
-        .required_attr(&unit.header, gimli::constants::DW_AT_lower_bound)?
-        .sdata_value()
+        .attr(gimli::constants::DW_AT_lower_bound)?
+        .and_then(|lower_bound| lower_bound.sdata_value())
         .unwrap_or(0);
 
-    // There's either a count or an upper bound
+    // There's either a count or an upper bound.
+    // A flexible array member (`char data[]` in C) has neither, so it's an array of 0 elements.
+    // The real length of it can only be found when its data is read.
+    let has_bound = child_entry
+        .attr(gimli::constants::DW_AT_count)?
+        .or(child_entry.attr(gimli::constants::DW_AT_upper_bound)?)
+        .is_some();
     let count = match (
         child_entry
             .required_attr(&unit.header, gimli::constants::DW_AT_count)
@@ -66,34 +91,95 @@ pub fn build_array<W: funty::Integral>(
     ) {
         // We've got a count, so let's use that
         (Ok(count), _) => Ok(count),
-        // We've got an upper bound, so let's calculate the count from that
-        (_, Ok(upper_bound)) => Ok((upper_bound - lower_bound).try_into().unwrap()),
+        // We've got an upper bound, so let's calculate the count from that.
+        // The upper bound is the index of the last element, so a zero-length array has an upper bound below the lower bound.
+        (_, Ok(upper_bound)) => Ok((upper_bound - lower_bound + 1).try_into().unwrap_or(0)),
+        // There's no bound at all
+        (Err(_), Err(_)) if !has_bound => Ok(0),
         // Both are not readable
         (Err(e), Err(_)) => Err(e),
     }?;
 
+    // The elements start at whole bytes, unless the array says otherwise.
+    // The size of the element type doesn't always include its padding, but the size of the array does.
+    let stride = match array_stride.or(read_stride(child_entry)?) {
+        Some(stride) => stride,
+        None => match (byte_size, &data_location) {
+            (Some(byte_size), None) if count > 0 && byte_size * 8 / count >= element_bitsize => {
+                byte_size * 8 / count
+            }
+            _ => element_bitsize.div_ceil(8) * 8,
+        },
+    };
+
     type_value.data_mut().bit_range.end = type_value.data_mut().bit_range.start
-        + byte_size
-            .map(|byte_size| byte_size * 8)
-            .unwrap_or_else(|| element_bitsize * count);
+        + match (byte_size, &data_location) {
+            (Some(byte_size), _) => byte_size * 8,
+            (None, Some(_)) => W::BITS as u64,
+            (None, None) => stride * count,
+        };
     type_value.data_mut().variable_type.name = format!(
         "[{};{}]",
         base_element_type_tree.data().variable_type.name,
         count
     );
     type_value.data_mut().variable_type.archetype = Archetype::Array;
+    type_value.data_mut().variable_type.data_location = data_location;
 
-    for data_index in lower_bound..(lower_bound + count as i64) {
-        let mut element_type_tree = base_element_type_tree.clone();
+    // An array without elements keeps its element type, because it can be a flexible array member.
+    // Its real length is only known when its data is read.
+    let lazy = count == 0 || state.is_lazy_array(count);
+    type_value.data_mut().variable_type.array_layout = Some(ArrayLayout {
+        lower_bound,
+        count,
+        stride,
+        lazy,
+    });
 
-        element_type_tree.root_mut().data_mut().name = data_index.to_string();
-        element_type_tree.root_mut().data_mut().bit_range.start +=
-            data_index as u64 * element_bitsize;
-        element_type_tree.root_mut().data_mut().bit_range.end +=
-            data_index as u64 * element_bitsize;
-
-        type_value.push_back(element_type_tree);
+    if lazy {
+        // The elements are created when the data is read, so only the element type is added as the only child
+        type_value.push_back(base_element_type_tree);
+    } else {
+        for (position, data_index) in (lower_bound..(lower_bound + count as i64)).enumerate() {
+            type_value.push_back(array_element(
+                &base_element_type_tree,
+                data_index,
+                position as u64 * stride,
+            ));
+        }
     }
 
     Ok(type_value_tree)
 }
+
+/// Creates the element of an array with the given index that starts `bit_offset` bits into the array
+pub(crate) fn array_element<W: funty::Integral>(
+    base_element_type_tree: &TypeValueTree<W>,
+    index: i64,
+    bit_offset: u64,
+) -> TypeValueTree<W> {
+    let mut element_type_tree = base_element_type_tree.clone();
+
+    let mut element = element_type_tree.root_mut();
+    element.data_mut().name = index.to_string();
+    element.data_mut().bit_range.start += bit_offset;
+    element.data_mut().bit_range.end += bit_offset;
+
+    element_type_tree
+}
+
+/// Reads the `DW_AT_byte_stride` or `DW_AT_bit_stride` of an array or subrange in bits
+fn read_stride(
+    entry: &gimli::DebuggingInformationEntry<DefaultReader, usize>,
+) -> Result<Option<u64>, TraceError> {
+    if let Some(byte_stride) = entry
+        .attr(gimli::constants::DW_AT_byte_stride)?
+        .and_then(|byte_stride| byte_stride.udata_value())
+    {
+        return Ok(Some(byte_stride * 8));
+    }
+
+    Ok(entry
+        .attr(gimli::constants::DW_AT_bit_stride)?
+        .and_then(|bit_stride| bit_stride.udata_value()))
+}
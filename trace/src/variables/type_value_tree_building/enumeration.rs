@@ -7,18 +7,18 @@ use crate::{
         variable_type::{Archetype, VariableType},
         TypeValue, TypeValueTree,
     },
-    variables::{build_type_value_tree, get_entry_name},
+    variables::{build_type_value_tree, get_entry_name, DecodeState, TypeCache},
     DefaultReader,
 };
-use gimli::{Abbreviations, DebugInfoOffset, Dwarf, Unit};
-use std::collections::HashMap;
+use gimli::{Abbreviations, Dwarf, Unit};
 
 pub fn build_enumeration<W: funty::Integral>(
     dwarf: &Dwarf<DefaultReader>,
     unit: &Unit<DefaultReader, usize>,
     abbreviations: &Abbreviations,
     node: gimli::EntriesTreeNode<DefaultReader>,
-    type_cache: &mut HashMap<DebugInfoOffset, Result<TypeValueTree<W>, TraceError>>,
+    type_cache: &mut TypeCache<W>,
+    state: &DecodeState,
 ) -> Result<TypeValueTree<W>, TraceError> {
     let mut type_value_tree = TypeValueTree::new(TypeValue::default());
     let mut type_value = type_value_tree.root_mut();
@@ -31,12 +31,21 @@ pub fn build_enumeration<W: funty::Integral>(
     let name = get_entry_name(dwarf, unit, entry)?;
 
     get_entry_type_reference_tree_recursive!(
-        underlying_type_tree = (dwarf, unit, abbreviations, entry)
+        underlying_type_tree,
+        type_unit,
+        type_abbreviations = (dwarf, unit, abbreviations, entry)
     );
     let mut underlying_type_tree = underlying_type_tree.map(|mut type_tree| {
-        type_tree
-            .root()
-            .map(|root| build_type_value_tree(dwarf, unit, abbreviations, root, type_cache))
+        type_tree.root().map(|root| {
+            build_type_value_tree(
+                dwarf,
+                type_unit,
+                type_abbreviations,
+                root,
+                type_cache,
+                state,
+            )
+        })
     })???;
     underlying_type_tree.root_mut().data_mut().name = "base".into();
     let underlying_type_bitrange = underlying_type_tree.root().data().bit_range.clone();
@@ -73,6 +82,7 @@ pub fn build_enumeration<W: funty::Integral>(
             },
             bit_range: underlying_type_bitrange.clone(),
             variable_value: Ok(Value::Int(const_value as i128)),
+            raw_data: None,
         }));
     }
 
@@ -10,7 +10,11 @@ pub use base_type::build_base_type;
 mod pointer;
 pub use pointer::build_pointer;
 
+mod reference;
+pub use reference::{build_reference, build_rvalue_reference};
+
 mod array;
+pub(crate) use array::array_element;
 pub use array::build_array;
 
 mod typedef;
@@ -24,3 +28,12 @@ pub use volatile_type::build_volatile_type;
 
 mod const_type;
 pub use const_type::build_const_type;
+
+mod restrict_type;
+pub use restrict_type::build_restrict_type;
+
+mod atomic_type;
+pub use atomic_type::build_atomic_type;
+
+mod unspecified_type;
+pub use unspecified_type::build_unspecified_type;
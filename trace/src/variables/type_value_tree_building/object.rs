@@ -1,20 +1,26 @@
 use crate::{
     error::TraceError,
     get_entry_type_reference_tree_recursive,
-    gimli_extensions::{AttributeExt, DebuggingInformationEntryExt},
-    type_value_tree::{variable_type::Archetype, TypeValue, TypeValueTree},
-    variables::{build_type_value_tree, get_entry_name, read_data_member_location},
+    gimli_extensions::AttributeExt,
+    type_value_tree::{variable_type::Archetype, TypeValue, TypeValueNode, TypeValueTree},
+    variables::{
+        build_type_value_tree, get_entry_name, read_data_member_location, DecodeState, TypeCache,
+    },
     DefaultReader,
 };
-use gimli::{Abbreviations, DebugInfoOffset, DwTag, Dwarf, Unit};
-use std::collections::HashMap;
+use gimli::{Abbreviations, AttributeValue, DwTag, Dwarf, Unit};
+use std::pin::Pin;
+
+/// The name that is used for structs and unions that don't have a name
+const ANONYMOUS_TYPE_NAME: &str = "<anonymous>";
 
 pub fn build_object<W: funty::Integral>(
     dwarf: &Dwarf<DefaultReader>,
     unit: &Unit<DefaultReader, usize>,
     abbreviations: &Abbreviations,
     node: gimli::EntriesTreeNode<DefaultReader>,
-    type_cache: &mut HashMap<DebugInfoOffset, Result<TypeValueTree<W>, TraceError>>,
+    type_cache: &mut TypeCache<W>,
+    state: &DecodeState,
     tag: DwTag,
 ) -> Result<TypeValueTree<W>, TraceError> {
     let mut type_value_tree = TypeValueTree::new(TypeValue::default());
@@ -28,10 +34,18 @@ pub fn build_object<W: funty::Integral>(
     // - the members of the object
     // - the byte size of the object
 
-    let type_name = get_entry_name(dwarf, unit, entry)?;
+    // Anonymous structs and unions (C) don't have a name
+    let type_name =
+        get_entry_name(dwarf, unit, entry).unwrap_or_else(|_| ANONYMOUS_TYPE_NAME.into());
+    // Without a byte size (which some compilers leave out), the size is calculated from the members
     let byte_size = entry
-        .required_attr(&unit.header, gimli::constants::DW_AT_byte_size)?
-        .required_udata_value()?;
+        .attr(gimli::constants::DW_AT_byte_size)?
+        .and_then(|byte_size| byte_size.udata_value());
+    let alignment = entry
+        .attr(gimli::constants::DW_AT_alignment)?
+        .and_then(|alignment| alignment.udata_value())
+        .unwrap_or(1)
+        .max(1);
 
     // Check if this is a type that wraps another type
     let is_member_pointer = entry
@@ -48,7 +62,7 @@ pub fn build_object<W: funty::Integral>(
 
     type_value.data_mut().variable_type.name = type_name.clone();
     type_value.data_mut().variable_type.archetype = archetype;
-    type_value.data_mut().bit_range = 0..byte_size * 8;
+    type_value.data_mut().bit_range = 0..byte_size.unwrap_or_default() * 8;
 
     // The members of the object can be found by looking at the children of the node
     let mut children = node.children();
@@ -63,13 +77,13 @@ pub fn build_object<W: funty::Integral>(
         if member_entry.tag() == gimli::constants::DW_TAG_variant_part {
             // This is a tagged union, so ignore everything and build the type value tree from this child
             let mut tagged_union =
-                build_type_value_tree(dwarf, unit, abbreviations, child, type_cache);
+                build_type_value_tree(dwarf, unit, abbreviations, child, type_cache, state);
 
             if let Ok(tagged_union) = tagged_union.as_mut() {
                 // The tagged union child doesn't have a name or byte size, so we need to give it the name of the object we
                 // we thought we would get
                 tagged_union.root_mut().data_mut().variable_type.name = type_name;
-                tagged_union.root_mut().data_mut().bit_range = 0..byte_size * 8;
+                tagged_union.root_mut().data_mut().bit_range = 0..byte_size.unwrap_or_default() * 8;
             }
 
             return tagged_union;
@@ -84,36 +98,57 @@ pub fn build_object<W: funty::Integral>(
 
         // Member fields have a name, a type and a location offset (relative to the base of the object).
         // Type parameters only have a name and a type.
+        // C++ static members are declared in the object, but live at a global address. They are not part of the object data.
         // The rest of the children are ignored.
 
         let member_name = match get_entry_name(dwarf, unit, member_entry) {
             Ok(member_name) => member_name,
+            // C can have anonymous struct and union members of which the fields are accessed as if they are part of this object
+            Err(_) if member_entry.tag() == gimli::constants::DW_TAG_member => {
+                let mut member_tree =
+                    build_member_tree(dwarf, unit, abbreviations, member_entry, type_cache, state)?;
+                // The fields are relative to the anonymous member, so they need to be moved to where it is in this object
+                let member_location_offset_bits = member_tree.data().bit_range.start;
+
+                // The type of the member can be a typedef of the struct or union (GCC allows this with `-fms-extensions`)
+                while member_tree.data().variable_type.archetype == Archetype::Typedef {
+                    match member_tree.root_mut().pop_front() {
+                        Some(underlying_type_tree) => member_tree = underlying_type_tree,
+                        None => break,
+                    }
+                }
+
+                if matches!(
+                    member_tree.data().variable_type.archetype,
+                    Archetype::Structure | Archetype::Union | Archetype::Class
+                ) {
+                    while let Some(mut field_tree) = member_tree.root_mut().pop_front() {
+                        field_tree.root_mut().data_mut().bit_range.start +=
+                            member_location_offset_bits;
+                        field_tree.root_mut().data_mut().bit_range.end +=
+                            member_location_offset_bits;
+
+                        type_value.push_back(field_tree);
+                    }
+                }
+
+                continue;
+            }
             Err(_) => continue, // Only care about named members for now
         };
 
         match member_entry.tag() {
+            gimli::constants::DW_TAG_member if is_static_member(member_entry)? => {} // Ignore
             gimli::constants::DW_TAG_member => {
-                let member_location_offset_bits =
-                    read_data_member_location(&unit.header, member_entry)?;
-
-                get_entry_type_reference_tree_recursive!(
-                    member_tree = (dwarf, unit, abbreviations, member_entry)
-                );
-
-                let mut member_tree = member_tree.map(|mut type_tree| {
-                    type_tree.root().map(|root| {
-                        build_type_value_tree(dwarf, unit, abbreviations, root, type_cache)
-                    })
-                })???;
-
+                let mut member_tree =
+                    build_member_tree(dwarf, unit, abbreviations, member_entry, type_cache, state)?;
                 member_tree.root_mut().data_mut().name = member_name;
-                member_tree.root_mut().data_mut().bit_range.end += member_location_offset_bits;
-                member_tree.root_mut().data_mut().bit_range.start += member_location_offset_bits;
 
                 type_value.push_back(member_tree);
             }
             gimli::constants::DW_TAG_template_type_parameter => {} // Ignore
             gimli::constants::DW_TAG_subprogram => {}              // Ignore
+            gimli::constants::DW_TAG_variable => {} // Ignore, these are static members in DWARF 5
             gimli::constants::DW_TAG_structure_type
             | gimli::constants::DW_TAG_union_type
             | gimli::constants::DW_TAG_class_type => {} // Ignore
@@ -127,5 +162,98 @@ pub fn build_object<W: funty::Integral>(
         }
     }
 
+    if byte_size.is_none() {
+        let members_byte_size = type_value
+            .iter()
+            .map(|member| member.data().bit_range.end.div_ceil(8))
+            .max()
+            .unwrap_or_default();
+        type_value.data_mut().bit_range = 0..members_byte_size.next_multiple_of(alignment) * 8;
+    }
+
     Ok(type_value_tree)
 }
+
+/// Builds the type value tree of a member and places it at the location of the member in the object
+fn build_member_tree<W: funty::Integral>(
+    dwarf: &Dwarf<DefaultReader>,
+    unit: &Unit<DefaultReader, usize>,
+    abbreviations: &Abbreviations,
+    member_entry: &gimli::DebuggingInformationEntry<DefaultReader, usize>,
+    type_cache: &mut TypeCache<W>,
+    state: &DecodeState,
+) -> Result<TypeValueTree<W>, TraceError> {
+    let member_location_offset_bits = read_data_member_location(&unit.header, member_entry)?;
+
+    get_entry_type_reference_tree_recursive!(
+        member_tree,
+        type_unit,
+        type_abbreviations = (dwarf, unit, abbreviations, member_entry)
+    );
+
+    let mut member_tree = member_tree.map(|mut type_tree| {
+        type_tree.root().map(|root| {
+            build_type_value_tree(
+                dwarf,
+                type_unit,
+                type_abbreviations,
+                root,
+                type_cache,
+                state,
+            )
+        })
+    })???;
+
+    member_tree.root_mut().data_mut().bit_range.end += member_location_offset_bits;
+    member_tree.root_mut().data_mut().bit_range.start += member_location_offset_bits;
+
+    // Bitfield members only use some of the bits of their type, like an enum that is packed into 3 bits
+    let member_bit_size = member_entry
+        .attr(gimli::constants::DW_AT_bit_size)?
+        .map(|bit_size| bit_size.required_udata_value())
+        .transpose()?;
+    if let Some(member_bit_size) = member_bit_size {
+        narrow_bit_size(member_tree.root_mut(), member_bit_size);
+    }
+
+    Ok(member_tree)
+}
+
+/// Shrinks the bit range of the node to the given size.
+///
+/// A typedef covers the same bits as its type, so the type is shrunk as well.
+fn narrow_bit_size<W: funty::Integral>(mut node: Pin<&mut TypeValueNode<W>>, bit_size: u64) {
+    let start = node.data().bit_range.start;
+    node.data_mut().bit_range.end = start + bit_size;
+
+    if matches!(node.data().variable_type.archetype, Archetype::Typedef) {
+        if let Some(child) = node.front_mut() {
+            narrow_bit_size(child, bit_size);
+        }
+    }
+}
+
+/// Returns true if the member is a (C++) static member.
+///
+/// Up to DWARF 4 these are members with a declaration, but without a location in the object.
+fn is_static_member(
+    member_entry: &gimli::DebuggingInformationEntry<DefaultReader, usize>,
+) -> Result<bool, TraceError> {
+    // The flags can be present with a false value, which means the same as when they're absent
+    let is_flag_set = |name| -> Result<bool, TraceError> {
+        Ok(matches!(
+            member_entry.attr_value(name)?,
+            Some(AttributeValue::Flag(true))
+        ))
+    };
+    let is_declaration = is_flag_set(gimli::constants::DW_AT_declaration)?
+        || is_flag_set(gimli::constants::DW_AT_external)?;
+    let has_location = member_entry
+        .attr(gimli::constants::DW_AT_data_member_location)?
+        .is_some()
+        || member_entry
+            .attr(gimli::constants::DW_AT_data_bit_offset)?
+            .is_some();
+
+    Ok(is_declaration && !has_location)
+}
@@ -3,18 +3,37 @@ use crate::{
     get_entry_type_reference_tree_recursive,
     gimli_extensions::AttributeExt,
     type_value_tree::{variable_type::Archetype, TypeValue, TypeValueTree},
-    variables::{build_type_value_tree, get_entry_name},
+    variables::{
+        build_type_value_tree, get_entry_name, read_data_location, DecodeState, TypeCache,
+    },
     DefaultReader,
 };
-use gimli::{Abbreviations, DebugInfoOffset, Dwarf, Unit};
-use std::collections::HashMap;
+use gimli::{Abbreviations, Dwarf, Unit};
+use std::rc::Rc;
 
 pub fn build_pointer<W: funty::Integral>(
     dwarf: &Dwarf<DefaultReader>,
     unit: &Unit<DefaultReader, usize>,
     abbreviations: &Abbreviations,
     node: gimli::EntriesTreeNode<DefaultReader>,
-    type_cache: &mut HashMap<DebugInfoOffset, Result<TypeValueTree<W>, TraceError>>,
+    type_cache: &mut TypeCache<W>,
+    state: &DecodeState,
+) -> Result<TypeValueTree<W>, TraceError> {
+    build_pointer_like(dwarf, unit, abbreviations, node, type_cache, state, None)
+}
+
+/// Builds the tree of anything that behaves like a pointer.
+///
+/// - `reference_prefix`: If the pointer is a reference (like in C++), then this is the prefix of the pointee type name
+///   that forms the type name of the reference. A normal pointer gets the name from the debug info.
+pub(super) fn build_pointer_like<W: funty::Integral>(
+    dwarf: &Dwarf<DefaultReader>,
+    unit: &Unit<DefaultReader, usize>,
+    abbreviations: &Abbreviations,
+    node: gimli::EntriesTreeNode<DefaultReader>,
+    type_cache: &mut TypeCache<W>,
+    state: &DecodeState,
+    reference_prefix: Option<&str>,
 ) -> Result<TypeValueTree<W>, TraceError> {
     let mut type_value_tree = TypeValueTree::new(TypeValue::default());
     let mut type_value = type_value_tree.root_mut();
@@ -27,7 +46,9 @@ pub fn build_pointer<W: funty::Integral>(
 
     let (pointee_type_name, pointee_type_die_offset) = {
         get_entry_type_reference_tree_recursive!(
-            pointee_type_tree = (dwarf, unit, abbreviations, entry)
+            pointee_type_tree,
+            type_unit,
+            type_abbreviations = (dwarf, unit, abbreviations, entry)
         );
 
         pointee_type_tree.map(|mut type_tree| {
@@ -35,10 +56,10 @@ pub fn build_pointer<W: funty::Integral>(
                 let die_offset = root
                     .entry()
                     .offset()
-                    .to_debug_info_offset(&unit.header)
+                    .to_debug_info_offset(&type_unit.header)
                     .unwrap();
 
-                let pointee_type_name = get_entry_name(dwarf, unit, root.entry());
+                let pointee_type_name = get_entry_name(dwarf, type_unit, root.entry());
 
                 pointee_type_name.map(|ptn| (ptn, die_offset))
             })
@@ -46,10 +67,15 @@ pub fn build_pointer<W: funty::Integral>(
     };
 
     // Some pointers don't have names, but generally it is just `&<typename>`
-    // So if only the name is missing, we can recover
+    // So if only the name is missing, we can recover.
+    // References never have a name, so they always get their prefix.
 
-    let name =
-        get_entry_name(dwarf, unit, entry).unwrap_or_else(|_| format!("&{pointee_type_name}"));
+    let name = match reference_prefix {
+        Some(reference_prefix) => format!("{reference_prefix}{pointee_type_name}"),
+        None => {
+            get_entry_name(dwarf, unit, entry).unwrap_or_else(|_| format!("&{pointee_type_name}"))
+        }
+    };
 
     // The debug info also contains an address class that can describe what kind of pointer it is.
     // We only support `DW_ADDR_none` for now, which means that there's no special specification.
@@ -70,24 +96,35 @@ pub fn build_pointer<W: funty::Integral>(
 
     type_value.data_mut().variable_type.name = name;
     type_value.data_mut().variable_type.archetype = Archetype::Pointer(pointee_type_die_offset);
+    type_value.data_mut().variable_type.reference = reference_prefix.is_some();
+    type_value.data_mut().variable_type.data_location = read_data_location(unit, entry)?;
     type_value.data_mut().bit_range = 0..W::BITS as u64;
 
     // Insert this pointer into the type cache
-    type_cache.insert(entry_die_offset, Ok(type_value_tree.clone()));
+    type_cache.insert(entry_die_offset, Ok(Rc::new(type_value_tree.clone())));
 
     // Insert the pointee into the type cache
     #[allow(clippy::map_entry)] // Can't use the entry api because of the type_cache borrow later
     if !type_cache.contains_key(&pointee_type_die_offset) {
         get_entry_type_reference_tree_recursive!(
-            pointee_type_tree = (dwarf, unit, abbreviations, entry)
+            pointee_type_tree,
+            type_unit,
+            type_abbreviations = (dwarf, unit, abbreviations, entry)
         );
 
         let pointee_type_tree = pointee_type_tree.map(|mut type_tree| {
-            type_tree
-                .root()
-                .map(|root| build_type_value_tree(dwarf, unit, abbreviations, root, type_cache))
+            type_tree.root().map(|root| {
+                build_type_value_tree(
+                    dwarf,
+                    type_unit,
+                    type_abbreviations,
+                    root,
+                    type_cache,
+                    state,
+                )
+            })
         })???;
-        type_cache.insert(pointee_type_die_offset, Ok(pointee_type_tree));
+        type_cache.insert(pointee_type_die_offset, Ok(Rc::new(pointee_type_tree)));
     }
 
     Ok(type_value_tree)
@@ -0,0 +1,49 @@
+use super::pointer::build_pointer_like;
+use crate::{
+    error::TraceError,
+    type_value_tree::TypeValueTree,
+    variables::{DecodeState, TypeCache},
+    DefaultReader,
+};
+use gimli::{Abbreviations, Dwarf, Unit};
+
+pub fn build_reference<W: funty::Integral>(
+    dwarf: &Dwarf<DefaultReader>,
+    unit: &Unit<DefaultReader, usize>,
+    abbreviations: &Abbreviations,
+    node: gimli::EntriesTreeNode<DefaultReader>,
+    type_cache: &mut TypeCache<W>,
+    state: &DecodeState,
+) -> Result<TypeValueTree<W>, TraceError> {
+    // A (C++) reference is stored exactly like a pointer, so we can read it like one.
+    // Only the name is different.
+    build_pointer_like(
+        dwarf,
+        unit,
+        abbreviations,
+        node,
+        type_cache,
+        state,
+        Some("&"),
+    )
+}
+
+pub fn build_rvalue_reference<W: funty::Integral>(
+    dwarf: &Dwarf<DefaultReader>,
+    unit: &Unit<DefaultReader, usize>,
+    abbreviations: &Abbreviations,
+    node: gimli::EntriesTreeNode<DefaultReader>,
+    type_cache: &mut TypeCache<W>,
+    state: &DecodeState,
+) -> Result<TypeValueTree<W>, TraceError> {
+    // Same as a normal reference, but the C++ name is `&&T`
+    build_pointer_like(
+        dwarf,
+        unit,
+        abbreviations,
+        node,
+        type_cache,
+        state,
+        Some("&&"),
+    )
+}
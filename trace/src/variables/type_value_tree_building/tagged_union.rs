@@ -4,21 +4,22 @@ use crate::{
     gimli_extensions::{AttributeExt, DebuggingInformationEntryExt},
     type_value_tree::{
         value::Value,
-        variable_type::{Archetype, VariableType},
-        TypeValue, TypeValueTree, VariableDataError,
+        variable_type::{Archetype, VariableType, VariantDiscriminant},
+        TypeValue, TypeValueNode, TypeValueTree,
     },
-    variables::{build_type_value_tree, read_data_member_location},
+    variables::{build_type_value_tree, read_data_member_location, DecodeState, TypeCache},
     DefaultReader,
 };
-use gimli::{Abbreviations, AttributeValue, DebugInfoOffset, Dwarf, Unit};
-use std::collections::HashMap;
+use gimli::{Abbreviations, Attribute, AttributeValue, Dwarf, Reader, Unit};
+use std::ops::RangeInclusive;
 
 pub fn build_tagged_union<W: funty::Integral>(
     dwarf: &Dwarf<DefaultReader>,
     unit: &Unit<DefaultReader, usize>,
     abbreviations: &Abbreviations,
     node: gimli::EntriesTreeNode<DefaultReader>,
-    type_cache: &mut HashMap<DebugInfoOffset, Result<TypeValueTree<W>, TraceError>>,
+    type_cache: &mut TypeCache<W>,
+    state: &DecodeState,
 ) -> Result<TypeValueTree<W>, TraceError> {
     let mut type_value_tree = TypeValueTree::new(TypeValue::default());
     let mut type_value = type_value_tree.root_mut();
@@ -49,13 +50,22 @@ pub fn build_tagged_union<W: funty::Integral>(
     // We've got some data about the discriminant, let's make it our first type value child
 
     get_entry_type_reference_tree_recursive!(
-        discriminant_tree = (dwarf, unit, abbreviations, &discriminant_entry)
+        discriminant_tree,
+        type_unit,
+        type_abbreviations = (dwarf, unit, abbreviations, &discriminant_entry)
     );
 
     let mut discriminant_tree = discriminant_tree.map(|mut type_tree| {
-        type_tree
-            .root()
-            .map(|root| build_type_value_tree(dwarf, unit, abbreviations, root, type_cache))
+        type_tree.root().map(|root| {
+            build_type_value_tree(
+                dwarf,
+                type_unit,
+                type_abbreviations,
+                root,
+                type_cache,
+                state,
+            )
+        })
     })???;
     discriminant_tree.root_mut().data_mut().name = "discriminant".into();
 
@@ -65,6 +75,9 @@ pub fn build_tagged_union<W: funty::Integral>(
     discriminant_tree.root_mut().data_mut().bit_range.start += discriminant_location_offset_bits;
     discriminant_tree.root_mut().data_mut().bit_range.end += discriminant_location_offset_bits;
 
+    // The discriminant values of the variants are encoded differently depending on the signedness of the discriminant
+    let discriminant_signed = is_signed(discriminant_tree.root());
+
     type_value_tree.push_back(discriminant_tree);
 
     // Now we need to read all of the variant parts which are the children of the entry.
@@ -88,23 +101,26 @@ pub fn build_tagged_union<W: funty::Integral>(
         // 3. It has nothing
         //
         // The first gives the value the discriminant has to have for this variant to be active.
-        // The second one has a list of values, but I haven't seen that that being generated so far. We'll check
-        // and give an error in that case.
+        // The second one has a list of values and ranges. Rust uses this when a variant is stored in a niche
+        // that spans a range of values.
         // A variant with nothing is the default case. If no other variant matches, then this one is selected.
 
         let discr_value = variant_entry.attr(gimli::constants::DW_AT_discr_value)?;
         let discr_list = variant_entry.attr(gimli::constants::DW_AT_discr_list)?;
 
-        let discriminator_value = match (discr_value, discr_list) {
-            (Some(discr_value), _) => Some(discr_value.required_sdata_value()?),
-            (_, Some(_)) => {
-                return Err(TraceError::OperationNotImplemented {
-                    operation: "Reading the discr_list".into(),
-                    file: file!(),
-                    line: line!(),
-                })
+        let variant_discriminant = match (discr_value, discr_list) {
+            (Some(discr_value), _) => {
+                let value = if discriminant_signed {
+                    discr_value.required_sdata_value()? as i128
+                } else {
+                    discr_value.required_udata_value()? as i128
+                };
+                VariantDiscriminant::Values(vec![value..=value])
+            }
+            (_, Some(discr_list)) => {
+                VariantDiscriminant::Values(read_discr_list(&discr_list, discriminant_signed)?)
             }
-            (None, None) => None,
+            (None, None) => VariantDiscriminant::Default,
         };
 
         // We know the value, so we can create a type value tree for the variant part
@@ -112,13 +128,12 @@ pub fn build_tagged_union<W: funty::Integral>(
         let mut variant_tree = TypeValueTree::new(TypeValue {
             name: "variant".into(),
             variable_type: VariableType {
-                archetype: Archetype::TaggedUnionVariant,
+                archetype: Archetype::TaggedUnionVariant(variant_discriminant),
                 ..Default::default()
             },
             bit_range: 0..0,
-            variable_value: discriminator_value
-                .map(|v| Value::Int(v as _))
-                .ok_or(VariableDataError::NoDataAvailable),
+            variable_value: Ok(Value::Object),
+            raw_data: None,
         });
 
         // Variant parts have one child that is their actual value
@@ -135,13 +150,22 @@ pub fn build_tagged_union<W: funty::Integral>(
             read_data_member_location(&unit.header, variant_member.entry())?;
 
         get_entry_type_reference_tree_recursive!(
-            variant_member_tree = (dwarf, unit, abbreviations, variant_member.entry())
+            variant_member_tree,
+            type_unit,
+            type_abbreviations = (dwarf, unit, abbreviations, variant_member.entry())
         );
 
         let variant_member_tree = variant_member_tree.map(|mut type_tree| {
-            type_tree
-                .root()
-                .map(|root| build_type_value_tree(dwarf, unit, abbreviations, root, type_cache))
+            type_tree.root().map(|root| {
+                build_type_value_tree(
+                    dwarf,
+                    type_unit,
+                    type_abbreviations,
+                    root,
+                    type_cache,
+                    state,
+                )
+            })
         })???;
 
         variant_tree.root_mut().data_mut().bit_range = variant_member_bit_offset
@@ -153,3 +177,68 @@ pub fn build_tagged_union<W: funty::Integral>(
 
     Ok(type_value_tree)
 }
+
+/// Returns true if the (underlying) base type of the node is signed
+fn is_signed<W: funty::Integral>(node: &TypeValueNode<W>) -> bool {
+    match node.data().variable_type.archetype {
+        Archetype::BaseType(encoding) => matches!(
+            encoding,
+            gimli::constants::DW_ATE_signed | gimli::constants::DW_ATE_signed_char
+        ),
+        Archetype::Typedef | Archetype::Enumeration => node.front().map(is_signed).unwrap_or(false),
+        _ => false,
+    }
+}
+
+/// Reads the block of a `DW_AT_discr_list`.
+///
+/// The block is a list of `DW_DSC_label` followed by a value or `DW_DSC_range` followed by the low and high value.
+/// The values are signed LEB128 if the discriminant is signed and unsigned LEB128 otherwise.
+fn read_discr_list(
+    discr_list: &Attribute<DefaultReader>,
+    signed: bool,
+) -> Result<Vec<RangeInclusive<i128>>, TraceError> {
+    let mut block = match discr_list.value() {
+        AttributeValue::Block(block) => block,
+        value => {
+            return Err(TraceError::WrongAttributeValueType {
+                attribute_name: discr_list.name().to_string(),
+                expected_type_name: "Block",
+                gotten_value: format!("{:X?}", value),
+            })
+        }
+    };
+
+    let read_value = |block: &mut DefaultReader| -> Result<i128, TraceError> {
+        Ok(if signed {
+            block.read_sleb128()? as i128
+        } else {
+            block.read_uleb128()? as i128
+        })
+    };
+
+    let mut ranges = Vec::new();
+
+    while !block.is_empty() {
+        match gimli::DwDsc(block.read_u8()?) {
+            gimli::constants::DW_DSC_label => {
+                let value = read_value(&mut block)?;
+                ranges.push(value..=value);
+            }
+            gimli::constants::DW_DSC_range => {
+                let low = read_value(&mut block)?;
+                let high = read_value(&mut block)?;
+                ranges.push(low..=high);
+            }
+            dsc => {
+                return Err(TraceError::WrongAttributeValueType {
+                    attribute_name: discr_list.name().to_string(),
+                    expected_type_name: "DW_DSC_label or DW_DSC_range",
+                    gotten_value: format!("{:X?}", dsc),
+                })
+            }
+        }
+    }
+
+    Ok(ranges)
+}
@@ -0,0 +1,34 @@
+use crate::{
+    error::TraceError,
+    type_value_tree::{value::Value, variable_type::Archetype, TypeValue, TypeValueTree},
+    variables::get_entry_name,
+    DefaultReader,
+};
+use gimli::{Dwarf, Unit};
+
+pub fn build_unspecified_type<W: funty::Integral>(
+    dwarf: &Dwarf<DefaultReader>,
+    unit: &Unit<DefaultReader, usize>,
+    node: gimli::EntriesTreeNode<DefaultReader>,
+) -> Result<TypeValueTree<W>, TraceError> {
+    let mut type_value_tree = TypeValueTree::new(TypeValue::default());
+    let mut type_value = type_value_tree.root_mut();
+    let entry = node.entry();
+
+    // An unspecified type is a type of which the debug info doesn't tell us anything except (maybe) the name.
+    // In Rust this is the never type `!` and in C++ it's the type of `nullptr`.
+    // There's no data we can read, so we treat it as a zero-sized type.
+
+    let name = match get_entry_name(dwarf, unit, entry) {
+        Ok(name) if name == "decltype(nullptr)" => "nullptr_t".into(),
+        Ok(name) => name,
+        Err(_) => "!".into(),
+    };
+
+    type_value.data_mut().variable_type.name = name;
+    type_value.data_mut().variable_type.archetype = Archetype::Unspecified;
+    type_value.data_mut().bit_range = 0..0;
+    type_value.data_mut().variable_value = Ok(Value::Unit);
+
+    Ok(type_value_tree)
+}